@@ -0,0 +1,43 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// Records the resolved pdfium-render version from Cargo.lock so the Python
+// module can report it for support diagnostics
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let version = fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let mut lines = lock.lines();
+            lines.find(|line| *line == "name = \"pdfium-render\"")?;
+            let version = lines.next()?.strip_prefix("version = \"")?.strip_suffix('"')?;
+            Some(version.to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=PDFIUM_RENDER_VERSION={}", version);
+
+    if env::var_os("CARGO_FEATURE_HEIC").is_some() {
+        check_libheif();
+    }
+}
+
+// libheif-sys fails with a bare pkg-config error when libheif is missing, so
+// spell out what the `heic` feature needs. `jpeg2000` builds OpenJPEG from
+// vendored sources and needs no system library.
+fn check_libheif() {
+    match Command::new("pkg-config").args(["--exists", "libheif"]).status() {
+        Ok(status) if status.success() => {}
+        Ok(_) => panic!(
+            "The `heic` feature requires the libheif system library, which pkg-config could not find. \
+             Install it (e.g. `apt install libheif-dev` or `brew install libheif`) or build without `--features heic`."
+        ),
+        Err(_) => panic!(
+            "The `heic` feature locates the libheif system library with pkg-config, which is not installed. \
+             Install both (e.g. `apt install pkg-config libheif-dev` or `brew install pkg-config libheif`) \
+             or build without `--features heic`."
+        ),
+    }
+}
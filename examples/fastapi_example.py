@@ -0,0 +1,36 @@
+"""Serves rendered PDF pages from FastAPI without blocking the event loop.
+
+Run with:
+
+    pip install fastapi uvicorn python-multipart
+    uvicorn fastapi_example:app
+
+then POST a PDF:
+
+    curl -F file=@samples/test.pdf http://127.0.0.1:8000/render
+"""
+
+from fastapi import FastAPI, HTTPException, UploadFile
+
+import ztron_pdf
+
+app = FastAPI()
+
+
+@app.post("/render")
+async def render(file: UploadFile, quality: int = 75, format: str = "WEBP"):
+    pdf_bytes = await file.read()
+    if len(pdf_bytes) > ztron_pdf.DEFAULT_MAX_PDF_BYTES:
+        raise HTTPException(status_code=413, detail="PDF exceeds maximum allowed size")
+
+    try:
+        # Rendering runs on a background thread; other requests keep being
+        # served while this one waits
+        pages = await ztron_pdf.render_pdf_pages_async(pdf_bytes, quality, format)
+    except ValueError as e:
+        raise HTTPException(status_code=422, detail=str(e))
+
+    return {
+        "page_count": len(pages),
+        "pages": [page.image_buffer_as_base64 for page in pages],
+    }
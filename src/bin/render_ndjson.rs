@@ -0,0 +1,92 @@
+//! Renders every page of a PDF and streams the images to stdout as
+//! newline-delimited JSON, one `{"page","width","height","data"}` object per
+//! page, for use in shell pipelines:
+//!
+//! ```text
+//! render_ndjson --pdf report.pdf --format PNG | jq -r .data
+//! cat report.pdf | render_ndjson --pdf - --quality 75
+//! ```
+
+use std::error::Error;
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use clap::Parser;
+use pdfium_render::prelude::*;
+use ztron_pdf::core::{self, ImageFormat, RenderOptions};
+
+#[derive(Parser)]
+#[command(about = "Render PDF pages as newline-delimited JSON")]
+struct Args {
+    /// The PDF to render, or - to read it from stdin
+    #[arg(long)]
+    pdf: PathBuf,
+    /// Quality of the encoded images, 0 to 100
+    #[arg(long, default_value_t = 75)]
+    quality: u8,
+    /// Image format: WEBP, PNG, JPEG, or GIF
+    #[arg(long, default_value = "WEBP")]
+    format: ImageFormat,
+}
+
+/// Reads a whole PDF from stdin, failing if the input stops before the
+/// final `%%EOF` marker, e.g. because the producer died mid-write
+fn read_stdin_pdf() -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut pdf_bytes = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut pdf_bytes)
+        .map_err(|e| format!("Failed to read PDF from stdin: {}", e))?;
+
+    if pdf_bytes.is_empty() {
+        return Err("No PDF data on stdin".into());
+    }
+    let tail = &pdf_bytes[pdf_bytes.len().saturating_sub(1024)..];
+    if !tail.windows(5).any(|window| window == b"%%EOF") {
+        return Err(format!(
+            "stdin closed before the end of the PDF: read {} bytes without a trailing %%EOF",
+            pdf_bytes.len()
+        ).into());
+    }
+
+    Ok(pdf_bytes)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    // Read the input before binding pdfium so bad input fails fast
+    let pdf_bytes = if args.pdf.as_os_str() == "-" {
+        read_stdin_pdf()?
+    } else {
+        std::fs::read(&args.pdf)?
+    };
+
+    let pdfium = Pdfium::new(
+        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+            .or_else(|_| Pdfium::bind_to_system_library())?
+    );
+    let options = RenderOptions::default().with_quality(args.quality).with_format(args.format);
+    let document = core::open_document(&pdfium, pdf_bytes, None)?;
+
+    let mut stdout = std::io::stdout().lock();
+    for page_index in 0..document.pages().len() as usize {
+        let page = core::render_document_page(&document, page_index, &options)?;
+        let (width, height) = image::ImageReader::new(Cursor::new(&page.image_buffer))
+            .with_guessed_format()?
+            .into_dimensions()?;
+
+        writeln!(
+            stdout,
+            r#"{{"page":{},"width":{},"height":{},"data":"{}"}}"#,
+            page_index,
+            width,
+            height,
+            BASE64.encode(&page.image_buffer)
+        )?;
+        // Let consumers start on each page as soon as it's rendered
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
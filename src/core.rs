@@ -1,153 +1,10273 @@
 use pdfium_render::prelude::*;
-use image::DynamicImage;
-use std::io::{Cursor, Write};
+use image::{Delay, DynamicImage, Frame, GrayImage, ImageBuffer, PixelWithColorType, Rgba, RgbaImage, RgbImage};
+use imageproc::drawing::draw_line_segment_mut;
+use imageproc::edges::canny;
+use imageproc::filter::{gaussian_blur_f32, laplacian_filter};
+use imageproc::hough::{detect_lines, LineDetectionOptions};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::colorops::ColorMap;
+use base64::Engine as _;
+use cms::cert::x509::der::asn1::{Null, ObjectIdentifier, OctetString};
+use cms::cert::x509::der::{Decode, Encode, SliceReader};
+use cms::cert::x509::ext::pkix::SubjectKeyIdentifier;
+use cms::cert::x509::spki::AlgorithmIdentifierOwned;
+use cms::cert::x509::Certificate;
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use lcms2::{CIExyY, CIExyYTRIPLE, Intent, Locale, PixelFormat, Profile, Tag, TagSignature, ToneCurve, Transform, MLU};
+use owned_ttf_parser::AsFaceRef;
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::io::{Cursor, Read, Write};
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
+use std::fmt;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
 #[derive(Debug, Clone)]
 pub struct PageData {
     pub image_buffer: Vec<u8>,
+    /// Why the page has no image, e.g. "render timeout" when it was skipped
+    /// for exceeding `RenderOptions::per_page_timeout`. `None` for rendered pages.
+    pub error: Option<String>,
 }
 
-/// Converts PDF bytes into a vector of base64-encoded images (one per page)
-/// Optionally extracts text from the PDF (not using OCR)
-pub fn render_base64_pdf(
+/// Output encodings for rendered pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Webp,
+    Jpeg,
+    Png,
+    /// Quantized to 256 colors. `frame_delay_ms` is how long each page is
+    /// shown when several pages are combined by `render_pdf_as_gif`.
+    Gif { frame_delay_ms: u16 },
+    /// JPEG 2000 (JP2 container), available with the `jpeg2000` feature
+    #[cfg(feature = "jpeg2000")]
+    Jpeg2000,
+    /// HEIC (HEVC in a HEIF container), available with the `heic` feature
+    #[cfg(feature = "heic")]
+    Heic,
+}
+
+impl ImageFormat {
+    /// File extension used when writing images of this format to disk
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Gif { .. } => "gif",
+            #[cfg(feature = "jpeg2000")]
+            ImageFormat::Jpeg2000 => "jp2",
+            #[cfg(feature = "heic")]
+            ImageFormat::Heic => "heic",
+        }
+    }
+
+    /// Detects the format of encoded image data from its signature. Only the
+    /// formats the `image` crate can decode are recognised.
+    pub fn guess(data: &[u8]) -> Result<Self, PdfError> {
+        match image::guess_format(data) {
+            Ok(image::ImageFormat::WebP) => Ok(ImageFormat::Webp),
+            Ok(image::ImageFormat::Jpeg) => Ok(ImageFormat::Jpeg),
+            Ok(image::ImageFormat::Png) => Ok(ImageFormat::Png),
+            Ok(image::ImageFormat::Gif) => Ok(ImageFormat::Gif { frame_delay_ms: DEFAULT_GIF_FRAME_DELAY_MS }),
+            _ => Err(PdfError::InvalidArgument("Image is not WEBP, PNG, JPEG, or GIF data".to_string())),
+        }
+    }
+
+    /// The `image` crate decoder for this format, if it has one
+    fn decoder_format(&self) -> Option<image::ImageFormat> {
+        match self {
+            ImageFormat::Webp => Some(image::ImageFormat::WebP),
+            ImageFormat::Jpeg => Some(image::ImageFormat::Jpeg),
+            ImageFormat::Png => Some(image::ImageFormat::Png),
+            ImageFormat::Gif { .. } => Some(image::ImageFormat::Gif),
+            #[cfg(any(feature = "jpeg2000", feature = "heic"))]
+            _ => None,
+        }
+    }
+}
+
+/// Frame delay of the `ImageFormat::Gif` parsed from "GIF"
+pub const DEFAULT_GIF_FRAME_DELAY_MS: u16 = 2000;
+
+/// Format names accepted by `ImageFormat::from_str` in this build
+fn supported_formats() -> String {
+    let names = [
+        "WEBP",
+        "PNG",
+        "JPEG",
+        "GIF",
+        #[cfg(feature = "jpeg2000")]
+        "JP2",
+        #[cfg(feature = "heic")]
+        "HEIC",
+    ];
+
+    let (last, rest) = names.split_last().expect("at least one format is always supported");
+    format!("{}, or {}", rest.join(", "), last)
+}
+
+impl FromStr for ImageFormat {
+    type Err = PdfError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "WEBP" => Ok(ImageFormat::Webp),
+            "JPEG" | "JPG" => Ok(ImageFormat::Jpeg),
+            "PNG" => Ok(ImageFormat::Png),
+            "GIF" => Ok(ImageFormat::Gif { frame_delay_ms: DEFAULT_GIF_FRAME_DELAY_MS }),
+            #[cfg(feature = "jpeg2000")]
+            "JP2" | "JPEG2000" => Ok(ImageFormat::Jpeg2000),
+            #[cfg(feature = "heic")]
+            "HEIC" | "HEIF" => Ok(ImageFormat::Heic),
+            _ => Err(PdfError::InvalidArgument(format!("Format must be {}, got {}", supported_formats(), s))),
+        }
+    }
+}
+
+/// The RGB color space rendered pages are delivered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputColorSpace {
+    #[default]
+    Srgb,
+    /// Adobe RGB (1998)
+    AdobeRgb,
+    /// Display P3: the DCI-P3 primaries with a D65 white point and the sRGB
+    /// transfer curve
+    DisplayP3,
+}
+
+impl OutputColorSpace {
+    /// An ICC profile for the color space, or `None` for sRGB, which is what
+    /// pdfium renders and what untagged images are assumed to be
+    pub fn icc_profile(self) -> Option<Vec<u8>> {
+        let xy = |x, y| CIExyY { x, y, Y: 1.0 };
+        let d65 = xy(0.3127, 0.3290);
+        let (name, primaries, curve) = match self {
+            OutputColorSpace::Srgb => return None,
+            OutputColorSpace::AdobeRgb => (
+                "Adobe RGB (1998)",
+                CIExyYTRIPLE { Red: xy(0.64, 0.33), Green: xy(0.21, 0.71), Blue: xy(0.15, 0.06) },
+                ToneCurve::new(563.0 / 256.0),
+            ),
+            OutputColorSpace::DisplayP3 => (
+                "Display P3",
+                CIExyYTRIPLE { Red: xy(0.680, 0.320), Green: xy(0.265, 0.690), Blue: xy(0.150, 0.060) },
+                ToneCurve::new_parametric(4, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])
+                    .expect("The sRGB curve parameters are valid"),
+            ),
+        };
+
+        let mut profile = Profile::new_rgb(&d65, &primaries, &[&curve, &curve, &curve])
+            .expect("The built-in color spaces are valid");
+        let mut description = MLU::new(1);
+        description.set_text_ascii(name, Locale::none());
+        profile.write_tag(TagSignature::ProfileDescriptionTag, Tag::MLU(&description));
+
+        Some(profile.icc().expect("Failed to serialize a built-in ICC profile"))
+    }
+}
+
+impl FromStr for OutputColorSpace {
+    type Err = PdfError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srgb" => Ok(OutputColorSpace::Srgb),
+            "adobe_rgb" => Ok(OutputColorSpace::AdobeRgb),
+            "display_p3" => Ok(OutputColorSpace::DisplayP3),
+            _ => Err(PdfError::InvalidArgument(format!(
+                "Color space must be srgb, adobe_rgb, or display_p3, got {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Options controlling how pages are rendered
+///
+/// Build with the `with_*` methods, e.g.
+/// `RenderOptions::default().with_quality(75).with_format(ImageFormat::Webp).with_dpi(150)`.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Encoder quality between 0 and 100. Defaults to 75.
+    pub quality: u8,
+    /// Output encoding. Defaults to WebP.
+    pub format: ImageFormat,
+    /// Render resolution in dots per inch; `None` uses pdfium's default
+    /// size of one pixel per point (72 DPI).
+    pub dpi: Option<u32>,
+    /// Render pages in grayscale. Off by default.
+    pub grayscale: bool,
+    /// Raw ICC profile bytes. Rendered pages are treated as sRGB and converted
+    /// into this profile before encoding; `None` keeps the sRGB output.
+    pub icc_profile: Option<Vec<u8>>,
+    /// Render through pdfium's print path, which honours the `/OP` overprint
+    /// flags used by CMYK print production files. Off by default.
+    pub simulate_overprint: bool,
+    /// Refuse documents with more pages than this before rendering any of
+    /// them. Unlimited by default.
+    pub max_pages: Option<usize>,
+    /// Refuse input larger than this many bytes before handing it to
+    /// pdfium. Unlimited by default; see `DEFAULT_MAX_PDF_BYTES`.
+    pub max_pdf_bytes: Option<usize>,
+    /// Color space of the output. Pages are converted from sRGB and PNG,
+    /// JPEG and WebP output is tagged with the matching ICC profile.
+    /// Defaults to sRGB, which is left untagged.
+    pub color_space: OutputColorSpace,
+    /// Render each page's bleed box (falling back to the media box) inside a
+    /// white margin carrying crop marks at the trim box corners, for print
+    /// proofs. Honoured by every function that renders pages one by one
+    /// (`render_pdf`, `render_page_with_options`, `render_document_page`,
+    /// `render_page_by_label_with_options`, `render_pdf_pages_to_dir_with_options`).
+    /// Python takes it as `print_marks` on `render_pdf` and `render_base64_pdf`;
+    /// there is no `render_pdf_preset` binding, so `render_pdf` stands in for
+    /// it. Off by default.
+    pub render_with_print_marks: bool,
+    /// Give up on any page whose render takes longer than this. Skipped pages
+    /// come back from `render_pdf` and the single-page renders with an empty
+    /// image and a `RENDER_TIMEOUT` error rather than failing the whole
+    /// document; `render_pdf_pages_to_dir_with_options` fails with
+    /// `PdfError::Timeout` instead of writing an empty file. These renders use
+    /// pdfium's progressive renderer, which takes its size and rotation from
+    /// the render config (including changes `RenderHooks::before_render`
+    /// makes) but its render flags from these options. No timeout by default.
+    pub per_page_timeout: Option<Duration>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            quality: 75,
+            format: ImageFormat::default(),
+            dpi: None,
+            grayscale: false,
+            icc_profile: None,
+            simulate_overprint: false,
+            max_pages: None,
+            max_pdf_bytes: None,
+            color_space: OutputColorSpace::Srgb,
+            render_with_print_marks: false,
+            per_page_timeout: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_dpi(mut self, dpi: u32) -> Self {
+        self.dpi = Some(dpi);
+        self
+    }
+
+    pub fn with_grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    pub fn with_icc_profile(mut self, icc_profile: Vec<u8>) -> Self {
+        self.icc_profile = Some(icc_profile);
+        self
+    }
+
+    pub fn with_simulate_overprint(mut self, simulate_overprint: bool) -> Self {
+        self.simulate_overprint = simulate_overprint;
+        self
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn with_max_pdf_bytes(mut self, max_pdf_bytes: usize) -> Self {
+        self.max_pdf_bytes = Some(max_pdf_bytes);
+        self
+    }
+
+    pub fn with_color_space(mut self, color_space: OutputColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    pub fn with_print_marks(mut self, render_with_print_marks: bool) -> Self {
+        self.render_with_print_marks = render_with_print_marks;
+        self
+    }
+
+    pub fn with_per_page_timeout(mut self, per_page_timeout: Duration) -> Self {
+        self.per_page_timeout = Some(per_page_timeout);
+        self
+    }
+
+    /// Rejects option combinations no renderer can honour
+    fn validate(&self) -> Result<(), PdfError> {
+        if self.quality > 100 {
+            return Err(PdfError::InvalidArgument("Quality must be between 0 and 100".to_string()));
+        }
+        if self.dpi == Some(0) {
+            return Err(PdfError::InvalidArgument("DPI must be greater than 0".to_string()));
+        }
+        if self.icc_profile.is_some() && self.color_space != OutputColorSpace::Srgb {
+            return Err(PdfError::InvalidArgument("Pass either icc_profile or color_space, not both".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors produced by the PDF processing functions in this module
+#[derive(Debug)]
+pub enum PdfError {
+    /// The input bytes could not be opened as a PDF document
+    Load(String),
+    /// Pdfium failed while reading or modifying an opened document
+    Pdfium(PdfiumError),
+    /// A caller-supplied argument was out of range or malformed
+    InvalidArgument(String),
+    /// Rendered output could not be encoded into the requested format
+    Encode(String),
+    /// Reading or writing files failed
+    Io(std::io::Error),
+    /// A `RenderHooks` implementation rejected a rendered page
+    Hook(String),
+    /// A page render ran past `RenderOptions::per_page_timeout`
+    Timeout,
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfError::Load(e) => write!(f, "Failed to load PDF: {}", e),
+            PdfError::Pdfium(e) => write!(f, "Pdfium error: {}", e),
+            PdfError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            PdfError::Encode(e) => write!(f, "Failed to encode output: {}", e),
+            PdfError::Io(e) => write!(f, "I/O error: {}", e),
+            PdfError::Hook(e) => write!(f, "Render hook failed: {}", e),
+            PdfError::Timeout => write!(f, "Render timed out"),
+        }
+    }
+}
+
+impl Error for PdfError {}
+
+impl From<PdfiumError> for PdfError {
+    fn from(e: PdfiumError) -> Self {
+        PdfError::Pdfium(e)
+    }
+}
+
+impl From<std::io::Error> for PdfError {
+    fn from(e: std::io::Error) -> Self {
+        PdfError::Io(e)
+    }
+}
+
+/// Opens `pdf_bytes` as a document, mapping failures to `PdfError::Load`
+fn load_document<'a>(pdfium: &'a Pdfium, pdf_bytes: &'a [u8]) -> Result<PdfDocument<'a>, PdfError> {
+    pdfium
+        .load_pdf_from_byte_slice(pdf_bytes, None)
+        .map_err(|e| PdfError::Load(e.to_string()))
+}
+
+/// A sensible `RenderOptions::max_pdf_bytes` for servers taking uploads
+pub const DEFAULT_MAX_PDF_BYTES: usize = 100 * 1024 * 1024;
+
+/// Rejects input larger than `max_pdf_bytes` before pdfium parses any of it
+fn check_size_limit(pdf_bytes: &[u8], max_pdf_bytes: Option<usize>) -> Result<(), PdfError> {
+    if pdf_bytes.len() > max_pdf_bytes.unwrap_or(usize::MAX) {
+        return Err(PdfError::InvalidArgument("PDF exceeds maximum allowed size".to_string()));
+    }
+    Ok(())
+}
+
+/// Rejects documents with more than `max_pages` pages, so callers can bail
+/// out right after loading instead of working through a hostile upload
+fn check_page_limit(document: &PdfDocument, max_pages: Option<usize>) -> Result<(), PdfError> {
+    let count = document.pages().len() as usize;
+    match max_pages {
+        Some(max) if count > max => Err(PdfError::InvalidArgument(format!(
+            "PDF has {} pages, max allowed is {}", count, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The pdfium render settings for `options`; shared so that page coordinates
+/// can be mapped onto rendered pixels with the same transform
+fn render_config(options: &RenderOptions) -> PdfRenderConfig {
+    let mut config = PdfRenderConfig::new();
+    if let Some(dpi) = options.dpi {
+        config = config.scale_page_by_factor(dpi as f32 / 72.0);
+    }
+
+    config
+        .rotate_if_landscape(PdfPageRenderRotation::Degrees90, true)
+        .render_form_data(true)
+        .use_grayscale_rendering(options.grayscale)
+        .use_print_quality(options.simulate_overprint)
+}
+
+/// Renders a page as RGB according to `options`, applying any ICC profile
+fn render_page_rgb(page: &PdfPage, options: &RenderOptions) -> Result<RgbImage, PdfError> {
+    render_page_rgb_with_config(page, &render_config(options), options)
+}
+
+/// `render_page_rgb` with the pdfium settings supplied by the caller
+fn render_page_rgb_with_config(
+    page: &PdfPage,
+    config: &PdfRenderConfig,
+    options: &RenderOptions
+) -> Result<RgbImage, PdfError> {
+    let bitmap = page.render_with_config(config)?;
+
+    let mut image = bitmap.as_image().into_rgb8();
+    apply_output_profiles(&mut image, options)?;
+
+    Ok(image)
+}
+
+/// Converts a rendered sRGB page into the ICC profile or color space `options` asks for
+fn apply_output_profiles(image: &mut RgbImage, options: &RenderOptions) -> Result<(), PdfError> {
+    if let Some(icc_profile) = &options.icc_profile {
+        apply_icc_profile(image, icc_profile).map_err(PdfError::InvalidArgument)?;
+    }
+    if let Some(icc_profile) = options.color_space.icc_profile() {
+        apply_icc_profile(image, &icc_profile).map_err(PdfError::Encode)?;
+    }
+
+    Ok(())
+}
+
+/// `PageData::error` of pages skipped for exceeding `RenderOptions::per_page_timeout`
+pub const RENDER_TIMEOUT: &str = "render timeout";
+
+// Render flags and progressive render statuses from pdfium's fpdfview.h
+const FPDF_ANNOT: c_int = 0x01;
+const FPDF_GRAYSCALE: c_int = 0x08;
+const FPDF_PRINTING: c_int = 0x800;
+const FPDF_RENDER_TOBECONTINUED: c_int = 1;
+const FPDF_RENDER_DONE: c_int = 2;
+
+/// pdfium's `IFSDK_PAUSE` followed by the deadline `deadline_passed` checks;
+/// pdfium only reads the leading fields
+#[repr(C)]
+struct DeadlinePause {
+    version: c_int,
+    need_to_pause_now: unsafe extern "C" fn(*mut DeadlinePause) -> c_int,
+    user: *mut c_void,
+    deadline: Instant,
+}
+
+unsafe extern "C" fn deadline_passed(pause: *mut DeadlinePause) -> c_int {
+    // SAFETY: pdfium hands back the pause passed to FPDF_RenderPageBitmap_Start
+    c_int::from(Instant::now() >= unsafe { (*pause).deadline })
+}
+
+/// The bitmap width, height and pdfium rotation (quarter turns clockwise)
+/// that `config` renders `page` with. pdfium-render keeps these private, so
+/// they are read back by mapping the page's corners through `config`, which
+/// keeps them from drifting from what `render_with_config` does.
+fn render_geometry(page: &PdfPage, config: &PdfRenderConfig) -> Result<(c_int, c_int, c_int), PdfError> {
+    let bounds = page.boundaries().bounding()?.bounds;
+    let (left, top) = page.points_to_pixels(bounds.left(), bounds.top(), config)?;
+    let (right, bottom) = page.points_to_pixels(bounds.right(), bounds.bottom(), config)?;
+    let (width, height) = ((right - left).abs(), (bottom - top).abs());
+
+    // Where the page's top left corner lands tells the rotation
+    let rotate = match (left > right, top > bottom) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (true, true) => 2,
+        (false, true) => 3,
+    };
+
+    Ok((width, height, rotate))
+}
+
+/// Renders a page as `render_page_rgb_with_config` would with `config`,
+/// through pdfium's progressive renderer so the render can be abandoned with
+/// `PdfError::Timeout` once `deadline` passes
+///
+/// The progressive renderer doesn't draw form fields, so a page with any is
+/// flattened in memory first. pdfium checks the deadline between page
+/// objects, so a single very expensive object can still overrun it.
+fn render_page_rgb_until(
+    page: &mut PdfPage,
+    config: &PdfRenderConfig,
+    options: &RenderOptions,
+    deadline: Instant
+) -> Result<RgbImage, PdfError> {
+    if Instant::now() >= deadline {
+        return Err(PdfError::Timeout);
+    }
+    if page.annotations().iter().any(|annotation| annotation.annotation_type() == PdfPageAnnotationType::Widget) {
+        page.flatten()?;
+    }
+
+    let (width, height, rotate) = render_geometry(page, config)?;
+    let mut flags = FPDF_ANNOT;
+    if options.grayscale {
+        flags |= FPDF_GRAYSCALE;
+    }
+    if options.simulate_overprint {
+        flags |= FPDF_PRINTING;
+    }
+
+    let bindings = page.bindings();
+    let page_handle = bindings.get_handle_from_page(page);
+    let bitmap = bindings.FPDFBitmap_Create(width, height, 0);
+    if bitmap.is_null() {
+        return Err(PdfError::Pdfium(PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::Unknown)));
+    }
+    bindings.FPDFBitmap_FillRect(bitmap, 0, 0, width, height, 0xFFFFFFFF);
+
+    let mut pause = DeadlinePause {
+        version: 1,
+        need_to_pause_now: deadline_passed,
+        user: std::ptr::null_mut(),
+        deadline,
+    };
+    let pause_ptr = &mut pause as *mut DeadlinePause as *mut _;
+    let mut status = bindings.FPDF_RenderPageBitmap_Start(bitmap, page_handle, 0, 0, width, height, rotate, flags, pause_ptr);
+    while status == FPDF_RENDER_TOBECONTINUED && Instant::now() < deadline {
+        status = bindings.FPDF_RenderPage_Continue(page_handle, pause_ptr);
+    }
+    bindings.FPDF_RenderPage_Close(page_handle);
+
+    // The bitmap is BGRx, rows `stride` bytes apart
+    let result = match status {
+        FPDF_RENDER_DONE => {
+            let stride = bindings.FPDFBitmap_GetStride(bitmap) as usize;
+            let buffer = bindings.FPDFBitmap_GetBuffer_as_slice(bitmap);
+            Ok(RgbImage::from_fn(width as u32, height as u32, |x, y| {
+                let at = y as usize * stride + x as usize * 4;
+                image::Rgb([buffer[at + 2], buffer[at + 1], buffer[at]])
+            }))
+        }
+        FPDF_RENDER_TOBECONTINUED => Err(PdfError::Timeout),
+        _ => Err(PdfError::Pdfium(PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::Unknown))),
+    };
+    bindings.FPDFBitmap_Destroy(bitmap);
+
+    let mut image = result?;
+    apply_output_profiles(&mut image, options)?;
+
+    Ok(image)
+}
+
+/// Renders a page with `config`, or against `deadline` with
+/// `render_page_rgb_until` when there is one
+fn render_page_rgb_within(
+    page: &mut PdfPage,
+    config: &PdfRenderConfig,
+    options: &RenderOptions,
+    deadline: Option<Instant>
+) -> Result<RgbImage, PdfError> {
+    match deadline {
+        Some(deadline) => render_page_rgb_until(page, config, options, deadline),
+        None => render_page_rgb_with_config(page, config, options),
+    }
+}
+
+/// Width of the margin around the bleed area that holds crop marks, in points
+const PRINT_MARK_MARGIN_PT: f32 = 18.0;
+/// Gap left between crop marks and the bleed area, in points
+const PRINT_MARK_OFFSET_PT: f32 = 3.0;
+
+/// Renders a page's bleed box, or its media box when it has none, centred in
+/// a white margin with crop marks drawn in line with the trim box edges. The
+/// page's crop box is replaced in memory so pdfium renders the bleed area.
+fn render_page_with_print_marks(
+    page: &mut PdfPage,
+    config: &PdfRenderConfig,
+    options: &RenderOptions,
+    deadline: Option<Instant>
+) -> Result<RgbImage, PdfError> {
+    let boundaries = page.boundaries();
+    let bleed = boundaries.bleed().or_else(|_| boundaries.media())?.bounds;
+    let trim = boundaries.trim().or_else(|_| boundaries.crop()).map_or(bleed, |trim| trim.bounds);
+    page.boundaries_mut().set_crop(bleed)?;
+
+    let image = render_page_rgb_within(page, config, options, deadline)?;
+    let from = page.points_to_pixels(trim.left(), trim.bottom(), config)?;
+    let to = page.points_to_pixels(trim.right(), trim.top(), config)?;
+
+    let (width, height) = image.dimensions();
+    let scale = width.max(height) as f32 / bleed.width().value.max(bleed.height().value);
+    let margin = (PRINT_MARK_MARGIN_PT * scale).round() as u32;
+    let offset = PRINT_MARK_OFFSET_PT * scale;
+
+    let mut canvas = RgbImage::from_pixel(width + 2 * margin, height + 2 * margin, image::Rgb([255, 255, 255]));
+    image::imageops::replace(&mut canvas, &image, margin as i64, margin as i64);
+
+    // Trim edges in canvas pixels; rotation may have swapped the corners
+    let margin = margin as f32;
+    let trim_x = [from.0.min(to.0), from.0.max(to.0)].map(|x| x as f32 + margin);
+    let trim_y = [from.1.min(to.1), from.1.max(to.1)].map(|y| y as f32 + margin);
+    let (right, bottom) = (margin + width as f32, margin + height as f32);
+    let black = image::Rgb([0, 0, 0]);
+    for y in trim_y {
+        draw_line_segment_mut(&mut canvas, (offset, y), (margin - offset, y), black);
+        draw_line_segment_mut(&mut canvas, (right + offset, y), (right + margin - offset, y), black);
+    }
+    for x in trim_x {
+        draw_line_segment_mut(&mut canvas, (x, offset), (x, margin - offset), black);
+        draw_line_segment_mut(&mut canvas, (x, bottom + offset), (x, bottom + margin - offset), black);
+    }
+
+    Ok(canvas)
+}
+
+/// Converts an sRGB image in place into the color space described by `icc_profile`
+fn apply_icc_profile(image: &mut RgbImage, icc_profile: &[u8]) -> Result<(), String> {
+    let target = Profile::new_icc(icc_profile)
+        .map_err(|e| format!("Invalid ICC profile: {}", e))?;
+    let transform = Transform::<[u8; 3], [u8; 3]>::new(
+        &Profile::new_srgb(),
+        PixelFormat::RGB_8,
+        &target,
+        PixelFormat::RGB_8,
+        Intent::Perceptual,
+    )
+    .map_err(|e| format!("Failed to create ICC transform: {}", e))?;
+
+    let (pixels, _) = image.as_mut().as_chunks_mut::<3>();
+    transform.transform_in_place(pixels);
+
+    Ok(())
+}
+
+fn encode_jpeg<P: PixelWithColorType>(image: &ImageBuffer<P, Vec<P::Subpixel>>, quality: u8) -> Result<Vec<u8>, PdfError> {
+    let mut buffer = Vec::new();
+    JpegEncoder::new_with_quality(&mut buffer, quality)
+        .encode_image(image)
+        .map_err(|e| PdfError::Encode(format!("JPEG: {}", e)))?;
+
+    Ok(buffer)
+}
+
+/// Lowest and highest target PSNR (dB) that JPEG 2000 quality 0..99 maps onto
+#[cfg(feature = "jpeg2000")]
+const JPEG2000_PSNR_RANGE: (f32, f32) = (25.0, 45.0);
+
+// OpenJPEG stream callbacks over a `Cursor<Vec<u8>>`. The JP2 writer seeks
+// back to patch box lengths, so the stream must support skip and seek too.
+#[cfg(feature = "jpeg2000")]
+unsafe extern "C" fn jp2_write(buffer: *mut std::ffi::c_void, len: usize, user_data: *mut std::ffi::c_void) -> usize {
+    let output = &mut *(user_data as *mut Cursor<Vec<u8>>);
+    match output.write_all(std::slice::from_raw_parts(buffer as *const u8, len)) {
+        Ok(()) => len,
+        Err(_) => usize::MAX,
+    }
+}
+
+#[cfg(feature = "jpeg2000")]
+unsafe extern "C" fn jp2_skip(offset: i64, user_data: *mut std::ffi::c_void) -> i64 {
+    let output = &mut *(user_data as *mut Cursor<Vec<u8>>);
+    match std::io::Seek::seek(output, std::io::SeekFrom::Current(offset)) {
+        Ok(_) => offset,
+        Err(_) => -1,
+    }
+}
+
+#[cfg(feature = "jpeg2000")]
+unsafe extern "C" fn jp2_seek(offset: i64, user_data: *mut std::ffi::c_void) -> i32 {
+    let output = &mut *(user_data as *mut Cursor<Vec<u8>>);
+    std::io::Seek::seek(output, std::io::SeekFrom::Start(offset as u64)).is_ok() as i32
+}
+
+/// Encodes an RGB image as a JP2 file with OpenJPEG
+///
+/// Quality 100 is lossless (reversible 5/3 wavelet); anything lower uses the
+/// irreversible 9/7 wavelet with a single layer targeting a PSNR between
+/// `JPEG2000_PSNR_RANGE.0` (quality 0) and `JPEG2000_PSNR_RANGE.1` (quality 99).
+#[cfg(feature = "jpeg2000")]
+fn encode_jpeg2000(image: &RgbImage, quality: u8) -> Result<Vec<u8>, PdfError> {
+    use openjpeg_sys as opj;
+
+    let (width, height) = image.dimensions();
+    let component = opj::opj_image_cmptparm_t {
+        dx: 1,
+        dy: 1,
+        w: width,
+        h: height,
+        x0: 0,
+        y0: 0,
+        prec: 8,
+        bpp: 8,
+        sgnd: 0,
+    };
+    let mut components = [component; 3];
+
+    let mut output = Cursor::new(Vec::new());
+
+    unsafe {
+        let raw = opj::opj_image_create(3, components.as_mut_ptr(), opj::OPJ_COLOR_SPACE::OPJ_CLRSPC_SRGB);
+        if raw.is_null() {
+            return Err(PdfError::Encode("JPEG 2000: failed to allocate image".to_string()));
+        }
+        (*raw).x1 = width;
+        (*raw).y1 = height;
+        let comps = std::slice::from_raw_parts_mut((*raw).comps, 3);
+        for (channel, comp) in comps.iter_mut().enumerate() {
+            let data = std::slice::from_raw_parts_mut(comp.data, (width * height) as usize);
+            for (sample, pixel) in data.iter_mut().zip(image.pixels()) {
+                *sample = pixel[channel] as i32;
+            }
+        }
+
+        let mut parameters = std::mem::zeroed::<opj::opj_cparameters_t>();
+        opj::opj_set_default_encoder_parameters(&mut parameters);
+        parameters.tcp_numlayers = 1;
+        parameters.tcp_mct = 1;
+        if quality < 100 {
+            let (low, high) = JPEG2000_PSNR_RANGE;
+            parameters.irreversible = 1;
+            parameters.cp_fixed_quality = 1;
+            parameters.tcp_distoratio[0] = low + (high - low) * quality as f32 / 99.0;
+        }
+
+        let codec = opj::opj_create_compress(opj::OPJ_CODEC_FORMAT::OPJ_CODEC_JP2);
+        if codec.is_null() {
+            opj::opj_image_destroy(raw);
+            return Err(PdfError::Encode("JPEG 2000: failed to create encoder".to_string()));
+        }
+        let stream = opj::opj_stream_create(1 << 16, 0);
+        if stream.is_null() {
+            opj::opj_destroy_codec(codec);
+            opj::opj_image_destroy(raw);
+            return Err(PdfError::Encode("JPEG 2000: failed to allocate output stream".to_string()));
+        }
+        opj::opj_stream_set_write_function(stream, Some(jp2_write));
+        opj::opj_stream_set_skip_function(stream, Some(jp2_skip));
+        opj::opj_stream_set_seek_function(stream, Some(jp2_seek));
+        opj::opj_stream_set_user_data(stream, &mut output as *mut Cursor<Vec<u8>> as *mut std::ffi::c_void, None);
+
+        let encoded = opj::opj_setup_encoder(codec, &mut parameters, raw) != 0
+            && opj::opj_start_compress(codec, raw, stream) != 0
+            && opj::opj_encode(codec, stream) != 0
+            && opj::opj_end_compress(codec, stream) != 0;
+
+        opj::opj_stream_destroy(stream);
+        opj::opj_destroy_codec(codec);
+        opj::opj_image_destroy(raw);
+
+        if !encoded {
+            return Err(PdfError::Encode("JPEG 2000: OpenJPEG failed to encode image".to_string()));
+        }
+    }
+
+    Ok(output.into_inner())
+}
+
+/// Encodes an RGB image as HEIC with libheif's HEVC encoder
+#[cfg(feature = "heic")]
+fn encode_heic(image: &RgbImage, quality: u8) -> Result<Vec<u8>, PdfError> {
+    use libheif_rs::{Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, Image, LibHeif, RgbChroma};
+
+    let heic_error = |e: libheif_rs::HeifError| PdfError::Encode(format!("HEIC: {}", e));
+    let (width, height) = image.dimensions();
+
+    let mut heif_image = Image::new(width, height, ColorSpace::Rgb(RgbChroma::Rgb)).map_err(heic_error)?;
+    heif_image.create_plane(Channel::Interleaved, width, height, 8).map_err(heic_error)?;
+    let plane = heif_image
+        .planes_mut()
+        .interleaved
+        .ok_or_else(|| PdfError::Encode("HEIC: image has no interleaved plane".to_string()))?;
+    let row_len = width as usize * 3;
+    for (dst, src) in plane.data.chunks_mut(plane.stride).zip(image.as_raw().chunks(row_len)) {
+        dst[..row_len].copy_from_slice(src);
+    }
+
+    let lib_heif = LibHeif::new();
+    let mut context = HeifContext::new().map_err(heic_error)?;
+    let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Hevc).map_err(heic_error)?;
+    encoder.set_quality(EncoderQuality::Lossy(quality.min(100))).map_err(heic_error)?;
+    context.encode_image(&heif_image, &mut encoder, None).map_err(heic_error)?;
+    context.write_to_bytes().map_err(heic_error)
+}
+
+/// A fixed 6x7x6 RGB cube (252 colors), green getting the extra level since
+/// the eye is most sensitive to it. Images dithered onto it fit a GIF palette.
+struct GifPalette;
+
+impl GifPalette {
+    const LEVELS: [u32; 3] = [6, 7, 6];
+
+    fn level(value: u8, levels: u32) -> u32 {
+        (value as u32 * (levels - 1) + 127) / 255
+    }
+
+    fn value(level: u32, levels: u32) -> u8 {
+        ((level * 255 + (levels - 1) / 2) / (levels - 1)) as u8
+    }
+}
+
+impl ColorMap for GifPalette {
+    type Color = image::Rgb<u8>;
+
+    fn index_of(&self, color: &Self::Color) -> usize {
+        let [r, g, b] = Self::LEVELS;
+        let index = (Self::level(color[0], r) * g + Self::level(color[1], g)) * b + Self::level(color[2], b);
+        index as usize
+    }
+
+    fn map_color(&self, color: &mut Self::Color) {
+        for (channel, levels) in color.0.iter_mut().zip(Self::LEVELS) {
+            *channel = Self::value(Self::level(*channel, levels), levels);
+        }
+    }
+}
+
+/// Largest width or height a WebP image can have
+const WEBP_MAX_DIMENSION: u32 = 16383;
+
+/// Largest width or height a GIF frame can have
+const GIF_MAX_DIMENSION: u32 = u16::MAX as u32;
+
+/// Rejects images too large for a format up front, rather than letting its
+/// encoder fail or panic
+fn check_encodable_size((width, height): (u32, u32), format_name: &str, max: u32) -> Result<(), PdfError> {
+    if width > max || height > max {
+        return Err(PdfError::InvalidArgument(format!(
+            "A {}x{} image is too large for {}, which allows at most {}px per side; use PNG or JPEG instead",
+            width, height, format_name, max
+        )));
+    }
+
+    Ok(())
+}
+
+/// Encodes `frames` as a GIF, dithering each onto `GifPalette`. A single
+/// frame gives a still image; several give a looping animation showing each
+/// for `frame_delay_ms`.
+fn encode_gif(frames: &[RgbImage], frame_delay_ms: u16) -> Result<Vec<u8>, PdfError> {
+    for frame in frames {
+        check_encodable_size(frame.dimensions(), "GIF", GIF_MAX_DIMENSION)?;
+    }
+
+    let mut buffer = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut buffer);
+        if frames.len() > 1 {
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(|e| PdfError::Encode(format!("GIF: {}", e)))?;
+        }
+
+        let delay = Delay::from_numer_denom_ms(frame_delay_ms as u32, 1);
+        for frame in frames {
+            let mut quantized = frame.clone();
+            image::imageops::dither(&mut quantized, &GifPalette);
+            let rgba = DynamicImage::ImageRgb8(quantized).into_rgba8();
+            encoder
+                .encode_frame(Frame::from_parts(rgba, 0, 0, delay))
+                .map_err(|e| PdfError::Encode(format!("GIF: {}", e)))?;
+        }
+    }
+
+    Ok(buffer)
+}
+
+/// Encodes a rendered page in the given format. `quality` is ignored for PNG.
+fn encode_image(image: &RgbImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, PdfError> {
+    match format {
+        ImageFormat::Webp => {
+            check_encodable_size(image.dimensions(), "WebP", WEBP_MAX_DIMENSION)?;
+            let encoder = webp::Encoder::from_rgb(image.as_raw(), image.width(), image.height());
+            let encoded = encoder
+                .encode_simple(false, quality as f32)
+                .map_err(|e| PdfError::Encode(format!("WebP: {:?}", e)))?;
+            Ok(encoded.to_vec())
+        }
+        ImageFormat::Jpeg => encode_jpeg(image, quality.max(1)),
+        ImageFormat::Png => {
+            let mut buffer = Cursor::new(Vec::new());
+            image
+                .write_to(&mut buffer, image::ImageFormat::Png)
+                .map_err(|e| PdfError::Encode(format!("PNG: {}", e)))?;
+            Ok(buffer.into_inner())
+        }
+        ImageFormat::Gif { frame_delay_ms } => encode_gif(std::slice::from_ref(image), frame_delay_ms),
+        #[cfg(feature = "jpeg2000")]
+        ImageFormat::Jpeg2000 => encode_jpeg2000(image, quality),
+        #[cfg(feature = "heic")]
+        ImageFormat::Heic => encode_heic(image, quality),
+    }
+}
+
+/// Encodes a page rendered with `options`, tagging it with the ICC profile
+/// of a non-sRGB `options.color_space`
+fn encode_page(image: &RgbImage, options: &RenderOptions) -> Result<Vec<u8>, PdfError> {
+    let encoded = encode_image(image, options.format, options.quality)?;
+
+    match options.color_space.icc_profile() {
+        Some(icc_profile) => embed_icc_profile(encoded, options.format, &icc_profile, image.dimensions()),
+        None => Ok(encoded),
+    }
+}
+
+/// Embeds `icc_profile` into encoded PNG (iCCP chunk), JPEG (APP2
+/// ICC_PROFILE segments) or WebP (extended format ICCP chunk) data. Other
+/// formats are returned unchanged.
+fn embed_icc_profile(
+    encoded: Vec<u8>,
+    format: ImageFormat,
+    icc_profile: &[u8],
+    (width, height): (u32, u32)
+) -> Result<Vec<u8>, PdfError> {
+    match format {
+        ImageFormat::Png => {
+            // The signature and IHDR chunk come first; iCCP must precede IDAT
+            const IHDR_END: usize = 8 + 12 + 13;
+            let mut compressed = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            compressed.write_all(icc_profile)?;
+            let mut data = b"ICC profile\0\0".to_vec();
+            data.extend(compressed.finish()?);
+
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(b"iCCP");
+            crc.update(&data);
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(b"iCCP");
+            chunk.extend(data);
+            chunk.extend_from_slice(&crc.finalize().to_be_bytes());
+
+            Ok([&encoded[..IHDR_END], &chunk, &encoded[IHDR_END..]].concat())
+        }
+        ImageFormat::Jpeg => {
+            // Each APP2 segment carries a sequence number and the segment count
+            const MAX_CHUNK: usize = 65_519;
+            let chunks: Vec<&[u8]> = icc_profile.chunks(MAX_CHUNK).collect();
+            let mut segments = Vec::new();
+            for (index, chunk) in chunks.iter().enumerate() {
+                segments.extend_from_slice(&[0xFF, 0xE2]);
+                segments.extend_from_slice(&((2 + 12 + 2 + chunk.len()) as u16).to_be_bytes());
+                segments.extend_from_slice(b"ICC_PROFILE\0");
+                segments.extend_from_slice(&[index as u8 + 1, chunks.len() as u8]);
+                segments.extend_from_slice(chunk);
+            }
+
+            // After SOI and, when present, the JFIF APP0 segment
+            let mut insert_at = 2;
+            if encoded.get(2..4) == Some(&[0xFF, 0xE0]) {
+                insert_at += 2 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+            }
+            Ok([&encoded[..insert_at], &segments, &encoded[insert_at..]].concat())
+        }
+        ImageFormat::Webp => {
+            let riff_chunk = |fourcc: &[u8], data: &[u8]| {
+                let mut chunk = fourcc.to_vec();
+                chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                chunk.extend_from_slice(data);
+                if data.len() % 2 == 1 {
+                    chunk.push(0);
+                }
+                chunk
+            };
+
+            // ICCP goes straight after the VP8X header, which a simple
+            // VP8/VP8L file first needs adding, with the ICC flag set
+            const ICC_FLAG: u8 = 0x20;
+            let mut body = b"WEBP".to_vec();
+            let image_chunks = if encoded.get(12..16) == Some(b"VP8X") {
+                let mut vp8x = encoded[20..30].to_vec();
+                vp8x[0] |= ICC_FLAG;
+                body.extend(riff_chunk(b"VP8X", &vp8x));
+                &encoded[30..]
+            } else {
+                let mut vp8x = vec![ICC_FLAG, 0, 0, 0];
+                vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+                vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+                body.extend(riff_chunk(b"VP8X", &vp8x));
+                &encoded[12..]
+            };
+            body.extend(riff_chunk(b"ICCP", icc_profile));
+            body.extend_from_slice(image_chunks);
+
+            Ok([b"RIFF".as_slice(), &(body.len() as u32).to_le_bytes(), &body].concat())
+        }
+        _ => Ok(encoded),
+    }
+}
+
+/// Extension points around each page render, for post-processing pages
+/// (watermarking, classification, logging) without forking the render loop
+pub trait RenderHooks {
+    /// Called before page `page_index` is rendered, with the pdfium settings
+    /// derived from the `RenderOptions`
+    fn before_render(&self, page_index: usize, config: &mut PdfRenderConfig);
+
+    /// Called with each rendered page before it is encoded. Returning an
+    /// error stops rendering.
+    fn after_render(&self, page_index: usize, image: &mut DynamicImage) -> Result<(), String>;
+}
+
+/// `RenderHooks` that leave every page as rendered
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoOpHooks;
+
+impl RenderHooks for NoOpHooks {
+    fn before_render(&self, _page_index: usize, _config: &mut PdfRenderConfig) {}
+
+    fn after_render(&self, _page_index: usize, _image: &mut DynamicImage) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Renders every page of a PDF according to `options`
+pub fn render_pdf(
     pdfium: &Pdfium,
-    pdf_bytes: &Vec<u8>,
-    quality: u8
-) -> Result<Vec<PageData>, String> {
-    if quality > 100 {
-        return Err("Quality must be between 0 and 100".to_string());
+    pdf_bytes: &[u8],
+    options: &RenderOptions
+) -> Result<Vec<PageData>, PdfError> {
+    render_pdf_with_hooks(pdfium, pdf_bytes, options, &NoOpHooks)
+}
+
+/// `render_pdf`, invoking `hooks` around each page render. Color management
+/// happens before `after_render`, so hooks see the pixels that get encoded.
+pub fn render_pdf_with_hooks(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    options: &RenderOptions,
+    hooks: &dyn RenderHooks
+) -> Result<Vec<PageData>, PdfError> {
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+
+    document
+        .pages()
+        .iter()
+        .enumerate()
+        .map(|(page_index, mut page)| render_page_with_hooks(&mut page, page_index, options, hooks))
+        .collect()
+}
+
+/// Renders one page the way `render_pdf_with_hooks` does
+fn render_page_with_hooks(
+    page: &mut PdfPage,
+    page_index: usize,
+    options: &RenderOptions,
+    hooks: &dyn RenderHooks
+) -> Result<PageData, PdfError> {
+    let deadline = options.per_page_timeout.map(|timeout| Instant::now() + timeout);
+    let mut config = render_config(options);
+    hooks.before_render(page_index, &mut config);
+    let image = if options.render_with_print_marks {
+        render_page_with_print_marks(page, &config, options, deadline)
+    } else {
+        render_page_rgb_within(page, &config, options, deadline)
+    };
+    let image = match image {
+        Err(PdfError::Timeout) => {
+            return Ok(PageData { image_buffer: Vec::new(), error: Some(RENDER_TIMEOUT.to_string()) });
+        }
+        image => image?,
+    };
+    let mut image = DynamicImage::ImageRgb8(image);
+    hooks
+        .after_render(page_index, &mut image)
+        .map_err(|e| PdfError::Hook(format!("page {}: {}", page_index, e)))?;
+
+    Ok(PageData {
+        image_buffer: encode_page(&image.into_rgb8(), options)?,
+        error: None,
+    })
+}
+
+/// Renders the pages of a document one at a time; see `render_pdf_iter`
+pub struct PdfPageFrameIter<'a> {
+    document: PdfDocument<'a>,
+    current: usize,
+    total: usize,
+    options: RenderOptions,
+}
+
+impl Iterator for PdfPageFrameIter<'_> {
+    type Item = Result<PageData, PdfError>;
+
+    /// Renders the next page. Its bitmap is freed before this returns, so only
+    /// the encoded image of the current page is ever held.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current >= self.total {
+            return None;
+        }
+        let page_index = self.current;
+        self.current += 1;
+
+        let frame = page_at(&self.document, page_index)
+            .and_then(|mut page| render_page_with_hooks(&mut page, page_index, &self.options, &NoOpHooks));
+        Some(frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total - self.current;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for PdfPageFrameIter<'_> {}
+
+/// `render_pdf` as an iterator that renders one page per `next()` call, for
+/// callers that can't hold every rendered page in memory at once
+///
+/// The iterator owns a copy of `pdf_bytes`, so it only borrows `pdfium`.
+pub fn render_pdf_iter<'a>(
+    pdfium: &'a Pdfium,
+    pdf_bytes: &[u8],
+    options: RenderOptions
+) -> Result<PdfPageFrameIter<'a>, PdfError> {
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = open_document(pdfium, pdf_bytes.to_vec(), options.max_pages)?;
+    let total = document.pages().len() as usize;
+
+    Ok(PdfPageFrameIter { document, current: 0, total, options })
+}
+
+/// Converts PDF bytes into a vector of base64-encoded images (one per page)
+/// Optionally extracts text from the PDF (not using OCR)
+#[deprecated(note = "Use `render_pdf` with `RenderOptions::with_quality` instead")]
+pub fn render_base64_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    quality: u8,
+    options: &RenderOptions
+) -> Result<Vec<PageData>, String> {
+    let options = options.clone().with_quality(quality).with_format(ImageFormat::Webp);
+
+    render_pdf(pdfium, pdf_bytes, &options).map_err(|e| e.to_string())
+}
+
+/// Renders every page with the given quality and format, invoking `hooks`
+/// around each page render
+pub fn render_base64_pdf_with_hooks(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    quality: u8,
+    format: ImageFormat,
+    hooks: &dyn RenderHooks
+) -> Result<Vec<PageData>, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+
+    render_pdf_with_hooks(pdfium, pdf_bytes, &options, hooks)
+}
+
+
+/// Controls which embedded images `compress_pdf_with_strategy` re-encodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressStrategy {
+    /// Keep an image's original stream when re-encoding wouldn't make it smaller
+    pub skip_if_no_savings: bool,
+    /// Keep 1-bit images bi-level rather than JPEG-encoding them. JBIG2 images
+    /// are converted to CCITT G4; other bi-level images are left untouched.
+    pub preserve_bitonal: bool,
+}
+
+impl Default for CompressStrategy {
+    fn default() -> Self {
+        CompressStrategy {
+            skip_if_no_savings: true,
+            preserve_bitonal: true,
+        }
+    }
+}
+
+/// The codec of an embedded image, detected from the last filter in its chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceImageType {
+    Jpeg,
+    Jpeg2000,
+    Jbig2,
+    Ccitt,
+    /// Samples stored losslessly (Flate, LZW, or uncompressed), as PNGs are embedded
+    Png,
+}
+
+impl SourceImageType {
+    /// Classifies an image by the last filter of its chain, or `None` when unfiltered
+    fn from_last_filter(filter: Option<&[u8]>) -> Self {
+        match filter {
+            Some(b"DCTDecode") => SourceImageType::Jpeg,
+            Some(b"JPXDecode") => SourceImageType::Jpeg2000,
+            Some(b"JBIG2Decode") => SourceImageType::Jbig2,
+            Some(b"CCITTFaxDecode") => SourceImageType::Ccitt,
+            _ => SourceImageType::Png,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SourceImageType::Jpeg => "JPEG",
+            SourceImageType::Jpeg2000 => "JPEG2000",
+            SourceImageType::Jbig2 => "JBIG2",
+            SourceImageType::Ccitt => "CCITT",
+            SourceImageType::Png => "PNG",
+        }
+    }
+}
+
+fn detect_image_type(stream: &lopdf::Stream) -> SourceImageType {
+    SourceImageType::from_last_filter(stream.filters().unwrap_or_default().last().copied())
+}
+
+/// Collects the pixels of a JBIG2 page as 8-bit gray, black as 0
+struct Jbig2GrayDecoder {
+    pixels: Vec<u8>,
+}
+
+impl hayro_jbig2::Decoder for Jbig2GrayDecoder {
+    fn push_pixel(&mut self, black: bool) {
+        self.pixels.push(if black { 0 } else { 255 });
+    }
+
+    fn push_pixel_chunk(&mut self, black: bool, chunk_count: u32) {
+        let value = if black { 0 } else { 255 };
+        self.pixels.extend(std::iter::repeat_n(value, chunk_count as usize * 8));
+    }
+
+    fn next_line(&mut self) {}
+}
+
+/// Decodes a JBIG2 image stream, including any /JBIG2Globals it references
+fn decode_jbig2(document: &lopdf::Document, stream: &lopdf::Stream) -> Option<GrayImage> {
+    let globals = stream
+        .dict
+        .get(b"DecodeParms")
+        .and_then(lopdf::Object::as_dict)
+        .and_then(|params| params.get(b"JBIG2Globals"))
+        .and_then(lopdf::Object::as_reference)
+        .and_then(|id| document.get_object(id))
+        .and_then(lopdf::Object::as_stream)
+        .ok()
+        .map(lopdf::Stream::get_plain_content)
+        .transpose()
+        .ok()?;
+
+    let image = hayro_jbig2::Image::new_embedded(&stream.content, globals.as_deref()).ok()?;
+    let mut decoder = Jbig2GrayDecoder { pixels: Vec::new() };
+    image.decode(&mut decoder).ok()?;
+
+    GrayImage::from_raw(image.width(), image.height(), decoder.pixels)
+}
+
+/// Encodes a bi-level image (pixels below 128 are black) as CCITT Group 4
+fn encode_ccitt_g4(image: &GrayImage) -> Option<Vec<u8>> {
+    let width = u16::try_from(image.width()).ok()?;
+    let mut encoder = fax::encoder::Encoder::new(fax::VecWriter::new());
+    for row in image.rows() {
+        let pels = row.map(|p| if p[0] < 128 { fax::Color::Black } else { fax::Color::White });
+        encoder.encode_line(pels, width).ok()?;
+    }
+
+    Some(encoder.finish().ok()?.finish())
+}
+
+/// Decodes an image XObject to 8-bit gray or RGB samples, or `None` when its
+/// codec, color space or bit depth isn't one we can safely re-encode as JPEG
+fn decode_image_samples(
+    document: &lopdf::Document,
+    stream: &lopdf::Stream,
+    kind: SourceImageType,
+) -> Option<DynamicImage> {
+    let dict = &stream.dict;
+    // A /Decode array remaps sample values, which a plain re-encode would lose
+    if dict.get(b"ImageMask").and_then(lopdf::Object::as_bool).unwrap_or(false) || dict.has(b"Decode") {
+        return None;
+    }
+    let width = u32::try_from(dict.get(b"Width").and_then(lopdf::Object::as_i64).ok()?).ok()?;
+    let height = u32::try_from(dict.get(b"Height").and_then(lopdf::Object::as_i64).ok()?).ok()?;
+    let gray = match dict.get(b"ColorSpace").and_then(lopdf::Object::as_name).ok()? {
+        b"DeviceGray" => true,
+        b"DeviceRGB" => false,
+        _ => return None,
+    };
+    let bits = dict.get(b"BitsPerComponent").and_then(lopdf::Object::as_i64).ok()?;
+    let single_filter = stream.filters().map(|f| f.len() == 1).unwrap_or(false);
+
+    match kind {
+        SourceImageType::Jpeg if single_filter => {
+            let image = image::load_from_memory_with_format(&stream.content, image::ImageFormat::Jpeg).ok()?;
+            Some(if gray {
+                DynamicImage::ImageLuma8(image.into_luma8())
+            } else {
+                DynamicImage::ImageRgb8(image.into_rgb8())
+            })
+        }
+        SourceImageType::Jbig2 if single_filter && gray => decode_jbig2(document, stream).map(DynamicImage::ImageLuma8),
+        SourceImageType::Png if bits == 8 => {
+            let channels = if gray { 1 } else { 3 };
+            let mut samples = stream
+                .get_plain_content_with_limit(width as usize * height as usize * channels)
+                .ok()?;
+            samples.truncate(width as usize * height as usize * channels);
+            if gray {
+                GrayImage::from_raw(width, height, samples).map(DynamicImage::ImageLuma8)
+            } else {
+                RgbImage::from_raw(width, height, samples).map(DynamicImage::ImageRgb8)
+            }
+        }
+        SourceImageType::Png if bits == 1 && gray => {
+            let stride = width.div_ceil(8) as usize;
+            let samples = stream.get_plain_content_with_limit(stride * height as usize).ok()?;
+            if samples.len() < stride * height as usize {
+                return None;
+            }
+            Some(DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+                let byte = samples[y as usize * stride + x as usize / 8];
+                image::Luma([if byte & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }])
+            })))
+        }
+        _ => None,
+    }
+}
+
+/// A replacement for an image stream's content and the dictionary entries that describe it
+enum ImageReplacement {
+    Jpeg { content: Vec<u8>, gray: bool, width: u32, height: u32 },
+    CcittG4 { content: Vec<u8>, width: u32, height: u32 },
+    /// 8-bit samples, PNG Up-predicted and zlib-compressed
+    Flate { content: Vec<u8>, color_space: lopdf::Object, colors: u8, width: u32 },
+}
+
+impl ImageReplacement {
+    fn content(&self) -> &[u8] {
+        match self {
+            ImageReplacement::Jpeg { content, .. }
+            | ImageReplacement::CcittG4 { content, .. }
+            | ImageReplacement::Flate { content, .. } => content,
+        }
+    }
+
+    /// Swaps the content of `stream` for this one and rewrites the entries describing it
+    fn apply(self, stream: &mut lopdf::Stream) {
+        match self {
+            ImageReplacement::Jpeg { content, gray, width, height } => {
+                stream.set_content(content);
+                stream.dict.set("Filter", lopdf::Object::Name(b"DCTDecode".to_vec()));
+                stream.dict.remove(b"DecodeParms");
+                stream.dict.set("Width", width as i64);
+                stream.dict.set("Height", height as i64);
+                stream.dict.set("BitsPerComponent", 8);
+                let color_space = if gray { "DeviceGray" } else { "DeviceRGB" };
+                stream.dict.set("ColorSpace", lopdf::Object::Name(color_space.as_bytes().to_vec()));
+            }
+            ImageReplacement::CcittG4 { content, width, height } => {
+                stream.set_content(content);
+                stream.dict.set("Filter", lopdf::Object::Name(b"CCITTFaxDecode".to_vec()));
+                stream.dict.set("BitsPerComponent", 1);
+                let mut params = lopdf::Dictionary::new();
+                params.set("K", -1);
+                params.set("Columns", width as i64);
+                params.set("Rows", height as i64);
+                stream.dict.set("DecodeParms", params);
+
+                // Both filters decode black to 0, so the /Decode of an image
+                // mask or a gray image carries over. Any other source, such
+                // as a black-and-white RGB image, is now one-component gray,
+                // and its /Decode and /SMask described the old image.
+                let image_mask = stream.dict.get(b"ImageMask").and_then(lopdf::Object::as_bool).unwrap_or(false);
+                let gray = stream.dict.get(b"ColorSpace").and_then(lopdf::Object::as_name).is_ok_and(|name| name == b"DeviceGray");
+                if !image_mask && !gray {
+                    stream.dict.set("ColorSpace", lopdf::Object::Name(b"DeviceGray".to_vec()));
+                    stream.dict.remove(b"Decode");
+                    stream.dict.remove(b"SMask");
+                }
+            }
+            ImageReplacement::Flate { content, color_space, colors, width } => {
+                stream.set_content(content);
+                // The samples are plain device or palette values now, which a
+                // /Decode array for the old color space would misread
+                stream.dict.remove(b"Decode");
+                stream.dict.set("Filter", lopdf::Object::Name(b"FlateDecode".to_vec()));
+                stream.dict.set("BitsPerComponent", 8);
+                stream.dict.set("ColorSpace", color_space);
+                let mut params = lopdf::Dictionary::new();
+                params.set("Predictor", 12);
+                params.set("Colors", colors as i64);
+                params.set("BitsPerComponent", 8);
+                params.set("Columns", width as i64);
+                stream.dict.set("DecodeParms", params);
+            }
+        }
+    }
+}
+
+/// Picks the re-encoding for one image XObject according to `strategy`, or
+/// `None` to keep it as it is. Images re-encoded as JPEG are first resized
+/// by `scale` when one is given.
+fn recompress_image(
+    document: &lopdf::Document,
+    stream: &lopdf::Stream,
+    quality: u8,
+    strategy: CompressStrategy,
+    scale: Option<f32>,
+) -> Option<ImageReplacement> {
+    let kind = detect_image_type(stream);
+    let bitonal = matches!(kind, SourceImageType::Jbig2 | SourceImageType::Ccitt)
+        || stream.dict.get(b"ImageMask").and_then(lopdf::Object::as_bool).unwrap_or(false)
+        || stream.dict.get(b"BitsPerComponent").and_then(lopdf::Object::as_i64).ok() == Some(1);
+
+    let replacement = if bitonal && strategy.preserve_bitonal {
+        if kind != SourceImageType::Jbig2 || stream.filters().ok()?.len() != 1 {
+            return None;
+        }
+        let image = decode_jbig2(document, stream)?;
+        ImageReplacement::CcittG4 {
+            content: encode_ccitt_g4(&image)?,
+            width: image.width(),
+            height: image.height(),
+        }
+    } else {
+        let mut image = decode_image_samples(document, stream, kind)?;
+        if let Some(scale) = scale {
+            let width = ((image.width() as f32 * scale).round() as u32).max(1);
+            let height = ((image.height() as f32 * scale).round() as u32).max(1);
+            image = image.resize_exact(width, height, image::imageops::FilterType::Lanczos3);
+        }
+        let (width, height) = (image.width(), image.height());
+        match image {
+            DynamicImage::ImageLuma8(image) => ImageReplacement::Jpeg { content: encode_jpeg(&image, quality).ok()?, gray: true, width, height },
+            DynamicImage::ImageRgb8(image) => ImageReplacement::Jpeg { content: encode_jpeg(&image, quality).ok()?, gray: false, width, height },
+            _ => return None,
+        }
+    };
+
+    if strategy.skip_if_no_savings && replacement.content().len() >= stream.content.len() {
+        return None;
+    }
+
+    Some(replacement)
+}
+
+/// Opens a PDF from a base64 string and compresses its internal images to JPEG.
+///
+/// # Arguments
+/// * `base64_pdf` - A base64 encoded string of the source PDF file.
+/// * `quality` - The JPEG quality setting, from 1 (lowest) to 100 (highest).
+///   A value around 75 is a good balance between size and quality.
+///
+/// # Returns
+/// A `Result` containing the base64-encoded compressed PDF, or an error.
+pub fn compress_pdf(
+    base64_pdf: &str,
+    quality: u8,
+) -> Result<String, Box<dyn Error>> {
+    compress_pdf_with_strategy(base64_pdf, quality, CompressStrategy::default())
+}
+
+/// Like `compress_pdf`, with `strategy` deciding which images are re-encoded
+///
+/// Each image's codec is detected from its filter chain. Gray and RGB images
+/// are re-encoded as JPEG; bi-level images are either kept bi-level (JBIG2
+/// becoming CCITT G4) or JPEG-encoded depending on `strategy.preserve_bitonal`.
+/// Images in color spaces other than DeviceGray and DeviceRGB, and JPEG 2000
+/// images, are always left as they are.
+pub fn compress_pdf_with_strategy(
+    base64_pdf: &str,
+    quality: u8,
+    strategy: CompressStrategy,
+) -> Result<String, Box<dyn Error>> {
+    let pdf_bytes = base64::engine::general_purpose::STANDARD.decode(base64_pdf)?;
+    let output = compress_pdf_bytes(&pdf_bytes, quality, strategy)?;
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(output))
+}
+
+/// The raw-bytes core of `compress_pdf_with_strategy`
+pub fn compress_pdf_bytes(
+    pdf_bytes: &[u8],
+    quality: u8,
+    strategy: CompressStrategy,
+) -> Result<Vec<u8>, PdfError> {
+    if quality == 0 || quality > 100 {
+        return Err(PdfError::InvalidArgument("Quality must be between 1 and 100".to_string()));
+    }
+
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let replacements: Vec<(lopdf::ObjectId, ImageReplacement)> = document
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let stream = object.as_stream().ok()?;
+            if stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok()? != b"Image" {
+                return None;
+            }
+            Some((id, recompress_image(&document, stream, quality, strategy, None)?))
+        })
+        .collect();
+
+    for (id, replacement) in replacements {
+        if let Ok(stream) = document.get_object_mut(id).and_then(lopdf::Object::as_stream_mut) {
+            replacement.apply(stream);
+        }
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Compresses each `(pdf_bytes, quality)` pair independently in parallel,
+/// returning one result per input in input order so that a corrupt file
+/// doesn't fail the batch. Uses the default `CompressStrategy`.
+pub fn batch_compress_pdfs(inputs: Vec<(Vec<u8>, u8)>) -> Vec<Result<Vec<u8>, PdfError>> {
+    inputs
+        .par_iter()
+        .map(|(pdf_bytes, quality)| compress_pdf_bytes(pdf_bytes, *quality, CompressStrategy::default()))
+        .collect()
+}
+
+/// Images sharper than this multiple of the target resolution are downsampled
+/// by `compress_pdf_with_downsample`
+const DOWNSAMPLE_THRESHOLD: f32 = 1.5;
+
+/// Form XObjects nested deeper than this are not searched for images
+const MAX_FORM_DEPTH: usize = 8;
+
+/// A PDF transformation matrix `[a b c d e f]`
+type Matrix = [f32; 6];
+
+const IDENTITY_MATRIX: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// The matrix applying `m` and then `n`
+fn concat_matrix(m: Matrix, n: Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+/// The XObjects named in a resource dictionary
+fn xobject_ids(document: &lopdf::Document, resources: &lopdf::Dictionary) -> Vec<(Vec<u8>, lopdf::ObjectId)> {
+    resources
+        .get_deref(b"XObject", document)
+        .and_then(lopdf::Object::as_dict)
+        .map(|xobjects| {
+            xobjects
+                .iter()
+                .filter_map(|(name, xobject)| Some((name.clone(), xobject.as_reference().ok()?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Walks a content stream, recording for each image it draws the lowest
+/// resolution, in pixels per inch, at which it is placed. Form XObjects are
+/// followed, inheriting `xobjects` when they have no resources of their own.
+fn collect_image_dpi(
+    document: &lopdf::Document,
+    content: &[u8],
+    xobjects: &[(Vec<u8>, lopdf::ObjectId)],
+    ctm: Matrix,
+    depth: usize,
+    dpi: &mut BTreeMap<lopdf::ObjectId, f32>
+) {
+    let Ok(content) = lopdf::content::Content::decode(content) else {
+        return;
+    };
+
+    let mut ctm = ctm;
+    let mut saved = Vec::new();
+    for operation in content.operations {
+        match operation.operator.as_str() {
+            "q" => saved.push(ctm),
+            "Q" => ctm = saved.pop().unwrap_or(ctm),
+            "cm" => {
+                let values: Vec<f32> = operation.operands.iter().filter_map(|operand| operand.as_float().ok()).collect();
+                if let Ok(matrix) = Matrix::try_from(values) {
+                    ctm = concat_matrix(matrix, ctm);
+                }
+            }
+            "Do" => {
+                let name = operation.operands.first().and_then(|name| name.as_name().ok());
+                let Some(&(_, id)) = xobjects.iter().find(|(xobject, _)| Some(xobject.as_slice()) == name) else {
+                    continue;
+                };
+                let Ok(stream) = document.get_object(id).and_then(lopdf::Object::as_stream) else {
+                    continue;
+                };
+                match stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name) {
+                    Ok(b"Image") => {
+                        // The image fills the unit square, so the CTM's axes
+                        // are its size on the page
+                        let size = |key: &[u8]| stream.dict.get(key).and_then(lopdf::Object::as_float).unwrap_or(0.0);
+                        let width_in = ctm[0].hypot(ctm[1]) / 72.0;
+                        let height_in = ctm[2].hypot(ctm[3]) / 72.0;
+                        if width_in > 0.0 && height_in > 0.0 {
+                            let placed = (size(b"Width") / width_in).min(size(b"Height") / height_in);
+                            let entry = dpi.entry(id).or_insert(placed);
+                            *entry = entry.min(placed);
+                        }
+                    }
+                    Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                        let matrix = stream
+                            .dict
+                            .get(b"Matrix")
+                            .and_then(lopdf::Object::as_array)
+                            .ok()
+                            .and_then(|values| Matrix::try_from(values.iter().filter_map(|v| v.as_float().ok()).collect::<Vec<_>>()).ok())
+                            .unwrap_or(IDENTITY_MATRIX);
+                        let form_xobjects = match stream.dict.get_deref(b"Resources", document).and_then(lopdf::Object::as_dict) {
+                            Ok(resources) => xobject_ids(document, resources),
+                            Err(_) => xobjects.to_vec(),
+                        };
+                        let form_content = stream.get_plain_content().unwrap_or_default();
+                        collect_image_dpi(document, &form_content, &form_xobjects, concat_matrix(matrix, ctm), depth + 1, dpi);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The lowest resolution each image is displayed at across all pages.
+/// Images that are never drawn have no entry.
+fn image_display_dpi(document: &lopdf::Document) -> BTreeMap<lopdf::ObjectId, f32> {
+    let mut dpi = BTreeMap::new();
+    for page_id in document.get_pages().into_values() {
+        let mut xobjects = Vec::new();
+        if let Ok((inline, referenced)) = document.get_page_resources(page_id) {
+            xobjects.extend(inline.map(|resources| xobject_ids(document, resources)).unwrap_or_default());
+            for resources_id in referenced {
+                xobjects.extend(document.get_dictionary(resources_id).map(|resources| xobject_ids(document, resources)).unwrap_or_default());
+            }
+        }
+        let content = document.get_page_content(page_id);
+        collect_image_dpi(document, &content, &xobjects, IDENTITY_MATRIX, 0, &mut dpi);
+    }
+
+    dpi
+}
+
+/// The lopdf part of `compress_pdf_with_downsample`
+fn downsample_pdf_images(pdf_bytes: &[u8], quality: u8, target_dpi: f32) -> Result<Vec<u8>, PdfError> {
+    if quality == 0 || quality > 100 {
+        return Err(PdfError::InvalidArgument("Quality must be between 1 and 100".to_string()));
+    }
+    if !(target_dpi.is_finite() && target_dpi > 0.0) {
+        return Err(PdfError::InvalidArgument("Target DPI must be greater than 0".to_string()));
+    }
+
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let dpi = image_display_dpi(&document);
+
+    let replacements: Vec<(lopdf::ObjectId, ImageReplacement)> = document
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let stream = object.as_stream().ok()?;
+            if stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok()? != b"Image" {
+                return None;
+            }
+            let scale = dpi
+                .get(&id)
+                .filter(|&&dpi| dpi > target_dpi * DOWNSAMPLE_THRESHOLD)
+                .map(|dpi| target_dpi / dpi);
+            Some((id, recompress_image(&document, stream, quality, CompressStrategy::default(), scale)?))
+        })
+        .collect();
+
+    for (id, replacement) in replacements {
+        if let Ok(stream) = document.get_object_mut(id).and_then(lopdf::Object::as_stream_mut) {
+            replacement.apply(stream);
+        }
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Like `compress_pdf_bytes`, additionally downsampling images to
+/// `target_dpi` where they are placed at more than one and a half times
+/// that resolution
+///
+/// An image's resolution is its pixel size over the size it is drawn at on
+/// the page, taking the lowest across the places it is drawn. Images that are
+/// never drawn, or only at lower resolutions, keep their pixel size.
+pub fn compress_pdf_with_downsample(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    quality: u8,
+    target_dpi: f32,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if max_pages.is_some() {
+        check_page_limit(&load_document(pdfium, pdf_bytes)?, max_pages)?;
+    }
+    let compressed = downsample_pdf_images(pdf_bytes, quality, target_dpi)?;
+    // lopdf writes whatever it's given, so make sure the result still opens
+    load_document(pdfium, &compressed)?;
+
+    Ok(compressed)
+}
+
+/// JPEG quality `smart_compress_pdf` uses for photographs
+const SMART_JPEG_QUALITY: u8 = 75;
+
+/// Share of horizontally adjacent pixel pairs that must be identical for a
+/// grayscale image, or a color one with more than 256 colors, to count as a
+/// screenshot or line art rather than a photograph: flat UI backgrounds
+/// repeat exactly, camera noise never does
+const SCREENSHOT_FLAT_RATIO: f32 = 0.5;
+
+/// What `smart_compress_pdf` did to a PDF's images
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmartCompressReport {
+    /// Images that could be decoded and classified
+    pub images_processed: usize,
+    /// Bytes saved across the image streams
+    pub savings_bytes: i64,
+    /// The object number of each re-encoded image with the encoding chosen
+    /// for it: "JPEG", "Flate", "Flate (indexed)", or "CCITT G4"
+    pub per_image_choices: Vec<(usize, String)>,
+}
+
+/// Zlib-compresses rows of 8-bit samples using the PNG Up predictor, as read
+/// back by /FlateDecode with /Predictor 12
+fn encode_flate_png_up(samples: &[u8], row_len: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    let mut previous = vec![0; row_len];
+    for row in samples.chunks(row_len) {
+        let filtered: Vec<u8> = row.iter().zip(&previous).map(|(value, above)| value.wrapping_sub(*above)).collect();
+        encoder.write_all(&[2])?;
+        encoder.write_all(&filtered)?;
+        previous.copy_from_slice(row);
+    }
+
+    encoder.finish()
+}
+
+/// Classifies `image` by content and encodes it the way that suits it:
+///
+/// * bitonal scans (only black and white) as CCITT G4
+/// * color line art (at most 256 colors) as indexed Flate
+/// * screenshots and grayscale line art (mostly flat areas) as lossless Flate
+/// * photographs as JPEG
+///
+/// An 8-bit grayscale image never has more than 256 shades, so only the
+/// share of flat areas tells gray line art from a gray photograph.
+///
+/// PNG-style Flate stands in for lossless WebP, which PDF has no filter for.
+fn smart_replacement(image: &DynamicImage) -> Option<(&'static str, ImageReplacement)> {
+    let gray = matches!(image, DynamicImage::ImageLuma8(_));
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+
+    let mut palette: BTreeMap<[u8; 3], u8> = BTreeMap::new();
+    for pixel in rgb.pixels() {
+        if palette.len() > 256 {
+            break;
+        }
+        let next = palette.len() as u8;
+        palette.entry(pixel.0).or_insert(next);
+    }
+
+    if palette.keys().all(|color| *color == [0, 0, 0] || *color == [255, 255, 255]) {
+        let content = encode_ccitt_g4(&image.to_luma8())?;
+        return Some(("CCITT G4", ImageReplacement::CcittG4 { content, width, height }));
+    }
+
+    if palette.len() <= 256 && !gray {
+        let mut lookup = vec![0u8; palette.len() * 3];
+        for (color, &index) in &palette {
+            lookup[index as usize * 3..][..3].copy_from_slice(color);
+        }
+        let indices: Vec<u8> = rgb.pixels().map(|pixel| palette[&pixel.0]).collect();
+        let color_space = lopdf::Object::Array(vec![
+            lopdf::Object::Name(b"Indexed".to_vec()),
+            lopdf::Object::Name(b"DeviceRGB".to_vec()),
+            (palette.len() as i64 - 1).into(),
+            lopdf::Object::String(lookup, lopdf::StringFormat::Hexadecimal),
+        ]);
+        let content = encode_flate_png_up(&indices, width as usize).ok()?;
+        return Some(("Flate (indexed)", ImageReplacement::Flate { content, color_space, colors: 1, width }));
+    }
+
+    let pairs = width.saturating_sub(1) as usize * height as usize;
+    let mut flat_pairs = 0;
+    for y in 0..height {
+        for x in 1..width {
+            if rgb.get_pixel(x - 1, y) == rgb.get_pixel(x, y) {
+                flat_pairs += 1;
+            }
+        }
+    }
+    if pairs > 0 && flat_pairs as f32 / pairs as f32 >= SCREENSHOT_FLAT_RATIO {
+        let (samples, colors, color_space) = match gray {
+            true => (image.to_luma8().into_raw(), 1, "DeviceGray"),
+            false => (rgb.into_raw(), 3, "DeviceRGB"),
+        };
+        let content = encode_flate_png_up(&samples, width as usize * colors as usize).ok()?;
+        let color_space = lopdf::Object::Name(color_space.as_bytes().to_vec());
+        return Some(("Flate", ImageReplacement::Flate { content, color_space, colors, width }));
+    }
+
+    let content = match gray {
+        true => encode_jpeg(&image.to_luma8(), SMART_JPEG_QUALITY).ok()?,
+        false => encode_jpeg(&rgb, SMART_JPEG_QUALITY).ok()?,
+    };
+    Some(("JPEG", ImageReplacement::Jpeg { content, gray, width, height }))
+}
+
+/// The lopdf part of `smart_compress_pdf`
+fn smart_compress_images(pdf_bytes: &[u8]) -> Result<(Vec<u8>, SmartCompressReport), PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let images: Vec<(lopdf::ObjectId, usize, DynamicImage)> = document
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let stream = object.as_stream().ok()?;
+            if stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok()? != b"Image" {
+                return None;
+            }
+            let image = decode_image_samples(&document, stream, detect_image_type(stream))?;
+            Some((id, stream.content.len(), image))
+        })
+        .collect();
+
+    let mut report = SmartCompressReport { images_processed: images.len(), ..Default::default() };
+    for (id, old_len, image) in images {
+        // Only replacements that shrink the stream are kept
+        let Some((choice, replacement)) = smart_replacement(&image) else { continue };
+        let new_len = replacement.content().len();
+        if new_len >= old_len {
+            continue;
+        }
+        if let Ok(stream) = document.get_object_mut(id).and_then(lopdf::Object::as_stream_mut) {
+            replacement.apply(stream);
+            report.savings_bytes += (old_len - new_len) as i64;
+            report.per_image_choices.push((id.0 as usize, choice.to_string()));
+        }
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok((output, report))
+}
+
+/// Recompresses each embedded image with the encoding that suits its
+/// content (see `smart_replacement`), keeping the original wherever that is
+/// smaller, and reports the choices made. Images `compress_pdf` can't decode
+/// are left alone.
+pub fn smart_compress_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<(Vec<u8>, SmartCompressReport), PdfError> {
+    if max_pages.is_some() {
+        check_page_limit(&load_document(pdfium, pdf_bytes)?, max_pages)?;
+    }
+    let (compressed, report) = smart_compress_images(pdf_bytes)?;
+    load_document(pdfium, &compressed)?;
+
+    Ok((compressed, report))
+}
+
+/// Renders every page straight to PNG without going through `DynamicImage`
+#[deprecated(note = "Use `render_pdf_to_png_with_options` instead")]
+pub fn render_pdf_to_png(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<PageData>, PdfError> {
+    let options = RenderOptions { max_pages, ..RenderOptions::default() };
+
+    render_pdf_to_png_with_options(pdfium, pdf_bytes, &options)
+}
+
+/// Renders every page straight to PNG without going through `DynamicImage`,
+/// honouring the resolution, grayscale, overprint and limit settings of
+/// `options`; the output is always PNG, whatever `options.format` says
+///
+/// Pdfium is asked to swap the red and blue channels while rasterizing, so its
+/// BGRA buffer already holds RGBA pixels and can be handed to the PNG encoder
+/// as-is. This skips the RGBA and RGB copies `as_image().into_rgb8()` makes
+/// on the regular render path, and with them the color conversion, print
+/// marks and timeouts that path applies, so options asking for those are
+/// refused.
+pub fn render_pdf_to_png_with_options(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    options: &RenderOptions
+) -> Result<Vec<PageData>, PdfError> {
+    options.validate()?;
+    if options.icc_profile.is_some() || options.color_space.icc_profile().is_some() {
+        return Err(PdfError::InvalidArgument("The direct PNG render only produces sRGB".to_string()));
+    }
+    if options.render_with_print_marks || options.per_page_timeout.is_some() {
+        return Err(PdfError::InvalidArgument(
+            "The direct PNG render supports neither print marks nor a per-page timeout".to_string()
+        ));
+    }
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+
+    let config = render_config(options)
+        .set_format(PdfBitmapFormat::BGRA)
+        .set_reverse_byte_order(true);
+
+    document
+        .pages()
+        .iter()
+        .map(|page| {
+            let bitmap = page.render_with_config(&config)?;
+            let (width, height) = (bitmap.width() as u32, bitmap.height() as u32);
+            let pixels = bitmap.as_raw_bytes();
+            drop(bitmap);
+
+            let mut image_buffer = Vec::new();
+            let mut encoder = png::Encoder::new(&mut image_buffer, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(&pixels))
+                .map_err(|e| PdfError::Encode(format!("PNG: {}", e)))?;
+
+            Ok(PageData { image_buffer, error: None })
+        })
+        .collect()
+}
+
+/// A line of text reassembled from pdfium's character stream, with the
+/// font metadata needed to classify it as Markdown
+struct TextLine {
+    text: String,
+    font_size: f32,
+    bold: bool,
+    italic: bool,
+    bottom: f32,
+}
+
+fn is_bold_char(ch: &PdfPageTextChar) -> bool {
+    let heavy = match ch.font_weight() {
+        Some(PdfFontWeight::Weight600)
+        | Some(PdfFontWeight::Weight700Bold)
+        | Some(PdfFontWeight::Weight800)
+        | Some(PdfFontWeight::Weight900) => true,
+        Some(PdfFontWeight::Custom(weight)) => weight >= 600,
+        _ => false,
+    };
+
+    heavy || ch.font_is_bold_reenforced() || ch.font_name().to_lowercase().contains("bold")
+}
+
+/// Splits a page's text into lines on the CR/LF characters pdfium inserts
+/// between text runs, recording the largest font size on each line and
+/// whether most of its glyphs are bold or italic
+fn collect_text_lines(page: &PdfPage) -> Result<Vec<TextLine>, PdfError> {
+    let text = page.text()?;
+    let mut lines = Vec::new();
+
+    let mut current = String::new();
+    let mut font_size = 0.0f32;
+    let mut bottom = 0.0f32;
+    let mut glyphs = 0usize;
+    let mut bold_glyphs = 0usize;
+    let mut italic_glyphs = 0usize;
+
+    for ch in text.chars().iter() {
+        match ch.unicode_char() {
+            Some('\r') | Some('\n') | None => {
+                if !current.trim().is_empty() {
+                    lines.push(TextLine {
+                        text: current.trim().to_string(),
+                        font_size,
+                        bold: bold_glyphs * 2 > glyphs,
+                        italic: italic_glyphs * 2 > glyphs,
+                        bottom,
+                    });
+                }
+                current.clear();
+                font_size = 0.0;
+                glyphs = 0;
+                bold_glyphs = 0;
+                italic_glyphs = 0;
+            }
+            Some(c) => {
+                current.push(c);
+                if c.is_whitespace() {
+                    continue;
+                }
+
+                glyphs += 1;
+                font_size = font_size.max(ch.scaled_font_size().value);
+                if is_bold_char(&ch) {
+                    bold_glyphs += 1;
+                }
+                if ch.font_is_italic() {
+                    italic_glyphs += 1;
+                }
+                if let Ok(bounds) = ch.loose_bounds() {
+                    bottom = bounds.bottom().value;
+                }
+            }
+        }
+    }
+
+    if !current.trim().is_empty() {
+        lines.push(TextLine {
+            text: current.trim().to_string(),
+            font_size,
+            bold: bold_glyphs * 2 > glyphs,
+            italic: italic_glyphs * 2 > glyphs,
+            bottom,
+        });
+    }
+
+    Ok(lines)
+}
+
+/// Returns the most common font size (rounded to half a point) across all
+/// lines, which is taken to be the body text size
+fn body_font_size<'a>(lines: impl Iterator<Item = &'a TextLine>) -> f32 {
+    let mut counts: Vec<(i32, usize)> = Vec::new();
+    for line in lines {
+        let key = (line.font_size * 2.0).round() as i32;
+        let weight = line.text.chars().count();
+        match counts.iter_mut().find(|(size, _)| *size == key) {
+            Some((_, count)) => *count += weight,
+            None => counts.push((key, weight)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(size, _)| size as f32 / 2.0)
+        .unwrap_or(12.0)
+}
+
+fn strip_bullet(text: &str) -> Option<&str> {
+    const BULLETS: [char; 7] = ['•', '◦', '▪', '‣', '●', '○', '■'];
+
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    if BULLETS.contains(&first) || ((first == '-' || first == '*') && text[1..].starts_with(' ')) {
+        Some(chars.as_str().trim_start())
+    } else {
+        None
+    }
+}
+
+/// Converts the text of a PDF into Markdown
+///
+/// Headings are detected by font size relative to the body text: large bold
+/// lines become `## Heading`, bold body-sized lines become `**bold**`, italic
+/// lines become `*italic*`, and lines starting with a bullet glyph become
+/// `- item`. Consecutive body lines are joined into paragraphs, which are
+/// separated by blank lines.
+pub fn pdf_to_markdown(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<String, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut pages = Vec::new();
+    for page in document.pages().iter() {
+        pages.push(collect_text_lines(&page)?);
+    }
+
+    let body_size = body_font_size(pages.iter().flatten());
+
+    let mut blocks: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+
+    for lines in &pages {
+        let mut previous_bottom: Option<f32> = None;
+
+        for line in lines {
+            let is_heading = line.font_size >= body_size * 1.2
+                && (line.bold || line.font_size >= body_size * 1.5);
+
+            // A vertical gap of more than one and a half lines ends the paragraph
+            let gap = previous_bottom
+                .map(|bottom| (bottom - line.bottom).abs() > line.font_size.max(1.0) * 1.8)
+                .unwrap_or(false);
+            previous_bottom = Some(line.bottom);
+
+            let block = if is_heading {
+                Some(format!("## {}", line.text))
+            } else if let Some(item) = strip_bullet(&line.text) {
+                Some(format!("- {}", item))
+            } else if line.bold {
+                Some(format!("**{}**", line.text))
+            } else {
+                None
+            };
+
+            if (gap || block.is_some()) && !paragraph.is_empty() {
+                blocks.push(std::mem::take(&mut paragraph));
+            }
+
+            match block {
+                Some(block) => blocks.push(block),
+                None => {
+                    let text = if line.italic {
+                        format!("*{}*", line.text)
+                    } else {
+                        line.text.clone()
+                    };
+                    if !paragraph.is_empty() {
+                        paragraph.push(' ');
+                    }
+                    paragraph.push_str(&text);
+                }
+            }
+        }
+
+        if !paragraph.is_empty() {
+            blocks.push(std::mem::take(&mut paragraph));
+        }
+    }
+
+    Ok(blocks.join("\n\n"))
+}
+
+/// A glyph's text and loose bounds, in points
+struct PositionedChar {
+    c: char,
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+}
+
+/// The median of `values`, or `None` when empty
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Lays out a page's characters on a grid of monospace cells
+///
+/// The cell is as wide as the median glyph and as tall as the median line,
+/// so a character's column and row follow from its position. Characters on
+/// the same row that would land in the same cell keep their order, with a
+/// space between them when their glyphs are visibly apart.
+fn layout_page_text(page: &PdfPage) -> Result<String, PdfError> {
+    let mut chars = Vec::new();
+    for ch in page.text()?.chars().iter() {
+        let (Some(c), Ok(bounds)) = (ch.unicode_char(), ch.loose_bounds()) else {
+            continue;
+        };
+        if c.is_whitespace() || c.is_control() {
+            continue;
+        }
+        chars.push(PositionedChar {
+            c,
+            left: bounds.left().value,
+            right: bounds.right().value,
+            bottom: bounds.bottom().value,
+            top: bounds.top().value,
+        });
+    }
+
+    let (Some(cell_width), Some(line_height)) = (
+        median(chars.iter().map(|ch| ch.right - ch.left).collect()),
+        median(chars.iter().map(|ch| ch.top - ch.bottom).collect()),
+    ) else {
+        return Ok(String::new());
+    };
+    let cell_width = cell_width.max(1.0);
+    let line_height = line_height.max(1.0);
+
+    // Top to bottom; a glyph joins the current row when its middle lies
+    // within half a line of the row's first glyph
+    chars.sort_by(|a, b| (b.top + b.bottom).total_cmp(&(a.top + a.bottom)));
+    let mut rows: Vec<Vec<PositionedChar>> = Vec::new();
+    for ch in chars {
+        let middle = (ch.top + ch.bottom) / 2.0;
+        match rows.last_mut() {
+            Some(row) if ((row[0].top + row[0].bottom) / 2.0 - middle).abs() < line_height / 2.0 => row.push(ch),
+            _ => rows.push(vec![ch]),
+        }
+    }
+
+    let margin = rows.iter().flatten().map(|ch| ch.left).fold(f32::INFINITY, f32::min);
+    let mut lines: Vec<String> = Vec::new();
+    let mut previous_top: Option<f32> = None;
+    for mut row in rows {
+        row.sort_by(|a, b| a.left.total_cmp(&b.left));
+
+        let top = row.iter().map(|ch| ch.top).fold(f32::NEG_INFINITY, f32::max);
+        if let Some(previous_top) = previous_top {
+            let skipped = ((previous_top - top) / line_height).round() as usize;
+            lines.extend(std::iter::repeat_n(String::new(), skipped.saturating_sub(1)));
+        }
+        previous_top = Some(top);
+
+        let mut line = String::new();
+        let mut columns = 0;
+        let mut previous_right: Option<f32> = None;
+        for ch in row {
+            let column = ((ch.left - margin) / cell_width).round() as usize;
+            if column > columns {
+                line.extend(std::iter::repeat_n(' ', column - columns));
+                columns = column;
+            } else if previous_right.is_some_and(|right| ch.left - right > cell_width * 0.3) {
+                line.push(' ');
+                columns += 1;
+            }
+            line.push(ch.c);
+            columns += 1;
+            previous_right = Some(ch.right);
+        }
+        lines.push(line);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Extracts the text of a PDF as plain text, one form feed between pages
+///
+/// With `preserve_layout`, characters are placed by their bounding boxes so
+/// that columns and tables stay aligned, using spaces for horizontal
+/// position and blank lines for vertical gaps. Otherwise the text comes out
+/// compact: each line trimmed, runs of whitespace collapsed and blank lines
+/// dropped.
+pub fn pdf_to_text(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    preserve_layout: bool,
+    max_pages: Option<usize>
+) -> Result<String, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let pages = document
+        .pages()
+        .iter()
+        .map(|page| {
+            if preserve_layout {
+                return layout_page_text(&page);
+            }
+
+            let text = page.text()?.all();
+            let lines: Vec<String> = text
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect();
+            Ok(lines.join("\n"))
+        })
+        .collect::<Result<Vec<String>, PdfError>>()?;
+
+    Ok(pages.join("\x0c"))
+}
+
+/// Aggregate text statistics for a document
+#[derive(Debug, Clone)]
+pub struct DocumentStats {
+    pub page_count: usize,
+    pub total_words: usize,
+    /// Count of non-whitespace characters
+    pub total_chars: usize,
+    pub avg_words_per_page: f64,
+}
+
+/// Returns the number of whitespace-separated words on each page
+pub fn page_word_count(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<usize>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    document
+        .pages()
+        .iter()
+        .map(|page| Ok(page.text()?.all().split_whitespace().count()))
+        .collect()
+}
+
+/// Collects word and character counts across all pages of a document
+pub fn document_stats(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>
+) -> Result<DocumentStats, PdfError> {
+    check_size_limit(pdf_bytes, max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut stats = DocumentStats {
+        page_count: 0,
+        total_words: 0,
+        total_chars: 0,
+        avg_words_per_page: 0.0,
+    };
+
+    for page in document.pages().iter() {
+        let text = page.text()?.all();
+        stats.page_count += 1;
+        stats.total_words += text.split_whitespace().count();
+        stats.total_chars += text.chars().filter(|c| !c.is_whitespace()).count();
+    }
+
+    if stats.page_count > 0 {
+        stats.avg_words_per_page = stats.total_words as f64 / stats.page_count as f64;
+    }
+
+    Ok(stats)
+}
+
+/// Renders every page as a JPEG and packs them into a CBZ comic book archive
+#[deprecated(note = "Use `render_pdf_to_cbz_with_options` instead")]
+pub fn render_pdf_to_cbz(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    quality: u8,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let options = RenderOptions { quality, max_pages, ..RenderOptions::default() };
+
+    render_pdf_to_cbz_with_options(pdfium, pdf_bytes, &options)
+}
+
+/// Renders every page according to `options` as a JPEG, whatever
+/// `options.format` says, and packs them into a CBZ comic book archive
+///
+/// The archive is a plain ZIP whose entries are named `0001.jpg`, `0002.jpg`, ...
+/// in page order, so it can be saved with a `.cbz` extension and opened by
+/// e-readers directly. JPEG data is already compressed, so entries are stored
+/// without deflate. A page that exceeds `options.per_page_timeout` fails the
+/// archive with `PdfError::Timeout`.
+pub fn render_pdf_to_cbz_with_options(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    options: &RenderOptions
+) -> Result<Vec<u8>, PdfError> {
+    if options.quality == 0 || options.quality > 100 {
+        return Err(PdfError::InvalidArgument("Quality must be between 1 and 100".to_string()));
+    }
+    let options = options.clone().with_format(ImageFormat::Jpeg);
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let file_options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (index, mut page) in document.pages().iter().enumerate() {
+        let page = render_page_with_hooks(&mut page, index, &options, &NoOpHooks)?;
+        if page.error.is_some() {
+            return Err(PdfError::Timeout);
+        }
+
+        archive
+            .start_file(format!("{:04}.jpg", index + 1), file_options)
+            .map_err(|e| PdfError::Encode(format!("CBZ: {}", e)))?;
+        archive
+            .write_all(&page.image_buffer)
+            .map_err(|e| PdfError::Encode(format!("CBZ: {}", e)))?;
+    }
+
+    let buffer = archive
+        .finish()
+        .map_err(|e| PdfError::Encode(format!("CBZ: {}", e)))?;
+
+    Ok(buffer.into_inner())
+}
+
+/// A node in a tagged PDF's logical structure tree
+#[derive(Debug, Clone, Default)]
+pub struct StructureNode {
+    /// The structure type, e.g. `H1`, `P`, `Table` or `Figure`
+    pub tag: String,
+    pub alt_text: Option<String>,
+    pub actual_text: Option<String>,
+    pub children: Vec<StructureNode>,
+}
+
+/// Reads a UTF-16LE string from a pdfium getter following the usual
+/// "call with a null buffer to get the length, then fill" convention
+fn read_pdfium_string(
+    bindings: &dyn PdfiumLibraryBindings,
+    read: impl Fn(*mut c_void, c_ulong) -> c_ulong
+) -> Option<String> {
+    let length = read(std::ptr::null_mut(), 0);
+    if length <= 2 {
+        return None;
+    }
+
+    let mut buffer = vec![0u8; length as usize];
+    read(buffer.as_mut_ptr() as *mut c_void, length);
+
+    bindings
+        .get_string_from_pdfium_utf16le_bytes(buffer)
+        .filter(|text| !text.is_empty())
+}
+
+/// Extracts the logical structure tree of a tagged PDF
+///
+/// The returned root is a synthetic `StructTreeRoot` node whose children are
+/// the top-level structure elements in reading order. Elements spanning
+/// several pages appear once. Untagged documents produce a root with no
+/// children.
+pub fn extract_structure_tree(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<StructureNode, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    // pdfium only exposes the tree page by page, repeating every ancestor
+    // the pages share, so walk it from the catalog with lopdf instead
+    let saved = document.save_to_bytes()?;
+    let document = lopdf::Document::load_mem(&saved).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let mut root = StructureNode {
+        tag: "StructTreeRoot".to_string(),
+        ..Default::default()
+    };
+    let Ok(kids) = document
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"StructTreeRoot", &document))
+        .and_then(lopdf::Object::as_dict)
+        .and_then(|tree| tree.get(b"K"))
+    else {
+        return Ok(root);
+    };
+
+    // Walk the tree depth first, recording each element with the index of its
+    // parent so the nested structure can be rebuilt afterwards
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![(kids, None)];
+    let mut visited: Vec<(StructureNode, Option<usize>)> = Vec::new();
+    while let Some((object, parent)) = pending.pop() {
+        let object = match object {
+            lopdf::Object::Reference(id) if seen.insert(*id) => match document.get_object(*id) {
+                Ok(object) => object,
+                Err(_) => continue,
+            },
+            lopdf::Object::Reference(_) => continue,
+            object => object,
+        };
+        let element = match object {
+            lopdf::Object::Array(kids) => {
+                pending.extend(kids.iter().rev().map(|kid| (kid, parent)));
+                continue;
+            }
+            lopdf::Object::Dictionary(element) => element,
+            _ => continue,
+        };
+        // Marked-content and object references carry no /S
+        let Ok(tag) = element.get(b"S").and_then(lopdf::Object::as_name) else {
+            continue;
+        };
+        let text = |key: &[u8]| {
+            element
+                .get(key)
+                .and_then(lopdf::decode_text_string)
+                .ok()
+                .filter(|text| !text.is_empty())
+        };
+
+        let node = StructureNode {
+            tag: String::from_utf8_lossy(tag).into_owned(),
+            alt_text: text(b"Alt"),
+            actual_text: text(b"ActualText"),
+            children: Vec::new(),
+        };
+
+        let index = visited.len();
+        visited.push((node, parent));
+        if let Ok(kids) = element.get(b"K") {
+            pending.push((kids, Some(index)));
+        }
+    }
+
+    // Children always come after their parent, so folding from the end
+    // attaches every subtree before its parent is moved
+    while let Some((node, parent)) = visited.pop() {
+        match parent {
+            Some(parent) => visited[parent].0.children.insert(0, node),
+            None => root.children.insert(0, node),
+        }
+    }
+
+    Ok(root)
+}
+
+/// Returns `true` if the PDF looks like a raster scan with no usable text layer
+///
+/// Up to the first five pages are sampled; the document is considered scanned
+/// when the total number of non-whitespace characters pdfium extracts from
+/// them is below `text_threshold`.
+pub fn is_scanned_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    text_threshold: usize,
+    max_pages: Option<usize>
+) -> Result<bool, PdfError> {
+    const SAMPLE_PAGES: usize = 5;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut total_chars = 0;
+    for page in document.pages().iter().take(SAMPLE_PAGES) {
+        total_chars += page.text()?.all().chars().filter(|c| !c.is_whitespace()).count();
+        if total_chars >= text_threshold {
+            return Ok(false);
+        }
+    }
+
+    Ok(total_chars < text_threshold)
+}
+
+/// Renders every page into `output_dir` as `page_0001.<ext>`, `page_0002.<ext>`, ...
+///
+/// The directory is created if it does not exist. Returns the paths of the
+/// written files in page order.
+#[deprecated(note = "Use `render_pdf_pages_to_dir_with_options` instead")]
+pub fn render_pdf_pages_to_dir(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    output_dir: &Path,
+    quality: u8,
+    format: ImageFormat
+) -> Result<Vec<PathBuf>, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+
+    render_pdf_pages_to_dir_with_options(pdfium, pdf_bytes, output_dir, &options)
+}
+
+/// Renders every page according to `options` and writes the images into
+/// `output_dir` as `page_0001.<ext>`, `page_0002.<ext>`, ...
+pub fn render_pdf_pages_to_dir_with_options(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    output_dir: &Path,
+    options: &RenderOptions
+) -> Result<Vec<PathBuf>, PdfError> {
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+    std::fs::create_dir_all(output_dir)?;
+
+    document
+        .pages()
+        .iter()
+        .enumerate()
+        .map(|(index, mut page)| {
+            let page = render_page_with_hooks(&mut page, index, options, &NoOpHooks)?;
+            if page.error.is_some() {
+                return Err(PdfError::Timeout);
+            }
+            let path = output_dir.join(format!("page_{:04}.{}", index + 1, options.format.extension()));
+            std::fs::write(&path, page.image_buffer)?;
+
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Renders a single page, addressed by its zero-based index
+#[deprecated(note = "Use `render_page_with_options` instead")]
+pub fn render_page(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    quality: u8,
+    format: ImageFormat
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+
+    render_page_with_options(pdfium, pdf_bytes, page_index, &options)
+}
+
+/// Returns the page at `page_index`, or an `InvalidArgument` error naming the page count
+fn page_at<'a>(document: &'a PdfDocument, page_index: usize) -> Result<PdfPage<'a>, PdfError> {
+    let page_count = document.pages().len();
+    if page_index >= page_count as usize {
+        return Err(PdfError::InvalidArgument(format!(
+            "Page index {} is out of range for a document with {} pages", page_index, page_count
+        )));
+    }
+
+    Ok(document.pages().get(page_index as PdfPageIndex)?)
+}
+
+/// Renders a single page, addressed by its zero-based index, according to `options`
+pub fn render_page_with_options(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    options: &RenderOptions
+) -> Result<PageData, PdfError> {
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+
+    render_document_page(&document, page_index, options)
+}
+
+/// Loads a PDF that stays open across calls, taking ownership of its bytes
+pub fn open_document(
+    pdfium: &Pdfium,
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>
+) -> Result<PdfDocument<'_>, PdfError> {
+    let document = pdfium
+        .load_pdf_from_byte_vec(pdf_bytes, None)
+        .map_err(|e| PdfError::Load(e.to_string()))?;
+    check_page_limit(&document, max_pages)?;
+
+    Ok(document)
+}
+
+/// Renders a single page of an already loaded document according to `options`
+pub fn render_document_page(
+    document: &PdfDocument,
+    page_index: usize,
+    options: &RenderOptions
+) -> Result<PageData, PdfError> {
+    options.validate()?;
+    check_page_limit(document, options.max_pages)?;
+
+    let mut page = page_at(document, page_index)?;
+
+    render_page_with_hooks(&mut page, page_index, options, &NoOpHooks)
+}
+
+/// Returns the display label of every page (e.g. "iii", "1", "A-2"), or
+/// `None` for pages not covered by the document's page label tree
+pub fn get_page_labels(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>
+) -> Result<Vec<Option<String>>, PdfError> {
+    check_size_limit(pdf_bytes, max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    Ok(document
+        .pages()
+        .iter()
+        .map(|page| page.label().map(str::to_string))
+        .collect())
+}
+
+/// Renders the page whose display label matches `label` exactly
+#[deprecated(note = "Use `render_page_by_label_with_options` instead")]
+pub fn render_page_by_label(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    label: &str,
+    quality: u8,
+    format: ImageFormat
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+
+    render_page_by_label_with_options(pdfium, pdf_bytes, label, &options)
+}
+
+/// Renders the page whose display label matches `label` exactly, according to `options`
+pub fn render_page_by_label_with_options(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    label: &str,
+    options: &RenderOptions
+) -> Result<PageData, PdfError> {
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+    let page_index = get_page_labels(pdfium, pdf_bytes, options.max_pages, options.max_pdf_bytes)?
+        .iter()
+        .position(|page_label| page_label.as_deref() == Some(label))
+        .ok_or_else(|| PdfError::InvalidArgument(format!("No page is labelled {:?}", label)))?;
+
+    render_page_with_options(pdfium, pdf_bytes, page_index, options)
+}
+
+/// Renders every page as an exactly `thumb_size` × `thumb_size` image
+///
+/// Each page is scaled so its longer edge fills the square, then centred on a
+/// white background. `options.dpi` is ignored; the other options apply as usual.
+pub fn render_thumbnails(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    thumb_size: u32,
+    options: &RenderOptions
+) -> Result<Vec<PageData>, PdfError> {
+    options.validate()?;
+    if thumb_size == 0 {
+        return Err(PdfError::InvalidArgument("Thumbnail size must be greater than 0".to_string()));
+    }
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+
+    document
+        .pages()
+        .iter()
+        .map(|page| {
+            let longest_edge = page.width().value.max(page.height().value);
+            let config = PdfRenderConfig::new()
+                .scale_page_by_factor(thumb_size as f32 / longest_edge)
+                .render_form_data(true)
+                .use_grayscale_rendering(options.grayscale)
+                .use_print_quality(options.simulate_overprint);
+            let rendered = render_page_rgb_with_config(&page, &config, options)?;
+
+            // Rounding can leave the render a pixel over; overlay clips it
+            let mut thumbnail = RgbImage::from_pixel(thumb_size, thumb_size, image::Rgb([255, 255, 255]));
+            let x = (thumb_size as i64 - rendered.width() as i64) / 2;
+            let y = (thumb_size as i64 - rendered.height() as i64) / 2;
+            image::imageops::overlay(&mut thumbnail, &rendered, x.max(0), y.max(0));
+
+            Ok(PageData {
+                image_buffer: encode_page(&thumbnail, options)?,
+                error: None,
+            })
+        })
+        .collect()
+}
+
+/// Renders the first page as a WebP preview at quality 75, scaled so its
+/// longer edge is `max_size` pixels, and returns the encoded image
+pub fn render_cover(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_size: u32,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if max_size == 0 {
+        return Err(PdfError::InvalidArgument("Maximum size must be greater than 0".to_string()));
+    }
+
+    let options = RenderOptions::default();
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, 0)?;
+    let longest_edge = page.width().value.max(page.height().value);
+    let config = render_config(&options).scale_page_by_factor(max_size as f32 / longest_edge);
+
+    encode_page(&render_page_rgb_with_config(&page, &config, &options)?, &options)
+}
+
+/// One page's entry in the output of `generate_page_previews_json`
+#[derive(Serialize)]
+struct PagePreview {
+    page_index: usize,
+    /// `data:image/webp;base64,...`, usable directly as an `<img>` source
+    thumbnail_url: String,
+    width_pt: f32,
+    height_pt: f32,
+    label: Option<String>,
+    word_count: usize,
+    has_text: bool,
+}
+
+/// Describes every page as a JSON array for web page pickers: a WebP
+/// thumbnail data URL scaled so its longer edge is `thumb_size` pixels, the
+/// page size in points, its label (or `null`), word count and whether it has
+/// any text
+pub fn generate_page_previews_json(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    thumb_size: u32,
+    quality: u8,
+    max_pages: Option<usize>
+) -> Result<String, PdfError> {
+    if thumb_size == 0 {
+        return Err(PdfError::InvalidArgument("Thumbnail size must be greater than 0".to_string()));
+    }
+    let options = RenderOptions::default().with_quality(quality);
+    options.validate()?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let previews = document
+        .pages()
+        .iter()
+        .enumerate()
+        .map(|(page_index, page)| {
+            let (width_pt, height_pt) = (page.width().value, page.height().value);
+            let config = render_config(&options).scale_page_by_factor(thumb_size as f32 / width_pt.max(height_pt));
+            let thumbnail = encode_page(&render_page_rgb_with_config(&page, &config, &options)?, &options)?;
+            let word_count = page.text()?.all().split_whitespace().count();
+
+            Ok(PagePreview {
+                page_index,
+                thumbnail_url: format!("data:image/webp;base64,{}", base64::engine::general_purpose::STANDARD.encode(thumbnail)),
+                width_pt,
+                height_pt,
+                label: page.label().map(str::to_string),
+                word_count,
+                has_text: word_count > 0,
+            })
+        })
+        .collect::<Result<Vec<_>, PdfError>>()?;
+
+    serde_json::to_string(&previews).map_err(|e| PdfError::Encode(format!("JSON: {}", e)))
+}
+
+/// Access granted to readers who open a password-protected PDF with the user password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PdfPermissions {
+    pub allow_print: bool,
+    pub allow_copy: bool,
+    pub allow_modify: bool,
+}
+
+impl From<PdfPermissions> for lopdf::Permissions {
+    fn from(permissions: PdfPermissions) -> Self {
+        // Accessibility extraction must always be granted for compatibility
+        let mut flags = lopdf::Permissions::COPYABLE_FOR_ACCESSIBILITY;
+        if permissions.allow_print {
+            flags |= lopdf::Permissions::PRINTABLE | lopdf::Permissions::PRINTABLE_IN_HIGH_QUALITY;
+        }
+        if permissions.allow_copy {
+            flags |= lopdf::Permissions::COPYABLE;
+        }
+        if permissions.allow_modify {
+            flags |= lopdf::Permissions::MODIFIABLE
+                | lopdf::Permissions::ANNOTABLE
+                | lopdf::Permissions::FILLABLE
+                | lopdf::Permissions::ASSEMBLABLE;
+        }
+        flags
+    }
+}
+
+/// Encrypts a PDF with AES-128 under the given passwords and permissions
+///
+/// pdfium can only save unencrypted documents, so the file is normalised by a
+/// pdfium save and then encrypted with lopdf's standard security handler.
+pub fn set_pdf_password(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    user_password: &str,
+    owner_password: &str,
+    permissions: PdfPermissions,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+    let encryption_error = |e: lopdf::Error| PdfError::Encode(format!("Encryption: {}", e));
+
+    let mut document = lopdf::Document::load_mem(&saved).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    // The encryption key is derived from the file identifier, which pdfium
+    // does not always write
+    if document.trailer.get(b"ID").is_err() {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        saved.hash(&mut hasher);
+        let first = hasher.finish();
+        owner_password.hash(&mut hasher);
+        let id: Vec<u8> = [first, hasher.finish()].iter().flat_map(|part| part.to_be_bytes()).collect();
+        let id = lopdf::Object::String(id, lopdf::StringFormat::Hexadecimal);
+        document.trailer.set("ID", lopdf::Object::Array(vec![id.clone(), id]));
+    }
+
+    let crypt_filter: std::sync::Arc<dyn lopdf::encryption::crypt_filters::CryptFilter> =
+        std::sync::Arc::new(lopdf::encryption::crypt_filters::Aes128CryptFilter);
+    let state = lopdf::EncryptionState::try_from(lopdf::EncryptionVersion::V4 {
+        document: &document,
+        encrypt_metadata: true,
+        crypt_filters: BTreeMap::from([(b"StdCF".to_vec(), crypt_filter)]),
+        stream_filter: b"StdCF".to_vec(),
+        string_filter: b"StdCF".to_vec(),
+        owner_password,
+        user_password,
+        permissions: permissions.into(),
+    })
+    .map_err(encryption_error)?;
+    document.encrypt(&state).map_err(encryption_error)?;
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Deletes zero-length streams, replacing every reference to them with `null`
+///
+/// lopdf's `delete_zero_length_streams` instead drops the referencing
+/// dictionary entries and array items, which shifts positional arrays such
+/// as `/Nums` out of step.
+fn delete_empty_streams(document: &mut lopdf::Document) {
+    let empty: BTreeSet<lopdf::ObjectId> = document
+        .objects
+        .iter()
+        .filter(|(_, object)| matches!(object, lopdf::Object::Stream(stream) if stream.content.is_empty()))
+        .map(|(id, _)| *id)
+        .collect();
+    if empty.is_empty() {
+        return;
+    }
+
+    document.traverse_objects(|object| {
+        if matches!(object, lopdf::Object::Reference(id) if empty.contains(id)) {
+            *object = lopdf::Object::Null;
+        }
+    });
+    document.objects.retain(|id, _| !empty.contains(id));
+}
+
+/// Drops objects unreachable from the trailer and empty streams, and
+/// renumbers the rest so the cross-reference table has no gaps
+fn prune_pdf_objects(pdf_bytes: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    document.prune_objects();
+    delete_empty_streams(&mut document);
+    document.renumber_objects();
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Rewrites a PDF without superseded revisions, orphaned objects, or dead
+/// cross-reference entries
+///
+/// pdfium's full (non-incremental) save keeps only the latest revision of
+/// each object but still writes unreferenced ones, so the result is pruned
+/// afterwards.
+pub fn garbage_collect_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+
+    prune_pdf_objects(&saved)
+}
+
+/// Rebuilds a mildly corrupt PDF, e.g. one with a truncated cross-reference
+/// table or wrong offsets
+///
+/// pdfium has no separate recovery mode: `FPDF_LoadMemDocument` already
+/// falls back to rebuilding the cross-reference table by scanning for
+/// objects when the one in the file is unusable. A full save then writes a
+/// fresh, correct table.
+pub fn repair_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Reads the `/Name` of the signature dictionary whose `/ByteRange` is
+/// `byte_range`, decoded as a PDF text string
+///
+/// pdfium does not expose the signer name, so the dictionary is looked up
+/// with lopdf, which also finds it inside compressed object streams.
+fn signature_signer_name(document: &lopdf::Document, byte_range: [usize; 4]) -> Option<String> {
+    document.objects.values().find_map(|object| {
+        let dictionary = object.as_dict().ok()?;
+        let range = dictionary.get(b"ByteRange").and_then(lopdf::Object::as_array).ok()?;
+        let same_range = range.len() == 4
+            && range.iter().zip(byte_range).all(|(value, expected)| value.as_i64().ok() == Some(expected as i64));
+        if !same_range {
+            return None;
+        }
+        dictionary.get(b"Name").and_then(lopdf::decode_text_string).ok()
+    })
+}
+
+/// The `/ByteRange` of signature `index`: [offset1, length1, offset2,
+/// length2]. The signature value lives in the gap between the two ranges.
+fn signature_byte_range(pdfium: &Pdfium, document: &PdfDocument, index: usize) -> Option<[usize; 4]> {
+    let bindings = pdfium.bindings();
+    let handle = bindings.FPDF_GetSignatureObject(bindings.get_handle_from_document(document), index as c_int);
+    let mut byte_range: [c_int; 4] = [0; 4];
+    if bindings.FPDFSignatureObj_GetByteRange(handle, byte_range.as_mut_ptr(), 4) != 4 {
+        return None;
+    }
+
+    let [offset1, length1, offset2, length2] = byte_range.map(|value| usize::try_from(value).ok());
+    Some([offset1?, length1?, offset2?, length2?])
+}
+
+/// Returns the signer name (if any) of every signature whose `/Contents` is non-empty
+fn signed_signatures(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<Option<String>>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let dictionaries = lopdf::Document::load_mem(pdf_bytes).ok();
+
+    let mut signers = Vec::new();
+    for (index, signature) in document.signatures().iter().enumerate() {
+        if signature.bytes().iter().all(|&b| b == 0) {
+            continue;
+        }
+
+        let name = signature_byte_range(pdfium, &document, index)
+            .zip(dictionaries.as_ref())
+            .and_then(|(byte_range, dictionaries)| signature_signer_name(dictionaries, byte_range));
+        signers.push(name);
+    }
+
+    Ok(signers)
+}
+
+const OID_SHA1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.14.3.2.26");
+const OID_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const OID_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+const OID_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+const OID_RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const OID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+
+/// Hashes `parts` in order with the SHA-1 or SHA-2 algorithm `algorithm`
+/// names, or returns `None` for any other algorithm
+fn digest_parts(algorithm: ObjectIdentifier, parts: &[&[u8]]) -> Option<Vec<u8>> {
+    fn hash<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().to_vec()
+    }
+
+    match algorithm {
+        oid if oid == OID_SHA1 => Some(hash::<sha1::Sha1>(parts)),
+        oid if oid == OID_SHA256 => Some(hash::<Sha256>(parts)),
+        oid if oid == OID_SHA384 => Some(hash::<Sha384>(parts)),
+        oid if oid == OID_SHA512 => Some(hash::<Sha512>(parts)),
+        _ => None,
+    }
+}
+
+/// The DER DigestInfo that a PKCS#1 v1.5 signature puts in front of a
+/// `digest_len`-byte digest made with `algorithm`
+fn digest_info_prefix(algorithm: ObjectIdentifier, digest_len: usize) -> Option<Vec<u8>> {
+    let algorithm = AlgorithmIdentifierOwned { oid: algorithm, parameters: Some(Null.into()) }.to_der().ok()?;
+
+    let mut prefix = vec![0x30, u8::try_from(algorithm.len() + 2 + digest_len).ok()?];
+    prefix.extend_from_slice(&algorithm);
+    prefix.extend_from_slice(&[0x04, u8::try_from(digest_len).ok()?]);
+    Some(prefix)
+}
+
+/// Whether `signature` over a message hashing to `hash` was made with the
+/// private key of `certificate`. RSA PKCS#1 v1.5 and ECDSA on P-256 are
+/// supported; other key types and RSA-PSS never verify.
+fn public_key_verifies(certificate: &Certificate, digest_alg: ObjectIdentifier, hash: &[u8], signature: &[u8]) -> bool {
+    use p256::ecdsa::signature::hazmat::PrehashVerifier;
+    use rsa::pkcs8::DecodePublicKey;
+
+    let public_key = &certificate.tbs_certificate.subject_public_key_info;
+    let Ok(public_key_der) = public_key.to_der() else {
+        return false;
+    };
+
+    match public_key.algorithm.oid {
+        oid if oid == OID_RSA_ENCRYPTION => {
+            let (Ok(key), Some(prefix)) = (
+                rsa::RsaPublicKey::from_public_key_der(&public_key_der),
+                digest_info_prefix(digest_alg, hash.len()),
+            ) else {
+                return false;
+            };
+            let scheme = rsa::Pkcs1v15Sign { hash_len: Some(hash.len()), prefix: prefix.into() };
+            key.verify(scheme, hash, signature).is_ok()
+        }
+        oid if oid == OID_EC_PUBLIC_KEY => {
+            let (Ok(key), Ok(signature)) = (
+                p256::ecdsa::VerifyingKey::from_public_key_der(&public_key_der),
+                p256::ecdsa::Signature::from_der(signature),
+            ) else {
+                return false;
+            };
+            key.verify_prehash(hash, &signature).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// The certificate embedded in `signed_data` that `signer` names
+fn signer_certificate<'a>(signed_data: &'a SignedData, signer: &SignerInfo) -> Option<&'a Certificate> {
+    signed_data
+        .certificates
+        .as_ref()?
+        .0
+        .iter()
+        .filter_map(|choice| match choice {
+            CertificateChoices::Certificate(certificate) => Some(certificate),
+            _ => None,
+        })
+        .find(|certificate| {
+            let tbs = &certificate.tbs_certificate;
+            match &signer.sid {
+                SignerIdentifier::IssuerAndSerialNumber(id) => {
+                    tbs.issuer == id.issuer && tbs.serial_number == id.serial_number
+                }
+                SignerIdentifier::SubjectKeyIdentifier(id) => {
+                    matches!(tbs.get::<SubjectKeyIdentifier>(), Ok(Some((_, key_id))) if key_id == *id)
+                }
+            }
+        })
+}
+
+/// Checks the CMS SignedData in a signature's `/Contents` against the bytes
+/// it signs: the messageDigest attribute must match `signed_parts`, and the
+/// signer's signature over the signed attributes must verify against its
+/// embedded certificate. Returns `None` when the blob can't be parsed.
+fn cms_signature_valid(contents: &[u8], signed_parts: [&[u8]; 2]) -> Option<bool> {
+    // `/Contents` is zero-padded to its reserved size after the DER
+    let content_info = ContentInfo::decode(&mut SliceReader::new(contents).ok()?).ok()?;
+    if content_info.content_type != OID_SIGNED_DATA {
+        return None;
+    }
+    let signed_data: SignedData = content_info.content.decode_as().ok()?;
+    let [signer] = signed_data.signer_infos.0.as_slice() else {
+        return None;
+    };
+
+    let digest_alg = signer.digest_alg.oid;
+    let content_digest = digest_parts(digest_alg, &signed_parts)?;
+    let signed_hash = match &signer.signed_attrs {
+        Some(attributes) => {
+            let message_digest: OctetString = attributes
+                .iter()
+                .find(|attribute| attribute.oid == OID_MESSAGE_DIGEST)?
+                .values
+                .get(0)?
+                .decode_as()
+                .ok()?;
+            if message_digest.as_bytes() != content_digest {
+                return Some(false);
+            }
+            // Signed attributes are signed as an explicit SET OF, not their
+            // implicitly tagged encoding inside the SignerInfo
+            digest_parts(digest_alg, &[&attributes.to_der().ok()?])?
+        }
+        None => content_digest,
+    };
+    let certificate = signer_certificate(&signed_data, signer)?;
+
+    Some(public_key_verifies(certificate, digest_alg, &signed_hash, signer.signature.as_bytes()))
+}
+
+/// Whether a signature's byte range lies within the file and its CMS
+/// `contents` is a valid signature over the bytes that range covers
+fn signature_valid(pdf_bytes: &[u8], byte_range: [usize; 4], contents: &[u8]) -> bool {
+    let [offset1, length1, offset2, length2] = byte_range;
+    let (Some(first), Some(second)) = (
+        pdf_bytes.get(offset1..offset1.saturating_add(length1)),
+        pdf_bytes.get(offset2..offset2.saturating_add(length2)),
+    ) else {
+        return false;
+    };
+    if offset1 + length1 > offset2 {
+        return false;
+    }
+
+    cms_signature_valid(contents, [first, second]).unwrap_or(false)
+}
+
+/// Whether a byte range covers the whole file apart from the signature
+/// value itself, i.e. it starts at 0 and runs to EOF
+fn byte_range_covers_document(pdf_bytes: &[u8], [offset1, _, offset2, length2]: [usize; 4]) -> bool {
+    offset1 == 0 && offset2.checked_add(length2) == Some(pdf_bytes.len())
+}
+
+/// The outcome of checking one signature with `verify_signatures`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureVerification {
+    /// The signature's `/Name`, or empty when it records none
+    pub signer: String,
+    /// Whether the signature verifies against its embedded certificate and
+    /// the signed byte ranges are intact
+    pub valid: bool,
+    /// Whether the signed byte ranges span the whole file. False when content
+    /// was appended after signing, e.g. by a later incremental update.
+    pub covers_whole_document: bool,
+    /// The `/Reason` given for signing
+    pub reason: Option<String>,
+    /// The raw `/M` signing time, e.g. "D:20240102030405Z"
+    pub signing_time: Option<String>,
+}
+
+/// Checks every signed signature in a PDF, returning an empty list for
+/// unsigned documents
+///
+/// A signature is valid when the bytes its byte range covers hash (SHA-1 or
+/// SHA-2) to the messageDigest in its CMS signed attributes, and the signer's
+/// RSA or P-256 signature over those attributes verifies against the
+/// certificate embedded with it. That proves the signed content is unchanged
+/// since it was signed with that certificate's key; whether the certificate
+/// itself is trusted, expired or revoked is not checked. Changes appended
+/// after the signed range are reported through `covers_whole_document`.
+pub fn verify_signatures(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<SignatureVerification>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let dictionaries = lopdf::Document::load_mem(pdf_bytes).ok();
+
+    let mut verifications = Vec::new();
+    for (index, signature) in document.signatures().iter().enumerate() {
+        let contents = signature.bytes();
+        if contents.iter().all(|&b| b == 0) {
+            continue;
+        }
+
+        let byte_range = signature_byte_range(pdfium, &document, index);
+        let signer = byte_range
+            .zip(dictionaries.as_ref())
+            .and_then(|(byte_range, dictionaries)| signature_signer_name(dictionaries, byte_range))
+            .unwrap_or_default();
+        verifications.push(SignatureVerification {
+            signer,
+            valid: byte_range.is_some_and(|byte_range| signature_valid(pdf_bytes, byte_range, &contents)),
+            covers_whole_document: byte_range.is_some_and(|byte_range| byte_range_covers_document(pdf_bytes, byte_range)),
+            reason: signature.reason(),
+            signing_time: signature.signing_date(),
+        });
+    }
+
+    Ok(verifications)
+}
+
+/// Returns `true` if the document contains at least one signed signature field
+pub fn has_digital_signature(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<bool, PdfError> {
+    Ok(!signed_signatures(pdfium, pdf_bytes, max_pages)?.is_empty())
+}
+
+/// Counts the signature fields that carry a signature value
+pub fn count_digital_signatures(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<usize, PdfError> {
+    Ok(signed_signatures(pdfium, pdf_bytes, max_pages)?.len())
+}
+
+/// Lists the `/Name` of each signature that records one
+pub fn list_signature_signers(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<String>, PdfError> {
+    Ok(signed_signatures(pdfium, pdf_bytes, max_pages)?.into_iter().flatten().collect())
+}
+
+/// Skew angles (in degrees) below this are left uncorrected
+const DESKEW_MIN_ANGLE: f32 = 0.3;
+
+/// Estimates how far the text lines of a page image are rotated clockwise
+/// from horizontal, in degrees
+///
+/// imageproc's Hough transform only resolves whole degrees, so the dominant
+/// near-horizontal angle it finds is refined in 0.05 degree steps by picking
+/// the angle whose edge projection is the sharpest.
+fn estimate_skew_degrees(image: &GrayImage) -> Option<f32> {
+    let edges = canny(image, 50.0, 100.0);
+    let lines = detect_lines(&edges, LineDetectionOptions {
+        vote_threshold: (image.width() / 8).max(1),
+        suppression_radius: 8,
+    });
+
+    // Horizontal lines have a normal at 90 degrees; only consider ±10 degrees
+    let mut votes = [0usize; 21];
+    for line in &lines {
+        if (80..=100).contains(&line.angle_in_degrees) {
+            votes[(line.angle_in_degrees - 80) as usize] += 1;
+        }
+    }
+    let (coarse, &count) = votes.iter().enumerate().max_by_key(|&(_, count)| *count)?;
+    if count == 0 {
+        return None;
+    }
+    let coarse = 80.0 + coarse as f32;
+
+    let points: Vec<(f32, f32)> = edges
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] > 0)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    let diagonal = (image.width() as f32).hypot(image.height() as f32) as usize + 1;
+
+    let mut best = (coarse, 0u64);
+    for step in -20..=20 {
+        let angle = coarse + step as f32 * 0.05;
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        let mut bins = vec![0u64; 2 * diagonal + 1];
+        for &(x, y) in &points {
+            let r = (x * cos + y * sin).round() as isize + diagonal as isize;
+            if let Some(bin) = bins.get_mut(r as usize) {
+                *bin += 1;
+            }
+        }
+
+        let score = bins.iter().map(|&n| n * n).sum();
+        if score > best.1 {
+            best = (angle, score);
+        }
+    }
+
+    Some(best.0 - 90.0)
+}
+
+/// Straightens scanned pages whose text lines are rotated by more than 0.3 degrees
+///
+/// Each page is rendered at 150 DPI to measure its skew; skewed pages have
+/// their content rotated back around the page centre. The corrected document
+/// is returned as new PDF bytes.
+pub fn deskew_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    for mut page in document.pages_mut().iter() {
+        let image = page.render_with_config(&PdfRenderConfig::new()
+            .scale_page_by_factor(150.0 / 72.0)
+            .render_form_data(true))?
+            .as_image()
+            .into_luma8();
+
+        let Some(skew) = estimate_skew_degrees(&image) else {
+            continue;
+        };
+        if skew.abs() <= DESKEW_MIN_ANGLE {
+            continue;
+        }
+
+        // A positive skew is clockwise on screen; PDF space has y pointing up,
+        // so undo it with a counter-clockwise rotation about the page centre
+        let (sin, cos) = skew.to_radians().sin_cos();
+        let cx = page.width().value / 2.0;
+        let cy = page.height().value / 2.0;
+        page.transform(
+            cos,
+            sin,
+            -sin,
+            cos,
+            cx - cx * cos + cy * sin,
+            cy - cx * sin - cy * cos,
+        )?;
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// A PDF embedded in a portfolio, rendered page by page
+#[derive(Debug, Clone)]
+pub struct PortfolioEntry {
+    pub filename: String,
+    pub pages: Vec<PageData>,
+}
+
+/// Returns `true` if the document catalog has a `/Collection` dictionary,
+/// which marks the file as a PDF portfolio
+fn is_portfolio(pdf_bytes: &[u8]) -> Result<bool, PdfError> {
+    let document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let catalog = document.catalog().map_err(|e| PdfError::Load(e.to_string()))?;
+
+    Ok(catalog.has(b"Collection"))
+}
+
+/// Renders every PDF embedded in a portfolio as its own document
+///
+/// The cover document is not rendered, and attachments that are not PDFs are
+/// skipped. Returns an error if the file is not a portfolio.
+pub fn render_portfolio(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    options: &RenderOptions
+) -> Result<Vec<PortfolioEntry>, PdfError> {
+    options.validate()?;
+    if !is_portfolio(pdf_bytes)? {
+        return Err(PdfError::InvalidArgument("PDF is not a portfolio (no /Collection dictionary)".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+
+    let mut entries = Vec::new();
+    for attachment in document.attachments().iter() {
+        let embedded = attachment.save_to_bytes()?;
+        if !embedded.starts_with(b"%PDF") {
+            continue;
+        }
+
+        entries.push(PortfolioEntry {
+            filename: attachment.name(),
+            pages: render_pdf(pdfium, &embedded, options)?,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// A file attached to a PDF through its `/EmbeddedFiles` name tree
+#[derive(Debug, Clone)]
+pub struct EmbeddedFile {
+    pub filename: String,
+    /// The `/Subtype` of the embedded file stream, e.g. "text/csv"
+    pub mime_type: Option<String>,
+    pub data: Vec<u8>,
+    /// The raw `/CreationDate` from the file's parameters, e.g. "D:20240102030405Z"
+    pub creation_date: Option<String>,
+}
+
+/// The filename of every file specification in the `/EmbeddedFiles` name
+/// tree, with the id of its embedded file stream
+///
+/// pdfium does not expose the stream dictionaries, so the name tree is walked
+/// with lopdf.
+fn embedded_file_streams(document: &lopdf::Document) -> Option<Vec<(String, lopdf::ObjectId)>> {
+    let names = document.dereference(document.catalog().ok()?.get(b"Names").ok()?).ok()?.1;
+    let root = names.as_dict().ok()?.get(b"EmbeddedFiles").ok()?;
+
+    let mut streams = Vec::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut pending = vec![root];
+
+    while let Some(node) = pending.pop() {
+        let (id, node) = document.dereference(node).ok()?;
+        if id.is_some_and(|id| !visited.insert(id)) {
+            continue;
+        }
+        let Ok(node) = node.as_dict() else {
+            continue;
+        };
+
+        if let Ok(kids) = node.get(b"Kids").and_then(lopdf::Object::as_array) {
+            pending.extend(kids.iter().rev());
+        }
+
+        let Ok(entries) = node.get(b"Names").and_then(lopdf::Object::as_array) else {
+            continue;
+        };
+        for file_spec in entries.iter().skip(1).step_by(2) {
+            let Ok((_, lopdf::Object::Dictionary(file_spec))) = document.dereference(file_spec) else {
+                continue;
+            };
+
+            let filename = file_spec
+                .get(b"UF")
+                .or_else(|_| file_spec.get(b"F"))
+                .and_then(lopdf::decode_text_string);
+            let stream_id = file_spec
+                .get(b"EF")
+                .and_then(|ef| document.dereference(ef))
+                .and_then(|(_, ef)| ef.as_dict())
+                .and_then(|ef| ef.get(b"F"))
+                .and_then(lopdf::Object::as_reference);
+
+            if let (Ok(filename), Ok(stream_id)) = (filename, stream_id) {
+                streams.push((filename, stream_id));
+            }
+        }
+    }
+
+    Some(streams)
+}
+
+/// Maps the filename of every embedded file to the `/Subtype` of its stream
+fn embedded_file_mime_types(pdf_bytes: &[u8]) -> Option<std::collections::HashMap<String, String>> {
+    let document = lopdf::Document::load_mem(pdf_bytes).ok()?;
+
+    let mime_types = embedded_file_streams(&document)?
+        .into_iter()
+        .filter_map(|(filename, stream_id)| {
+            let stream = document.get_object(stream_id).and_then(lopdf::Object::as_stream).ok()?;
+            let subtype = stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok()?;
+            Some((filename, String::from_utf8_lossy(subtype).into_owned()))
+        })
+        .collect();
+
+    Some(mime_types)
+}
+
+/// Sets the `/Subtype` of the embedded file stream attached as `filename`
+fn set_embedded_file_mime_type(pdf_bytes: &[u8], filename: &str, mime_type: &str) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let stream_id = embedded_file_streams(&document)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == filename)
+        .map(|(_, stream_id)| stream_id)
+        .ok_or_else(|| PdfError::InvalidArgument(format!("No embedded file named {}", filename)))?;
+
+    let stream = document
+        .get_object_mut(stream_id)
+        .and_then(lopdf::Object::as_stream_mut)
+        .map_err(|e| PdfError::Load(e.to_string()))?;
+    stream.dict.set("Subtype", lopdf::Object::Name(mime_type.as_bytes().to_vec()));
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Embeds `file_data` in the PDF's `/EmbeddedFiles` name tree as `filename`,
+/// recording `mime_type` as the stream's `/Subtype` when given
+pub fn attach_file(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    filename: &str,
+    file_data: &[u8],
+    mime_type: Option<&str>,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if filename.is_empty() {
+        return Err(PdfError::InvalidArgument("Filename must not be empty".to_string()));
+    }
+
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    // pdfium refuses duplicate names without saying why
+    if document.attachments().iter().any(|attachment| attachment.name() == filename) {
+        return Err(PdfError::InvalidArgument(format!("An attachment named {} already exists", filename)));
+    }
+    document.attachments_mut().create_attachment_from_bytes(filename, file_data)?;
+    let output = document.save_to_bytes()?;
+
+    match mime_type {
+        Some(mime_type) => set_embedded_file_mime_type(&output, filename, mime_type),
+        None => Ok(output),
+    }
+}
+
+/// Removes every attached file: the entries of the `/EmbeddedFiles` name tree
+/// and the `/AF` associated files of the catalog and pages. Objects left
+/// unreferenced are dropped so the file data doesn't stay in the output.
+pub fn strip_embedded_files(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    for index in (0..document.attachments().len()).rev() {
+        document.attachments_mut().delete_at_index(index)?;
+    }
+    let output = document.save_to_bytes()?;
+
+    let mut document = lopdf::Document::load_mem(&output).map_err(|e| PdfError::Load(e.to_string()))?;
+    let catalog_id = document.trailer.get(b"Root").and_then(lopdf::Object::as_reference).ok();
+    for id in catalog_id.into_iter().chain(document.get_pages().into_values()) {
+        if let Ok(dict) = document.get_dictionary_mut(id) {
+            dict.remove(b"AF");
+        }
+    }
+    document.prune_objects();
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Action types that run code or open other files or applications
+const ACTIVE_ACTION_TYPES: [&[u8]; 3] = [b"JavaScript", b"Launch", b"ImportData"];
+
+/// Whether `action`, or any action chained after it through `/Next`, is one
+/// of `ACTIVE_ACTION_TYPES`
+fn is_active_action(document: &lopdf::Document, action: &lopdf::Object) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![action];
+    while let Some(object) = pending.pop() {
+        let object = match object {
+            lopdf::Object::Reference(id) if visited.insert(*id) => match document.get_object(*id) {
+                Ok(object) => object,
+                Err(_) => continue,
+            },
+            lopdf::Object::Reference(_) => continue,
+            object => object,
+        };
+        match object {
+            // `/Next` may hold a single action or an array of them
+            lopdf::Object::Array(actions) => pending.extend(actions),
+            lopdf::Object::Dictionary(action) => {
+                if action.get(b"S").and_then(lopdf::Object::as_name).is_ok_and(|kind| ACTIVE_ACTION_TYPES.contains(&kind)) {
+                    return true;
+                }
+                pending.extend(action.get(b"Next"));
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Removes what makes a flattened PDF interactive: the AcroForm, document
+/// JavaScript, any remaining annotations, additional actions (`/AA`) and
+/// action chains that run code at any step
+fn strip_interactive_objects(pdf_bytes: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let action_keys: Vec<_> = document
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let dict = match object {
+                lopdf::Object::Dictionary(dict) => dict,
+                lopdf::Object::Stream(stream) => &stream.dict,
+                _ => return None,
+            };
+            let keys: Vec<&[u8]> = [b"A".as_slice(), b"OpenAction"]
+                .into_iter()
+                .filter(|&key| dict.get(key).is_ok_and(|action| is_active_action(&document, action)))
+                .collect();
+            Some((id, keys))
+        })
+        .collect();
+    for (id, keys) in action_keys {
+        let dict = match document.get_object_mut(id) {
+            Ok(lopdf::Object::Dictionary(dict)) => dict,
+            Ok(lopdf::Object::Stream(stream)) => &mut stream.dict,
+            _ => continue,
+        };
+        dict.remove(b"AA");
+        for key in keys {
+            dict.remove(key);
+        }
+    }
+
+    for page_id in document.get_pages().into_values() {
+        if let Ok(page) = document.get_dictionary_mut(page_id) {
+            page.remove(b"Annots");
+        }
+    }
+    if let Ok(catalog) = document.catalog_mut() {
+        catalog.remove(b"AcroForm");
+        catalog.remove(b"AA");
+    }
+    let names = document.catalog().and_then(|catalog| catalog.get(b"Names")).cloned();
+    match names {
+        Ok(lopdf::Object::Reference(names_id)) => {
+            if let Ok(names) = document.get_dictionary_mut(names_id) {
+                names.remove(b"JavaScript");
+            }
+        }
+        Ok(lopdf::Object::Dictionary(_)) => {
+            if let Ok(lopdf::Object::Dictionary(names)) = document.catalog_mut().and_then(|catalog| catalog.get_mut(b"Names")) {
+                names.remove(b"JavaScript");
+            }
+        }
+        _ => {}
+    }
+    document.prune_objects();
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Flattens a PDF into static content, e.g. before printing or archiving
+///
+/// Form fields and annotations are drawn into the page contents, then the
+/// interactive form, JavaScript, remaining annotations (including 3D and
+/// other rich media) and any actions that run code are removed.
+pub fn flatten_pdf(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    for mut page in document.pages().iter() {
+        page.flatten()?;
+    }
+    let output = document.save_to_bytes()?;
+
+    strip_interactive_objects(&output)
+}
+
+/// Extracts every attached file with its raw bytes and metadata
+pub fn extract_embedded_files(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<EmbeddedFile>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let bindings = pdfium.bindings();
+    let document_handle = bindings.get_handle_from_document(&document);
+    let mime_types = embedded_file_mime_types(pdf_bytes).unwrap_or_default();
+
+    document
+        .attachments()
+        .iter()
+        .enumerate()
+        .map(|(index, attachment)| {
+            let handle = bindings.FPDFDoc_GetAttachment(document_handle, index as c_int);
+            let creation_date = read_pdfium_string(bindings, |buffer, length| {
+                bindings.FPDFAttachment_GetStringValue(handle, "CreationDate", buffer as *mut _, length)
+            });
+            let filename = attachment.name();
+
+            Ok(EmbeddedFile {
+                mime_type: mime_types.get(&filename).cloned(),
+                data: attachment.save_to_bytes()?,
+                filename,
+                creation_date,
+            })
+        })
+        .collect()
+}
+
+/// Directories pdfium scans for fonts on Linux. Supplying user font paths
+/// replaces these defaults, so they are passed along with any extra ones.
+const SYSTEM_FONT_DIRS: &[&str] = &[
+    "/usr/share/fonts",
+    "/usr/share/X11/fonts/Type1",
+    "/usr/share/X11/fonts/TTF",
+    "/usr/local/share/fonts",
+];
+
+/// Version 2 layout of pdfium's `FPDF_LIBRARY_CONFIG`, which pdfium-render
+/// does not export
+#[repr(C)]
+struct LibraryConfig {
+    version: c_int,
+    user_font_paths: *const *const c_char,
+    isolate: *mut c_void,
+    v8_embedder_slot: c_uint,
+    platform: *mut c_void,
+}
+
+/// A null-terminated list of font directories for `LibraryConfig`, kept for
+/// the life of the process because pdfium reads it lazily
+struct FontPaths {
+    _dirs: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+// SAFETY: the pointers only point into `_dirs`, which is never mutated
+unsafe impl Send for FontPaths {}
+unsafe impl Sync for FontPaths {}
+
+static FONT_PATHS: OnceLock<FontPaths> = OnceLock::new();
+
+/// Creates a `Pdfium` whose font substitution also searches `font_dirs`,
+/// so PDFs referencing fonts that are neither embedded nor installed still
+/// render legibly on minimal servers
+///
+/// The library is initialized with the font paths before `Pdfium::new`, whose
+/// own initialization is then a no-op, so pdfium is never restarted under
+/// other threads. Font paths only apply when this is the first
+/// initialization in the process, and the first call's directories are kept
+/// for every later one.
+pub fn init_pdfium(bindings: Box<dyn PdfiumLibraryBindings>, font_dirs: &[PathBuf]) -> Pdfium {
+    if font_dirs.is_empty() {
+        return Pdfium::new(bindings);
+    }
+
+    let paths = FONT_PATHS.get_or_init(|| {
+        let dirs: Vec<CString> = SYSTEM_FONT_DIRS
+            .iter()
+            .map(Path::new)
+            .chain(font_dirs.iter().map(PathBuf::as_path))
+            .filter_map(|dir| CString::new(dir.as_os_str().as_encoded_bytes()).ok())
+            .collect();
+        let pointers = dirs.iter().map(|dir| dir.as_ptr()).chain(std::iter::once(std::ptr::null())).collect();
+        FontPaths { _dirs: dirs, pointers }
+    });
+    let config = LibraryConfig {
+        version: 2,
+        user_font_paths: paths.pointers.as_ptr(),
+        isolate: std::ptr::null_mut(),
+        v8_embedder_slot: 0,
+        platform: std::ptr::null_mut(),
+    };
+    bindings.FPDF_InitLibraryWithConfig(&config as *const LibraryConfig as *const _);
+
+    Pdfium::new(bindings)
+}
+
+#[cfg(feature = "bundled-fonts")]
+const BUNDLED_FONTS: &[u8] = include_bytes!("../fonts/bundled-fonts.zip");
+
+/// Unpacks the bundled fonts into a per-version directory under the system
+/// temp directory, reusing it if a previous process already did
+#[cfg(feature = "bundled-fonts")]
+pub fn bundled_font_dir() -> Result<PathBuf, PdfError> {
+    let dir = std::env::temp_dir().join(format!("ztron_pdf-fonts-{}", env!("CARGO_PKG_VERSION")));
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+
+    // Extract next to the target and rename, so concurrent processes never
+    // see a half-written directory
+    let staging = dir.with_extension(format!("partial-{}", std::process::id()));
+    zip::ZipArchive::new(Cursor::new(BUNDLED_FONTS))
+        .and_then(|mut archive| archive.extract(&staging))
+        .map_err(|e| PdfError::Io(std::io::Error::other(e)))?;
+    if std::fs::rename(&staging, &dir).is_err() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+
+    Ok(dir)
+}
+
+/// Texts shorter than this many non-whitespace characters are too short to classify
+const MIN_LANGUAGE_CHARS: usize = 100;
+
+/// Classifies `text` and returns its ISO 639-1 code, or "unknown" when there
+/// is too little text to judge
+fn detect_text_language(text: &str) -> &'static str {
+    if text.chars().filter(|c| !c.is_whitespace()).count() < MIN_LANGUAGE_CHARS {
+        return "unknown";
+    }
+
+    match whichlang::detect_language(text) {
+        whichlang::Lang::Ara => "ar",
+        whichlang::Lang::Cmn => "zh",
+        whichlang::Lang::Deu => "de",
+        whichlang::Lang::Eng => "en",
+        whichlang::Lang::Fra => "fr",
+        whichlang::Lang::Hin => "hi",
+        whichlang::Lang::Ita => "it",
+        whichlang::Lang::Jpn => "ja",
+        whichlang::Lang::Kor => "ko",
+        whichlang::Lang::Nld => "nl",
+        whichlang::Lang::Por => "pt",
+        whichlang::Lang::Rus => "ru",
+        whichlang::Lang::Spa => "es",
+        whichlang::Lang::Swe => "sv",
+        whichlang::Lang::Tur => "tr",
+        whichlang::Lang::Vie => "vi",
+    }
+}
+
+/// Detects the dominant language of a document's text layer
+///
+/// Returns an ISO 639-1 code such as "en", or "unknown" for documents with
+/// fewer than 100 characters of text (including scans without a text layer).
+pub fn detect_language(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<String, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut text = String::new();
+    for page in document.pages().iter() {
+        text.push_str(&page.text()?.all());
+        text.push('\n');
+    }
+
+    Ok(detect_text_language(&text).to_string())
+}
+
+/// Where on the page a QR watermark is placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QrPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl FromStr for QrPosition {
+    type Err = PdfError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "top_left" => Ok(QrPosition::TopLeft),
+            "top_right" => Ok(QrPosition::TopRight),
+            "bottom_left" => Ok(QrPosition::BottomLeft),
+            "bottom_right" => Ok(QrPosition::BottomRight),
+            "center" => Ok(QrPosition::Center),
+            _ => Err(PdfError::InvalidArgument(format!(
+                "Position must be top_left, top_right, bottom_left, bottom_right, or center, got {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Side length of a QR watermark on the page, in points (one inch)
+const QR_WATERMARK_SIZE: f32 = 72.0;
+/// Distance between a corner QR watermark and the page edges, in points
+const QR_WATERMARK_MARGIN: f32 = 18.0;
+/// Pixels per QR module in the generated bitmap
+const QR_MODULE_PIXELS: u32 = 8;
+/// Light modules around the symbol, as required by the QR specification
+const QR_QUIET_ZONE: u32 = 4;
+
+/// Draws `data` as a QR code bitmap whose every pixel has the given opacity
+fn qr_code_image(data: &str, opacity: f32) -> Result<RgbaImage, PdfError> {
+    let code = qrcode::QrCode::new(data.as_bytes())
+        .map_err(|e| PdfError::InvalidArgument(format!("Cannot encode QR data: {}", e)))?;
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let alpha = (opacity * 255.0).round() as u8;
+    let side = (modules + 2 * QR_QUIET_ZONE) * QR_MODULE_PIXELS;
+
+    Ok(RgbaImage::from_fn(side, side, |x, y| {
+        let mx = (x / QR_MODULE_PIXELS).checked_sub(QR_QUIET_ZONE).filter(|&m| m < modules);
+        let my = (y / QR_MODULE_PIXELS).checked_sub(QR_QUIET_ZONE).filter(|&m| m < modules);
+        let dark = match (mx, my) {
+            (Some(mx), Some(my)) => colors[(my * modules + mx) as usize] == qrcode::Color::Dark,
+            _ => false,
+        };
+        let value = if dark { 0 } else { 255 };
+        Rgba([value, value, value, alpha])
+    }))
+}
+
+/// Stamps a QR code encoding `data` onto every page
+///
+/// The code is one inch square and placed at `position`, inset from the page
+/// edges for the corner positions. `opacity` (0.0 to 1.0) is baked into the
+/// image's alpha channel, so low values give a faint tracking mark that
+/// still scans. The stamped document is returned as new PDF bytes.
+pub fn add_qr_watermark(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    data: &str,
+    position: QrPosition,
+    opacity: f32,
+    max_pages: Option<usize>,
+) -> Result<Vec<u8>, PdfError> {
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err(PdfError::InvalidArgument("Opacity must be between 0.0 and 1.0".to_string()));
+    }
+    let qr = DynamicImage::ImageRgba8(qr_code_image(data, opacity)?);
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    for mut page in document.pages().iter() {
+        let width = page.width().value;
+        let height = page.height().value;
+        let (x, y) = match position {
+            QrPosition::TopLeft => (QR_WATERMARK_MARGIN, height - QR_WATERMARK_MARGIN - QR_WATERMARK_SIZE),
+            QrPosition::TopRight => (
+                width - QR_WATERMARK_MARGIN - QR_WATERMARK_SIZE,
+                height - QR_WATERMARK_MARGIN - QR_WATERMARK_SIZE,
+            ),
+            QrPosition::BottomLeft => (QR_WATERMARK_MARGIN, QR_WATERMARK_MARGIN),
+            QrPosition::BottomRight => (width - QR_WATERMARK_MARGIN - QR_WATERMARK_SIZE, QR_WATERMARK_MARGIN),
+            QrPosition::Center => ((width - QR_WATERMARK_SIZE) / 2.0, (height - QR_WATERMARK_SIZE) / 2.0),
+        };
+
+        let mut object = PdfPageImageObject::new_with_width(&document, &qr, PdfPoints::new(QR_WATERMARK_SIZE))?;
+        object.translate(PdfPoints::new(x), PdfPoints::new(y))?;
+        page.objects_mut().add_image_object(object)?;
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Semi-transparent yellow, the default colour for search result highlights
+pub const HIGHLIGHT_YELLOW: [u8; 4] = [255, 235, 0, 96];
+
+/// A rectangle to highlight on a rendered page, in PDF points with the origin
+/// at the bottom-left of the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HighlightRect {
+    pub x_pt: f32,
+    pub y_pt: f32,
+    pub w_pt: f32,
+    pub h_pt: f32,
+    /// RGBA; the alpha channel controls how strongly the page shows through
+    pub color: [u8; 4],
+}
+
+/// Alpha-blends `color` over the pixels of `image` within the given bounds
+fn blend_rect(image: &mut RgbImage, (x0, y0): (i32, i32), (x1, y1): (i32, i32), color: [u8; 4]) {
+    let alpha = color[3] as u32;
+    let clamp_x = |x: i32| x.clamp(0, image.width() as i32) as u32;
+    let clamp_y = |y: i32| y.clamp(0, image.height() as i32) as u32;
+    let (left, right) = (clamp_x(x0.min(x1)), clamp_x(x0.max(x1)));
+    let (top, bottom) = (clamp_y(y0.min(y1)), clamp_y(y0.max(y1)));
+
+    for y in top..bottom {
+        for x in left..right {
+            let pixel = image.get_pixel_mut(x, y);
+            for c in 0..3 {
+                pixel[c] = ((color[c] as u32 * alpha + pixel[c] as u32 * (255 - alpha)) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Renders a single page with each of `highlights` drawn over it, e.g. to
+/// show where search results were found
+///
+/// Rectangles are mapped through the same transform used for rendering, so
+/// they line up with the page content at any DPI and on rotated pages.
+pub fn render_page_with_highlights(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    highlights: &[HighlightRect],
+    options: &RenderOptions
+) -> Result<PageData, PdfError> {
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let config = render_config(options);
+
+    let mut image = render_page_rgb(&page, options)?;
+    for highlight in highlights {
+        let from = page.points_to_pixels(
+            PdfPoints::new(highlight.x_pt),
+            PdfPoints::new(highlight.y_pt),
+            &config,
+        )?;
+        let to = page.points_to_pixels(
+            PdfPoints::new(highlight.x_pt + highlight.w_pt),
+            PdfPoints::new(highlight.y_pt + highlight.h_pt),
+            &config,
+        )?;
+        blend_rect(&mut image, from, to, highlight.color);
+    }
+
+    Ok(PageData {
+        image_buffer: encode_page(&image, options)?,
+        error: None,
+    })
+}
+
+/// Searches a page for `query` and renders it with every match highlighted
+/// in `HIGHLIGHT_YELLOW`
+///
+/// Matching is case-insensitive. A match that wraps across lines gets one
+/// highlight per line; an empty query highlights nothing.
+pub fn render_page_with_search_results(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    query: &str,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    let mut highlights = Vec::new();
+    if !query.is_empty() {
+        let document = load_document(pdfium, pdf_bytes)?;
+        check_page_limit(&document, max_pages)?;
+        let page = page_at(&document, page_index)?;
+        let text = page.text()?;
+        let search = text.search(query, &PdfSearchOptions::new());
+        for segments in search.iter(PdfSearchDirection::SearchForward) {
+            for segment in segments.iter() {
+                let bounds = segment.bounds();
+                highlights.push(HighlightRect {
+                    x_pt: bounds.left().value,
+                    y_pt: bounds.bottom().value,
+                    w_pt: bounds.width().value,
+                    h_pt: bounds.height().value,
+                    color: HIGHLIGHT_YELLOW,
+                });
+            }
+        }
+    }
+
+    let options = RenderOptions::default().with_dpi(dpi).with_quality(quality);
+    render_page_with_highlights(pdfium, pdf_bytes, page_index, &highlights, &options)
+}
+
+/// Standard deviation of the Gaussian applied before the Laplacian, in pixels
+const NOISE_BLUR_SIGMA: f32 = 1.0;
+
+/// Variance of the Laplacian of Gaussian response over `image`
+fn laplacian_of_gaussian_variance(image: &GrayImage) -> f32 {
+    let response = laplacian_filter(&gaussian_blur_f32(image, NOISE_BLUR_SIGMA));
+    let count = response.len() as f64;
+    if count == 0.0 {
+        return 0.0;
+    }
+
+    let (sum, sum_sq) = response
+        .iter()
+        .fold((0.0f64, 0.0f64), |(sum, sum_sq), &v| (sum + v as f64, sum_sq + (v as f64) * (v as f64)));
+    let mean = sum / count;
+
+    (sum_sq / count - mean * mean) as f32
+}
+
+/// Estimates how noisy a page is, to help decide whether it needs denoising
+/// before OCR
+///
+/// The page is rendered at 150 DPI in grayscale and the variance of its
+/// Laplacian of Gaussian response is returned. Higher values mean more noise;
+/// a blank page scores 0.
+pub fn estimate_page_noise(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    max_pages: Option<usize>
+) -> Result<f32, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let options = RenderOptions::default().with_dpi(150).with_grayscale(true);
+    let image = DynamicImage::ImageRgb8(render_page_rgb(&page, &options)?).into_luma8();
+
+    Ok(laplacian_of_gaussian_variance(&image))
+}
+
+/// Counts the pages of each document in a batch, returning one result per input
+///
+/// Only the cross-reference table, trailer and page tree are read, with
+/// lopdf rather than pdfium, so this is much cheaper than loading each
+/// document for rendering and the inputs really are processed in parallel
+/// (pdfium-render's `sync` feature would serialize them on its global lock).
+pub fn batch_get_page_counts(pdf_slices: &[&[u8]]) -> Vec<Result<usize, PdfError>> {
+    pdf_slices
+        .par_iter()
+        .map(|pdf_bytes| {
+            let metadata = lopdf::Document::load_metadata_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+            if metadata.encrypted && metadata.page_count == 0 {
+                return Err(PdfError::Load("PDF is password protected".to_string()));
+            }
+            Ok(metadata.page_count as usize)
+        })
+        .collect()
+}
+
+/// Rows and columns of the n-up grid, putting more tiles along the longer side
+/// of the sheet so that portrait source pages keep a sensible size
+fn nup_grid(n: usize, target_page_size: [f32; 2]) -> Result<(u8, u8), PdfError> {
+    let (short, long) = match n {
+        2 => (1, 2),
+        4 => (2, 2),
+        6 => (2, 3),
+        8 => (2, 4),
+        _ => return Err(PdfError::InvalidArgument(format!("Pages per sheet must be 2, 4, 6, or 8, got {}", n))),
+    };
+
+    let [width, height] = target_page_size;
+    if width > height {
+        Ok((short, long))
+    } else {
+        Ok((long, short))
+    }
+}
+
+/// Tiles `n` source pages onto each output page of `target_page_size` points
+/// ([width, height]), for 2-up to 8-up printing
+///
+/// Pages are scaled to fit their cell and laid out left to right, top to
+/// bottom. When the page count isn't a multiple of `n`, the remaining cells of
+/// the last sheet are left blank.
+pub fn impose_nup(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    n: usize,
+    target_page_size: [f32; 2],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let (rows, columns) = nup_grid(n, target_page_size)?;
+    let [width, height] = target_page_size;
+    if width <= 0.0 || height <= 0.0 {
+        return Err(PdfError::InvalidArgument("Page width and height must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let imposed = document.pages().tile_into_new_document(
+        rows,
+        columns,
+        PdfPagePaperSize::new_custom(PdfPoints::new(width), PdfPoints::new(height)),
+    )?;
+
+    Ok(imposed.save_to_bytes()?)
+}
+
+/// Appends the pages of `source_pdf` at `source_page_indices`, in the order
+/// given, to the end of `destination_pdf`
+///
+/// Indices may repeat to copy a page more than once. The combined document is
+/// returned as new PDF bytes.
+pub fn copy_pages(
+    pdfium: &Pdfium,
+    source_pdf: &[u8],
+    destination_pdf: &[u8],
+    source_page_indices: &[usize],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let source = load_document(pdfium, source_pdf)?;
+    check_page_limit(&source, max_pages)?;
+    let mut destination = load_document(pdfium, destination_pdf)?;
+    check_page_limit(&destination, max_pages)?;
+
+    let page_count = source.pages().len();
+    if let Some(&page_index) = source_page_indices.iter().find(|&&i| i >= page_count as usize) {
+        return Err(PdfError::InvalidArgument(format!(
+            "Page index {} is out of range for a document with {} pages", page_index, page_count
+        )));
+    }
+
+    for &page_index in source_page_indices {
+        let end = destination.pages().len();
+        destination
+            .pages_mut()
+            .copy_page_from_document(&source, page_index as PdfPageIndex, end)?;
+    }
+
+    Ok(destination.save_to_bytes()?)
+}
+
+/// Uncompressed pixels of a rendered page, row-major with `channels` bytes
+/// per pixel (3 for RGB, 4 for RGBA)
+#[derive(Debug, Clone)]
+pub struct RawPageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub data: Vec<u8>,
+}
+
+/// Renders a single page to an uncompressed RGB or RGBA pixel buffer
+///
+/// This skips encoding entirely, for callers that upload or encode the pixels
+/// themselves. `options.format` and `options.quality` are ignored.
+pub fn render_page_to_raw(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    channels: u8,
+    options: &RenderOptions
+) -> Result<RawPageBuffer, PdfError> {
+    if channels != 3 && channels != 4 {
+        return Err(PdfError::InvalidArgument(format!("Channels must be 3 (RGB) or 4 (RGBA), got {}", channels)));
+    }
+    options.validate()?;
+    check_size_limit(pdf_bytes, options.max_pdf_bytes)?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, options.max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let image = render_page_rgb(&page, options)?;
+    let (width, height) = image.dimensions();
+
+    let data = if channels == 4 {
+        DynamicImage::ImageRgb8(image).into_rgba8().into_raw()
+    } else {
+        image.into_raw()
+    };
+
+    Ok(RawPageBuffer { width, height, channels, data })
+}
+
+/// Resizes a page to `width_pt` × `height_pt`
+///
+/// The page's MediaBox and CropBox are both set to the new size. With
+/// `scale_content`, the page content is scaled uniformly to fit the new size
+/// and centred on it; otherwise it keeps its position and may be clipped or
+/// surrounded by blank space. Returns the modified document as new PDF bytes.
+pub fn set_page_size(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    width_pt: f32,
+    height_pt: f32,
+    scale_content: bool,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if width_pt <= 0.0 || height_pt <= 0.0 {
+        return Err(PdfError::InvalidArgument("Page width and height must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let mut page = page_at(&document, page_index)?;
+    let media = page.boundaries().media()?.bounds;
+
+    let bounds = if scale_content {
+        let (old_width, old_height) = (media.width().value, media.height().value);
+        let scale = (width_pt / old_width).min(height_pt / old_height);
+        page.transform(
+            scale,
+            0.0,
+            0.0,
+            scale,
+            (width_pt - old_width * scale) / 2.0 - media.left().value * scale,
+            (height_pt - old_height * scale) / 2.0 - media.bottom().value * scale,
+        )?;
+        PdfRect::new_from_values(0.0, 0.0, height_pt, width_pt)
+    } else {
+        let (left, bottom) = (media.left().value, media.bottom().value);
+        PdfRect::new_from_values(bottom, left, bottom + height_pt, left + width_pt)
+    };
+
+    page.boundaries_mut().set_media(bounds)?;
+    page.boundaries_mut().set_crop(bounds)?;
+    // Dropping the page regenerates its content stream before the save
+    drop(page);
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Reads the XMP packet referenced by the catalog's `/Metadata` entry
+fn read_xmp_metadata(pdf_bytes: &[u8]) -> Result<Option<String>, PdfError> {
+    let document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let catalog = document.catalog().map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let Ok(stream) = catalog
+        .get_deref(b"Metadata", &document)
+        .and_then(lopdf::Object::as_stream)
+    else {
+        return Ok(None);
+    };
+    let xml = stream.get_plain_content().map_err(|e| PdfError::Load(format!("XMP metadata: {}", e)))?;
+
+    Ok(Some(String::from_utf8_lossy(&xml).into_owned()))
+}
+
+/// Returns the document's XMP metadata as XML, or `None` if the catalog has
+/// no `/Metadata` stream
+///
+/// Page- and object-level XMP streams are not included.
+pub fn get_xmp_metadata(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Option<String>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+
+    read_xmp_metadata(&saved)
+}
+
+/// Points the catalog's `/Metadata` entry at an XMP stream holding `xmp_xml`,
+/// replacing the existing stream in place if there is one
+fn write_xmp_metadata(pdf_bytes: &[u8], xmp_xml: &str) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let mut dict = lopdf::Dictionary::new();
+    dict.set("Type", lopdf::Object::Name(b"Metadata".to_vec()));
+    dict.set("Subtype", lopdf::Object::Name(b"XML".to_vec()));
+    // XMP is conventionally left uncompressed so tools that scan files for
+    // packets without parsing the PDF can still find it
+    let stream = lopdf::Stream::new(dict, xmp_xml.as_bytes().to_vec()).with_compression(false);
+
+    let existing = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Metadata"))
+        .and_then(lopdf::Object::as_reference)
+        .ok();
+    match existing {
+        Some(id) => {
+            document.objects.insert(id, stream.into());
+        }
+        None => {
+            let id = document.add_object(stream);
+            document
+                .catalog_mut()
+                .map_err(|e| PdfError::Load(e.to_string()))?
+                .set("Metadata", id);
+        }
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Writes `xmp_xml` as the document's XMP metadata, replacing any existing
+/// packet, and returns the modified document as new PDF bytes
+///
+/// The XML is stored as given; it is not validated or merged with the
+/// document information dictionary.
+pub fn set_xmp_metadata(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    xmp_xml: &str,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+
+    write_xmp_metadata(&saved, xmp_xml)
+}
+
+/// The document information dictionary fields compared by `compare_metadata`,
+/// with their keys in the dictionary
+const INFO_FIELDS: [(&str, PdfDocumentMetadataTagType); 8] = [
+    ("Title", PdfDocumentMetadataTagType::Title),
+    ("Author", PdfDocumentMetadataTagType::Author),
+    ("Subject", PdfDocumentMetadataTagType::Subject),
+    ("Keywords", PdfDocumentMetadataTagType::Keywords),
+    ("Creator", PdfDocumentMetadataTagType::Creator),
+    ("Producer", PdfDocumentMetadataTagType::Producer),
+    ("CreationDate", PdfDocumentMetadataTagType::CreationDate),
+    ("ModDate", PdfDocumentMetadataTagType::ModificationDate),
+];
+
+/// Reads the fields of a document's information dictionary, in `INFO_FIELDS`
+/// order; missing and empty fields are `None`
+fn document_info(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<Option<String>>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let metadata = document.metadata();
+
+    Ok(INFO_FIELDS
+        .iter()
+        .map(|(_, tag)| {
+            metadata
+                .get(*tag)
+                .map(|tag| tag.value().to_string())
+                .filter(|value| !value.is_empty())
+        })
+        .collect())
+}
+
+/// A document information field whose value differs between two PDFs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataDiff {
+    /// The field's key in the information dictionary, e.g. "Title" or "ModDate"
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// Lists the document information fields that differ between `pdf_a` and
+/// `pdf_b`, e.g. to show what changed between two versions of a document
+///
+/// `old_value` comes from `pdf_a` and `new_value` from `pdf_b`. Returns an
+/// empty list when every field matches.
+pub fn compare_metadata(
+    pdfium: &Pdfium,
+    pdf_a: &[u8],
+    pdf_b: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<MetadataDiff>, PdfError> {
+    let old = document_info(pdfium, pdf_a, max_pages)?;
+    let new = document_info(pdfium, pdf_b, max_pages)?;
+
+    Ok(INFO_FIELDS
+        .iter()
+        .zip(old.into_iter().zip(new))
+        .filter(|(_, (old_value, new_value))| old_value != new_value)
+        .map(|((field, _), (old_value, new_value))| MetadataDiff {
+            field: field.to_string(),
+            old_value,
+            new_value,
+        })
+        .collect())
+}
+
+/// A font used by the text on a document's pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontInfo {
+    /// The font's `/BaseFont` name, e.g. "Helvetica-Bold" or "ABCDEF+Calibri"
+    pub name: String,
+    pub is_embedded: bool,
+}
+
+/// The /BaseFont name of a text object's font. `PdfFont::family` reports the
+/// family of whatever face pdfium loaded, which for unembedded fonts is a
+/// substitute.
+fn base_font_name(bindings: &dyn PdfiumLibraryBindings, object: &PdfPageObject) -> String {
+    let font = bindings.FPDFTextObj_GetFont(bindings.get_handle_from_object(object));
+    let length = bindings.FPDFFont_GetBaseFontName(font, std::ptr::null_mut(), 0);
+    let mut buffer = vec![0u8; length];
+    bindings.FPDFFont_GetBaseFontName(font, buffer.as_mut_ptr() as *mut c_char, length);
+
+    String::from_utf8_lossy(&buffer).trim_end_matches('\0').to_string()
+}
+
+/// Lists the distinct fonts used by text objects across all pages, in order of first use
+pub fn get_fonts(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>
+) -> Result<Vec<FontInfo>, PdfError> {
+    check_size_limit(pdf_bytes, max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let bindings = pdfium.bindings();
+
+    let mut fonts: Vec<FontInfo> = Vec::new();
+    for page in document.pages().iter() {
+        for object in page.objects().iter().filter(|object| object.as_text_object().is_some()) {
+            let font = bindings.FPDFTextObj_GetFont(bindings.get_handle_from_object(&object));
+            let info = FontInfo {
+                name: base_font_name(bindings, &object),
+                is_embedded: bindings.FPDFFont_GetIsEmbedded(font) == 1,
+            };
+            if !fonts.contains(&info) {
+                fonts.push(info);
+            }
+        }
+    }
+
+    Ok(fonts)
+}
+
+/// Lower-cased alphanumerics of a font name without any subset prefix, so
+/// "ABCDEF+Arial,Bold" and the file stem "Arial-Bold" both become "arialbold"
+fn font_lookup_key(name: &str) -> String {
+    let name = match name.split_once('+') {
+        Some((prefix, rest)) if prefix.len() == 6 => rest,
+        _ => name,
+    };
+
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Maps the lookup key of every .ttf and .otf file directly in `font_dir` to its path
+fn index_font_files(font_dir: &Path) -> Result<Vec<(String, PathBuf)>, PdfError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(font_dir)? {
+        let path = entry?.path();
+        let is_font = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf"));
+        if let (true, Some(stem)) = (is_font, path.file_stem().and_then(|stem| stem.to_str())) {
+            files.push((font_lookup_key(stem), path));
+        }
+    }
+
+    Ok(files)
+}
+
+/// The character WinAnsiEncoding (Windows-1252) assigns to `code`
+fn win_ansi_char(code: u8) -> Option<char> {
+    const HIGH: [u16; 32] = [
+        0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+        0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+    ];
+
+    match code {
+        0x80..=0x9F => char::from_u32(HIGH[code as usize - 0x80] as u32).filter(|&c| c != '\0'),
+        0x20..=0x7E | 0xA0..=0xFF => Some(code as char),
+        _ => None,
+    }
+}
+
+/// A `/FontDescriptor` for `base_font` with metrics read from `face`, scaled
+/// to PDF glyph space (1000 units per em)
+fn font_descriptor(face: &ttf_parser::Face, base_font: &[u8]) -> lopdf::Dictionary {
+    let scale = 1000.0 / face.units_per_em() as f32;
+    let units = |v: i16| (v as f32 * scale).round() as i64;
+    let bbox = face.global_bounding_box();
+
+    // Nonsymbolic, plus FixedPitch and Italic where they apply
+    let mut flags = 32;
+    if face.is_monospaced() {
+        flags |= 1;
+    }
+    if face.is_italic() {
+        flags |= 64;
+    }
+
+    let mut descriptor = lopdf::Dictionary::new();
+    descriptor.set("Type", lopdf::Object::Name(b"FontDescriptor".to_vec()));
+    descriptor.set("FontName", lopdf::Object::Name(base_font.to_vec()));
+    descriptor.set("Flags", flags);
+    descriptor.set("FontBBox", vec![
+        units(bbox.x_min).into(),
+        units(bbox.y_min).into(),
+        units(bbox.x_max).into(),
+        units(bbox.y_max).into(),
+    ]);
+    descriptor.set("ItalicAngle", face.italic_angle());
+    descriptor.set("Ascent", units(face.ascender()));
+    descriptor.set("Descent", units(face.descender()));
+    descriptor.set("CapHeight", units(face.capital_height().unwrap_or(face.ascender())));
+    descriptor.set("StemV", if face.is_bold() { 120 } else { 80 });
+
+    descriptor
+}
+
+/// Glyph widths for codes 32 to 255 under WinAnsiEncoding
+fn win_ansi_widths(face: &ttf_parser::Face) -> Vec<lopdf::Object> {
+    let scale = 1000.0 / face.units_per_em() as f32;
+
+    (32..=255u8)
+        .map(|code| {
+            let advance = win_ansi_char(code)
+                .and_then(|c| face.glyph_index(c))
+                .and_then(|glyph| face.glyph_hor_advance(glyph))
+                .unwrap_or(0);
+            ((advance as f32 * scale).round() as i64).into()
+        })
+        .collect()
+}
+
+/// Attaches the matching file from `font_files` to every simple font named in
+/// `missing` that has no font program yet
+fn embed_font_files(
+    pdf_bytes: &[u8],
+    missing: &[String],
+    font_files: &[(String, PathBuf)]
+) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let missing: Vec<String> = missing.iter().map(|name| font_lookup_key(name)).collect();
+
+    let mut targets = Vec::new();
+    for (&id, object) in &document.objects {
+        let Ok(font) = object.as_dict() else {
+            continue;
+        };
+        if font.get(b"Type").and_then(lopdf::Object::as_name).ok() != Some(b"Font".as_slice())
+            || !matches!(
+                font.get(b"Subtype").and_then(lopdf::Object::as_name).ok(),
+                Some(b"Type1" | b"MMType1" | b"TrueType")
+            )
+        {
+            continue;
+        }
+        let has_program = font
+            .get_deref(b"FontDescriptor", &document)
+            .and_then(lopdf::Object::as_dict)
+            .is_ok_and(|descriptor| {
+                [b"FontFile".as_slice(), b"FontFile2", b"FontFile3"].iter().any(|key| descriptor.has(key))
+            });
+        let Ok(base_font) = font.get(b"BaseFont").and_then(lopdf::Object::as_name) else {
+            continue;
+        };
+        let key = font_lookup_key(&String::from_utf8_lossy(base_font));
+        if has_program || !missing.contains(&key) {
+            continue;
+        }
+        if let Some((_, path)) = font_files.iter().find(|(file_key, _)| *file_key == key) {
+            targets.push((id, base_font.to_vec(), path.clone()));
+        }
+    }
+
+    let mut embedded: Vec<(PathBuf, lopdf::ObjectId)> = Vec::new();
+    for (font_id, base_font, path) in targets {
+        let data = std::fs::read(&path)?;
+        let Ok(face) = ttf_parser::Face::parse(&data, 0) else {
+            continue;
+        };
+        let descriptor = font_descriptor(&face, &base_font);
+        let widths = win_ansi_widths(&face);
+        // CFF-flavoured OpenType goes in FontFile3; TrueType outlines in FontFile2
+        let is_cff = data.starts_with(b"OTTO");
+
+        let file_id = match embedded.iter().find(|(embedded_path, _)| *embedded_path == path) {
+            Some(&(_, file_id)) => file_id,
+            None => {
+                let mut dict = lopdf::Dictionary::new();
+                if is_cff {
+                    dict.set("Subtype", lopdf::Object::Name(b"OpenType".to_vec()));
+                } else {
+                    dict.set("Length1", data.len() as i64);
+                }
+                let mut stream = lopdf::Stream::new(dict, data);
+                let _ = stream.compress();
+                let file_id = document.add_object(stream);
+                embedded.push((path, file_id));
+                file_id
+            }
+        };
+        let file_key = if is_cff { "FontFile3" } else { "FontFile2" };
+
+        let descriptor_ref = document
+            .get_object(font_id)
+            .and_then(lopdf::Object::as_dict)
+            .and_then(|font| font.get(b"FontDescriptor"))
+            .and_then(lopdf::Object::as_reference)
+            .ok();
+        match descriptor_ref {
+            Some(descriptor_id) => {
+                if let Ok(descriptor) = document.get_object_mut(descriptor_id).and_then(lopdf::Object::as_dict_mut) {
+                    descriptor.set(file_key, file_id);
+                }
+            }
+            None => {
+                let mut descriptor = descriptor;
+                descriptor.set(file_key, file_id);
+                let descriptor_id = document.add_object(descriptor);
+                if let Ok(font) = document.get_object_mut(font_id).and_then(lopdf::Object::as_dict_mut) {
+                    font.set("FontDescriptor", descriptor_id);
+                }
+            }
+        }
+
+        let Ok(font) = document.get_object_mut(font_id).and_then(lopdf::Object::as_dict_mut) else {
+            continue;
+        };
+        // FontFile2 is only valid for TrueType fonts
+        if !is_cff {
+            font.set("Subtype", lopdf::Object::Name(b"TrueType".to_vec()));
+        }
+        if !font.has(b"Widths") {
+            if !font.has(b"Encoding") {
+                font.set("Encoding", lopdf::Object::Name(b"WinAnsiEncoding".to_vec()));
+            }
+            font.set("FirstChar", 32);
+            font.set("LastChar", 255);
+            font.set("Widths", widths);
+        }
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Embeds fonts that the document references by name only, using matching
+/// .ttf or .otf files from `font_dir`
+///
+/// Fonts are matched case-insensitively on their name, ignoring any subset
+/// prefix and punctuation, so "Arial,Bold" matches `arial-bold.ttf`. Fonts
+/// without a matching file, and composite (Type0) fonts, are left as they
+/// are. pdfium can load new fonts but can't attach a program to an existing
+/// font dictionary, so the files are added with lopdf. Returns the modified
+/// document as new PDF bytes.
+pub fn embed_missing_fonts(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    font_dir: &Path,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let missing: Vec<String> = get_fonts(pdfium, pdf_bytes, max_pages, None)?
+        .into_iter()
+        .filter(|font| !font.is_embedded)
+        .map(|font| font.name)
+        .collect();
+    let font_files = index_font_files(font_dir)?;
+    let saved = load_document(pdfium, pdf_bytes)?.save_to_bytes()?;
+
+    embed_font_files(&saved, &missing, &font_files)
+}
+
+/// An image object on a page and how heavily it is already compressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageQualityStat {
+    pub page_index: usize,
+    /// The index of the image among all of the page's objects
+    pub object_index: usize,
+    pub width: u32,
+    pub height: u32,
+    /// The JPEG quality factor (1-100) the image was most likely saved with;
+    /// `None` for images that aren't plain JPEG
+    pub estimated_quality: Option<u8>,
+    /// The image's codec: "JPEG", "JPEG2000", "JBIG2", "CCITT", or "PNG" for
+    /// losslessly stored samples
+    pub format: String,
+}
+
+/// The JPEG Annex K luminance quantization table, which libjpeg-style
+/// encoders scale by the quality factor
+const STANDARD_LUMINANCE_TABLE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Finds quantization table 0 (luminance) in a JPEG's DQT segments
+fn jpeg_luminance_table(jpeg: &[u8]) -> Option<[u16; 64]> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() {
+        if jpeg[pos] != 0xFF {
+            return None;
+        }
+        let marker = jpeg[pos + 1];
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let segment = jpeg.get(pos + 4..pos + 2 + length)?;
+        match marker {
+            // Start of scan: the tables all come before the entropy-coded data
+            0xDA => return None,
+            0xDB => {
+                let mut table = segment;
+                while let Some((&info, rest)) = table.split_first() {
+                    let wide = info >> 4 == 1;
+                    let size = if wide { 128 } else { 64 };
+                    let values = rest.get(..size)?;
+                    if info & 0x0F == 0 {
+                        let mut result = [0u16; 64];
+                        for (i, value) in result.iter_mut().enumerate() {
+                            *value = if wide {
+                                u16::from_be_bytes([values[2 * i], values[2 * i + 1]])
+                            } else {
+                                values[i] as u16
+                            };
+                        }
+                        return Some(result);
+                    }
+                    table = &rest[size..];
+                }
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+
+    None
+}
+
+/// Estimates the quality factor a JPEG was encoded with by inverting the
+/// libjpeg scaling of the standard luminance table
+///
+/// Encoders that use custom tables get the quality whose standard table is
+/// closest in overall coarseness.
+fn estimate_jpeg_quality(jpeg: &[u8]) -> Option<u8> {
+    let table = jpeg_luminance_table(jpeg)?;
+    let sum: u32 = table.iter().map(|&v| v as u32).sum();
+    let standard: u32 = STANDARD_LUMINANCE_TABLE.iter().map(|&v| v as u32).sum();
+
+    // libjpeg scales the table by `scale` percent, where scale = 5000 / q
+    // below quality 50 and 200 - 2q above it
+    let scale = sum as f32 * 100.0 / standard as f32;
+    let quality = if scale <= 100.0 {
+        (200.0 - scale) / 2.0
+    } else {
+        5000.0 / scale
+    };
+
+    Some(quality.round().clamp(1.0, 100.0) as u8)
+}
+
+/// Reports the size, codec and estimated JPEG quality of every image object,
+/// to help decide whether a document is worth recompressing
+pub fn image_quality_stats(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<ImageQualityStat>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let bindings = pdfium.bindings();
+
+    let mut stats = Vec::new();
+    for (page_index, page) in document.pages().iter().enumerate() {
+        for (object_index, object) in page.objects().iter().enumerate() {
+            let Some(image) = object.as_image_object() else {
+                continue;
+            };
+            let handle = bindings.get_handle_from_object(&object);
+
+            let (mut width, mut height): (c_uint, c_uint) = (0, 0);
+            bindings.FPDFImageObj_GetImagePixelSize(handle, &mut width, &mut height);
+
+            let filters = image.filters();
+            let last = filters.iter().last();
+            let format = SourceImageType::from_last_filter(last.as_ref().map(|f| f.name().as_bytes()));
+
+            // The raw data is only a JPEG file when DCT is the sole filter
+            let estimated_quality = if format == SourceImageType::Jpeg && filters.len() == 1 {
+                let length = bindings.FPDFImageObj_GetImageDataRaw(handle, std::ptr::null_mut(), 0);
+                let mut jpeg = vec![0u8; length as usize];
+                bindings.FPDFImageObj_GetImageDataRaw(handle, jpeg.as_mut_ptr() as *mut c_void, length);
+                estimate_jpeg_quality(&jpeg)
+            } else {
+                None
+            };
+
+            stats.push(ImageQualityStat {
+                page_index,
+                object_index,
+                width,
+                height,
+                estimated_quality,
+                format: format.name().to_string(),
+            });
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Margin around the table of contents, in points
+const TOC_MARGIN: f32 = 72.0;
+/// Font size of the "Contents" heading on each table of contents page
+const TOC_HEADING_SIZE: f32 = 20.0;
+/// Font size of the table of contents entries
+const TOC_ENTRY_SIZE: f32 = 12.0;
+/// Baseline-to-baseline distance between entries
+const TOC_LINE_HEIGHT: f32 = 18.0;
+/// Minimum space between the end of a title and its page number
+const TOC_NUMBER_GAP: f32 = 12.0;
+
+/// Shortens `text` to the longest prefix of `full_text` that, followed by an
+/// ellipsis, is no wider than `max_width`
+fn truncate_text_object(text: &mut PdfPageTextObject, full_text: &str, max_width: PdfPoints) -> Result<(), PdfError> {
+    let boundaries: Vec<usize> = full_text.char_indices().map(|(index, _)| index).collect();
+    let shortened = |chars: usize| format!("{}...", full_text[..boundaries[chars]].trim_end());
+
+    // Binary search for the most characters that still fit
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        text.set_text(shortened(middle))?;
+        if text.width()? <= max_width {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    text.set_text(shortened(low))?;
+
+    Ok(())
+}
+
+/// Inserts a table of contents listing `entries` as (title, page number) at
+/// the front of the document
+///
+/// TOC pages match the size of the existing first page (US Letter for an
+/// empty document). Each lists as many entries as fit, titles on the left and
+/// page numbers right-aligned, so long tables continue onto further pages.
+/// Titles too long to fit beside their page number end in an ellipsis.
+/// Page numbers are printed as given; they are not shifted to account for
+/// the inserted pages. Returns the modified document as new PDF bytes.
+pub fn prepend_toc_page(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    entries: &[(String, usize)],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let (width, height) = match document.pages().first() {
+        Ok(page) => (page.width().value, page.height().value),
+        Err(_) => (612.0, 792.0),
+    };
+
+    let heading_font = document.fonts_mut().helvetica_bold();
+    let entry_font = document.fonts_mut().helvetica();
+
+    let heading_y = height - TOC_MARGIN - TOC_HEADING_SIZE;
+    let first_entry_y = heading_y - TOC_HEADING_SIZE * 2.0;
+    let per_page = (((first_entry_y - TOC_MARGIN) / TOC_LINE_HEIGHT) as usize + 1).max(1);
+    let chunks: Vec<&[(String, usize)]> = if entries.is_empty() {
+        vec![&[]]
+    } else {
+        entries.chunks(per_page).collect()
+    };
+
+    for (toc_index, chunk) in chunks.into_iter().enumerate() {
+        let mut page = document.pages_mut().create_page_at_index(
+            PdfPagePaperSize::new_custom(PdfPoints::new(width), PdfPoints::new(height)),
+            toc_index as PdfPageIndex,
+        )?;
+        let objects = page.objects_mut();
+        objects.create_text_object(
+            PdfPoints::new(TOC_MARGIN),
+            PdfPoints::new(heading_y),
+            "Contents",
+            heading_font,
+            PdfPoints::new(TOC_HEADING_SIZE),
+        )?;
+
+        for (line, (title, page_number)) in chunk.iter().enumerate() {
+            let y = PdfPoints::new(first_entry_y - line as f32 * TOC_LINE_HEIGHT);
+            let mut number = objects.create_text_object(
+                PdfPoints::ZERO,
+                y,
+                page_number,
+                entry_font,
+                PdfPoints::new(TOC_ENTRY_SIZE),
+            )?;
+            let number_width = number.width()?;
+            number.translate(PdfPoints::new(width - TOC_MARGIN) - number_width, PdfPoints::ZERO)?;
+
+            let mut title_object =
+                objects.create_text_object(PdfPoints::new(TOC_MARGIN), y, title, entry_font, PdfPoints::new(TOC_ENTRY_SIZE))?;
+            let available = PdfPoints::new(width - 2.0 * TOC_MARGIN - TOC_NUMBER_GAP) - number_width;
+            if title_object.width()? > available {
+                let text = title_object
+                    .as_text_object_mut()
+                    .expect("create_text_object returns a text object");
+                truncate_text_object(text, title, available)?;
+            }
+        }
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Minimum luminance change for a pixel to count as different between two renders
+const DIFF_THRESHOLD: u8 = 32;
+/// Darkest green used for unchanged pixels, so unchanged ink stays visibly green
+const DIFF_UNCHANGED_FLOOR: u8 = 64;
+
+/// Colour-codes the per-pixel luminance difference between `before` and `after`
+///
+/// Unchanged pixels are green, shaded by their luminance so the page stays
+/// legible; pixels that got lighter (ink removed) are red and pixels that got
+/// darker (ink added) are blue. Pixels outside one image count as white.
+fn difference_map(before: &GrayImage, after: &GrayImage) -> RgbImage {
+    let width = before.width().max(after.width());
+    let height = before.height().max(after.height());
+    let luma = |image: &GrayImage, x: u32, y: u32| {
+        if x < image.width() && y < image.height() { image.get_pixel(x, y)[0] } else { 255 }
+    };
+
+    RgbImage::from_fn(width, height, |x, y| {
+        let (a, b) = (luma(before, x, y), luma(after, x, y));
+        if a.abs_diff(b) < DIFF_THRESHOLD {
+            image::Rgb([0, a.max(DIFF_UNCHANGED_FLOOR), 0])
+        } else if b > a {
+            image::Rgb([255, 0, 0])
+        } else {
+            image::Rgb([0, 0, 255])
+        }
+    })
+}
+
+/// Renders the same page from two versions of a document and returns a PNG
+/// highlighting what changed: green unchanged, red removed, blue added
+pub fn render_page_difference(
+    pdfium: &Pdfium,
+    pdf_a: &[u8],
+    pdf_b: &[u8],
+    page_index: usize,
+    dpi: u32,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_dpi(dpi);
+    options.validate()?;
+
+    let render_gray = |pdf_bytes: &[u8]| -> Result<GrayImage, PdfError> {
+        let document = load_document(pdfium, pdf_bytes)?;
+        check_page_limit(&document, max_pages)?;
+        let page = page_at(&document, page_index)?;
+        Ok(DynamicImage::ImageRgb8(render_page_rgb(&page, &options)?).into_luma8())
+    };
+    let before = render_gray(pdf_a)?;
+    let after = render_gray(pdf_b)?;
+
+    Ok(PageData {
+        image_buffer: encode_image(&difference_map(&before, &after), ImageFormat::Png, options.quality)?,
+        error: None,
+    })
+}
+
+/// Whether `base_font` already carries a subset tag: six uppercase letters and a '+'
+fn is_subset_font_name(base_font: &[u8]) -> bool {
+    base_font.len() > 7 && base_font[6] == b'+' && base_font[..6].iter().all(u8::is_ascii_uppercase)
+}
+
+/// How the character codes shown with a font select glyphs in its embedded
+/// TrueType program
+enum GlyphMapping {
+    /// One-byte codes, resolved through the encoding's `/Differences` glyph
+    /// names and the program's own cmap. `encoded` holds the character each
+    /// code stands for under the font's `/Encoding`, when it has one.
+    Simple { differences: Vec<(u8, Vec<u8>)>, encoded: Vec<Option<char>> },
+    /// Two-byte CIDs (Identity-H/V), mapped to glyph ids through the
+    /// `/CIDToGIDMap` stream, or used directly when it is Identity
+    Cid { cid_to_gid: Option<Vec<u8>> },
+}
+
+/// A font whose FontFile2 program can be cut down to the glyphs it shows
+struct SubsetTarget {
+    /// Font dictionaries whose `/BaseFont` gets the subset tag: the font
+    /// itself, plus the descendant of a Type0 font
+    font_ids: Vec<lopdf::ObjectId>,
+    descriptor_id: lopdf::ObjectId,
+    file_id: lopdf::ObjectId,
+    mapping: GlyphMapping,
+}
+
+/// The character each one-byte code stands for under the simple font's
+/// `/Encoding` (MacRoman, WinAnsi, or a base encoding with `/Differences`
+/// glyph names), or nothing when the font has no `/Encoding`
+fn encoded_chars(document: &lopdf::Document, font: &lopdf::Dictionary) -> Vec<Option<char>> {
+    let Ok(mut encoding) = font.get_deref(b"Encoding", document).cloned() else {
+        return Vec::new();
+    };
+    // lopdf only accepts encoding dictionaries that carry the optional /Type
+    if let Ok(encoding) = encoding.as_dict_mut() {
+        encoding.set("Type", lopdf::Object::Name(b"Encoding".to_vec()));
+    }
+    let font = lopdf::dictionary! { "Type" => "Font", "Encoding" => encoding };
+    let Ok(encoding) = font.get_font_encoding(document) else {
+        return Vec::new();
+    };
+
+    (0..=u8::MAX)
+        .map(|code| encoding.bytes_to_string(&[code]).ok()?.chars().next())
+        .collect()
+}
+
+/// Works out whether the font at `font_id` is a full (non-subset) TrueType
+/// program this module knows how to subset
+fn subset_target(document: &lopdf::Document, font_id: lopdf::ObjectId) -> Option<SubsetTarget> {
+    let font = document.get_dictionary(font_id).ok()?;
+    let base_font = font.get(b"BaseFont").and_then(lopdf::Object::as_name).ok()?;
+    if is_subset_font_name(base_font) {
+        return None;
+    }
+
+    let (cid_font_id, mapping) = match font.get(b"Subtype").and_then(lopdf::Object::as_name).ok()? {
+        b"TrueType" => {
+            let mut differences = Vec::new();
+            if let Ok(encoding) = font.get_deref(b"Encoding", document).and_then(lopdf::Object::as_dict) {
+                let mut code = 0i64;
+                for entry in encoding.get(b"Differences").and_then(lopdf::Object::as_array).into_iter().flatten() {
+                    match entry {
+                        lopdf::Object::Integer(start) => code = *start,
+                        lopdf::Object::Name(name) => {
+                            if let Ok(code) = u8::try_from(code) {
+                                differences.push((code, name.clone()));
+                            }
+                            code += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (None, GlyphMapping::Simple { differences, encoded: encoded_chars(document, font) })
+        }
+        b"Type0" => {
+            let encoding = font.get(b"Encoding").and_then(lopdf::Object::as_name).ok()?;
+            if encoding != b"Identity-H" && encoding != b"Identity-V" {
+                return None;
+            }
+            let cid_font_id = font
+                .get_deref(b"DescendantFonts", document)
+                .and_then(lopdf::Object::as_array)
+                .ok()?
+                .first()?
+                .as_reference()
+                .ok()?;
+            let cid_font = document.get_dictionary(cid_font_id).ok()?;
+            if cid_font.get(b"Subtype").and_then(lopdf::Object::as_name).ok()? != b"CIDFontType2" {
+                return None;
+            }
+            let cid_to_gid = match cid_font.get_deref(b"CIDToGIDMap", document) {
+                Ok(lopdf::Object::Stream(stream)) => Some(stream.get_plain_content().ok()?),
+                _ => None,
+            };
+            (Some(cid_font_id), GlyphMapping::Cid { cid_to_gid })
+        }
+        _ => return None,
+    };
+
+    let descriptor_owner = document.get_dictionary(cid_font_id.unwrap_or(font_id)).ok()?;
+    let descriptor_id = descriptor_owner.get(b"FontDescriptor").and_then(lopdf::Object::as_reference).ok()?;
+    let file_id = document
+        .get_dictionary(descriptor_id)
+        .and_then(|descriptor| descriptor.get(b"FontFile2"))
+        .and_then(lopdf::Object::as_reference)
+        .ok()?;
+
+    Some(SubsetTarget {
+        font_ids: std::iter::once(font_id).chain(cid_font_id).collect(),
+        descriptor_id,
+        file_id,
+        mapping,
+    })
+}
+
+/// Named font and XObject references of one resource dictionary
+type NamedResources = Vec<(Vec<u8>, lopdf::ObjectId)>;
+
+/// Every string shown by the page and form XObject content streams, keyed by
+/// the object id of the font it was shown with
+fn shown_strings(document: &lopdf::Document) -> BTreeMap<lopdf::ObjectId, BTreeSet<Vec<u8>>> {
+    let named = |resources: &lopdf::Dictionary, key: &[u8]| -> NamedResources {
+        resources
+            .get_deref(key, document)
+            .and_then(lopdf::Object::as_dict)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|(name, entry)| Some((name.clone(), entry.as_reference().ok()?)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut shown: BTreeMap<lopdf::ObjectId, BTreeSet<Vec<u8>>> = BTreeMap::new();
+    for page_id in document.get_pages().into_values() {
+        let (mut fonts, mut xobjects) = (Vec::new(), Vec::new());
+        if let Ok((inline, referenced)) = document.get_page_resources(page_id) {
+            let referenced = referenced.into_iter().filter_map(|id| document.get_dictionary(id).ok());
+            for resources in inline.into_iter().chain(referenced) {
+                fonts.extend(named(resources, b"Font"));
+                xobjects.extend(named(resources, b"XObject"));
+            }
+        }
+        let content = document.get_page_content(page_id);
+        collect_shown_strings(document, &content, &fonts, &xobjects, &mut BTreeSet::new(), &mut shown);
+    }
+    // Forms with their own resources, wherever they are used from (pages,
+    // other forms, or annotation appearances)
+    for (&form_id, object) in &document.objects {
+        let Ok(stream) = object.as_stream() else {
+            continue;
+        };
+        if stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok() != Some(b"Form".as_slice()) {
+            continue;
+        }
+        let Ok(resources) = stream.dict.get_deref(b"Resources", document).and_then(lopdf::Object::as_dict) else {
+            continue;
+        };
+        let content = stream.get_plain_content().unwrap_or_default();
+        let (fonts, xobjects) = (named(resources, b"Font"), named(resources, b"XObject"));
+        collect_shown_strings(document, &content, &fonts, &xobjects, &mut BTreeSet::from([form_id]), &mut shown);
+    }
+
+    shown
+}
+
+/// Adds the strings `content` shows to `shown`, following `Do` into forms
+/// without `/Resources`, which draw with the resources of their caller.
+/// `visited` holds the forms already on this path, so cycles end.
+fn collect_shown_strings(
+    document: &lopdf::Document,
+    content: &[u8],
+    fonts: &NamedResources,
+    xobjects: &NamedResources,
+    visited: &mut BTreeSet<lopdf::ObjectId>,
+    shown: &mut BTreeMap<lopdf::ObjectId, BTreeSet<Vec<u8>>>,
+) {
+    let Ok(content) = lopdf::content::Content::decode(content) else {
+        return;
+    };
+    let lookup = |resources: &NamedResources, operation: &lopdf::content::Operation| {
+        let name = operation.operands.first().and_then(|name| name.as_name().ok());
+        resources.iter().find(|(entry, _)| Some(entry.as_slice()) == name).map(|&(_, id)| id)
+    };
+
+    let mut current = None;
+    for operation in &content.operations {
+        let strings: Vec<&lopdf::Object> = match operation.operator.as_str() {
+            "Tf" => {
+                current = lookup(fonts, operation);
+                continue;
+            }
+            "Do" => {
+                let Some(form_id) = lookup(xobjects, operation) else {
+                    continue;
+                };
+                let Ok(form) = document.get_object(form_id).and_then(lopdf::Object::as_stream) else {
+                    continue;
+                };
+                if form.dict.has(b"Resources") || !visited.insert(form_id) {
+                    continue;
+                }
+                let form_content = form.get_plain_content().unwrap_or_default();
+                collect_shown_strings(document, &form_content, fonts, xobjects, visited, shown);
+                visited.remove(&form_id);
+                continue;
+            }
+            "Tj" | "'" => operation.operands.iter().take(1).collect(),
+            "\"" => operation.operands.iter().skip(2).collect(),
+            "TJ" => operation.operands.first().and_then(|array| array.as_array().ok()).into_iter().flatten().collect(),
+            _ => continue,
+        };
+        let Some(font_id) = current else {
+            continue;
+        };
+        for string in strings {
+            if let lopdf::Object::String(bytes, _) = string {
+                shown.entry(font_id).or_default().insert(bytes.clone());
+            }
+        }
+    }
+}
+
+/// Glyph ids in `face` that `strings` select through `mapping`, always
+/// including `.notdef`
+fn mapped_glyphs(face: &ttf_parser::Face, mapping: &GlyphMapping, strings: &BTreeSet<Vec<u8>>) -> BTreeSet<u16> {
+    let mut glyphs = BTreeSet::from([0]);
+    match mapping {
+        GlyphMapping::Simple { differences, encoded } => {
+            let subtables: Vec<_> = face.tables().cmap.map(|cmap| cmap.subtables.into_iter().collect()).unwrap_or_default();
+            let codes: BTreeSet<u8> = strings.iter().flatten().copied().collect();
+            for code in codes {
+                // Without parsing the full encoding, take every glyph the code
+                // could plausibly select
+                let mut candidates = Vec::new();
+                if let Some((_, name)) = differences.iter().find(|(difference, _)| *difference == code) {
+                    candidates.extend(std::str::from_utf8(name).ok().and_then(|name| face.glyph_index_by_name(name)));
+                }
+                let encoded = encoded.get(code as usize).copied().flatten();
+                candidates.extend(encoded.or_else(|| win_ansi_char(code)).and_then(|c| face.glyph_index(c)));
+                for subtable in &subtables {
+                    match subtable.platform_id {
+                        ttf_parser::PlatformId::Windows if subtable.encoding_id == 0 => {
+                            candidates.extend(subtable.glyph_index(0xF000 + code as u32));
+                            candidates.extend(subtable.glyph_index(code as u32));
+                        }
+                        ttf_parser::PlatformId::Macintosh if subtable.encoding_id == 0 => {
+                            candidates.extend(subtable.glyph_index(code as u32));
+                        }
+                        _ => {}
+                    }
+                }
+                glyphs.extend(candidates.into_iter().map(|glyph| glyph.0));
+            }
+        }
+        GlyphMapping::Cid { cid_to_gid } => {
+            for string in strings {
+                for code in string.chunks_exact(2) {
+                    let cid = u16::from_be_bytes([code[0], code[1]]);
+                    let glyph = match cid_to_gid {
+                        Some(map) => match map.get(cid as usize * 2..cid as usize * 2 + 2) {
+                            Some(gid) => u16::from_be_bytes([gid[0], gid[1]]),
+                            None => continue,
+                        },
+                        None => cid,
+                    };
+                    glyphs.insert(glyph);
+                }
+            }
+        }
+    }
+
+    glyphs
+}
+
+/// Tables a TrueType program embedded in a PDF needs; layout tables such as
+/// GSUB and GPOS are never consulted by PDF consumers
+const SUBSET_TABLES: [&[u8; 4]; 13] = [
+    b"OS/2", b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"name", b"post", b"prep",
+];
+
+/// Cuts the TrueType program `data` down to `glyphs` and the components of
+/// any composite glyphs among them
+///
+/// Glyph ids are kept: dropped glyphs are left empty rather than removed, so
+/// content streams, widths and CIDToGIDMaps stay valid. Returns `None` when
+/// `data` isn't a glyf-based font or the subset wouldn't be any smaller.
+fn subset_truetype(data: &[u8], glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let face = ttf_parser::RawFace::parse(data, 0).ok()?;
+    let table = |tag: &[u8; 4]| face.table(ttf_parser::Tag::from_bytes(tag));
+    let read_u16 = |bytes: &[u8], at: usize| bytes.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let read_u32 = |bytes: &[u8], at: usize| bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+    let head = table(b"head")?;
+    let glyf = table(b"glyf")?;
+    let loca = table(b"loca")?;
+    let glyph_count = read_u16(table(b"maxp")?, 4)? as usize;
+    let long_offsets = read_u16(head, 50)? != 0;
+    let offsets: Vec<usize> = (0..=glyph_count)
+        .map(|glyph| match long_offsets {
+            true => read_u32(loca, glyph * 4).map(|offset| offset as usize),
+            false => read_u16(loca, glyph * 2).map(|offset| offset as usize * 2),
+        })
+        .collect::<Option<_>>()?;
+    let glyph_data = |glyph: usize| glyf.get(offsets[glyph]..offsets[glyph + 1]).unwrap_or_default();
+
+    // Pull in the components of composite glyphs
+    let mut keep: BTreeSet<usize> = glyphs.iter().map(|&glyph| glyph as usize).filter(|&g| g < glyph_count).collect();
+    let mut pending: Vec<usize> = keep.iter().copied().collect();
+    while let Some(glyph) = pending.pop() {
+        let outline = glyph_data(glyph);
+        if outline.len() < 10 || read_u16(outline, 0)? as i16 >= 0 {
+            continue;
+        }
+        let mut at = 10;
+        while let (Some(flags), Some(component)) = (read_u16(outline, at), read_u16(outline, at + 2)) {
+            if keep.insert(component as usize) && (component as usize) < glyph_count {
+                pending.push(component as usize);
+            }
+            at += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+            at += if flags & 0x0008 != 0 {
+                2
+            } else if flags & 0x0040 != 0 {
+                4
+            } else if flags & 0x0080 != 0 {
+                8
+            } else {
+                0
+            };
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count + 1) * 4);
+    for glyph in 0..glyph_count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&glyph) {
+            new_glyf.extend_from_slice(glyph_data(glyph));
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    // Long loca offsets, and a checksum adjustment PDF consumers don't verify
+    let mut new_head = head.to_vec();
+    new_head.get_mut(8..12)?.fill(0);
+    new_head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+
+    let tables: Vec<(&[u8; 4], Vec<u8>)> = SUBSET_TABLES
+        .iter()
+        .filter_map(|&tag| match tag {
+            b"glyf" => Some((tag, std::mem::take(&mut new_glyf))),
+            b"loca" => Some((tag, std::mem::take(&mut new_loca))),
+            b"head" => Some((tag, std::mem::take(&mut new_head))),
+            _ => table(tag).map(|data| (tag, data.to_vec())),
+        })
+        .collect();
+
+    let table_count = tables.len() as u16;
+    let entry_selector = 15 - table_count.leading_zeros() as u16;
+    let search_range = 16u16 << entry_selector;
+    let mut output = Vec::new();
+    output.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    output.extend_from_slice(&table_count.to_be_bytes());
+    output.extend_from_slice(&search_range.to_be_bytes());
+    output.extend_from_slice(&entry_selector.to_be_bytes());
+    output.extend_from_slice(&(table_count * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + tables.len() * 16;
+    let mut body = Vec::new();
+    for (tag, data) in &tables {
+        let mut padded = data.clone();
+        padded.resize(data.len().next_multiple_of(4), 0);
+        let checksum = padded
+            .chunks_exact(4)
+            .fold(0u32, |sum, word| sum.wrapping_add(u32::from_be_bytes([word[0], word[1], word[2], word[3]])));
+        output.extend_from_slice(*tag);
+        output.extend_from_slice(&checksum.to_be_bytes());
+        output.extend_from_slice(&(offset as u32).to_be_bytes());
+        output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += padded.len();
+        body.extend_from_slice(&padded);
+    }
+    output.extend_from_slice(&body);
+
+    (output.len() < data.len()).then_some(output)
+}
+
+/// A six-letter subset tag derived from the glyphs a subset keeps
+fn subset_tag(glyphs: &BTreeSet<u16>) -> Vec<u8> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    glyphs.hash(&mut hasher);
+    let mut hash = hasher.finish();
+    (0..6)
+        .map(|_| {
+            let letter = b'A' + (hash % 26) as u8;
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+/// Replaces every full TrueType program in the document with a subset of the
+/// glyphs its text actually shows
+fn subset_font_programs(pdf_bytes: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let shown = shown_strings(&document);
+
+    // AcroForm fields regenerate their appearances from the default
+    // resources, so those fonts need all of their glyphs
+    let form_fonts: BTreeSet<lopdf::ObjectId> = document
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"AcroForm", &document))
+        .and_then(lopdf::Object::as_dict)
+        .and_then(|form| form.get_deref(b"DR", &document))
+        .and_then(lopdf::Object::as_dict)
+        .and_then(|resources| resources.get_deref(b"Font", &document))
+        .and_then(lopdf::Object::as_dict)
+        .map(|fonts| fonts.iter().filter_map(|(_, font)| font.as_reference().ok()).collect())
+        .unwrap_or_default();
+
+    // Fonts can share one program, so group by the FontFile2 stream
+    let mut programs: BTreeMap<lopdf::ObjectId, Vec<SubsetTarget>> = BTreeMap::new();
+    let mut blocked = BTreeSet::new();
+    for &font_id in document.objects.keys() {
+        let Some(target) = subset_target(&document, font_id) else {
+            continue;
+        };
+        if shown.contains_key(&font_id) && !form_fonts.contains(&font_id) {
+            programs.entry(target.file_id).or_default().push(target);
+        } else {
+            blocked.insert(target.file_id);
+        }
+    }
+
+    let mut replacements = Vec::new();
+    for (file_id, targets) in programs {
+        if blocked.contains(&file_id) {
+            continue;
+        }
+        let Ok(stream) = document.get_object(file_id).and_then(lopdf::Object::as_stream) else {
+            continue;
+        };
+        let Ok(data) = stream.get_plain_content() else {
+            continue;
+        };
+        let Ok(face) = owned_ttf_parser::OwnedFace::from_vec(data, 0) else {
+            continue;
+        };
+        let face = face.as_face_ref();
+
+        let glyphs: BTreeSet<u16> = targets
+            .iter()
+            .flat_map(|target| mapped_glyphs(face, &target.mapping, &shown[&target.font_ids[0]]))
+            .collect();
+        if let Some(subset) = subset_truetype(face.raw_face().data, &glyphs) {
+            replacements.push((file_id, subset, subset_tag(&glyphs), targets));
+        }
+    }
+
+    for (file_id, subset, tag, targets) in replacements {
+        let tagged = |name: &[u8]| lopdf::Object::Name([tag.as_slice(), b"+", name].concat());
+        for target in targets {
+            for font_id in target.font_ids {
+                if let Ok(font) = document.get_object_mut(font_id).and_then(lopdf::Object::as_dict_mut) {
+                    if let Ok(base_font) = font.get(b"BaseFont").and_then(lopdf::Object::as_name) {
+                        font.set("BaseFont", tagged(base_font));
+                    }
+                }
+            }
+            if let Ok(descriptor) = document.get_object_mut(target.descriptor_id).and_then(lopdf::Object::as_dict_mut) {
+                if let Ok(font_name) = descriptor.get(b"FontName").and_then(lopdf::Object::as_name) {
+                    if !is_subset_font_name(font_name) {
+                        descriptor.set("FontName", tagged(font_name));
+                    }
+                }
+            }
+        }
+
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Length1", subset.len() as i64);
+        let mut stream = lopdf::Stream::new(dict, subset);
+        let _ = stream.compress();
+        document.objects.insert(file_id, lopdf::Object::Stream(stream));
+    }
+
+    let mut output = Vec::new();
+    document.save_to(&mut output)?;
+
+    Ok(output)
+}
+
+/// Subsets embedded TrueType fonts to the glyphs the document's text uses
+///
+/// Only full programs are touched: fonts whose `/BaseFont` already has a
+/// subset tag, CFF-based (FontFile3) fonts, composite fonts with a CMap other
+/// than Identity, fonts that no content stream uses and AcroForm default
+/// fonts are left as they are. Glyph ids are preserved so the text itself
+/// doesn't need rewriting, and subset fonts get the usual `ABCDEF+` prefix.
+/// Returns the modified document as new PDF bytes.
+pub fn subset_embedded_fonts(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+
+    subset_font_programs(&saved)
+}
+
+/// Resolution pages are rendered at to decide whether they are blank
+const BLANK_PAGE_DPI: u32 = 50;
+
+/// Standard deviation of the luminance over `image`
+fn luminance_std_dev(image: &GrayImage) -> f32 {
+    let count = image.len() as f64;
+    if count == 0.0 {
+        return 0.0;
+    }
+
+    let (sum, sum_sq) = image
+        .iter()
+        .fold((0.0f64, 0.0f64), |(sum, sum_sq), &v| (sum + v as f64, sum_sq + (v as f64) * (v as f64)));
+    let mean = sum / count;
+
+    (sum_sq / count - mean * mean).max(0.0).sqrt() as f32
+}
+
+/// Whether `page` is blank: its luminance standard deviation at 50 DPI is
+/// below `threshold`
+fn page_is_blank(page: &PdfPage, threshold: f32) -> Result<bool, PdfError> {
+    let options = RenderOptions::default().with_dpi(BLANK_PAGE_DPI).with_grayscale(true);
+    let image = DynamicImage::ImageRgb8(render_page_rgb(page, &options)?).into_luma8();
+
+    Ok(luminance_std_dev(&image) < threshold)
+}
+
+/// Checks whether a page is blank
+///
+/// The page is rendered at 50 DPI in grayscale and counts as blank when the
+/// standard deviation of its luminance is below `threshold`. An empty page
+/// scores 0 and a page of text typically 30 or more, while scanner noise on
+/// an empty sheet stays in the low single digits.
+pub fn is_blank_page(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    threshold: f32,
+    max_pages: Option<usize>
+) -> Result<bool, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+
+    page_is_blank(&page, threshold)
+}
+
+/// Splits a batch scan into separate documents at its blank separator pages
+///
+/// Pages are tested as in [`is_blank_page`]. The blank pages themselves are
+/// dropped, including any leading or trailing ones, and runs of several
+/// blanks count as a single separator. Returns one PDF per segment, in order.
+pub fn split_by_blank_pages(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    blank_threshold: f32,
+    max_pages: Option<usize>
+) -> Result<Vec<Vec<u8>>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut segments: Vec<Vec<PdfPageIndex>> = Vec::new();
+    let mut current = Vec::new();
+    for (page_index, page) in document.pages().iter().enumerate() {
+        if page_is_blank(&page, blank_threshold)? {
+            if !current.is_empty() {
+                segments.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(page_index as PdfPageIndex);
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments
+        .into_iter()
+        .map(|pages| {
+            let mut segment = pdfium.create_new_pdf()?;
+            segment
+                .pages_mut()
+                .copy_page_range_from_document(&document, pages[0]..=pages[pages.len() - 1], 0)?;
+            Ok(segment.save_to_bytes()?)
+        })
+        .collect()
+}
+
+/// Renders every page as a `thumb_size`-wide thumbnail and lays them out
+/// left to right, top to bottom, `columns` to a row, in one image
+///
+/// Each row is as tall as its tallest thumbnail; the gaps left by shorter
+/// pages and by a partial last row are white.
+pub fn render_pdf_grid(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    columns: usize,
+    thumb_size: u32,
+    quality: u8,
+    format: ImageFormat,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    RenderOptions::default().with_quality(quality).validate()?;
+    if columns == 0 {
+        return Err(PdfError::InvalidArgument("Grid must have at least one column".to_string()));
+    }
+    if thumb_size == 0 {
+        return Err(PdfError::InvalidArgument("Thumbnail size must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let config = PdfRenderConfig::new()
+        .set_target_width(thumb_size as Pixels)
+        .render_form_data(true);
+    let thumbnails = document
+        .pages()
+        .iter()
+        .map(|page| Ok(page.render_with_config(&config)?.as_image().into_rgb8()))
+        .collect::<Result<Vec<RgbImage>, PdfError>>()?;
+
+    let row_heights: Vec<u32> = thumbnails
+        .chunks(columns)
+        .map(|row| row.iter().map(RgbImage::height).max().unwrap_or(0))
+        .collect();
+    let width = thumb_size * columns.min(thumbnails.len()) as u32;
+    let height = row_heights.iter().sum();
+    if width == 0 || height == 0 {
+        return Err(PdfError::InvalidArgument("Document has no pages to render".to_string()));
+    }
+
+    let mut grid = RgbImage::from_pixel(width, height, image::Rgb([255, 255, 255]));
+    let mut y = 0;
+    for (row, row_height) in thumbnails.chunks(columns).zip(row_heights) {
+        for (column, thumbnail) in row.iter().enumerate() {
+            image::imageops::replace(&mut grid, thumbnail, column as i64 * thumb_size as i64, y);
+        }
+        y += row_height as i64;
+    }
+
+    Ok(PageData {
+        image_buffer: encode_image(&grid, format, quality)?,
+        error: None,
+    })
+}
+
+/// Bounding boxes, in points, of the whitespace-separated words on `page`
+///
+/// Each box is the union of the loose bounds of the word's characters, so it
+/// spans the font's full line height rather than just the inked glyphs.
+fn word_boxes(page: &PdfPage) -> Result<Vec<PdfRect>, PdfError> {
+    let mut boxes = Vec::new();
+    let mut current: Option<PdfRect> = None;
+
+    for ch in page.text()?.chars().iter() {
+        let is_word_char = ch.unicode_char().is_some_and(|c| !c.is_whitespace());
+        let bounds = if is_word_char { ch.loose_bounds().ok() } else { None };
+        match (bounds, current) {
+            (Some(bounds), Some(word)) => {
+                current = Some(PdfRect::new(
+                    word.bottom().min(bounds.bottom()),
+                    word.left().min(bounds.left()),
+                    word.top().max(bounds.top()),
+                    word.right().max(bounds.right()),
+                ));
+            }
+            (Some(bounds), None) => current = Some(bounds),
+            (None, _) if !is_word_char => boxes.extend(current.take()),
+            (None, _) => {}
+        }
+    }
+    boxes.extend(current);
+
+    Ok(boxes)
+}
+
+/// Fraction of a page's area covered by its words' bounding boxes, from 0.0
+/// to 1.0
+///
+/// A page of body text typically scores above 0.5 and a scanned image or
+/// photograph with no text layer close to 0. Overlapping boxes are counted
+/// twice, so the sum is clamped to 1.0.
+pub fn page_text_density(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    max_pages: Option<usize>
+) -> Result<f32, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+
+    let page_area = page.width().value * page.height().value;
+    if page_area <= 0.0 {
+        return Ok(0.0);
+    }
+    let text_area: f32 = word_boxes(&page)?
+        .iter()
+        .map(|word| word.width().value * word.height().value)
+        .sum();
+
+    Ok((text_area / page_area).clamp(0.0, 1.0))
+}
+
+/// Rewrites `pdf_bytes` with a compressed cross-reference stream, packing the
+/// non-stream objects into object streams
+fn write_xref_stream(pdf_bytes: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut document = lopdf::Document::load_mem(pdf_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+
+    let mut output = Vec::new();
+    document.save_modern(&mut output)?;
+
+    Ok(output)
+}
+
+/// Re-saves a document in the PDF 1.5 layout, with a compressed
+/// cross-reference stream and object streams instead of a plain-text xref
+/// table
+///
+/// pdfium's writer always emits a classic xref table, whatever version is
+/// passed to `FPDF_SaveWithVersion`, so the document is saved through pdfium
+/// and then rewritten with lopdf. Documents older than 1.5 are moved up to
+/// 1.5. Returns the document as new PDF bytes.
+pub fn enable_xref_stream(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let saved = document.save_to_bytes()?;
+
+    write_xref_stream(&saved)
+}
+
+/// Renders a single page with its colours inverted, for dark-mode viewing
+///
+/// The page is rendered as usual and every channel is then flipped, so black
+/// text on white becomes white text on black. Images on the page are inverted
+/// too. The result is encoded as WebP.
+pub fn render_page_inverted(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_dpi(dpi).with_quality(quality);
+    options.validate()?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let mut image = DynamicImage::ImageRgb8(render_page_rgb(&page, &options)?);
+    image::imageops::invert(&mut image);
+
+    Ok(PageData {
+        image_buffer: encode_image(&image.into_rgb8(), options.format, options.quality)?,
+        error: None,
+    })
+}
+
+/// Renders at most the first `n` pages of a PDF, for previews
+///
+/// Pages past the `n`th are never rendered, so the cost doesn't grow with the
+/// length of the document. Shorter documents return all of their pages.
+pub fn render_first_n_pages(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    n: usize,
+    quality: u8,
+    format: ImageFormat,
+    max_pages: Option<usize>
+) -> Result<Vec<PageData>, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+    options.validate()?;
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    document
+        .pages()
+        .iter()
+        .take(n)
+        .map(|page| {
+            Ok(PageData {
+                image_buffer: encode_image(&render_page_rgb(&page, &options)?, options.format, options.quality)?,
+                error: None,
+            })
+        })
+        .collect()
+}
+
+/// ISO A4 paper, width × height in points
+pub const A4_PT: (f32, f32) = (595.28, 841.89);
+/// US Letter paper, width × height in points
+pub const LETTER_PT: (f32, f32) = (612.0, 792.0);
+
+/// Creates a new document with one blank page per `(width_pt, height_pt)`
+/// entry, in order, and returns it as PDF bytes
+pub fn create_blank_pdf(pdfium: &Pdfium, pages: &[(f32, f32)]) -> Result<Vec<u8>, PdfError> {
+    if pages.is_empty() {
+        return Err(PdfError::InvalidArgument("A PDF needs at least one page".to_string()));
+    }
+    if pages.iter().any(|&(width, height)| !(width > 0.0 && height > 0.0)) {
+        return Err(PdfError::InvalidArgument("Page width and height must be greater than 0".to_string()));
+    }
+
+    let mut document = pdfium.create_new_pdf()?;
+    for &(width, height) in pages {
+        document.pages_mut().create_page_at_end(
+            PdfPagePaperSize::new_custom(PdfPoints::new(width), PdfPoints::new(height)),
+        )?;
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// pdfium's `FPDF_FILEACCESS`, reading from the byte slice `param` points to
+#[repr(C)]
+struct SliceFileAccess {
+    file_len: c_ulong,
+    get_block: unsafe extern "C" fn(*mut c_void, c_ulong, *mut u8, c_ulong) -> c_int,
+    param: *mut c_void,
+}
+
+unsafe extern "C" fn read_slice_block(param: *mut c_void, position: c_ulong, buffer: *mut u8, size: c_ulong) -> c_int {
+    // SAFETY: pdfium hands back the slice passed in `SliceFileAccess`, and
+    // `buffer` has room for `size` bytes
+    let data = unsafe { *(param as *const &[u8]) };
+    match data.get(position as usize..position as usize + size as usize) {
+        Some(block) => {
+            unsafe { std::ptr::copy_nonoverlapping(block.as_ptr(), buffer, block.len()) };
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Adds `jpeg` to `page` as an image object `width` by `height` points from
+/// the page's origin. The DCT data is embedded as it is rather than decoded
+/// and re-encoded, so scans keep their compression.
+fn add_jpeg_object(document: &PdfDocument, page: &PdfPage, jpeg: &[u8], width: f32, height: f32) -> Result<(), PdfError> {
+    let bindings = page.bindings();
+    let object = bindings.FPDFPageObj_NewImageObj(bindings.get_handle_from_document(document));
+    if object.is_null() {
+        return Err(PdfError::Pdfium(PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::Unknown)));
+    }
+
+    // The inline variant reads the whole file before returning, so `jpeg`
+    // only has to outlive this call
+    let mut data = jpeg;
+    let mut file_access = SliceFileAccess {
+        file_len: jpeg.len() as c_ulong,
+        get_block: read_slice_block,
+        param: &mut data as *mut &[u8] as *mut c_void,
+    };
+    let loaded = bindings.FPDFImageObj_LoadJpegFileInline(
+        std::ptr::null_mut(),
+        0,
+        object,
+        &mut file_access as *mut SliceFileAccess as *mut _,
+    );
+    if !bindings.is_true(loaded) {
+        bindings.FPDFPageObj_Destroy(object);
+        return Err(PdfError::InvalidArgument("pdfium could not read the JPEG".to_string()));
+    }
+
+    // Image objects are drawn into the unit square, so scale it to the page
+    bindings.FPDFPageObj_Transform(object, width as f64, 0.0, 0.0, height as f64, 0.0, 0.0);
+    let page_handle = bindings.get_handle_from_page(page);
+    bindings.FPDFPage_InsertObject(page_handle, object);
+    if !bindings.is_true(bindings.FPDFPage_GenerateContent(page_handle)) {
+        return Err(PdfError::Pdfium(PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::Unknown)));
+    }
+
+    Ok(())
+}
+
+/// Page edges `images_to_pdf` keeps at the image's natural size, in points.
+/// The upper bound is the largest page size Acrobat supports.
+const IMAGE_PAGE_RANGE_PT: (f32, f32) = (72.0, 14400.0);
+
+/// Creates a document with one page per image, in order, each image filling
+/// its page
+///
+/// Pages take the image's size at 72 DPI, one point per pixel. Images with an
+/// edge under 1 inch or over 200 inches at that size are scaled to fit A4
+/// instead, in the image's orientation. JPEGs keep their DCT compression;
+/// other formats are decoded and embedded as bitmaps.
+pub fn images_to_pdf(pdfium: &Pdfium, images: &[(Vec<u8>, ImageFormat)]) -> Result<Vec<u8>, PdfError> {
+    if images.is_empty() {
+        return Err(PdfError::InvalidArgument("A PDF needs at least one page".to_string()));
+    }
+
+    let mut document = pdfium.create_new_pdf()?;
+    for (index, (data, format)) in images.iter().enumerate() {
+        let decoder_format = format.decoder_format().ok_or_else(|| PdfError::InvalidArgument(format!(
+            "Image {}: {} images can't be decoded", index, format.extension()
+        )))?;
+        // JPEGs are embedded as they are, so only their header is read
+        let (image, (pixel_width, pixel_height)) = if *format == ImageFormat::Jpeg {
+            let dimensions = image::ImageReader::with_format(Cursor::new(data), decoder_format).into_dimensions();
+            (None, dimensions.map_err(|e| PdfError::InvalidArgument(format!("Image {}: {}", index, e)))?)
+        } else {
+            let image = image::load_from_memory_with_format(data, decoder_format)
+                .map_err(|e| PdfError::InvalidArgument(format!("Image {}: {}", index, e)))?;
+            let dimensions = (image.width(), image.height());
+            (Some(image), dimensions)
+        };
+
+        let (mut width, mut height) = (pixel_width as f32, pixel_height as f32);
+        let (min, max) = IMAGE_PAGE_RANGE_PT;
+        if width.min(height) < min || width.max(height) > max {
+            let (short, long) = (A4_PT.0.min(A4_PT.1), A4_PT.0.max(A4_PT.1));
+            let (fit_width, fit_height) = if width > height { (long, short) } else { (short, long) };
+            let scale = (fit_width / width).min(fit_height / height);
+            (width, height) = (width * scale, height * scale);
+        }
+
+        let mut page = document.pages_mut().create_page_at_end(
+            PdfPagePaperSize::new_custom(PdfPoints::new(width), PdfPoints::new(height)),
+        )?;
+        match image {
+            Some(image) => {
+                let object = PdfPageImageObject::new_with_size(&document, &image, PdfPoints::new(width), PdfPoints::new(height))?;
+                page.objects_mut().add_image_object(object)?;
+            }
+            None => add_jpeg_object(&document, &page, data, width, height)
+                .map_err(|e| PdfError::InvalidArgument(format!("Image {}: {}", index, e)))?,
+        }
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// The standard 14 PDF fonts every viewer provides without embedding
+const BUILT_IN_FONTS: [PdfFontBuiltin; 14] = [
+    PdfFontBuiltin::TimesRoman,
+    PdfFontBuiltin::TimesBold,
+    PdfFontBuiltin::TimesItalic,
+    PdfFontBuiltin::TimesBoldItalic,
+    PdfFontBuiltin::Helvetica,
+    PdfFontBuiltin::HelveticaBold,
+    PdfFontBuiltin::HelveticaOblique,
+    PdfFontBuiltin::HelveticaBoldOblique,
+    PdfFontBuiltin::Courier,
+    PdfFontBuiltin::CourierBold,
+    PdfFontBuiltin::CourierOblique,
+    PdfFontBuiltin::CourierBoldOblique,
+    PdfFontBuiltin::Symbol,
+    PdfFontBuiltin::ZapfDingbats,
+];
+
+/// The lookup key and path of every .ttf and .otf file under the system font
+/// directories and the bundled fonts, collected on first use
+///
+/// Fonts installed after that are not seen until the process restarts.
+fn font_file_index() -> &'static [(String, PathBuf)] {
+    static INDEX: OnceLock<Vec<(String, PathBuf)>> = OnceLock::new();
+    INDEX.get_or_init(|| {
+        let mut dirs: Vec<PathBuf> = SYSTEM_FONT_DIRS.iter().map(PathBuf::from).collect();
+        #[cfg(feature = "bundled-fonts")]
+        dirs.extend(bundled_font_dir().ok());
+
+        let mut index = Vec::new();
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            // `file_type` does not follow symlinks, so a link back up the
+            // tree cannot send the walk round in circles
+            dirs.extend(
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+                    .map(|entry| entry.path()),
+            );
+            index.extend(index_font_files(&dir).unwrap_or_default());
+        }
+        index
+    })
+}
+
+/// Finds a font file with TrueType outlines named like `font_name` among
+/// `font_file_index`
+///
+/// pdfium only loads TrueType programs, so CFF-flavoured OpenType files are
+/// passed over.
+fn find_font_file(font_name: &str) -> Option<PathBuf> {
+    let key = font_lookup_key(font_name);
+    let is_true_type = |path: &Path| {
+        let mut tag = [0u8; 4];
+        std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut tag)).is_ok() && &tag != b"OTTO"
+    };
+
+    font_file_index()
+        .iter()
+        .find(|(file_key, path)| *file_key == key && is_true_type(path))
+        .map(|(_, path)| path.clone())
+}
+
+/// Adds the font called `font_name` to `document`
+///
+/// `font_name` may be one of the standard 14 PDF fonts ("Times-Roman",
+/// "Courier-Bold", ...), or the name of a font file with TrueType outlines in
+/// the system font directories or the bundled fonts, which is then embedded.
+/// Names are matched ignoring case and punctuation, so "DejaVu Sans" finds
+/// `DejaVuSans.ttf`. With no name, Helvetica is used.
+fn resolve_font(document: &mut PdfDocument, font_name: Option<&str>) -> Result<PdfFontToken, PdfError> {
+    let Some(name) = font_name else {
+        return Ok(document.fonts_mut().helvetica());
+    };
+
+    let key = font_lookup_key(name);
+    match BUILT_IN_FONTS.iter().find(|font| font_lookup_key(font.to_pdf_font_name()) == key) {
+        Some(&built_in) => Ok(document.fonts_mut().new_built_in(built_in)),
+        None => {
+            let path = find_font_file(name)
+                .ok_or_else(|| PdfError::InvalidArgument(format!("No font named {:?} was found", name)))?;
+            // A CID font can show any character the file has a glyph for
+            Ok(document.fonts_mut().load_true_type_from_bytes(&std::fs::read(path)?, true)?)
+        }
+    }
+}
+
+/// Draws `text` on a page with its baseline starting at (`x`, `y`) points
+/// from the page's bottom-left corner, in the font `resolve_font` finds for
+/// `font_name`. Returns the modified document as new PDF bytes.
+#[allow(clippy::too_many_arguments)]
+pub fn add_text_to_page(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    text: &str,
+    x: f32,
+    y: f32,
+    font_size: f32,
+    color: [u8; 3],
+    font_name: Option<&str>,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if font_size <= 0.0 {
+        return Err(PdfError::InvalidArgument("Font size must be greater than 0".to_string()));
+    }
+
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let font = resolve_font(&mut document, font_name)?;
+
+    let mut page = page_at(&document, page_index)?;
+    let mut object = page.objects_mut().create_text_object(
+        PdfPoints::new(x),
+        PdfPoints::new(y),
+        text,
+        font,
+        PdfPoints::new(font_size),
+    )?;
+    object.set_fill_color(PdfColor::new(color[0], color[1], color[2], 255))?;
+    // Dropping the page regenerates its content stream before the save
+    drop(page);
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// The width in points `text` takes up when drawn by `add_text_to_page` with
+/// the same font and size, for centering or right-aligning it
+///
+/// The text is laid out on a scratch page and its object bounds measured.
+pub fn measure_text(
+    pdfium: &Pdfium,
+    text: &str,
+    font_name: Option<&str>,
+    font_size: f32
+) -> Result<f32, PdfError> {
+    if font_size <= 0.0 {
+        return Err(PdfError::InvalidArgument("Font size must be greater than 0".to_string()));
+    }
+
+    let mut document = pdfium.create_new_pdf()?;
+    let font = resolve_font(&mut document, font_name)?;
+    if text.is_empty() {
+        return Ok(0.0);
+    }
+
+    let mut page = document.pages_mut().create_page_at_end(PdfPagePaperSize::a4())?;
+    let object = page.objects_mut().create_text_object(
+        PdfPoints::ZERO,
+        PdfPoints::ZERO,
+        text,
+        font,
+        PdfPoints::new(font_size),
+    )?;
+
+    Ok(object.width()?.value)
+}
+
+/// One bookmark of a document's outline, flattened for indexing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// Nesting depth, 1 for top-level bookmarks
+    pub level: usize,
+    pub title: String,
+    /// The display label of the target page (e.g. "iv" or "12"), its 1-based
+    /// number when the document has no label for it, or empty when the
+    /// bookmark doesn't point at a page in this document
+    pub page_label: String,
+}
+
+/// Upper bound on outline entries read, so a malformed outline whose
+/// siblings loop back on themselves can't hang the walk
+const MAX_TOC_ENTRIES: usize = 10_000;
+
+/// Flattens the document's bookmark tree, depth first, into headings with
+/// the label of the page each one points at
+pub fn extract_toc_text(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>
+) -> Result<Vec<TocEntry>, PdfError> {
+    check_size_limit(pdf_bytes, max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page_label = |bookmark: &PdfBookmark| -> String {
+        let page_index = match (bookmark.destination(), bookmark.action()) {
+            (Some(destination), _) => destination.page_index().ok(),
+            (None, Some(PdfAction::LocalDestination(action))) => {
+                action.destination().ok().and_then(|destination| destination.page_index().ok())
+            }
+            _ => None,
+        };
+        let Some(page_index) = page_index else {
+            return String::new();
+        };
+
+        match document.pages().get(page_index).ok().and_then(|page| page.label().map(str::to_string)) {
+            Some(label) => label,
+            None => (page_index as usize + 1).to_string(),
+        }
+    };
+
+    let mut entries = Vec::new();
+    let mut pending = vec![(document.bookmarks().root(), 1)];
+    while let Some((bookmark, level)) = pending.pop() {
+        let Some(bookmark) = bookmark else {
+            continue;
+        };
+        if entries.len() >= MAX_TOC_ENTRIES {
+            break;
+        }
+
+        entries.push(TocEntry {
+            level,
+            title: bookmark.title().unwrap_or_default(),
+            page_label: page_label(&bookmark),
+        });
+        // Visit the children before moving on to the next sibling
+        pending.push((bookmark.next_sibling(), level));
+        pending.push((bookmark.first_child(), level + 1));
+    }
+
+    Ok(entries)
+}
+
+/// Renders every page of each PDF file in `paths`, processing the files in
+/// parallel
+///
+/// Results come back in input order, paired with their path, so one
+/// unreadable or corrupt file doesn't fail the batch. All files share the one
+/// `pdfium`: rather than one binding per worker thread (see the note on the
+/// Python module's shared instance), reading and image encoding run
+/// concurrently while pdfium-render's `sync` feature serializes the calls
+/// into pdfium itself.
+pub fn render_pdf_files_parallel(
+    pdfium: &Pdfium,
+    paths: &[&Path],
+    quality: u8,
+    format: ImageFormat
+) -> Vec<(PathBuf, Result<Vec<PageData>, PdfError>)> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+
+    paths
+        .par_iter()
+        .map(|&path| {
+            let result = std::fs::read(path)
+                .map_err(PdfError::from)
+                .and_then(|pdf_bytes| render_pdf(pdfium, &pdf_bytes, &options));
+            (path.to_path_buf(), result)
+        })
+        .collect()
+}
+
+/// How serious an accessibility finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationSeverity {
+    /// Breaks PDF/UA conformance
+    Error,
+    /// Likely to confuse assistive technology but not a hard failure
+    Warning,
+}
+
+impl ViolationSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ViolationSeverity::Error => "error",
+            ViolationSeverity::Warning => "warning",
+        }
+    }
+}
+
+/// One PDF/UA (ISO 14289-1) problem found by `validate_pdfua`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessibilityViolation {
+    /// Identifier of the failed check: `missing-language`, `untagged-page`,
+    /// `image-missing-alt-text`, `heading-level-skipped` or `table-missing-headers`
+    pub rule: String,
+    pub page_index: Option<usize>,
+    /// Index of the offending object in the page's object list, for
+    /// object-level checks
+    pub object_index: Option<usize>,
+    pub severity: ViolationSeverity,
+}
+
+impl AccessibilityViolation {
+    fn new(rule: &str, page_index: Option<usize>, object_index: Option<usize>, severity: ViolationSeverity) -> Self {
+        Self { rule: rule.to_string(), page_index, object_index, severity }
+    }
+}
+
+/// Returns the level of an `H1`..`H6` structure type
+fn heading_level(tag: &str) -> Option<u32> {
+    match tag.strip_prefix('H')?.parse() {
+        Ok(level @ 1..=6) => Some(level),
+        _ => None,
+    }
+}
+
+/// Checks heading order and table headers over the document's whole
+/// `/StructTreeRoot`, walked once in reading order, so a table whose header
+/// row is on an earlier page, or headings continuing across pages, are
+/// judged as a whole. Violations point at the page of the offending element
+/// (its own or its nearest ancestor's `/Pg`).
+fn structure_tree_violations(document: &lopdf::Document) -> Vec<AccessibilityViolation> {
+    let page_indices: BTreeMap<lopdf::ObjectId, usize> =
+        document.get_pages().into_iter().map(|(number, id)| (id, number as usize - 1)).collect();
+    let Ok(root) = document
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"StructTreeRoot", document))
+        .and_then(lopdf::Object::as_dict)
+    else {
+        return Vec::new();
+    };
+
+    // Depth-first in reading order, carrying the element's page and which
+    // table (if any) it sits in. Tables record whether they have a header
+    // cell and the page they start on.
+    let mut violations = Vec::new();
+    let mut tables: Vec<(bool, Option<usize>)> = Vec::new();
+    let mut previous_heading = 0;
+    let mut visited = BTreeSet::new();
+    let mut pending: Vec<(&lopdf::Object, Option<usize>, Option<usize>)> = Vec::new();
+    if let Ok(kids) = root.get(b"K") {
+        pending.push((kids, None, None));
+    }
+
+    while let Some((object, page, table)) = pending.pop() {
+        let object = match object {
+            lopdf::Object::Reference(id) if visited.insert(*id) => match document.get_object(*id) {
+                Ok(object) => object,
+                Err(_) => continue,
+            },
+            lopdf::Object::Reference(_) => continue,
+            object => object,
+        };
+        let element = match object {
+            lopdf::Object::Array(kids) => {
+                pending.extend(kids.iter().rev().map(|kid| (kid, page, table)));
+                continue;
+            }
+            lopdf::Object::Dictionary(element) => element,
+            _ => continue,
+        };
+        // Marked-content and object references carry no /S
+        let Ok(tag) = element.get(b"S").and_then(lopdf::Object::as_name) else {
+            continue;
+        };
+        let tag = String::from_utf8_lossy(tag);
+        let page = element
+            .get(b"Pg")
+            .and_then(lopdf::Object::as_reference)
+            .ok()
+            .and_then(|id| page_indices.get(&id).copied())
+            .or(page);
+
+        if let Some(level) = heading_level(&tag) {
+            if level > previous_heading + 1 {
+                violations.push(AccessibilityViolation::new("heading-level-skipped", page, None, ViolationSeverity::Warning));
+            }
+            previous_heading = level;
+        }
+
+        let table = match tag.as_ref() {
+            "Table" => {
+                tables.push((false, page));
+                Some(tables.len() - 1)
+            }
+            "TH" => {
+                if let Some(table) = table {
+                    tables[table].0 = true;
+                }
+                table
+            }
+            _ => table,
+        };
+
+        if let Ok(kids) = element.get(b"K") {
+            pending.push((kids, page, table));
+        }
+    }
+
+    for &(_, page) in tables.iter().filter(|(has_header, _)| !has_header) {
+        violations.push(AccessibilityViolation::new("table-missing-headers", page, None, ViolationSeverity::Error));
+    }
+
+    violations
+}
+
+/// Checks a PDF against a practical subset of PDF/UA
+///
+/// The document must declare a language, every page must carry structure,
+/// every image must be tagged with alternate text on its element (or an
+/// ancestor) or be marked as an artifact, heading levels must not skip (H1
+/// then H3), and every `Table` element must contain at least one `TH` cell.
+/// An empty result means none of these checks failed, not that the file is
+/// fully PDF/UA conformant.
+pub fn validate_pdfua(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<AccessibilityViolation>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let bindings = pdfium.bindings();
+    let mut violations = Vec::new();
+
+    let catalog_bytes = document.save_to_bytes()?;
+    let catalog_document = lopdf::Document::load_mem(&catalog_bytes).map_err(|e| PdfError::Load(e.to_string()))?;
+    let has_language = catalog_document
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Lang").ok())
+        .and_then(|lang| lang.as_str().ok())
+        .is_some_and(|lang| !lang.iter().all(u8::is_ascii_whitespace));
+    if !has_language {
+        violations.push(AccessibilityViolation::new("missing-language", None, None, ViolationSeverity::Error));
+    }
+    violations.extend(structure_tree_violations(&catalog_document));
+
+    for (page_index, page) in document.pages().iter().enumerate() {
+        let tree = bindings.FPDF_StructTree_GetForPage(bindings.get_handle_from_page(&page));
+        if tree.is_null() || bindings.FPDF_StructTree_CountChildren(tree) <= 0 {
+            if !tree.is_null() {
+                bindings.FPDF_StructTree_Close(tree);
+            }
+            violations.push(AccessibilityViolation::new("untagged-page", Some(page_index), None, ViolationSeverity::Error));
+            continue;
+        }
+
+        // Depth-first, carrying whether an ancestor supplies alternate text
+        let mut described_mcids = std::collections::HashSet::new();
+        let mut pending = Vec::new();
+        for index in (0..bindings.FPDF_StructTree_CountChildren(tree)).rev() {
+            pending.push((bindings.FPDF_StructTree_GetChildAtIndex(tree, index), false));
+        }
+
+        while let Some((element, described)) = pending.pop() {
+            if element.is_null() {
+                continue;
+            }
+
+            let described = described
+                || read_pdfium_string(bindings, |buffer, length| {
+                    bindings.FPDF_StructElement_GetAltText(element, buffer, length)
+                })
+                .is_some();
+
+            if described {
+                for index in 0..bindings.FPDF_StructElement_GetMarkedContentIdCount(element) {
+                    described_mcids.insert(bindings.FPDF_StructElement_GetMarkedContentIdAtIndex(element, index));
+                }
+            }
+
+            for child in (0..bindings.FPDF_StructElement_CountChildren(element)).rev() {
+                pending.push((bindings.FPDF_StructElement_GetChildAtIndex(element, child), described));
+            }
+        }
+
+        bindings.FPDF_StructTree_Close(tree);
+
+        for (object_index, object) in page.objects().iter().enumerate() {
+            if object.object_type() != PdfPageObjectType::Image {
+                continue;
+            }
+
+            let handle = bindings.get_handle_from_object(&object);
+            let mcid = bindings.FPDFPageObj_GetMarkedContentID(handle);
+            // Marked content without an MCID is how artifacts are tagged
+            let is_artifact = mcid < 0 && bindings.FPDFPageObj_CountMarks(handle) > 0;
+            if !is_artifact && !described_mcids.contains(&mcid) {
+                violations.push(AccessibilityViolation::new(
+                    "image-missing-alt-text",
+                    Some(page_index),
+                    Some(object_index),
+                    ViolationSeverity::Error,
+                ));
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Encodes `frames` as a looping animated WebP, showing each for
+/// `frame_duration_ms`. Frames smaller than the largest one are centred on a
+/// white canvas, since every frame of an animation shares one canvas size.
+fn encode_animated_webp(frames: &[RgbImage], quality: u8, frame_duration_ms: u32) -> Result<Vec<u8>, PdfError> {
+    let width = frames.iter().map(|frame| frame.width()).max().unwrap_or(0);
+    let height = frames.iter().map(|frame| frame.height()).max().unwrap_or(0);
+    if width == 0 || height == 0 {
+        return Err(PdfError::InvalidArgument("PDF has no pages to animate".to_string()));
+    }
+
+    let canvases: Vec<RgbImage> = frames
+        .iter()
+        .map(|frame| {
+            let mut canvas = RgbImage::from_pixel(width, height, image::Rgb([255, 255, 255]));
+            let x = (width - frame.width()) / 2;
+            let y = (height - frame.height()) / 2;
+            image::imageops::replace(&mut canvas, frame, x as i64, y as i64);
+            canvas
+        })
+        .collect();
+
+    let mut config = webp::WebPConfig::new().map_err(|_| PdfError::Encode("WebP: invalid encoder config".to_string()))?;
+    config.quality = quality as f32;
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    encoder.set_bgcolor([255, 255, 255, 255]);
+    encoder.set_loop_count(0);
+    for (index, canvas) in canvases.iter().enumerate() {
+        let timestamp = (index as u64 * frame_duration_ms as u64).min(i32::MAX as u64) as i32;
+        encoder.add_frame(webp::AnimFrame::from_rgb(canvas.as_raw(), width, height, timestamp));
+    }
+
+    let encoded = encoder
+        .try_encode()
+        .map_err(|e| PdfError::Encode(format!("Animated WebP: {:?}", e)))?;
+
+    Ok(encoded.to_vec())
+}
+
+/// Renders every page of a PDF and combines them into a looping animated
+/// WebP that shows each page for `frame_duration_ms`
+///
+/// libwebp writes a single-page PDF as a still WebP without an animation chunk.
+pub fn render_pdf_as_animated_webp(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    quality: u8,
+    frame_duration_ms: u32,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(ImageFormat::Webp);
+    options.validate()?;
+    if frame_duration_ms == 0 {
+        return Err(PdfError::InvalidArgument("frame_duration_ms must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let frames = document
+        .pages()
+        .iter()
+        .map(|page| render_page_rgb(&page, &options))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    encode_animated_webp(&frames, quality, frame_duration_ms)
+}
+
+/// Renders every page of a PDF into one GIF: a still image for a single-page
+/// PDF, otherwise a looping animation showing each page for `frame_delay_ms`.
+/// Pages are rendered at pdfium's default 72 DPI.
+pub fn render_pdf_as_gif(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    frame_delay_ms: u16,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let frames = document
+        .pages()
+        .iter()
+        .map(|page| render_page_rgb(&page, &RenderOptions::default()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if frames.is_empty() {
+        return Err(PdfError::InvalidArgument("PDF has no pages to render".to_string()));
+    }
+
+    encode_gif(&frames, frame_delay_ms)
+}
+
+/// Where on the page `add_page_numbers` places the number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageNumberPosition {
+    #[default]
+    BottomCenter,
+    BottomRight,
+    TopCenter,
+    TopRight,
+}
+
+impl FromStr for PageNumberPosition {
+    type Err = PdfError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bottom_center" => Ok(PageNumberPosition::BottomCenter),
+            "bottom_right" => Ok(PageNumberPosition::BottomRight),
+            "top_center" => Ok(PageNumberPosition::TopCenter),
+            "top_right" => Ok(PageNumberPosition::TopRight),
+            _ => Err(PdfError::InvalidArgument(format!(
+                "Position must be bottom_center, bottom_right, top_center, or top_right, got {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Distance between a page number and the nearest page edges, in points
+const PAGE_NUMBER_MARGIN: f32 = 36.0;
+
+/// Stamps a visible page number in Helvetica onto every page, counting up
+/// from `start_number` on the first page
+pub fn add_page_numbers(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    start_number: usize,
+    position: PageNumberPosition,
+    font_size: f32,
+    color: [u8; 3],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    if font_size <= 0.0 {
+        return Err(PdfError::InvalidArgument("Font size must be greater than 0".to_string()));
+    }
+
+    let mut document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let font = document.fonts_mut().helvetica();
+
+    for (index, mut page) in document.pages().iter().enumerate() {
+        let width = page.width().value;
+        let height = page.height().value;
+
+        let mut number = page.objects_mut().create_text_object(
+            PdfPoints::ZERO,
+            PdfPoints::ZERO,
+            (start_number + index).to_string(),
+            font,
+            PdfPoints::new(font_size),
+        )?;
+        number.set_fill_color(PdfColor::new(color[0], color[1], color[2], 255))?;
+
+        let number_width = number.width()?.value;
+        let x = match position {
+            PageNumberPosition::BottomCenter | PageNumberPosition::TopCenter => (width - number_width) / 2.0,
+            PageNumberPosition::BottomRight | PageNumberPosition::TopRight => width - PAGE_NUMBER_MARGIN - number_width,
+        };
+        let y = match position {
+            PageNumberPosition::BottomCenter | PageNumberPosition::BottomRight => PAGE_NUMBER_MARGIN,
+            PageNumberPosition::TopCenter | PageNumberPosition::TopRight => height - PAGE_NUMBER_MARGIN - font_size,
+        };
+        number.translate(PdfPoints::new(x), PdfPoints::new(y))?;
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Counts the image objects in `object`, descending into form XObjects
+fn count_image_objects(object: &PdfPageObject) -> usize {
+    match object.as_x_object_form_object() {
+        Some(form) => (0..form.len())
+            .filter_map(|index| form.get(index).ok())
+            .map(|child| count_image_objects(&child))
+            .sum(),
+        None => (object.object_type() == PdfPageObjectType::Image) as usize,
+    }
+}
+
+/// Counts the image objects on each page, including those nested in form
+/// XObjects, without decoding any pixel data
+pub fn get_page_image_count(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>
+) -> Result<Vec<usize>, PdfError> {
+    check_size_limit(pdf_bytes, max_pdf_bytes)?;
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    Ok(document
+        .pages()
+        .iter()
+        .map(|page| page.objects().iter().map(|object| count_image_objects(&object)).sum())
+        .collect())
+}
+
+/// Appends every page of `appendix_pdf` to the end of `base_pdf`
+///
+/// An empty `base_pdf` is treated as a document with no pages, so the
+/// result is a copy of `appendix_pdf`.
+pub fn append_pdf(
+    pdfium: &Pdfium,
+    base_pdf: &[u8],
+    appendix_pdf: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let appendix = load_document(pdfium, appendix_pdf)?;
+    check_page_limit(&appendix, max_pages)?;
+    if base_pdf.is_empty() {
+        return Ok(appendix_pdf.to_vec());
+    }
+
+    let mut base = load_document(pdfium, base_pdf)?;
+    check_page_limit(&base, max_pages)?;
+    base.pages_mut().append(&appendix)?;
+
+    Ok(base.save_to_bytes()?)
+}
+
+/// Turns every page for which `should_rotate(width, height)` holds a further
+/// 90 degrees clockwise, on top of any rotation it already has. Sizes are as
+/// displayed, i.e. after the existing rotation.
+fn rotate_pages_where(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    should_rotate: impl Fn(f32, f32) -> bool,
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    for mut page in document.pages().iter() {
+        if !should_rotate(page.width().value, page.height().value) {
+            continue;
+        }
+
+        let rotation = match page.rotation()? {
+            PdfPageRenderRotation::None => PdfPageRenderRotation::Degrees90,
+            PdfPageRenderRotation::Degrees90 => PdfPageRenderRotation::Degrees180,
+            PdfPageRenderRotation::Degrees180 => PdfPageRenderRotation::Degrees270,
+            PdfPageRenderRotation::Degrees270 => PdfPageRenderRotation::None,
+        };
+        page.set_rotation(rotation);
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+/// Rotates every portrait page 90 degrees clockwise so all pages display as
+/// landscape; landscape and square pages are left untouched
+pub fn rotate_to_landscape(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    rotate_pages_where(pdfium, pdf_bytes, |width, height| width < height, max_pages)
+}
+
+/// Rotates every landscape page 90 degrees clockwise so all pages display as
+/// portrait; portrait and square pages are left untouched
+pub fn rotate_to_portrait(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<u8>, PdfError> {
+    rotate_pages_where(pdfium, pdf_bytes, |width, height| width > height, max_pages)
+}
+
+/// A SHA-256 hex digest of what a PDF shows rather than how it is stored:
+/// each page's text, the sorted names of the fonts used and the decoded
+/// pixels of every image. Metadata edits and re-saves leave it unchanged.
+pub fn hash_pdf_content(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<String, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let bindings = pdfium.bindings();
+
+    let mut hasher = Sha256::new();
+    let mut fonts = BTreeSet::new();
+    for page in document.pages().iter() {
+        // Length prefixes keep "ab" + "c" apart from "a" + "bc"
+        let text = page.text()?.all();
+        hasher.update((text.len() as u64).to_le_bytes());
+        hasher.update(text.as_bytes());
+
+        for object in page.objects().iter() {
+            if object.as_text_object().is_some() {
+                fonts.insert(base_font_name(bindings, &object));
+            }
+            let mut images = Vec::new();
+            collect_images(&object, &mut images)?;
+            for image in images {
+                let pixels = image.to_rgba8();
+                hasher.update(pixels.width().to_le_bytes());
+                hasher.update(pixels.height().to_le_bytes());
+                hasher.update(Sha256::digest(pixels.as_raw()));
+            }
+        }
+    }
+    for font in fonts {
+        hasher.update((font.len() as u64).to_le_bytes());
+        hasher.update(font.as_bytes());
+    }
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Resolution `extract_color_palette` renders at
+const PALETTE_DPI: u32 = 72;
+
+/// Side of the square the render is downsampled to before clustering
+const PALETTE_SAMPLE_SIZE: u32 = 100;
+
+/// Clusters `pixels` by median cut into at most `n_colors` boxes and returns
+/// the mean color of each, most populous first
+fn median_cut(pixels: Vec<[u8; 3]>, n_colors: usize) -> Vec<[u8; 3]> {
+    // The channel with the widest spread in a box, and that spread
+    let widest_channel = |pixels: &[[u8; 3]]| -> (u8, usize) {
+        (0..3)
+            .map(|channel| {
+                let values = pixels.iter().map(|pixel| pixel[channel]);
+                (values.clone().max().unwrap_or(0) - values.min().unwrap_or(0), channel)
+            })
+            .max()
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < n_colors {
+        let (index, (spread, channel)) = boxes
+            .iter()
+            .map(|pixels| widest_channel(pixels))
+            .enumerate()
+            .max_by_key(|(_, (spread, _))| *spread)
+            .expect("There is always at least one box");
+        if spread == 0 {
+            break;
+        }
+
+        // Cut at the median value rather than the median index so that
+        // identical colors never end up in two boxes
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let median = pixels[pixels.len() / 2][channel];
+        let mut cut = pixels.partition_point(|pixel| pixel[channel] < median);
+        if cut == 0 {
+            cut = pixels.partition_point(|pixel| pixel[channel] <= median);
+        }
+        let upper = pixels.split_off(cut);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|pixels| std::cmp::Reverse(pixels.len()));
+    boxes
+        .iter()
+        .filter(|pixels| !pixels.is_empty())
+        .map(|pixels| {
+            let mut sums = [0u64; 3];
+            for pixel in pixels {
+                for channel in 0..3 {
+                    sums[channel] += pixel[channel] as u64;
+                }
+            }
+            sums.map(|sum| (sum / pixels.len() as u64) as u8)
+        })
+        .collect()
+}
+
+/// The `n_colors` most dominant colors of a page, most dominant first
+///
+/// The page is rendered at 72 DPI and downsampled to 100x100 pixels, then
+/// clustered by median cut. Pages with fewer distinct colors return fewer.
+pub fn extract_color_palette(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    n_colors: usize,
+    max_pages: Option<usize>
+) -> Result<Vec<[u8; 3]>, PdfError> {
+    if n_colors == 0 {
+        return Err(PdfError::InvalidArgument("n_colors must be at least 1".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let image = render_page_rgb(&page, &RenderOptions::default().with_dpi(PALETTE_DPI))?;
+    let sample = image::imageops::resize(
+        &image,
+        PALETTE_SAMPLE_SIZE,
+        PALETTE_SAMPLE_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+
+    Ok(median_cut(sample.pixels().map(|pixel| pixel.0).collect(), n_colors))
+}
+
+/// The visible image in a signature field's appearance, e.g. a scanned
+/// handwritten signature
+#[derive(Debug, Clone)]
+pub struct SignatureImage {
+    pub page_index: usize,
+    /// The fully qualified name of the signature field; empty if it has none
+    pub field_name: String,
+    /// The image, encoded as PNG
+    pub image_buffer: Vec<u8>,
+}
+
+/// Decodes every image in `object` into `images`, descending into form XObjects
+fn collect_images(object: &PdfPageObject, images: &mut Vec<DynamicImage>) -> Result<(), PdfError> {
+    if let Some(form) = object.as_x_object_form_object() {
+        for child in form.iter() {
+            collect_images(&child, images)?;
+        }
+    } else if let Some(image) = object.as_image_object() {
+        images.push(image.get_raw_image()?);
+    }
+    Ok(())
+}
+
+/// Extracts the images drawn by the normal appearance of each signature
+/// field's widget, in page order. Signatures without a visible image, such
+/// as purely cryptographic or typed ones, contribute nothing.
+pub fn extract_signature_images(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    max_pages: Option<usize>
+) -> Result<Vec<SignatureImage>, PdfError> {
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+
+    let mut signature_images = Vec::new();
+    for (page_index, page) in document.pages().iter().enumerate() {
+        for annotation in page.annotations().iter() {
+            let Some(field) = annotation.as_form_field().and_then(PdfFormField::as_signature_field) else {
+                continue;
+            };
+            let field_name = field.name().unwrap_or_default();
+
+            let mut images = Vec::new();
+            for object in annotation.objects().iter() {
+                collect_images(&object, &mut images)?;
+            }
+            for image in images {
+                let mut buffer = Cursor::new(Vec::new());
+                image
+                    .write_to(&mut buffer, image::ImageFormat::Png)
+                    .map_err(|e| PdfError::Encode(format!("PNG: {}", e)))?;
+                signature_images.push(SignatureImage {
+                    page_index,
+                    field_name: field_name.clone(),
+                    image_buffer: buffer.into_inner(),
+                });
+            }
+        }
+    }
+
+    Ok(signature_images)
+}
+
+/// Renders every page scaled to `target_width` pixels wide and stacks them
+/// top to bottom in one tall image, like a continuous scroll view, with
+/// `gap_pixels` of white between consecutive pages
+pub fn stitch_pages_vertically(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    target_width: u32,
+    quality: u8,
+    format: ImageFormat,
+    gap_pixels: u32,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_quality(quality).with_format(format);
+    options.validate()?;
+    if target_width == 0 {
+        return Err(PdfError::InvalidArgument("Target width must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let config = render_config(&options).set_target_width(target_width as Pixels);
+    let pages = document
+        .pages()
+        .iter()
+        .map(|page| render_page_rgb_with_config(&page, &config, &options))
+        .collect::<Result<Vec<RgbImage>, PdfError>>()?;
+    if pages.is_empty() {
+        return Err(PdfError::InvalidArgument("Document has no pages to render".to_string()));
+    }
+
+    let gaps = gap_pixels as u64 * (pages.len() as u64 - 1);
+    let height = pages.iter().map(|page| page.height() as u64).sum::<u64>() + gaps;
+    let height = u32::try_from(height)
+        .map_err(|_| PdfError::InvalidArgument("Stitched image would be too tall".to_string()))?;
+
+    // Landscape pages are rotated like everywhere else, so a page can come
+    // back narrower than the target width but never wider
+    let width = pages.iter().map(|page| page.width()).max().unwrap_or(target_width);
+    let mut strip = RgbImage::from_pixel(width, height, image::Rgb([255, 255, 255]));
+    let mut y = 0;
+    for page in &pages {
+        image::imageops::replace(&mut strip, page, 0, y);
+        y += page.height() as i64 + gap_pixels as i64;
+    }
+
+    Ok(PageData {
+        image_buffer: encode_page(&strip, &options)?,
+        error: None,
+    })
+}
+
+/// Applies `contrast` (a multiplier around mid-grey 128) and then
+/// `brightness` (an offset) to every channel, clamping to 0-255
+fn clamp_image_colors(image: &mut RgbImage, brightness: f32, contrast: f32) {
+    let lookup: Vec<u8> = (0..=255u8)
+        .map(|value| ((value as f32 - 128.0) * contrast + 128.0 + brightness).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    for value in image.iter_mut() {
+        *value = lookup[*value as usize];
+    }
+}
+
+/// Renders a page at `dpi` as WebP with brightness and contrast adjusted,
+/// for scans that come out too dark or too light. `brightness` is added to
+/// each channel and `contrast` scales each channel's distance from 128, so
+/// 0.0 and 1.0 leave the page unchanged.
+#[allow(clippy::too_many_arguments)]
+pub fn render_page_with_color_adjustment(
+    pdfium: &Pdfium,
+    pdf_bytes: &[u8],
+    page_index: usize,
+    brightness: f32,
+    contrast: f32,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>
+) -> Result<PageData, PdfError> {
+    let options = RenderOptions::default().with_dpi(dpi).with_quality(quality);
+    options.validate()?;
+    if !brightness.is_finite() {
+        return Err(PdfError::InvalidArgument("Brightness must be a finite number".to_string()));
+    }
+    if !(contrast.is_finite() && contrast > 0.0) {
+        return Err(PdfError::InvalidArgument("Contrast must be greater than 0".to_string()));
+    }
+
+    let document = load_document(pdfium, pdf_bytes)?;
+    check_page_limit(&document, max_pages)?;
+    let page = page_at(&document, page_index)?;
+    let mut image = render_page_rgb(&page, &options)?;
+    clamp_image_colors(&mut image, brightness, contrast);
+
+    Ok(PageData {
+        image_buffer: encode_page(&image, &options)?,
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+    use super::*;
+
+    /// One binding shared by every test, as in lib.rs. Dropping a `Pdfium`
+    /// calls the process-wide `FPDF_DestroyLibrary`, which would tear pdfium
+    /// down under tests still rendering on other threads.
+    fn load_pdfium() -> &'static Pdfium {
+        static PDFIUM: OnceLock<Pdfium> = OnceLock::new();
+        PDFIUM.get_or_init(|| {
+            Pdfium::new(
+                Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+                    .or_else(|_| Pdfium::bind_to_system_library())
+                    .expect("Failed to bind to Pdfium library")
+            )
+        })
+    }
+
+    /// Assembles a PDF from numbered object bodies (object 1 must be the catalog),
+    /// computing the xref offsets so pdfium loads it without repair
+    fn build_pdf(objects: &[String]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.7\n".to_vec();
+        let mut offsets = Vec::new();
+
+        for (index, body) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", index + 1, body).as_bytes());
+        }
+
+        let xref_offset = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        ).as_bytes());
+
+        pdf
+    }
+
+    fn stream_object(dictionary: &str, data: &str) -> String {
+        format!("<< {} /Length {} >>\nstream\n{}\nendstream", dictionary, data.len(), data)
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_render_base64_pdf() {
+        let pdfium = load_pdfium();
+        let test_pdf_path = "./samples/test.pdf";
+        let pdf_bytes = std::fs::read(test_pdf_path)
+            .expect("Failed to read test PDF file");
+
+        let pages = render_base64_pdf(pdfium, &pdf_bytes, 75, &RenderOptions::default())
+            .expect("Failed to render PDF");
+        assert_eq!(pages.len(), 5);
+        for page in &pages {
+            assert!(page.error.is_none());
+            assert!(page.image_buffer.starts_with(b"RIFF"));
+        }
+    }
+
+    #[test]
+    fn test_compress_pdf() {
+        let test_pdf_path = "./samples/test.pdf";
+        let pdf_bytes = std::fs::read(test_pdf_path)
+            .expect("Failed to read test PDF file");
+        let base64_pdf = BASE64.encode(&pdf_bytes);
+
+        let compressed_base64 = compress_pdf(&base64_pdf, 75).expect("Failed to compress PDF");
+        let compressed_bytes = BASE64.decode(&compressed_base64)
+            .expect("Failed to decode compressed PDF");
+        assert!(compressed_bytes.len() < pdf_bytes.len(), "{} bytes compressed to {}", pdf_bytes.len(), compressed_bytes.len());
+
+        let document = lopdf::Document::load_mem(&compressed_bytes).expect("Failed to load compressed PDF");
+        assert_eq!(document.get_pages().len(), 5);
+    }
+
+    #[test]
+    fn test_pdf_to_markdown() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        let markdown = pdf_to_markdown(pdfium, &pdf_bytes, None)
+            .expect("Failed to convert PDF to Markdown");
+
+        assert!(markdown.contains("## "), "Expected at least one heading in:\n{}", markdown);
+    }
+
+    #[test]
+    fn test_pdf_to_text() {
+        let pdfium = load_pdfium();
+        let font = "/Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Courier >> >> >>";
+        let content = "BT /F1 10 Tf 72 700 Td (Name) Tj 150 0 Td (Value) Tj ET \
+            BT /F1 10 Tf 72 688 Td (Alpha) Tj 150 0 Td (Beta) Tj ET \
+            BT /F1 10 Tf 72 640 Td (Total:    3) Tj ET";
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] {} /Contents 4 0 R >>", font),
+            stream_object("", content),
+        ]);
+
+        let text = pdf_to_text(pdfium, &pdf_bytes, true, None).expect("Failed to extract text");
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("Name ") && lines[1].starts_with("Alpha "), "{:?}", lines);
+        assert_eq!(lines[0].find("Value"), lines[1].find("Beta"));
+        assert!(lines[0].find("Value").unwrap() > "Alpha".len() + 1);
+        // The 48pt drop to the last line leaves blank lines in between
+        assert!(lines.len() > 3 && lines[2].is_empty());
+        assert!(lines.last().unwrap().starts_with("Total:"));
+
+        let compact = pdf_to_text(pdfium, &pdf_bytes, false, None).expect("Failed to extract text");
+        assert!(compact.contains("Total: 3"));
+        assert!(!compact.contains("  ") && !compact.contains("\n\n"));
+    }
+
+    #[test]
+    fn test_page_word_count() {
+        let pdfium = load_pdfium();
+        let font = "/Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >>";
+        let page = |contents: u32| format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] {} /Contents {} 0 R >>", font, contents
+        );
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_string(),
+            page(6),
+            page(7),
+            page(8),
+            stream_object("", "BT /F1 12 Tf 72 700 Td (The quick brown fox) Tj ET"),
+            stream_object("", ""),
+            stream_object("", "BT /F1 12 Tf 72 700 Td (Hello world) Tj ET BT /F1 12 Tf 72 650 Td (again  and again) Tj ET"),
+        ]);
+
+        let counts = page_word_count(pdfium, &pdf_bytes, None)
+            .expect("Failed to count words");
+        assert_eq!(counts, vec![4, 0, 5]);
+
+        let stats = document_stats(pdfium, &pdf_bytes, None, None)
+            .expect("Failed to collect document stats");
+        assert_eq!(stats.page_count, 3);
+        assert_eq!(stats.total_words, 9);
+        assert_eq!(stats.total_chars, "Thequickbrownfox".len() + "Helloworldagainandagain".len());
+        assert!((stats.avg_words_per_page - 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_render_pdf_to_cbz() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        // The format is always JPEG, whatever the options say
+        let options = RenderOptions::default().with_quality(85).with_format(ImageFormat::Png).with_grayscale(true);
+        let cbz = render_pdf_to_cbz_with_options(pdfium, &pdf_bytes, &options)
+            .expect("Failed to render CBZ");
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(cbz))
+            .expect("CBZ should be a valid ZIP archive");
+        assert_eq!(archive.len(), 5);
+
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).expect("Failed to read CBZ entry");
+            assert_eq!(entry.name(), format!("{:04}.jpg", index + 1));
+
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut data).expect("Failed to read CBZ entry");
+            assert_eq!(&data[..3], &[0xFF, 0xD8, 0xFF], "Entry should be a JPEG");
+            let image = image::load_from_memory(&data).expect("Failed to decode CBZ entry").into_rgb8();
+            assert!(image.pixels().all(|pixel| pixel[0].abs_diff(pixel[1]) < 8 && pixel[1].abs_diff(pixel[2]) < 8));
+        }
+
+        assert!(render_pdf_to_cbz_with_options(pdfium, &pdf_bytes, &RenderOptions::default().with_quality(0)).is_err());
+    }
+
+    #[test]
+    fn test_apply_srgb_icc_profile() {
+        let baseline = image::open("./samples/part_482.jpg")
+            .expect("Failed to read test image")
+            .into_rgb8();
+        let srgb = Profile::new_srgb().icc().expect("Failed to serialize sRGB profile");
+
+        let mut converted = baseline.clone();
+        apply_icc_profile(&mut converted, &srgb).expect("Failed to apply ICC profile");
+
+        for (a, b) in baseline.as_raw().iter().zip(converted.as_raw()) {
+            assert!(a.abs_diff(*b) <= 2, "Pixel drifted from {} to {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_simulate_overprint() {
+        let pdfium = load_pdfium();
+
+        // A cyan square with an overprinting magenta square on top: with
+        // overprint the intersection mixes to blue, without it magenta knocks out
+        let content = "0 0 1 0 rg 1 0 0 0 k 20 20 120 120 re f /GS1 gs 0 1 0 0 k 80 80 120 120 re f";
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 220 220] /Contents 4 0 R \
+             /Resources << /ExtGState << /GS1 << /Type /ExtGState /OP true /op true /OPM 1 >> >> >> >>".to_string(),
+            stream_object("", content),
+        ]);
+
+        let render = |simulate_overprint| {
+            let options = RenderOptions::default()
+                .with_quality(100)
+                .with_simulate_overprint(simulate_overprint);
+            let pages = render_pdf(pdfium, &pdf_bytes, &options)
+                .expect("Failed to render overprint PDF");
+            image::load_from_memory(&pages[0].image_buffer)
+                .expect("Failed to decode rendered page")
+                .into_rgb8()
+        };
+
+        let knockout = render(false);
+        let overprint = render(true);
+        assert_eq!(knockout.dimensions(), overprint.dimensions());
+        assert_ne!(knockout.as_raw(), overprint.as_raw());
+    }
+
+    #[test]
+    fn test_render_pdf_to_png() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        let pages = render_pdf_to_png_with_options(pdfium, &pdf_bytes, &RenderOptions::default().with_dpi(144))
+            .expect("Failed to render PNG pages");
+        assert_eq!(pages.len(), 5);
+        let default_size = image::load_from_memory(&render_pdf_to_png_with_options(pdfium, &pdf_bytes, &RenderOptions::default())
+            .expect("Failed to render PNG pages")[0].image_buffer).unwrap().width();
+        assert!(image::load_from_memory(&pages[0].image_buffer).unwrap().width().abs_diff(default_size * 2) <= 1);
+
+        // Color conversion happens on the regular path only
+        let adobe = RenderOptions::default().with_color_space(OutputColorSpace::AdobeRgb);
+        assert!(render_pdf_to_png_with_options(pdfium, &pdf_bytes, &adobe).is_err());
+
+        for page in pages {
+            assert_eq!(&page.image_buffer[..8], b"\x89PNG\r\n\x1a\n");
+            let image = image::load_from_memory(&page.image_buffer)
+                .expect("Failed to decode rendered PNG");
+            assert!(image.width() > 0 && image.height() > 0);
+        }
+    }
+
+    #[test]
+    fn test_extract_structure_tree() {
+        let pdfium = load_pdfium();
+
+        let content = "/H1 <</MCID 0>> BDC BT /F1 24 Tf 72 700 Td (Title) Tj ET EMC \
+                       /Figure <</MCID 1>> BDC 0 0 1 rg 72 500 100 100 re f EMC";
+        let tagged = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /StructTreeRoot 5 0 R /MarkInfo << /Marked true >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /StructParents 0 \
+             /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >> >>".to_string(),
+            stream_object("", content),
+            "<< /Type /StructTreeRoot /K [6 0 R] /ParentTree 9 0 R >>".to_string(),
+            "<< /Type /StructElem /S /Document /P 5 0 R /K [7 0 R 8 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /H1 /P 6 0 R /Pg 3 0 R /K 0 /ActualText (Title) >>".to_string(),
+            "<< /Type /StructElem /S /Figure /P 6 0 R /Pg 3 0 R /K 1 /Alt (Blue square) >>".to_string(),
+            "<< /Nums [0 [7 0 R 8 0 R]] >>".to_string(),
+        ]);
+
+        let root = extract_structure_tree(pdfium, &tagged, None)
+            .expect("Failed to extract structure tree");
+        assert_eq!(root.children.len(), 1);
+
+        let document = &root.children[0];
+        assert_eq!(document.tag, "Document");
+        let tags: Vec<&str> = document.children.iter().map(|child| child.tag.as_str()).collect();
+        assert_eq!(tags, ["H1", "Figure"]);
+        assert_eq!(document.children[0].actual_text.as_deref(), Some("Title"));
+        assert_eq!(document.children[1].alt_text.as_deref(), Some("Blue square"));
+
+        // A section running over two pages is still a single element
+        let spanning = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /StructTreeRoot 6 0 R /MarkInfo << /Marked true >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 5 0 R /StructParents 0 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 5 0 R /StructParents 1 >>".to_string(),
+            stream_object("", "/P <</MCID 0>> BDC 0 0 1 rg 72 500 100 100 re f EMC"),
+            "<< /Type /StructTreeRoot /K 7 0 R /ParentTree 10 0 R >>".to_string(),
+            "<< /Type /StructElem /S /Sect /P 6 0 R /K [8 0 R 9 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /P /P 7 0 R /Pg 3 0 R /K 0 >>".to_string(),
+            "<< /Type /StructElem /S /P /P 7 0 R /Pg 4 0 R /K 0 >>".to_string(),
+            "<< /Nums [0 [8 0 R] 1 [9 0 R]] >>".to_string(),
+        ]);
+        let root = extract_structure_tree(pdfium, &spanning, None)
+            .expect("Failed to extract structure tree");
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].tag, "Sect");
+        let tags: Vec<&str> = root.children[0].children.iter().map(|child| child.tag.as_str()).collect();
+        assert_eq!(tags, ["P", "P"]);
+
+        let untagged = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>".to_string(),
+        ]);
+        let root = extract_structure_tree(pdfium, &untagged, None)
+            .expect("Failed to extract structure tree");
+        assert!(root.children.is_empty());
+    }
+
+    #[test]
+    fn test_is_scanned_pdf() {
+        let pdfium = load_pdfium();
+
+        let text_pdf = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        assert!(!is_scanned_pdf(pdfium, &text_pdf, 50, None).expect("Failed to inspect PDF"));
+
+        // A page that only paints graphics has no text layer, like a scan
+        let scan_pdf = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0.5 g 36 36 540 720 re f"),
+        ]);
+        assert!(is_scanned_pdf(pdfium, &scan_pdf, 50, None).expect("Failed to inspect PDF"));
+    }
+
+    #[test]
+    fn test_render_pdf_pages_to_dir() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        let output_dir = Path::new("./target/test_render_pages_to_dir");
+        let _ = std::fs::remove_dir_all(output_dir);
+
+        let options = RenderOptions::default().with_format(ImageFormat::Jpeg);
+        let paths = render_pdf_pages_to_dir_with_options(pdfium, &pdf_bytes, output_dir, &options)
+            .expect("Failed to render pages to directory");
+
+        assert_eq!(paths.len(), 5);
+        assert_eq!(paths[0], output_dir.join("page_0001.jpg"));
+        assert_eq!(std::fs::read_dir(output_dir).expect("Output directory missing").count(), 5);
+    }
+
+    #[test]
+    fn test_image_format_from_str() {
+        assert_eq!("webp".parse::<ImageFormat>().unwrap(), ImageFormat::Webp);
+        assert_eq!("JPG".parse::<ImageFormat>().unwrap(), ImageFormat::Jpeg);
+        assert_eq!("PNG".parse::<ImageFormat>().unwrap(), ImageFormat::Png);
+        assert!("TIFF".parse::<ImageFormat>().is_err());
+    }
+
+    #[cfg(feature = "heic")]
+    #[test]
+    fn test_encode_heic_round_trip() {
+        use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+        assert_eq!("heic".parse::<ImageFormat>().unwrap(), ImageFormat::Heic);
+
+        let image = RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+        let encoded = encode_image(&image, ImageFormat::Heic, 90).expect("Failed to encode HEIC");
+
+        let context = HeifContext::read_from_bytes(&encoded).expect("Failed to read HEIC");
+        let handle = context.primary_image_handle().unwrap();
+        let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None).unwrap();
+        let plane = decoded.planes().interleaved.unwrap();
+        assert_eq!((plane.width, plane.height), (64, 64));
+
+        let mut total_difference = 0u64;
+        for (y, row) in plane.data.chunks(plane.stride).take(64).enumerate() {
+            for x in 0..64 {
+                for channel in 0..3 {
+                    let original = image.get_pixel(x as u32, y as u32)[channel];
+                    total_difference += original.abs_diff(row[x * 3 + channel]) as u64;
+                }
+            }
+        }
+        // Lossy HEVC at quality 90 should stay within a few levels per channel
+        assert!(total_difference / (64 * 64 * 3) <= 4);
+    }
+
+    #[cfg(feature = "jpeg2000")]
+    #[test]
+    fn test_encode_jpeg2000() {
+        assert_eq!("jp2".parse::<ImageFormat>().unwrap(), ImageFormat::Jpeg2000);
+
+        let image = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, 128]));
+        for quality in [50, 100] {
+            let encoded = encode_image(&image, ImageFormat::Jpeg2000, quality).expect("Failed to encode JPEG 2000");
+            assert!(encoded.starts_with(&[0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50, 0x20, 0x20, 0x0D, 0x0A, 0x87, 0x0A]));
+        }
+    }
+
+    /// Builds a single-page PDF with one signed signature field. The signature
+    /// value is a dummy blob, but the byte range points at its real location;
+    /// `sign_pdf` replaces the blob with a real signature.
+    fn signed_pdf(signer: &str) -> Vec<u8> {
+        let placeholder = "/ByteRange [0000000000 0000000000 0000000000 0000000000]";
+        let contents = format!("<{}>", "AB".repeat(1024));
+
+        let mut pdf = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R] /SigFlags 3 >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Annots [4 0 R] >>".to_string(),
+            "<< /Type /Annot /Subtype /Widget /FT /Sig /T (Signature1) /Rect [0 0 0 0] /P 3 0 R /V 5 0 R >>".to_string(),
+            format!(
+                "<< /Type /Sig /Filter /Adobe.PPKLite /SubFilter /adbe.pkcs7.detached /Name ({}) {} /Contents {} >>",
+                signer, placeholder, contents
+            ),
+        ]);
+
+        let text = String::from_utf8_lossy(&pdf).into_owned();
+        let gap_start = text.find(&contents).unwrap();
+        let gap_end = gap_start + contents.len();
+        let byte_range = format!(
+            "/ByteRange [{:010} {:010} {:010} {:010}]",
+            0, gap_start, gap_end, pdf.len() - gap_end
+        );
+        let at = text.find(placeholder).unwrap();
+        pdf[at..at + placeholder.len()].copy_from_slice(byte_range.as_bytes());
+
+        pdf
+    }
+
+    #[test]
+    fn test_digital_signatures() {
+        let pdfium = load_pdfium();
+
+        let signed = signed_pdf("Jane Doe");
+        assert!(has_digital_signature(pdfium, &signed, None).expect("Failed to inspect signatures"));
+        assert_eq!(count_digital_signatures(pdfium, &signed, None).expect("Failed to count signatures"), 1);
+        assert_eq!(list_signature_signers(pdfium, &signed, None).expect("Failed to list signers"), ["Jane Doe"]);
+
+        let unsigned = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        assert!(!has_digital_signature(pdfium, &unsigned, None).expect("Failed to inspect signatures"));
+    }
+
+    const OID_ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+    const OID_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.1");
+
+    /// A P-256 key derived from `seed` and a self-signed certificate for it
+    fn test_signer(seed: u8) -> (p256::ecdsa::SigningKey, Certificate) {
+        use cms::cert::x509::der::asn1::{BitString, UtcTime};
+        use cms::cert::x509::name::Name;
+        use cms::cert::x509::serial_number::SerialNumber;
+        use cms::cert::x509::spki::{EncodePublicKey, SubjectPublicKeyInfoOwned};
+        use cms::cert::x509::time::{Time, Validity};
+        use cms::cert::x509::{TbsCertificate, Version};
+        use p256::ecdsa::signature::Signer;
+
+        let key = p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap();
+        let name = Name::from_str("CN=Jane Doe").unwrap();
+        let time = |secs| Time::UtcTime(UtcTime::from_unix_duration(Duration::from_secs(secs)).unwrap());
+        let public_key = key.verifying_key().to_public_key_der().unwrap();
+        let algorithm = AlgorithmIdentifierOwned { oid: OID_ECDSA_WITH_SHA256, parameters: None };
+
+        let tbs_certificate = TbsCertificate {
+            version: Version::V3,
+            serial_number: SerialNumber::new(&[seed]).unwrap(),
+            signature: algorithm.clone(),
+            issuer: name.clone(),
+            validity: Validity { not_before: time(1_700_000_000), not_after: time(2_000_000_000) },
+            subject: name,
+            subject_public_key_info: SubjectPublicKeyInfoOwned::from_der(public_key.as_bytes()).unwrap(),
+            issuer_unique_id: None,
+            subject_unique_id: None,
+            extensions: None,
+        };
+        let signature: p256::ecdsa::Signature = key.sign(&tbs_certificate.to_der().unwrap());
+        let certificate = Certificate {
+            tbs_certificate,
+            signature_algorithm: algorithm,
+            signature: BitString::from_bytes(signature.to_der().as_bytes()).unwrap(),
+        };
+
+        (key, certificate)
+    }
+
+    /// A DER CMS SignedData over `parts`, embedding `certificate` but signed
+    /// with `key`, which only verifies when the two belong together
+    fn sign_cms(parts: [&[u8]; 2], key: &p256::ecdsa::SigningKey, certificate: &Certificate) -> Vec<u8> {
+        use cms::cert::x509::attr::Attribute;
+        use cms::cert::x509::der::asn1::SetOfVec;
+        use cms::cert::x509::der::Any;
+        use cms::cert::IssuerAndSerialNumber;
+        use cms::content_info::CmsVersion;
+        use cms::signed_data::{CertificateSet, EncapsulatedContentInfo, SignerInfos};
+        use p256::ecdsa::signature::Signer;
+
+        let sha256 = AlgorithmIdentifierOwned { oid: OID_SHA256, parameters: None };
+        let attribute = |oid, value| Attribute { oid, values: SetOfVec::try_from(vec![value]).unwrap() };
+        let message_digest = OctetString::new(digest_parts(OID_SHA256, &parts).unwrap()).unwrap();
+        let signed_attrs = SetOfVec::try_from(vec![
+            attribute(ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.3"), Any::encode_from(&OID_DATA).unwrap()),
+            attribute(OID_MESSAGE_DIGEST, Any::encode_from(&message_digest).unwrap()),
+        ]).unwrap();
+        let signature: p256::ecdsa::Signature = key.sign(&signed_attrs.to_der().unwrap());
+
+        let signer = SignerInfo {
+            version: CmsVersion::V1,
+            sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer: certificate.tbs_certificate.issuer.clone(),
+                serial_number: certificate.tbs_certificate.serial_number.clone(),
+            }),
+            digest_alg: sha256.clone(),
+            signed_attrs: Some(signed_attrs),
+            signature_algorithm: AlgorithmIdentifierOwned { oid: OID_ECDSA_WITH_SHA256, parameters: None },
+            signature: OctetString::new(signature.to_der().as_bytes()).unwrap(),
+            unsigned_attrs: None,
+        };
+        let signed_data = SignedData {
+            version: CmsVersion::V1,
+            digest_algorithms: SetOfVec::try_from(vec![sha256]).unwrap(),
+            encap_content_info: EncapsulatedContentInfo { econtent_type: OID_DATA, econtent: None },
+            certificates: Some(CertificateSet(
+                SetOfVec::try_from(vec![CertificateChoices::Certificate(certificate.clone())]).unwrap(),
+            )),
+            crls: None,
+            signer_infos: SignerInfos(SetOfVec::try_from(vec![signer]).unwrap()),
+        };
+
+        ContentInfo { content_type: OID_SIGNED_DATA, content: Any::encode_from(&signed_data).unwrap() }
+            .to_der()
+            .unwrap()
+    }
+
+    /// Replaces the dummy signature value of a `signed_pdf` with a CMS blob
+    /// over its byte range
+    fn sign_pdf(pdf: &mut [u8], key: &p256::ecdsa::SigningKey, certificate: &Certificate) {
+        let gap_start = pdf.windows(10).position(|window| window == b"/Contents ").unwrap() + 10;
+        let gap_end = gap_start + pdf[gap_start..].iter().position(|&b| b == b'>').unwrap() + 1;
+        let cms = sign_cms([&pdf[..gap_start], &pdf[gap_end..]], key, certificate);
+
+        let mut hex: String = cms.iter().map(|b| format!("{:02X}", b)).collect();
+        hex.extend(std::iter::repeat_n('0', gap_end - gap_start - 2 - hex.len()));
+        pdf[gap_start + 1..gap_end - 1].copy_from_slice(hex.as_bytes());
+    }
+
+    #[test]
+    fn test_signature_valid() {
+        let pdf = b"%PDF-signed<0000>trailer".to_vec();
+        let byte_range = [0, 11, 17, 7];
+        let (key, certificate) = test_signer(1);
+        let mut cms = sign_cms([&pdf[..11], &pdf[17..]], &key, &certificate);
+        // `/Contents` is padded out to its reserved size
+        cms.resize(cms.len() + 64, 0);
+
+        assert!(signature_valid(&pdf, byte_range, &cms));
+        assert!(byte_range_covers_document(&pdf, byte_range));
+
+        let mut tampered = pdf.clone();
+        tampered[1] = b'Q';
+        assert!(!signature_valid(&tampered, byte_range, &cms));
+
+        // A matching digest isn't enough without the certificate's key
+        let (other_key, _) = test_signer(2);
+        let forged = sign_cms([&tampered[..11], &tampered[17..]], &other_key, &certificate);
+        assert!(!signature_valid(&tampered, byte_range, &forged));
+
+        assert!(!signature_valid(&pdf, [0, 11, 17, 70], &cms));
+        assert!(!signature_valid(&pdf, byte_range, b"no signature here"));
+
+        let mut appended = pdf.clone();
+        appended.extend_from_slice(b"update");
+        assert!(signature_valid(&appended, byte_range, &cms));
+        assert!(!byte_range_covers_document(&appended, byte_range));
+        assert!(!byte_range_covers_document(&pdf, [1, 10, 17, 7]));
+    }
+
+    #[test]
+    fn test_digest_info_prefix() {
+        // RFC 8017 section 9.2, note 1
+        assert_eq!(
+            digest_info_prefix(OID_SHA256, 32).unwrap(),
+            [0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20]
+        );
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let pdfium = load_pdfium();
+        let (key, certificate) = test_signer(1);
+
+        let mut signed = signed_pdf("Jane Doe");
+        sign_pdf(&mut signed, &key, &certificate);
+        let verifications = verify_signatures(pdfium, &signed, None).expect("Failed to verify signatures");
+        assert_eq!(verifications.len(), 1);
+        assert_eq!(verifications[0].signer, "Jane Doe");
+        assert!(verifications[0].valid);
+        assert!(verifications[0].covers_whole_document);
+
+        let mut tampered = String::from_utf8_lossy(&signed).replace("Signature1", "Signature2").into_bytes();
+        let verifications = verify_signatures(pdfium, &tampered, None).expect("Failed to verify signatures");
+        assert!(!verifications[0].valid);
+
+        // Re-signing the edit with another key still names the original certificate
+        let (other_key, _) = test_signer(2);
+        sign_pdf(&mut tampered, &other_key, &certificate);
+        let verifications = verify_signatures(pdfium, &tampered, None).expect("Failed to verify signatures");
+        assert!(!verifications[0].valid);
+
+        let mut appended = signed.clone();
+        appended.extend_from_slice(b"% appended after signing\n");
+        let verifications = verify_signatures(pdfium, &appended, None).expect("Failed to verify signatures");
+        assert!(verifications[0].valid);
+        assert!(!verifications[0].covers_whole_document);
+
+        let unsigned = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        assert!(verify_signatures(pdfium, &unsigned, None).expect("Failed to verify signatures").is_empty());
+    }
+
+    #[test]
+    fn test_signature_signer_name() {
+        // One PDFDocEncoding name and one UTF-16BE hex name, both written into
+        // an object stream where no byte scan of the file could find them
+        let utf16: Vec<u8> = [0xfe, 0xff].into_iter().chain("Zoë 李".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        let mut document = lopdf::Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(lopdf::dictionary! { "Type" => "Page", "Parent" => pages_id });
+        document.objects.insert(pages_id, lopdf::Object::Dictionary(lopdf::dictionary! {
+            "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1
+        }));
+        let names = [
+            ([0, 10, 20, 30], lopdf::Object::string_literal(b"Jos\xe9 (QA)".to_vec())),
+            ([0, 40, 50, 60], lopdf::Object::String(utf16, lopdf::StringFormat::Hexadecimal)),
+        ];
+        for (byte_range, name) in names {
+            document.add_object(lopdf::dictionary! {
+                "Type" => "Sig",
+                "ByteRange" => byte_range.into_iter().map(lopdf::Object::from).collect::<Vec<_>>(),
+                "Name" => name
+            });
+        }
+        let catalog_id = document.add_object(lopdf::dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        document.trailer.set("Root", catalog_id);
+        let mut pdf = Vec::new();
+        document.save_modern(&mut pdf).expect("Failed to save PDF");
+        assert!(pdf.windows(7).any(|window| window == b"/ObjStm"));
+
+        let document = lopdf::Document::load_mem(&pdf).expect("Failed to load PDF");
+        assert_eq!(signature_signer_name(&document, [0, 10, 20, 30]).as_deref(), Some("José (QA)"));
+        assert_eq!(signature_signer_name(&document, [0, 40, 50, 60]).as_deref(), Some("Zoë 李"));
+        assert_eq!(signature_signer_name(&document, [0, 1, 2, 3]), None);
+        let signed = signed_pdf("Jane Doe");
+        let document = lopdf::Document::load_mem(&signed).expect("Failed to load PDF");
+        let byte_range = document.objects.values().find_map(|object| object.as_dict().ok()?.get(b"ByteRange").ok()).unwrap();
+        let byte_range: Vec<usize> = byte_range.as_array().unwrap().iter().map(|value| value.as_i64().unwrap() as usize).collect();
+        assert_eq!(signature_signer_name(&document, byte_range.try_into().unwrap()).as_deref(), Some("Jane Doe"));
+    }
+
+    #[test]
+    fn test_estimate_skew_degrees() {
+        // White page with dark bars sloping down to the right by 2 degrees
+        let slope = 2.0f32.to_radians().tan();
+        let image = GrayImage::from_fn(600, 400, |x, y| {
+            let offset = y as f32 - x as f32 * slope;
+            if offset > 20.0 && (offset as u32) % 40 < 6 {
+                image::Luma([0])
+            } else {
+                image::Luma([255])
+            }
+        });
+
+        let skew = estimate_skew_degrees(&image).expect("Failed to detect text lines");
+        assert!((skew - 2.0).abs() < 0.2, "Detected skew {} instead of 2 degrees", skew);
+
+        let blank = GrayImage::from_pixel(200, 200, image::Luma([255]));
+        assert_eq!(estimate_skew_degrees(&blank), None);
+    }
+
+    #[test]
+    fn test_deskew_pdf() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        let deskewed = deskew_pdf(pdfium, &pdf_bytes, None).expect("Failed to deskew PDF");
+        let original = pdfium.load_pdf_from_byte_slice(&pdf_bytes, None).unwrap();
+        let document = pdfium.load_pdf_from_byte_slice(&deskewed, None).unwrap();
+        assert_eq!(document.pages().len(), original.pages().len());
+
+        // Bars like lines of text, rotated 2 degrees about the page centre
+        let (sin, cos) = 2.0f32.to_radians().sin_cos();
+        let bars: String = (0..9).map(|line| format!("50 {} 300 3 re f ", 80 + line * 30)).collect();
+        let content = format!("1 0 0 1 200 200 cm {cos} {sin} {} {cos} 0 0 cm 1 0 0 1 -200 -200 cm 0 g {bars}", -sin);
+        let skewed = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 400 400] /Contents 4 0 R >>".to_string(),
+            stream_object("", &content),
+        ]);
+        let measure_skew = |pdf_bytes: &[u8]| {
+            let document = pdfium.load_pdf_from_byte_slice(pdf_bytes, None).unwrap();
+            let image = document.pages().get(0).unwrap()
+                .render_with_config(&PdfRenderConfig::new().scale_page_by_factor(150.0 / 72.0))
+                .unwrap()
+                .as_image()
+                .into_luma8();
+            estimate_skew_degrees(&image).expect("Failed to detect text lines")
+        };
+
+        let before = measure_skew(&skewed);
+        assert!(before.abs() > 1.5, "Fixture skew measured as {} degrees", before);
+        let after = measure_skew(&deskew_pdf(pdfium, &skewed, None).expect("Failed to deskew PDF"));
+        assert!(after.abs() <= DESKEW_MIN_ANGLE, "Skew of {} degrees left after deskewing", after);
+    }
+
+    #[test]
+    fn test_render_page_by_label() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /PageLabels << /Nums [0 << /S /r >> 2 << /S /D >> ] >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+
+        let labels = get_page_labels(pdfium, &pdf_bytes, None, None).expect("Failed to read page labels");
+        assert_eq!(labels, [Some("i".to_string()), Some("ii".to_string()), Some("1".to_string())]);
+
+        let options = RenderOptions::default().with_format(ImageFormat::Png);
+        let page = render_page_by_label_with_options(pdfium, &pdf_bytes, "ii", &options)
+            .expect("Failed to render page by label");
+        assert!(page.image_buffer.starts_with(b"\x89PNG"));
+
+        assert!(matches!(
+            render_page_by_label_with_options(pdfium, &pdf_bytes, "iv", &options),
+            Err(PdfError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_render_options_builder() {
+        let options = RenderOptions::default()
+            .with_quality(90)
+            .with_format(ImageFormat::Png)
+            .with_dpi(150)
+            .with_grayscale(true);
+
+        assert_eq!(options.quality, 90);
+        assert_eq!(options.format, ImageFormat::Png);
+        assert_eq!(options.dpi, Some(150));
+        assert!(options.grayscale);
+        assert!(options.icc_profile.is_none());
+        assert!(options.validate().is_ok());
+
+        assert_eq!(RenderOptions::default().quality, 75);
+        assert_eq!(RenderOptions::default().format, ImageFormat::Webp);
+        assert!(RenderOptions::default().with_quality(101).validate().is_err());
+        assert!(RenderOptions::default().with_dpi(0).validate().is_err());
+    }
+
+    #[test]
+    fn test_render_pdf_with_dpi() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 144] >>".to_string(),
+        ]);
+
+        let options = RenderOptions::default().with_format(ImageFormat::Png).with_dpi(144);
+        let pages = render_pdf(pdfium, &pdf_bytes, &options).expect("Failed to render PDF");
+        let image = image::load_from_memory(&pages[0].image_buffer)
+            .expect("Failed to decode rendered page");
+        assert_eq!((image.width(), image.height()), (144, 288));
+    }
+
+    #[test]
+    fn test_render_thumbnails() {
+        use image::ImageDecoder;
+
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 200] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0 g 0 0 100 200 re f"),
+        ]);
+
+        let options = RenderOptions::default().with_format(ImageFormat::Png);
+        let thumbnails = render_thumbnails(pdfium, &pdf_bytes, 64, &options)
+            .expect("Failed to render thumbnails");
+        assert_eq!(thumbnails.len(), 1);
+
+        let thumbnail = image::load_from_memory(&thumbnails[0].image_buffer)
+            .expect("Failed to decode thumbnail")
+            .into_rgb8();
+        assert_eq!(thumbnail.dimensions(), (64, 64));
+        // The portrait page is pillarboxed: black in the middle, white at the sides
+        assert_eq!(thumbnail.get_pixel(32, 32).0, [0, 0, 0]);
+        assert_eq!(thumbnail.get_pixel(2, 32).0, [255, 255, 255]);
+        assert_eq!(thumbnail.get_pixel(61, 32).0, [255, 255, 255]);
+
+        // Thumbnails are converted to and tagged with the output color space
+        let icc_profile = OutputColorSpace::AdobeRgb.icc_profile().unwrap();
+        let options = options.with_color_space(OutputColorSpace::AdobeRgb);
+        let thumbnails = render_thumbnails(pdfium, &pdf_bytes, 64, &options)
+            .expect("Failed to render thumbnails");
+        let mut decoder = image::codecs::png::PngDecoder::new(Cursor::new(&thumbnails[0].image_buffer)).unwrap();
+        assert_eq!(decoder.color_type(), image::ColorType::Rgb8);
+        assert_eq!(decoder.icc_profile().unwrap().as_ref(), Some(&icc_profile));
+    }
+
+    #[test]
+    fn test_set_pdf_password() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        let permissions = PdfPermissions { allow_print: true, allow_copy: false, allow_modify: false };
+
+        let protected = set_pdf_password(pdfium, &pdf_bytes, "user", "owner", permissions, None)
+            .expect("Failed to password-protect PDF");
+
+        assert!(pdfium.load_pdf_from_byte_slice(&protected, None).is_err());
+        for password in ["user", "owner"] {
+            let document = pdfium.load_pdf_from_byte_slice(&protected, Some(password))
+                .expect("Failed to open protected PDF");
+            assert_eq!(document.pages().len(), 5);
+        }
+    }
+
+    #[test]
+    fn test_pdf_permissions_flags() {
+        let flags: lopdf::Permissions = PdfPermissions { allow_print: true, allow_copy: false, allow_modify: false }.into();
+
+        assert!(flags.contains(lopdf::Permissions::PRINTABLE));
+        assert!(!flags.contains(lopdf::Permissions::COPYABLE));
+        assert!(!flags.contains(lopdf::Permissions::MODIFIABLE));
+        assert!(flags.contains(lopdf::Permissions::COPYABLE_FOR_ACCESSIBILITY));
+    }
+
+    /// Appends an incremental update that replaces object `number` with `body`
+    fn append_incremental_update(pdf: &mut Vec<u8>, number: usize, body: &str, size: usize) {
+        let text = String::from_utf8_lossy(pdf).into_owned();
+        let previous_xref = text[text.rfind("startxref").unwrap() + 9..]
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string();
+
+        let offset = pdf.len();
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", number, body).as_bytes());
+        let xref_offset = pdf.len();
+        pdf.extend_from_slice(format!(
+            "xref\n0 1\n0000000000 65535 f \n{} 1\n{:010} 00000 n \ntrailer\n<< /Size {} /Root 1 0 R /Prev {} >>\nstartxref\n{}\n%%EOF\n",
+            number, offset, size, previous_xref, xref_offset
+        ).as_bytes());
+    }
+
+    #[test]
+    fn test_garbage_collect_pdf() {
+        let pdfium = load_pdfium();
+        let mut pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0 g 10 10 50 50 re f"),
+        ]);
+        for revision in 0..3 {
+            let content = format!("{} g 10 10 50 50 re f\n%{}", revision, "x".repeat(4096));
+            append_incremental_update(&mut pdf_bytes, 4, &stream_object("", &content), 5);
+        }
+
+        let collected = garbage_collect_pdf(pdfium, &pdf_bytes, None).expect("Failed to garbage collect PDF");
+        assert!(collected.len() < pdf_bytes.len());
+
+        let document = pdfium.load_pdf_from_byte_slice(&collected, None).unwrap();
+        assert_eq!(document.pages().len(), 1);
+    }
+
+    #[test]
+    fn test_prune_pdf_objects() {
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            stream_object("/Orphaned true", &"x".repeat(1024)),
+        ]);
+
+        let pruned = prune_pdf_objects(&pdf_bytes).expect("Failed to prune PDF");
+        assert!(pruned.len() < pdf_bytes.len());
+
+        let document = lopdf::Document::load_mem(&pruned).expect("Failed to reload pruned PDF");
+        assert_eq!(document.objects.len(), 3);
+        assert_eq!(document.get_pages().len(), 1);
+
+        // References to an empty stream become null rather than disappearing
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents [4 0 R 5 0 R] >>".to_string(),
+            stream_object("", ""),
+            stream_object("", "0 g 10 10 50 50 re f"),
+        ]);
+
+        let pruned = prune_pdf_objects(&pdf_bytes).expect("Failed to prune PDF");
+        let document = lopdf::Document::load_mem(&pruned).expect("Failed to reload pruned PDF");
+        assert_eq!(document.objects.len(), 4);
+        let page_id = document.get_pages()[&1];
+        let contents = document
+            .get_dictionary(page_id)
+            .and_then(|page| page.get(b"Contents"))
+            .and_then(lopdf::Object::as_array)
+            .expect("Missing page contents");
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[0], lopdf::Object::Null);
+        assert!(document.get_object(contents[1].as_reference().unwrap()).is_ok());
+    }
+
+    /// Wraps `embedded` in a portfolio whose single attachment is `name`
+    fn portfolio_pdf(name: &str, embedded: &[u8]) -> Vec<u8> {
+        build_pdf(&[
+            format!(
+                "<< /Type /Catalog /Pages 2 0 R /Collection << /Type /Collection /View /D >> \
+                 /Names << /EmbeddedFiles << /Names [({}) 4 0 R] >> >> >>",
+                name
+            ),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            format!("<< /Type /Filespec /F ({0}) /UF ({0}) /EF << /F 5 0 R >> >>", name),
+            stream_object("/Type /EmbeddedFile", std::str::from_utf8(embedded).unwrap()),
+        ])
+    }
+
+    #[test]
+    fn test_render_portfolio() {
+        let pdfium = load_pdfium();
+        let embedded = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+        ]);
+        let portfolio = portfolio_pdf("report.pdf", &embedded);
+
+        let entries = render_portfolio(pdfium, &portfolio, &RenderOptions::default())
+            .expect("Failed to render portfolio");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].filename, "report.pdf");
+        assert_eq!(entries[0].pages.len(), 2);
+
+        assert!(matches!(
+            render_portfolio(pdfium, &embedded, &RenderOptions::default()),
+            Err(PdfError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_portfolio() {
+        let embedded = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [] /Count 0 >>".to_string(),
+        ]);
+
+        assert!(is_portfolio(&portfolio_pdf("a.pdf", &embedded)).unwrap());
+        assert!(!is_portfolio(&embedded).unwrap());
+    }
+
+    fn attachment_pdf() -> Vec<u8> {
+        build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /Names << /EmbeddedFiles << /Names [(notes.txt) 4 0 R] >> >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Filespec /F (notes.txt) /UF (notes.txt) /EF << /F 5 0 R >> >>".to_string(),
+            stream_object(
+                "/Type /EmbeddedFile /Subtype /text#2Fplain /Params << /Size 11 /CreationDate (D:20240102030405Z) >>",
+                "hello world",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_extract_embedded_files() {
+        let pdfium = load_pdfium();
+
+        let files = extract_embedded_files(pdfium, &attachment_pdf(), None)
+            .expect("Failed to extract embedded files");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].filename, "notes.txt");
+        assert_eq!(files[0].data, b"hello world");
+        assert_eq!(files[0].mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(files[0].creation_date.as_deref(), Some("D:20240102030405Z"));
+    }
+
+    #[test]
+    fn test_embedded_file_mime_types() {
+        let mime_types = embedded_file_mime_types(&attachment_pdf()).expect("Failed to read name tree");
+
+        assert_eq!(mime_types.get("notes.txt").map(String::as_str), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_set_embedded_file_mime_type() {
+        let pdf_bytes = set_embedded_file_mime_type(&attachment_pdf(), "notes.txt", "text/markdown")
+            .expect("Failed to set MIME type");
+        let mime_types = embedded_file_mime_types(&pdf_bytes).expect("Failed to read name tree");
+        assert_eq!(mime_types.get("notes.txt").map(String::as_str), Some("text/markdown"));
+
+        assert!(set_embedded_file_mime_type(&attachment_pdf(), "missing.txt", "text/plain").is_err());
+    }
+
+    #[test]
+    fn test_attach_file() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = attach_file(pdfium, &attachment_pdf(), "data.xml", b"<a/>", Some("application/xml"), None)
+            .expect("Failed to attach file");
+        let files = extract_embedded_files(pdfium, &pdf_bytes, None).expect("Failed to extract embedded files");
+        let attached = files.iter().find(|file| file.filename == "data.xml").expect("Attachment is missing");
+        assert_eq!(attached.data, b"<a/>");
+        assert_eq!(attached.mime_type.as_deref(), Some("application/xml"));
+        assert!(files.iter().any(|file| file.filename == "notes.txt"));
+
+        let pdf_bytes = attach_file(pdfium, &pdf_bytes, "plain.bin", &[0, 1, 2], None, None).expect("Failed to attach file");
+        assert_eq!(extract_embedded_files(pdfium, &pdf_bytes, None).unwrap().len(), 3);
+
+        assert!(attach_file(pdfium, &pdf_bytes, "notes.txt", b"again", None, None).is_err());
+        assert!(attach_file(pdfium, &pdf_bytes, "", b"data", None, None).is_err());
+    }
+
+    #[test]
+    fn test_strip_embedded_files() {
+        let pdfium = load_pdfium();
+
+        let payload = vec![b'x'; 64 * 1024];
+        let attached = attach_file(pdfium, &attachment_pdf(), "data.bin", &payload, None, None)
+            .expect("Failed to attach file");
+        let stripped = strip_embedded_files(pdfium, &attached, None).expect("Failed to strip embedded files");
+
+        assert!(extract_embedded_files(pdfium, &stripped, None).expect("Failed to extract embedded files").is_empty());
+        assert!(stripped.len() < attached.len() - payload.len() / 2);
+        assert_eq!(load_document(pdfium, &stripped).unwrap().pages().len(), 1);
+
+        // A file associated with a page through /AF goes too
+        let associated = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /AF [4 0 R] >>".to_string(),
+            "<< /Type /Filespec /F (notes.txt) /UF (notes.txt) /EF << /F 5 0 R >> >>".to_string(),
+            stream_object("/Type /EmbeddedFile", "hello world"),
+        ]);
+        let stripped = strip_embedded_files(pdfium, &associated, None).expect("Failed to strip embedded files");
+        assert!(!stripped.windows(11).any(|window| window == b"hello world"));
+    }
+
+    fn interactive_pdf() -> Vec<u8> {
+        build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R] >> /Names << /JavaScript << /Names [(init) 6 0 R] >> >> /OpenAction 6 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Annots [4 0 R 7 0 R] /AA << /O 6 0 R >> >>".to_string(),
+            "<< /Type /Annot /Subtype /Widget /FT /Tx /T (name) /V (Jane) /F 4 /Rect [20 20 120 40] /P 3 0 R /AP << /N 5 0 R >> >>".to_string(),
+            stream_object("/Type /XObject /Subtype /Form /BBox [0 0 100 20]", "0 0 1 rg 0 0 100 20 re f"),
+            "<< /S /JavaScript /JS (this.print) >>".to_string(),
+            "<< /Type /Annot /Subtype /Link /Rect [20 60 120 80] /A 6 0 R >>".to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_strip_interactive_objects() {
+        let stripped = strip_interactive_objects(&interactive_pdf()).expect("Failed to strip interactive objects");
+
+        let document = lopdf::Document::load_mem(&stripped).expect("Failed to parse stripped PDF");
+        let catalog = document.catalog().unwrap();
+        assert!(catalog.get(b"AcroForm").is_err() && catalog.get(b"OpenAction").is_err());
+        assert!(!stripped.windows(10).any(|window| window == b"JavaScript"));
+        assert_eq!(document.get_pages().len(), 1);
+
+        // A harmless GoTo hiding JavaScript further down its /Next chain is
+        // dropped; a plain GoTo is kept
+        let outlined = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /Outlines 4 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Outlines /First 5 0 R /Last 6 0 R /Count 2 >>".to_string(),
+            "<< /Title (Chained) /Parent 4 0 R /Next 6 0 R /A << /S /GoTo /D [3 0 R /Fit] \
+             /Next [<< /S /URI /URI (https://example.com) >> << /S /JavaScript /JS (app.alert) >>] >> >>".to_string(),
+            "<< /Title (Plain) /Parent 4 0 R /Prev 5 0 R /A << /S /GoTo /D [3 0 R /Fit] >> >>".to_string(),
+        ]);
+        let stripped = strip_interactive_objects(&outlined).expect("Failed to strip interactive objects");
+        let document = lopdf::Document::load_mem(&stripped).expect("Failed to parse stripped PDF");
+        assert!(document.get_dictionary((5, 0)).unwrap().get(b"A").is_err());
+        assert!(document.get_dictionary((6, 0)).unwrap().get(b"A").is_ok());
+        assert!(!stripped.windows(10).any(|window| window == b"JavaScript"));
+    }
+
+    #[test]
+    fn test_flatten_pdf() {
+        let pdfium = load_pdfium();
+
+        let flattened = flatten_pdf(pdfium, &interactive_pdf(), None).expect("Failed to flatten PDF");
+
+        let document = lopdf::Document::load_mem(&flattened).expect("Failed to parse flattened PDF");
+        assert!(document.catalog().unwrap().get(b"AcroForm").is_err());
+        assert!(!flattened.windows(10).any(|window| window == b"JavaScript"));
+        for page_id in document.get_pages().into_values() {
+            let page = document.get_dictionary(page_id).unwrap();
+            assert!(page.get(b"Annots").is_err() && page.get(b"AA").is_err());
+        }
+
+        // The field's appearance is now part of the page
+        let document = load_document(pdfium, &flattened).unwrap();
+        let page = document.pages().get(0).unwrap();
+        assert!(page.annotations().is_empty());
+        let rendered = render_page_rgb(&page, &RenderOptions::default()).unwrap();
+        assert_eq!(rendered.get_pixel(70, 170).0, [0, 0, 255]);
+    }
+
+    #[cfg(feature = "bundled-fonts")]
+    #[test]
+    fn test_bundled_font_dir() {
+        let dir = bundled_font_dir().expect("Failed to unpack bundled fonts");
+
+        assert!(dir.join("DejaVuSans.ttf").is_file());
+        assert_eq!(bundled_font_dir().unwrap(), dir);
+    }
+
+    #[test]
+    fn test_detect_text_language() {
+        let english = "The quick brown fox jumps over the lazy dog while the farmer watches \
+                       from the porch and wonders whether it will rain before the harvest.";
+        let german = "Der schnelle braune Fuchs springt über den faulen Hund, während der Bauer \
+                      von der Veranda aus zusieht und sich fragt, ob es vor der Ernte regnet.";
+
+        assert_eq!(detect_text_language(english), "en");
+        assert_eq!(detect_text_language(german), "de");
+        assert_eq!(detect_text_language("Too short"), "unknown");
+        assert_eq!(detect_text_language(""), "unknown");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let pdfium = load_pdfium();
+        let blank = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+
+        assert_eq!(detect_language(pdfium, &blank, None).expect("Failed to detect language"), "unknown");
+    }
+
+    #[test]
+    fn test_qr_position_from_str() {
+        assert_eq!("bottom_right".parse::<QrPosition>().unwrap(), QrPosition::BottomRight);
+        assert_eq!("TOP_LEFT".parse::<QrPosition>().unwrap(), QrPosition::TopLeft);
+        assert_eq!("center".parse::<QrPosition>().unwrap(), QrPosition::Center);
+        assert!("middle".parse::<QrPosition>().is_err());
+    }
+
+    #[test]
+    fn test_qr_code_image() {
+        let image = qr_code_image("DOC-0001", 0.3).unwrap();
+        assert_eq!(image.width(), image.height());
+        assert_eq!(image.width() % QR_MODULE_PIXELS, 0);
+        assert!(image.pixels().all(|p| p[3] == 77));
+        // The quiet zone is light and the finder pattern's corner is dark
+        assert_eq!(image.get_pixel(0, 0)[0], 255);
+        let corner = QR_QUIET_ZONE * QR_MODULE_PIXELS;
+        assert_eq!(image.get_pixel(corner, corner)[0], 0);
+    }
+
+    #[test]
+    fn test_add_qr_watermark() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>".to_string(),
+        ]);
+
+        let stamped = add_qr_watermark(pdfium, &pdf_bytes, "DOC-0001", QrPosition::BottomRight, 0.3, None)
+            .expect("Failed to add QR watermark");
+        let document = pdfium.load_pdf_from_byte_slice(&stamped, None).unwrap();
+        let page = document.pages().get(0).unwrap();
+        let bounds = page.objects().get(0).unwrap().bounds().unwrap();
+        assert!((bounds.left().value - 522.0).abs() < 0.5);
+        assert!((bounds.bottom().value - 18.0).abs() < 0.5);
+
+        assert!(add_qr_watermark(pdfium, &pdf_bytes, "DOC-0001", QrPosition::Center, 1.5, None).is_err());
+    }
+
+    #[test]
+    fn test_blend_rect() {
+        let mut image = RgbImage::from_pixel(10, 10, image::Rgb([255, 255, 255]));
+        // Corners may arrive in either order and extend past the image
+        blend_rect(&mut image, (12, 6), (4, -3), [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(4, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(9, 5).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(3, 0).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(4, 6).0, [255, 255, 255]);
+
+        blend_rect(&mut image, (0, 0), (1, 1), [0, 0, 0, 128]);
+        assert_eq!(image.get_pixel(0, 0).0, [127, 127, 127]);
+    }
+
+    #[test]
+    fn test_render_page_with_highlights() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+        let highlights = [HighlightRect { x_pt: 0.0, y_pt: 0.0, w_pt: 100.0, h_pt: 50.0, color: [255, 0, 0, 255] }];
+        let options = RenderOptions::default().with_format(ImageFormat::Png).with_dpi(144);
+
+        let page = render_page_with_highlights(pdfium, &pdf_bytes, 0, &highlights, &options)
+            .expect("Failed to render page with highlights");
+        let image = image::load_from_memory(&page.image_buffer).unwrap().into_rgb8();
+        assert_eq!(image.dimensions(), (400, 400));
+        // The highlight covers the bottom-left of the page, i.e. the bottom-left of the image
+        assert_eq!(image.get_pixel(10, 390).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(10, 10).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(390, 390).0, [255, 255, 255]);
+
+        assert!(render_page_with_highlights(pdfium, &pdf_bytes, 1, &highlights, &options).is_err());
+    }
+
+    #[test]
+    fn test_render_page_with_search_results() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 100] /Contents 4 0 R \
+             /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >> >>".to_string(),
+            stream_object("", "BT /F1 24 Tf 20 40 Td (Hello world) Tj ET"),
+        ]);
+        let decode = |page: PageData| image::load_from_memory(&page.image_buffer).unwrap().into_rgb8();
+        let yellow_pixels = |image: &RgbImage| {
+            image.pixels().filter(|pixel| pixel[0] > 200 && pixel[1] > 200 && pixel[2] < 200).count()
+        };
+
+        let highlighted = decode(render_page_with_search_results(pdfium, &pdf_bytes, 0, "WORLD", 72, 100, None)
+            .expect("Failed to render search results"));
+        assert_eq!(highlighted.dimensions(), (200, 100));
+        assert!(yellow_pixels(&highlighted) > 0);
+        // Nothing is highlighted over "Hello"
+        assert_eq!(highlighted.get_pixel(5, 50).0, [255, 255, 255]);
+
+        let unmatched = decode(render_page_with_search_results(pdfium, &pdf_bytes, 0, "absent", 72, 100, None)
+            .expect("Failed to render page without matches"));
+        assert_eq!(yellow_pixels(&unmatched), 0);
+
+        assert!(render_page_with_search_results(pdfium, &pdf_bytes, 1, "world", 72, 100, None).is_err());
+    }
+
+    #[test]
+    fn test_laplacian_of_gaussian_variance() {
+        let flat = GrayImage::from_pixel(64, 64, image::Luma([200]));
+        assert_eq!(laplacian_of_gaussian_variance(&flat), 0.0);
+
+        let mut seed = 12345u32;
+        let noisy = GrayImage::from_fn(64, 64, |_, _| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            image::Luma([(seed >> 24) as u8])
+        });
+        assert!(laplacian_of_gaussian_variance(&noisy) > 100.0);
+    }
+
+    #[test]
+    fn test_estimate_page_noise() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+
+        let noise = estimate_page_noise(pdfium, &pdf_bytes, 0, None).expect("Failed to estimate page noise");
+        assert_eq!(noise, 0.0);
+        assert!(estimate_page_noise(pdfium, &pdf_bytes, 1, None).is_err());
+    }
+
+    #[test]
+    fn test_batch_get_page_counts() {
+        let one_page = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+        let sample = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        let counts = batch_get_page_counts(&[&one_page, b"not a pdf", &sample]);
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[0].as_ref().unwrap(), &1);
+        assert!(matches!(counts[1], Err(PdfError::Load(_))));
+        assert_eq!(counts[2].as_ref().unwrap(), &5);
+    }
+
+    #[test]
+    fn test_nup_grid() {
+        let portrait = [595.0, 842.0];
+        let landscape = [842.0, 595.0];
+        assert_eq!(nup_grid(2, landscape).unwrap(), (1, 2));
+        assert_eq!(nup_grid(2, portrait).unwrap(), (2, 1));
+        assert_eq!(nup_grid(4, portrait).unwrap(), (2, 2));
+        assert_eq!(nup_grid(6, landscape).unwrap(), (2, 3));
+        assert_eq!(nup_grid(8, portrait).unwrap(), (4, 2));
+        assert!(nup_grid(3, portrait).is_err());
+    }
+
+    #[test]
+    fn test_impose_nup() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 300] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 300] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 300] >>".to_string(),
+        ]);
+
+        let imposed = impose_nup(pdfium, &pdf_bytes, 2, [842.0, 595.0], None).expect("Failed to impose pages");
+        let document = pdfium.load_pdf_from_byte_slice(&imposed, None).unwrap();
+        // Three pages 2-up need two sheets, the second half empty
+        assert_eq!(document.pages().len(), 2);
+        let sheet = document.pages().get(1).unwrap();
+        assert_eq!(sheet.width().value, 842.0);
+        assert_eq!(sheet.height().value, 595.0);
+
+        assert!(impose_nup(pdfium, &pdf_bytes, 5, [842.0, 595.0], None).is_err());
+    }
+
+    #[test]
+    fn test_copy_pages() {
+        let pdfium = load_pdfium();
+        let source = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 300 300] >>".to_string(),
+        ]);
+        let destination = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        let destination_pages = pdfium.load_pdf_from_byte_slice(&destination, None).unwrap().pages().len();
+        let page_indices = [2, 0];
+
+        let combined = copy_pages(pdfium, &source, &destination, &page_indices, None).expect("Failed to copy pages");
+        let document = pdfium.load_pdf_from_byte_slice(&combined, None).unwrap();
+        assert_eq!(document.pages().len() as usize, destination_pages as usize + page_indices.len());
+        assert_eq!(document.pages().get(destination_pages).unwrap().width().value, 300.0);
+        assert_eq!(document.pages().get(destination_pages + 1).unwrap().width().value, 100.0);
+
+        assert!(copy_pages(pdfium, &source, &destination, &[3], None).is_err());
+    }
+
+    fn image_stream(width: i64, height: i64, color_space: &str, bits: i64, samples: Vec<u8>) -> lopdf::Stream {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Type", lopdf::Object::Name(b"XObject".to_vec()));
+        dict.set("Subtype", lopdf::Object::Name(b"Image".to_vec()));
+        dict.set("Width", width);
+        dict.set("Height", height);
+        dict.set("ColorSpace", lopdf::Object::Name(color_space.as_bytes().to_vec()));
+        dict.set("BitsPerComponent", bits);
+        lopdf::Stream::new(dict, samples)
+    }
+
+    /// Builds a base64-encoded single-page PDF that draws each of `images`
+    fn image_pdf(images: Vec<lopdf::Stream>) -> String {
+        let mut document = lopdf::Document::with_version("1.7");
+        let mut xobjects = lopdf::Dictionary::new();
+        for (i, image) in images.into_iter().enumerate() {
+            xobjects.set(format!("Im{}", i), document.add_object(image));
+        }
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("XObject", xobjects);
+
+        let pages_id = document.new_object_id();
+        let mut page = lopdf::Dictionary::new();
+        page.set("Type", lopdf::Object::Name(b"Page".to_vec()));
+        page.set("Parent", pages_id);
+        page.set("MediaBox", vec![0.into(), 0.into(), 200.into(), 200.into()]);
+        page.set("Resources", resources);
+        let page_id = document.add_object(page);
+
+        let mut pages = lopdf::Dictionary::new();
+        pages.set("Type", lopdf::Object::Name(b"Pages".to_vec()));
+        pages.set("Kids", vec![page_id.into()]);
+        pages.set("Count", 1);
+        document.objects.insert(pages_id, pages.into());
+
+        let mut catalog = lopdf::Dictionary::new();
+        catalog.set("Type", lopdf::Object::Name(b"Catalog".to_vec()));
+        catalog.set("Pages", pages_id);
+        let catalog_id = document.add_object(catalog);
+        document.trailer.set("Root", catalog_id);
+
+        let mut buffer = Vec::new();
+        document.save_to(&mut buffer).unwrap();
+        BASE64.encode(buffer)
+    }
+
+    /// Filters of the image XObjects in a base64-encoded PDF, in object order
+    fn image_filters(base64_pdf: &str) -> Vec<Option<String>> {
+        let document = lopdf::Document::load_mem(&BASE64.decode(base64_pdf).unwrap()).unwrap();
+        document
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .filter(|stream| stream.dict.get(b"Subtype").and_then(lopdf::Object::as_name).ok() == Some(b"Image"))
+            .map(|stream| stream.filters().ok().and_then(|f| f.last().map(|name| String::from_utf8_lossy(name).into_owned())))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_image_type() {
+        let mut stream = image_stream(1, 1, "DeviceGray", 8, vec![0]);
+        assert_eq!(detect_image_type(&stream), SourceImageType::Png);
+        stream.dict.set("Filter", lopdf::Object::Name(b"DCTDecode".to_vec()));
+        assert_eq!(detect_image_type(&stream), SourceImageType::Jpeg);
+        stream.dict.set("Filter", vec![
+            lopdf::Object::Name(b"ASCII85Decode".to_vec()),
+            lopdf::Object::Name(b"JBIG2Decode".to_vec()),
+        ]);
+        assert_eq!(detect_image_type(&stream), SourceImageType::Jbig2);
+        stream.dict.set("Filter", lopdf::Object::Name(b"FlateDecode".to_vec()));
+        assert_eq!(detect_image_type(&stream), SourceImageType::Png);
+    }
+
+    #[test]
+    fn test_encode_ccitt_g4() {
+        let image = GrayImage::from_fn(40, 12, |x, y| image::Luma([if (x / 5 + y / 3) % 2 == 0 { 0 } else { 255 }]));
+        let encoded = encode_ccitt_g4(&image).expect("Failed to encode CCITT G4");
+
+        let mut rows = Vec::new();
+        fax::decoder::decode_g4(encoded.into_iter(), 40, Some(12), |transitions| {
+            rows.push(fax::decoder::pels(transitions, 40).collect::<Vec<_>>());
+        });
+        assert_eq!(rows.len(), 12);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &color) in row.iter().enumerate() {
+                let black = image.get_pixel(x as u32, y as u32)[0] == 0;
+                assert_eq!(color == fax::Color::Black, black, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_pdf_with_strategy() {
+        let photo: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128]).collect();
+        let bitonal = vec![0b1010_1010; 8 * 64];
+        let base64_pdf = image_pdf(vec![
+            image_stream(64, 64, "DeviceRGB", 8, photo),
+            image_stream(2, 2, "DeviceGray", 8, vec![0, 255, 255, 0]),
+            image_stream(64, 64, "DeviceGray", 1, bitonal),
+        ]);
+
+        let compressed = compress_pdf_with_strategy(&base64_pdf, 75, CompressStrategy::default()).unwrap();
+        assert_eq!(image_filters(&compressed), vec![Some("DCTDecode".to_string()), None, None]);
+
+        let strategy = CompressStrategy { skip_if_no_savings: false, preserve_bitonal: false };
+        let compressed = compress_pdf_with_strategy(&base64_pdf, 75, strategy).unwrap();
+        assert_eq!(image_filters(&compressed), vec![Some("DCTDecode".to_string()); 3]);
+    }
+
+    #[test]
+    fn test_batch_compress_pdfs() {
+        let photo: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128]).collect();
+        let pdf_bytes = base64::engine::general_purpose::STANDARD
+            .decode(image_pdf(vec![image_stream(64, 64, "DeviceRGB", 8, photo)]))
+            .unwrap();
+
+        let results = batch_compress_pdfs(vec![
+            (pdf_bytes.clone(), 75),
+            (pdf_bytes.clone(), 30),
+            (pdf_bytes.clone(), 0),
+        ]);
+        assert_eq!(results.len(), 3);
+        let high = results[0].as_ref().expect("Failed to compress");
+        let low = results[1].as_ref().expect("Failed to compress");
+        assert!(low.len() < high.len() && high.len() < pdf_bytes.len());
+        assert!(matches!(results[2], Err(PdfError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_render_page_to_raw() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 144] >>".to_string(),
+        ]);
+        let options = RenderOptions::default().with_dpi(150);
+
+        let rgb = render_page_to_raw(pdfium, &pdf_bytes, 0, 3, &options).expect("Failed to render raw RGB");
+        assert_eq!((rgb.width, rgb.height, rgb.channels), (150, 300, 3));
+        assert_eq!(rgb.data.len(), 150 * 300 * 3);
+
+        let rgba = render_page_to_raw(pdfium, &pdf_bytes, 0, 4, &options).expect("Failed to render raw RGBA");
+        assert_eq!(rgba.data.len(), 150 * 300 * 4);
+        assert_eq!(&rgba.data[..4], &[255, 255, 255, 255]);
+
+        assert!(render_page_to_raw(pdfium, &pdf_bytes, 0, 2, &options).is_err());
+    }
+
+    #[test]
+    fn test_set_page_size() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 200] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0 g 0 0 100 200 re f"),
+        ]);
+
+        let resized = set_page_size(pdfium, &pdf_bytes, 0, 400.0, 400.0, true, None).expect("Failed to resize page");
+        let document = pdfium.load_pdf_from_byte_slice(&resized, None).unwrap();
+        let page = document.pages().get(0).unwrap();
+        assert_eq!((page.width().value, page.height().value), (400.0, 400.0));
+        // Scaled by 2 to fit the height, then centred horizontally
+        let bounds = page.objects().get(0).unwrap().bounds().unwrap();
+        assert!((bounds.left().value - 100.0).abs() < 0.5);
+        assert!((bounds.width().value - 200.0).abs() < 0.5);
+        assert!((bounds.height().value - 400.0).abs() < 0.5);
+
+        let resized = set_page_size(pdfium, &pdf_bytes, 0, 400.0, 400.0, false, None).expect("Failed to resize page");
+        let document = pdfium.load_pdf_from_byte_slice(&resized, None).unwrap();
+        let page = document.pages().get(0).unwrap();
+        assert_eq!((page.width().value, page.height().value), (400.0, 400.0));
+        let bounds = page.objects().get(0).unwrap().bounds().unwrap();
+        assert!((bounds.width().value - 100.0).abs() < 0.5);
+
+        assert!(set_page_size(pdfium, &pdf_bytes, 1, 400.0, 400.0, true, None).is_err());
+    }
+
+    const TEST_XMP: &str = "<?xpacket begin=\"\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+        <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+        <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><dc:rights>CC-BY</dc:rights>\
+        </rdf:Description></rdf:RDF></x:xmpmeta><?xpacket end=\"w\"?>";
+
+    fn xmp_pdf() -> Vec<u8> {
+        build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /Metadata 4 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            stream_object("/Type /Metadata /Subtype /XML", TEST_XMP),
+        ])
+    }
+
+    #[test]
+    fn test_read_xmp_metadata() {
+        let xmp = read_xmp_metadata(&xmp_pdf()).unwrap().expect("Missing XMP metadata");
+        assert!(xmp.contains("<rdf:RDF"));
+        assert_eq!(xmp, TEST_XMP);
+
+        let plain = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [] /Count 0 >>".to_string(),
+        ]);
+        assert_eq!(read_xmp_metadata(&plain).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_xmp_metadata() {
+        let pdfium = load_pdfium();
+
+        let xmp = get_xmp_metadata(pdfium, &xmp_pdf(), None).expect("Failed to read XMP metadata");
+        assert!(xmp.expect("Missing XMP metadata").contains("<rdf:RDF"));
+    }
+
+    #[test]
+    fn test_write_xmp_metadata() {
+        let plain = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [] /Count 0 >>".to_string(),
+        ]);
+        let written = write_xmp_metadata(&plain, TEST_XMP).expect("Failed to write XMP metadata");
+        assert_eq!(read_xmp_metadata(&written).unwrap().as_deref(), Some(TEST_XMP));
+
+        let replaced = write_xmp_metadata(&xmp_pdf(), "<x:xmpmeta/>").expect("Failed to replace XMP metadata");
+        assert_eq!(read_xmp_metadata(&replaced).unwrap().as_deref(), Some("<x:xmpmeta/>"));
+        let document = lopdf::Document::load_mem(&replaced).unwrap();
+        assert_eq!(document.catalog().unwrap().get(b"Metadata").unwrap().as_reference().unwrap(), (4, 0));
+    }
+
+    #[test]
+    fn test_set_xmp_metadata() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+
+        let tagged = set_xmp_metadata(pdfium, &pdf_bytes, TEST_XMP, None).expect("Failed to set XMP metadata");
+        let xmp = get_xmp_metadata(pdfium, &tagged, None).expect("Failed to read XMP metadata");
+        assert_eq!(xmp.as_deref(), Some(TEST_XMP));
+    }
+
+    #[test]
+    fn test_compare_metadata() {
+        let pdfium = load_pdfium();
+        let with_info = |title: &str| {
+            let mut document = lopdf::Document::load_mem(&build_pdf(&[
+                "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+                "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            ])).unwrap();
+            let info = document.add_object(lopdf::dictionary! {
+                "Title" => lopdf::Object::string_literal(title),
+                "Author" => lopdf::Object::string_literal("Ada")
+            });
+            document.trailer.set("Info", info);
+            let mut pdf_bytes = Vec::new();
+            document.save_to(&mut pdf_bytes).unwrap();
+            pdf_bytes
+        };
+        let draft = with_info("Draft");
+        let final_version = with_info("Final");
+
+        let diffs = compare_metadata(pdfium, &draft, &final_version, None).expect("Failed to compare metadata");
+        assert_eq!(diffs, vec![MetadataDiff {
+            field: "Title".to_string(),
+            old_value: Some("Draft".to_string()),
+            new_value: Some("Final".to_string()),
+        }]);
+
+        assert!(compare_metadata(pdfium, &draft, &draft, None).expect("Failed to compare metadata").is_empty());
+    }
+
+    /// Extracts DejaVuSans.ttf from the bundled font archive into a fresh directory
+    fn dejavu_font_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ztron_pdf-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open("./fonts/bundled-fonts.zip").unwrap()).unwrap();
+        let mut font = archive.by_name("DejaVuSans.ttf").unwrap();
+        std::io::copy(&mut font, &mut std::fs::File::create(dir.join("DejaVuSans.ttf")).unwrap()).unwrap();
+        dir
+    }
+
+    fn unembedded_font_pdf(base_font: &str) -> Vec<u8> {
+        build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>".to_string(),
+            stream_object("", "BT /F1 12 Tf 20 100 Td (Hello) Tj ET"),
+            format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} >>", base_font),
+        ])
+    }
+
+    #[test]
+    fn test_font_lookup_key() {
+        assert_eq!(font_lookup_key("ABCDEF+Arial,Bold"), "arialbold");
+        assert_eq!(font_lookup_key("Arial-Bold"), "arialbold");
+        assert_eq!(font_lookup_key("Times New Roman"), "timesnewroman");
+        assert_eq!(font_lookup_key("AB+Font"), "abfont");
+    }
+
+    #[test]
+    fn test_win_ansi_char() {
+        assert_eq!(win_ansi_char(b'A'), Some('A'));
+        assert_eq!(win_ansi_char(0x80), Some('€'));
+        assert_eq!(win_ansi_char(0x81), None);
+        assert_eq!(win_ansi_char(0xE9), Some('é'));
+        assert_eq!(win_ansi_char(0x10), None);
+    }
+
+    #[test]
+    fn test_embed_font_files() {
+        let font_dir = dejavu_font_dir("embed-font-files");
+        let font_files = index_font_files(&font_dir).unwrap();
+        assert_eq!(font_files.len(), 1);
+        assert_eq!(font_files[0].0, "dejavusans");
+
+        let embedded = embed_font_files(&unembedded_font_pdf("DejaVu-Sans"), &["DejaVu-Sans".to_string()], &font_files)
+            .expect("Failed to embed fonts");
+        std::fs::remove_dir_all(&font_dir).unwrap();
+
+        let document = lopdf::Document::load_mem(&embedded).unwrap();
+        let font = document.get_object((5, 0)).unwrap().as_dict().unwrap();
+        assert_eq!(font.get(b"Subtype").unwrap().as_name().unwrap(), b"TrueType");
+        assert_eq!(font.get(b"Widths").unwrap().as_array().unwrap().len(), 224);
+        let descriptor = font.get_deref(b"FontDescriptor", &document).unwrap().as_dict().unwrap();
+        let program = descriptor.get_deref(b"FontFile2", &document).unwrap().as_stream().unwrap();
+        assert!(program.get_plain_content().unwrap().len() > 100_000);
+    }
+
+    #[test]
+    fn test_embed_missing_fonts() {
+        let pdfium = load_pdfium();
+        let font_dir = dejavu_font_dir("embed-missing-fonts");
+        let pdf_bytes = unembedded_font_pdf("DejaVuSans");
+        assert_eq!(
+            get_fonts(pdfium, &pdf_bytes, None, None).unwrap(),
+            vec![FontInfo { name: "DejaVuSans".to_string(), is_embedded: false }]
+        );
+
+        let embedded = embed_missing_fonts(pdfium, &pdf_bytes, &font_dir, None).expect("Failed to embed fonts");
+        std::fs::remove_dir_all(&font_dir).unwrap();
+        assert!(get_fonts(pdfium, &embedded, None, None).unwrap()[0].is_embedded);
+    }
+
+    #[test]
+    fn test_estimate_jpeg_quality() {
+        let image = RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 64]));
+        for quality in [30, 75, 95] {
+            let jpeg = encode_jpeg(&image, quality).unwrap();
+            let estimate = estimate_jpeg_quality(&jpeg).expect("Missing quantization table");
+            assert!((estimate as i32 - quality as i32).abs() <= 2, "estimated {} for quality {}", estimate, quality);
+        }
+
+        assert_eq!(estimate_jpeg_quality(b"not a jpeg"), None);
+    }
+
+    #[test]
+    fn test_image_quality_stats() {
+        let pdfium = load_pdfium();
+        let image = RgbImage::from_fn(32, 16, |x, y| image::Rgb([(x * 8) as u8, (y * 16) as u8, 64]));
+        let mut document = lopdf::Document::load_mem(&BASE64.decode(image_pdf(vec![
+            image_stream(8, 8, "DeviceGray", 8, vec![128; 64]),
+        ])).unwrap()).unwrap();
+        let mut jpeg = image_stream(32, 16, "DeviceRGB", 8, encode_jpeg(&image, 60).unwrap());
+        jpeg.dict.set("Filter", lopdf::Object::Name(b"DCTDecode".to_vec()));
+        let jpeg_id = document.add_object(jpeg);
+        let page_id = document.page_iter().next().unwrap();
+        let page = document.get_object_mut(page_id).unwrap().as_dict_mut().unwrap();
+        page.get_mut(b"Resources").unwrap().as_dict_mut().unwrap()
+            .get_mut(b"XObject").unwrap().as_dict_mut().unwrap()
+            .set("Im1", jpeg_id);
+        let contents = document.add_object(lopdf::Stream::new(
+            lopdf::Dictionary::new(),
+            b"q 100 0 0 100 0 0 cm /Im0 Do Q q 100 0 0 50 0 100 cm /Im1 Do Q".to_vec(),
+        ));
+        document.get_object_mut(page_id).unwrap().as_dict_mut().unwrap().set("Contents", contents);
+        let mut pdf_bytes = Vec::new();
+        document.save_to(&mut pdf_bytes).unwrap();
+
+        let stats = image_quality_stats(pdfium, &pdf_bytes, None).expect("Failed to read image stats");
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].width, stats[0].height), (8, 8));
+        assert_eq!(stats[0].format, "PNG");
+        assert_eq!(stats[0].estimated_quality, None);
+        assert_eq!((stats[1].page_index, stats[1].object_index), (0, 1));
+        assert_eq!((stats[1].width, stats[1].height), (32, 16));
+        assert_eq!(stats[1].format, "JPEG");
+        assert!(stats[1].estimated_quality.is_some_and(|q| q.abs_diff(60) <= 2));
+    }
+
+    #[test]
+    fn test_prepend_toc_page() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 300 400] >>".to_string(),
+        ]);
+
+        let entries = vec![("Introduction".to_string(), 1), ("Appendix".to_string(), 12)];
+        let with_toc = prepend_toc_page(pdfium, &pdf_bytes, &entries, None).expect("Failed to prepend TOC");
+        let document = pdfium.load_pdf_from_byte_slice(&with_toc, None).unwrap();
+        assert_eq!(document.pages().len(), 2);
+        let toc = document.pages().get(0).unwrap();
+        assert_eq!((toc.width().value, toc.height().value), (300.0, 400.0));
+        let text = toc.text().unwrap().all();
+        assert!(text.contains("Contents"));
+        assert!(text.contains("Introduction"));
+        assert!(text.contains("12"));
+
+        // Far more entries than fit on one 400pt page spill onto further TOC pages
+        let entries: Vec<(String, usize)> = (1..=40).map(|n| (format!("Section {}", n), n)).collect();
+        let with_toc = prepend_toc_page(pdfium, &pdf_bytes, &entries, None).expect("Failed to prepend TOC");
+        let document = pdfium.load_pdf_from_byte_slice(&with_toc, None).unwrap();
+        assert!(document.pages().len() > 3);
+        let last_toc = document.pages().get(document.pages().len() - 2).unwrap();
+        assert!(last_toc.text().unwrap().all().contains("Section 40"));
+
+        // A title wider than the page is cut short before its page number
+        let entries = vec![("Chapter ".repeat(20), 123)];
+        let with_toc = prepend_toc_page(pdfium, &pdf_bytes, &entries, None).expect("Failed to prepend TOC");
+        let document = pdfium.load_pdf_from_byte_slice(&with_toc, None).unwrap();
+        let toc = document.pages().get(0).unwrap();
+        let text = toc.text().unwrap().all();
+        assert!(text.contains("Chapter..."));
+        assert!(text.contains("123"));
+        let right_edges: Vec<f32> = toc
+            .objects()
+            .iter()
+            .filter(|object| object.as_text_object().is_some_and(|text| text.text() != "Contents"))
+            .map(|object| object.bounds().unwrap().right().value)
+            .collect();
+        assert_eq!(right_edges.len(), 2);
+        assert!(right_edges.iter().all(|&right| right <= 300.0 - TOC_MARGIN + 0.5));
+    }
+
+    #[test]
+    fn test_difference_map_colours() {
+        let before = GrayImage::from_raw(3, 1, vec![255, 0, 255]).unwrap();
+        let after = GrayImage::from_raw(2, 1, vec![255, 255]).unwrap();
+        let diff = difference_map(&before, &after);
+        assert_eq!(diff.get_pixel(0, 0).0, [0, 255, 0]);
+        assert_eq!(diff.get_pixel(1, 0).0, [255, 0, 0]);
+        // Past the end of `after` counts as white, so unchanged
+        assert_eq!(diff.get_pixel(2, 0).0, [0, 255, 0]);
+
+        let diff = difference_map(&after, &before);
+        assert_eq!(diff.get_pixel(1, 0).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_render_page_difference_identical() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0 g 20 20 60 60 re f"),
+        ]);
+
+        let page_data = render_page_difference(pdfium, &pdf_bytes, &pdf_bytes, 0, 72, None)
+            .expect("Failed to render difference");
+        let image = image::load_from_memory(&page_data.image_buffer).unwrap().into_rgb8();
+        assert!(image.pixels().all(|pixel| pixel[0] == 0 && pixel[1] > 0 && pixel[2] == 0));
+    }
+
+    #[test]
+    fn test_is_subset_font_name() {
+        assert!(is_subset_font_name(b"ABCDEF+Arial"));
+        assert!(!is_subset_font_name(b"Arial"));
+        assert!(!is_subset_font_name(b"ABCDEF+"));
+        assert!(!is_subset_font_name(b"AbCDEF+Arial"));
+    }
+
+    #[test]
+    fn test_subset_font_programs() {
+        let font_dir = dejavu_font_dir("subset-font-programs");
+        let font_files = index_font_files(&font_dir).unwrap();
+        let embedded = embed_font_files(&unembedded_font_pdf("DejaVu-Sans"), &["DejaVu-Sans".to_string()], &font_files)
+            .expect("Failed to embed fonts");
+        std::fs::remove_dir_all(&font_dir).unwrap();
+
+        let subset = subset_font_programs(&embedded).expect("Failed to subset fonts");
+        assert!(subset.len() * 4 < embedded.len());
+
+        let document = lopdf::Document::load_mem(&subset).unwrap();
+        let font = document.get_object((5, 0)).unwrap().as_dict().unwrap();
+        let base_font = font.get(b"BaseFont").unwrap().as_name().unwrap();
+        assert!(is_subset_font_name(base_font));
+        assert!(base_font.ends_with(b"+DejaVu-Sans"));
+
+        let descriptor = font.get_deref(b"FontDescriptor", &document).unwrap().as_dict().unwrap();
+        let program = descriptor.get_deref(b"FontFile2", &document).unwrap().as_stream().unwrap();
+        let data = program.get_plain_content().unwrap();
+        let face = ttf_parser::Face::parse(&data, 0).expect("Subset font doesn't parse");
+        let outline = |c: char| face.glyph_bounding_box(face.glyph_index(c).unwrap());
+        assert!(outline('H').is_some());
+        assert!(outline('o').is_some());
+        assert!(outline('Z').is_none());
+
+        // Already-subset fonts are left alone
+        assert_eq!(subset_font_programs(&subset).unwrap().len(), subset.len());
+    }
+
+    #[test]
+    fn test_subset_font_programs_encoding_and_forms() {
+        let font_dir = dejavu_font_dir("subset-encoding-and-forms");
+        let font_files = index_font_files(&font_dir).unwrap();
+        // 0x8E is é in MacRoman (Ž in WinAnsi), 65 is remapped to Ё by an
+        // Adobe glyph name the font's own glyph names don't use, and the
+        // form without /Resources shows W with the page's font
+        let pdf = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> /XObject << /Fm1 6 0 R >> >> >>".to_string(),
+            stream_object("", "BT /F1 12 Tf 20 100 Td (\\216A) Tj ET /Fm1 Do"),
+            "<< /Type /Font /Subtype /TrueType /BaseFont /DejaVu-Sans /Encoding << /BaseEncoding /MacRomanEncoding /Differences [65 /afii10023] >> >>".to_string(),
+            stream_object("/Type /XObject /Subtype /Form /BBox [0 0 200 200]", "BT /F1 12 Tf 20 50 Td (W) Tj ET"),
+        ]);
+        let embedded = embed_font_files(&pdf, &["DejaVu-Sans".to_string()], &font_files).expect("Failed to embed fonts");
+        std::fs::remove_dir_all(&font_dir).unwrap();
+
+        let subset = subset_font_programs(&embedded).expect("Failed to subset fonts");
+        let document = lopdf::Document::load_mem(&subset).unwrap();
+        let font = document.get_object((5, 0)).unwrap().as_dict().unwrap();
+        let descriptor = font.get_deref(b"FontDescriptor", &document).unwrap().as_dict().unwrap();
+        let program = descriptor.get_deref(b"FontFile2", &document).unwrap().as_stream().unwrap();
+        let data = program.get_plain_content().unwrap();
+        let face = ttf_parser::Face::parse(&data, 0).expect("Subset font doesn't parse");
+        let outline = |c: char| face.glyph_bounding_box(face.glyph_index(c).unwrap());
+        assert!(outline('é').is_some());
+        assert!(outline('Ё').is_some());
+        assert!(outline('W').is_some());
+        assert!(outline('Ž').is_none());
+    }
+
+    #[test]
+    fn test_luminance_std_dev() {
+        assert_eq!(luminance_std_dev(&GrayImage::from_pixel(10, 10, image::Luma([255]))), 0.0);
+        let half = GrayImage::from_fn(10, 10, |x, _| image::Luma([if x < 5 { 0 } else { 200 }]));
+        assert!((luminance_std_dev(&half) - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_split_by_blank_pages() {
+        let pdfium = load_pdfium();
+        // Ten pages with blank separators at pages 3 and 7 (1-based)
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count 10 >>",
+                (0..10).map(|i| format!("{} 0 R", 3 + i * 2)).collect::<Vec<_>>().join(" ")
+            ),
+        ];
+        for page in 0..10 {
+            let content = if page == 2 || page == 6 { String::new() } else { format!("0 g 10 10 {} 50 re f", 20 + page * 5) };
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Contents {} 0 R >>", 4 + page * 2));
+            objects.push(stream_object("", &content));
+        }
+        let pdf_bytes = build_pdf(&objects);
+
+        assert!(is_blank_page(pdfium, &pdf_bytes, 2, 5.0, None).unwrap());
+        assert!(!is_blank_page(pdfium, &pdf_bytes, 3, 5.0, None).unwrap());
+
+        let segments = split_by_blank_pages(pdfium, &pdf_bytes, 5.0, None).expect("Failed to split");
+        let page_counts: Vec<_> = segments
+            .iter()
+            .map(|segment| pdfium.load_pdf_from_byte_slice(segment, None).unwrap().pages().len())
+            .collect();
+        assert_eq!(page_counts, vec![2, 3, 3]);
+    }
+
+    #[test]
+    fn test_render_cover() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 200] >>".to_string(),
+        ]);
+
+        let cover = render_cover(pdfium, &pdf_bytes, 64, None).expect("Failed to render cover");
+        assert_eq!(image::guess_format(&cover).unwrap(), image::ImageFormat::WebP);
+        let image = image::load_from_memory(&cover).unwrap();
+        assert_eq!((image.width(), image.height()), (32, 64));
+
+        let empty = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [] /Count 0 >>".to_string(),
+        ]);
+        assert!(render_cover(pdfium, &empty, 64, None).is_err());
+        assert!(render_cover(pdfium, &pdf_bytes, 0, None).is_err());
+    }
+
+    #[test]
+    fn test_render_pdf_grid() {
+        let pdfium = load_pdfium();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 5 0 R 7 0 R] /Count 3 >>".to_string(),
+        ];
+        for page in 0..3 {
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 200] /Contents {} 0 R >>", 4 + page * 2));
+            objects.push(stream_object("", "0 g 0 0 100 200 re f"));
+        }
+        let pdf_bytes = build_pdf(&objects);
+
+        let grid = render_pdf_grid(pdfium, &pdf_bytes, 2, 50, 75, ImageFormat::Png, None).expect("Failed to render grid");
+        let grid = image::load_from_memory(&grid.image_buffer).unwrap().into_rgb8();
+        assert_eq!(grid.dimensions(), (100, 200));
+        assert_eq!(grid.get_pixel(25, 50).0, [0, 0, 0]);
+        assert_eq!(grid.get_pixel(75, 50).0, [0, 0, 0]);
+        assert_eq!(grid.get_pixel(25, 150).0, [0, 0, 0]);
+        // The third page leaves the second cell of the last row empty
+        assert_eq!(grid.get_pixel(75, 150).0, [255, 255, 255]);
+
+        assert!(render_pdf_grid(pdfium, &pdf_bytes, 0, 50, 75, ImageFormat::Png, None).is_err());
+    }
+
+    #[test]
+    fn test_page_text_density() {
+        let pdfium = load_pdfium();
+        let font = "/Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >>";
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 5 0 R] /Count 2 >>".to_string(),
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 40] /Contents 4 0 R {} >>", font),
+            stream_object("", "BT /F1 30 Tf 0 8 Td (MMMM) Tj ET"),
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 40] /Contents 6 0 R {} >>", font),
+            stream_object("", "0.5 g 0 0 100 40 re f"),
+        ]);
+
+        let text_page = page_text_density(pdfium, &pdf_bytes, 0, None).expect("Failed to measure density");
+        assert!(text_page > 0.5, "text page density {}", text_page);
+        let image_page = page_text_density(pdfium, &pdf_bytes, 1, None).expect("Failed to measure density");
+        assert!(image_page < 0.05, "image page density {}", image_page);
+    }
+
+    #[test]
+    fn test_write_xref_stream() {
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+        ]);
+
+        let output = write_xref_stream(&pdf_bytes).expect("Failed to write xref stream");
+        assert!(output.starts_with(b"%PDF-1.7"));
+        // No classic table; "startxref" still points at the stream
+        assert!(!output.windows(5).any(|window| window == b"\nxref"));
+
+        let document = lopdf::Document::load_mem(&output).unwrap();
+        assert!(matches!(document.reference_table.cross_reference_type, lopdf::xref::XrefType::CrossReferenceStream));
+        assert_eq!(document.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_enable_xref_stream() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+
+        let output = enable_xref_stream(pdfium, &pdf_bytes, None).expect("Failed to enable xref stream");
+        assert!(!output[..512].windows(4).any(|window| window == b"xref"));
+        assert_eq!(pdfium.load_pdf_from_byte_slice(&output, None).unwrap().pages().len(), 5);
+    }
+
+    #[test]
+    fn test_render_page_inverted() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0 g 0 0 30 100 re f 0.5 g 30 0 30 100 re f"),
+        ]);
+        let mean = |page_data: &PageData| {
+            let image = image::load_from_memory(&page_data.image_buffer).unwrap().into_rgb8();
+            image.iter().map(|&v| v as f64).sum::<f64>() / image.len() as f64
+        };
+
+        let options = RenderOptions::default().with_dpi(72).with_quality(75);
+        let normal = render_page_with_options(pdfium, &pdf_bytes, 0, &options).expect("Failed to render page");
+        let inverted = render_page_inverted(pdfium, &pdf_bytes, 0, 72, 75, None).expect("Failed to render inverted page");
+        assert!((mean(&inverted) - (255.0 - mean(&normal))).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_render_first_n_pages() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+
+        let preview = render_first_n_pages(pdfium, &pdf_bytes, 2, 75, ImageFormat::Png, None).expect("Failed to render preview");
+        assert_eq!(preview.len(), 2);
+        let first = render_page_with_options(pdfium, &pdf_bytes, 0, &RenderOptions::default().with_format(ImageFormat::Png))
+            .unwrap();
+        assert_eq!(preview[0].image_buffer, first.image_buffer);
+
+        assert_eq!(render_first_n_pages(pdfium, &pdf_bytes, 10, 75, ImageFormat::Png, None).unwrap().len(), 5);
+        assert!(render_first_n_pages(pdfium, &pdf_bytes, 0, 75, ImageFormat::Png, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_create_blank_pdf() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = create_blank_pdf(pdfium, &[A4_PT, LETTER_PT, (200.0, 100.0)]).expect("Failed to create PDF");
+        let document = pdfium.load_pdf_from_byte_slice(&pdf_bytes, None).unwrap();
+        let sizes: Vec<_> = document.pages().iter().map(|page| (page.width().value, page.height().value)).collect();
+        assert_eq!(sizes.len(), 3);
+        assert!((sizes[0].0 - 595.28).abs() < 0.01 && (sizes[0].1 - 841.89).abs() < 0.01);
+        assert_eq!(sizes[1], (612.0, 792.0));
+        assert_eq!(sizes[2], (200.0, 100.0));
+        assert_eq!(document.pages().get(0).unwrap().objects().len(), 0);
+
+        assert!(create_blank_pdf(pdfium, &[]).is_err());
+        assert!(create_blank_pdf(pdfium, &[(0.0, 100.0)]).is_err());
+    }
+
+    #[test]
+    fn test_add_text_to_page() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 300 200] >>".to_string(),
+        ]);
+
+        let stamped = add_text_to_page(pdfium, &pdf_bytes, 0, "INV-0042", 20.0, 150.0, 14.0, [200, 0, 0], None, None)
+            .expect("Failed to add text");
+        let stamped = add_text_to_page(pdfium, &stamped, 0, "2026-10-15", 20.0, 120.0, 12.0, [0, 0, 0], Some("courier"), None)
+            .expect("Failed to add text");
+
+        let document = pdfium.load_pdf_from_byte_slice(&stamped, None).unwrap();
+        let page = document.pages().get(0).unwrap();
+        let text = page.text().unwrap().all();
+        assert!(text.contains("INV-0042"));
+        assert!(text.contains("2026-10-15"));
+        assert_eq!(page.objects().len(), 2);
+        let first = page.objects().get(0).unwrap();
+        let color = first.fill_color().unwrap();
+        assert_eq!((color.red(), color.green(), color.blue()), (200, 0, 0));
+        assert!((first.bounds().unwrap().left().value - 20.0).abs() < 2.0);
+
+        assert!(add_text_to_page(pdfium, &pdf_bytes, 0, "x", 0.0, 0.0, 12.0, [0, 0, 0], Some("No Such Font"), None).is_err());
+        assert!(add_text_to_page(pdfium, &pdf_bytes, 1, "x", 0.0, 0.0, 12.0, [0, 0, 0], None, None).is_err());
+    }
+
+    #[test]
+    fn test_measure_text() {
+        let pdfium = load_pdfium();
+
+        // Helvetica's digits are all 556/1000 em wide
+        let width = measure_text(pdfium, "0123456789", None, 10.0).expect("Failed to measure text");
+        assert!((width - 55.6).abs() < 1.5, "width {}", width);
+        let courier = measure_text(pdfium, "abcd", Some("Courier"), 20.0).expect("Failed to measure text");
+        assert!((courier - 48.0).abs() < 2.0, "width {}", courier);
+        assert_eq!(measure_text(pdfium, "", None, 12.0).unwrap(), 0.0);
+
+        assert!(measure_text(pdfium, "x", None, 0.0).is_err());
+        assert!(measure_text(pdfium, "x", Some("No Such Font"), 12.0).is_err());
+    }
+
+    #[test]
+    fn test_extract_toc_text() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /Outlines 5 0 R \
+             /PageLabels << /Nums [0 << /S /r >> 1 << /S /D >>] >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>".to_string(),
+            "<< /Type /Outlines /First 6 0 R /Last 7 0 R /Count 3 >>".to_string(),
+            "<< /Title (Preface) /Parent 5 0 R /Next 7 0 R /Dest [3 0 R /Fit] >>".to_string(),
+            "<< /Title (Chapter 1) /Parent 5 0 R /Prev 6 0 R /First 8 0 R /Last 8 0 R /Count 1 /Dest [4 0 R /Fit] >>".to_string(),
+            "<< /Title (Section 1.1) /Parent 7 0 R /A << /S /GoTo /D [4 0 R /XYZ 0 50 0] >> >>".to_string(),
+        ]);
+
+        let toc = extract_toc_text(pdfium, &pdf_bytes, None, None).expect("Failed to extract TOC");
+        let entry = |level: usize, title: &str, page_label: &str| TocEntry {
+            level,
+            title: title.to_string(),
+            page_label: page_label.to_string(),
+        };
+        assert_eq!(toc, vec![
+            entry(1, "Preface", "i"),
+            entry(1, "Chapter 1", "1"),
+            entry(2, "Section 1.1", "1"),
+        ]);
+    }
+
+    #[test]
+    fn test_render_pdf_files_parallel() {
+        let pdfium = load_pdfium();
+        let paths = [Path::new("./samples/test.pdf"), Path::new("./samples/missing.pdf"), Path::new("./samples/test.pdf")];
+
+        let results = render_pdf_files_parallel(pdfium, &paths, 75, ImageFormat::Jpeg);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, PathBuf::from("./samples/test.pdf"));
+        assert_eq!(results[0].1.as_ref().unwrap().len(), 5);
+        assert!(matches!(results[1].1, Err(PdfError::Io(_))));
+        assert_eq!(results[2].1.as_ref().unwrap().len(), 5);
+    }
+
+    /// A one-page tagged document with a heading, an inline image inside a
+    /// Figure and a table, parameterised on the bits `validate_pdfua` checks
+    fn pdfua_fixture(lang: &str, second_heading: &str, figure_alt: &str, header_cell: &str) -> Vec<u8> {
+        let content = format!(
+            "/H1 <</MCID 0>> BDC BT /F1 24 Tf 72 700 Td (Title) Tj ET EMC \
+             /{second_heading} <</MCID 1>> BDC BT /F1 18 Tf 72 660 Td (Section) Tj ET EMC \
+             /Figure <</MCID 2>> BDC q 100 0 0 100 72 500 cm BI /W 1 /H 1 /CS /G /BPC 8 ID 0 EI Q EMC \
+             /{header_cell} <</MCID 3>> BDC BT /F1 12 Tf 72 400 Td (Name) Tj ET EMC \
+             /Artifact BMC q 100 0 0 10 72 50 cm BI /W 1 /H 1 /CS /G /BPC 8 ID 0 EI Q EMC"
+        );
+        build_pdf(&[
+            format!("<< /Type /Catalog /Pages 2 0 R /StructTreeRoot 5 0 R /MarkInfo << /Marked true >> {lang} >>"),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /StructParents 0 \
+             /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >> >>".to_string(),
+            stream_object("", &content),
+            "<< /Type /StructTreeRoot /K [6 0 R] /ParentTree 13 0 R >>".to_string(),
+            "<< /Type /StructElem /S /Document /P 5 0 R /K [7 0 R 8 0 R 9 0 R 10 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /H1 /P 6 0 R /Pg 3 0 R /K 0 >>".to_string(),
+            format!("<< /Type /StructElem /S /{second_heading} /P 6 0 R /Pg 3 0 R /K 1 >>"),
+            format!("<< /Type /StructElem /S /Figure /P 6 0 R /Pg 3 0 R /K 2 {figure_alt} >>"),
+            "<< /Type /StructElem /S /Table /P 6 0 R /Pg 3 0 R /K [11 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /TR /P 10 0 R /Pg 3 0 R /K [12 0 R] >>".to_string(),
+            format!("<< /Type /StructElem /S /{header_cell} /P 11 0 R /Pg 3 0 R /K 3 >>"),
+            "<< /Nums [0 [7 0 R 8 0 R 9 0 R 12 0 R]] >>".to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_validate_pdfua() {
+        let pdfium = load_pdfium();
+
+        let accessible = pdfua_fixture("/Lang (en-US)", "H2", "/Alt (A grey square)", "TH");
+        assert!(validate_pdfua(pdfium, &accessible, None).expect("Failed to validate").is_empty());
+
+        let inaccessible = pdfua_fixture("", "H3", "", "TD");
+        let violations = validate_pdfua(pdfium, &inaccessible, None).expect("Failed to validate");
+        let rules: Vec<&str> = violations.iter().map(|violation| violation.rule.as_str()).collect();
+        assert_eq!(rules, ["missing-language", "heading-level-skipped", "table-missing-headers", "image-missing-alt-text"]);
+        assert_eq!(violations[0].page_index, None);
+        assert_eq!(violations[1].severity, ViolationSeverity::Warning);
+        // The figure's image is the third object on the page, after the two text runs
+        assert_eq!(violations[3].object_index, Some(2));
+
+        let untagged = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /Lang (en) >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>".to_string(),
+        ]);
+        let violations = validate_pdfua(pdfium, &untagged, None).expect("Failed to validate");
+        assert_eq!(violations, [AccessibilityViolation::new("untagged-page", Some(0), None, ViolationSeverity::Error)]);
     }
 
-    let document = pdfium
-        .load_pdf_from_byte_slice(pdf_bytes, None)
-        .map_err(|e| format!("Failed to load PDF: {}", e))?;
+    #[test]
+    fn test_structure_tree_violations() {
+        // A table whose header row is on page 1 and body on page 2, and
+        // headings H1, H2 (page 1) then H4 (page 2)
+        let pdf = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /StructTreeRoot 5 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>".to_string(),
+            "<< /Type /StructTreeRoot /K 6 0 R >>".to_string(),
+            "<< /Type /StructElem /S /Document /P 5 0 R /K [7 0 R 8 0 R 9 0 R 12 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /H1 /P 6 0 R /Pg 3 0 R /K 0 >>".to_string(),
+            "<< /Type /StructElem /S /H2 /P 6 0 R /Pg 3 0 R /K 1 >>".to_string(),
+            "<< /Type /StructElem /S /Table /P 6 0 R /Pg 3 0 R /K [10 0 R 11 0 R] >>".to_string(),
+            "<< /Type /StructElem /S /TR /P 9 0 R /Pg 3 0 R /K << /Type /StructElem /S /TH /Pg 3 0 R /K 2 >> >>".to_string(),
+            "<< /Type /StructElem /S /TR /P 9 0 R /Pg 4 0 R /K << /Type /StructElem /S /TD /K 0 >> >>".to_string(),
+            "<< /Type /StructElem /S /H4 /P 6 0 R /Pg 4 0 R /K [<< /Type /MCR /Pg 4 0 R /MCID 1 >>] >>".to_string(),
+        ]);
+        let document = lopdf::Document::load_mem(&pdf).unwrap();
 
-    let images: Vec<_> = document
-        .pages()
-        .iter()
-        .map(|page| {
-            let bitmap = page.render_with_config(&PdfRenderConfig::new()
-                .rotate_if_landscape(PdfPageRenderRotation::Degrees90, true)
-                .render_form_data(true)
-                .use_grayscale_rendering(false))
-                .map_err(|e| format!("Failed to render PDF page: {}", e))
-                .map(|bitmap| bitmap.as_image().into_rgb8())?;
+        assert_eq!(
+            structure_tree_violations(&document),
+            [AccessibilityViolation::new("heading-level-skipped", Some(1), None, ViolationSeverity::Warning)]
+        );
+    }
 
-            let result = {
-                let mut buffer = Cursor::new(Vec::new());
+    #[test]
+    fn test_heading_level() {
+        assert_eq!(heading_level("H1"), Some(1));
+        assert_eq!(heading_level("H6"), Some(6));
+        assert_eq!(heading_level("H7"), None);
+        assert_eq!(heading_level("H"), None);
+        assert_eq!(heading_level("Figure"), None);
+    }
 
-                let dynamic_image = DynamicImage::ImageRgb8(bitmap.to_owned());
-                let webp_image = {
-                    let encoder = webp::Encoder::from_image(&dynamic_image)
-                        .map_err(|e| format!("Failed to create WebP encoder: {}", e))?;
-                    encoder.encode(quality as f32)
-                };
-                
-                buffer.write_all(&*webp_image)
-                    .map_err(|e| format!("Failed to write WebP image: {}", e))?;
-                
-                drop(dynamic_image);
-                drop(webp_image);
-
-                let image_buffer = buffer.into_inner();
-                
-                PageData {
-                    image_buffer: image_buffer,
+    #[test]
+    fn test_encode_animated_webp() {
+        let frames = [
+            RgbImage::from_pixel(40, 60, image::Rgb([255, 0, 0])),
+            RgbImage::from_pixel(60, 40, image::Rgb([0, 0, 255])),
+        ];
+        let animation = encode_animated_webp(&frames, 75, 500).expect("Failed to encode animation");
+
+        assert_eq!(&animation[0..4], b"RIFF");
+        assert_eq!(&animation[8..12], b"WEBP");
+        assert!(animation.windows(4).any(|chunk| chunk == b"ANIM"));
+
+        let decoded = webp::AnimDecoder::new(&animation).decode().expect("Failed to decode animation");
+        assert_eq!(decoded.len(), 2);
+        let timestamps: Vec<i32> = (0..decoded.len()).map(|index| decoded.get_frame(index).unwrap().get_time_ms()).collect();
+        // Decoded timestamps mark when each frame ends
+        assert_eq!(timestamps, [500, 1000]);
+
+        let single = encode_animated_webp(&frames[..1], 75, 500).expect("Failed to encode animation");
+        assert!(!single.windows(4).any(|chunk| chunk == b"ANIM"));
+
+        assert!(encode_animated_webp(&[], 75, 500).is_err());
+    }
+
+    #[test]
+    fn test_render_pdf_as_animated_webp() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+
+        let animation = render_pdf_as_animated_webp(pdfium, &pdf_bytes, 50, 1000, None).expect("Failed to render animation");
+        assert!(animation.windows(4).any(|chunk| chunk == b"ANIM"));
+        assert!(render_pdf_as_animated_webp(pdfium, &pdf_bytes, 50, 0, None).is_err());
+    }
+
+    fn gif_frame_count(gif: &[u8]) -> usize {
+        use image::AnimationDecoder;
+
+        image::codecs::gif::GifDecoder::new(Cursor::new(gif))
+            .expect("Failed to read GIF")
+            .into_frames()
+            .count()
+    }
+
+    #[test]
+    fn test_encode_gif() {
+        assert_eq!("gif".parse::<ImageFormat>().unwrap(), ImageFormat::Gif { frame_delay_ms: DEFAULT_GIF_FRAME_DELAY_MS });
+
+        let page = RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 200]));
+        let still = encode_image(&page, ImageFormat::Gif { frame_delay_ms: 500 }, 75).expect("Failed to encode GIF");
+        assert!(still.starts_with(b"GIF89a"));
+        assert_eq!(gif_frame_count(&still), 1);
+
+        let animation = encode_gif(&[page.clone(), page.clone(), page], 500).expect("Failed to encode GIF");
+        assert!(animation.starts_with(b"GIF89a"));
+        assert_eq!(gif_frame_count(&animation), 3);
+    }
+
+    #[test]
+    fn test_gif_palette() {
+        for value in 0..=255u8 {
+            for color in [image::Rgb([value, 0, 0]), image::Rgb([0, value, 0]), image::Rgb([value, value, value])] {
+                let mut mapped = color;
+                GifPalette.map_color(&mut mapped);
+                assert!(GifPalette.index_of(&mapped) < 252);
+                assert_eq!(GifPalette.index_of(&mapped), GifPalette.index_of(&color));
+            }
+        }
+        let mut white = image::Rgb([255, 255, 255]);
+        GifPalette.map_color(&mut white);
+        assert_eq!(white, image::Rgb([255, 255, 255]));
+        assert_eq!(GifPalette.index_of(&white), 251);
+    }
+
+    #[test]
+    fn test_render_pdf_as_gif() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+        let page_count = load_document(pdfium, &pdf_bytes).unwrap().pages().len() as usize;
+
+        let gif = render_pdf_as_gif(pdfium, &pdf_bytes, 1000, None).expect("Failed to render GIF");
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(gif_frame_count(&gif), page_count);
+    }
+
+    #[test]
+    fn test_page_number_position_from_str() {
+        assert_eq!("bottom_center".parse::<PageNumberPosition>().unwrap(), PageNumberPosition::BottomCenter);
+        assert_eq!("TOP_RIGHT".parse::<PageNumberPosition>().unwrap(), PageNumberPosition::TopRight);
+        assert!("middle".parse::<PageNumberPosition>().is_err());
+    }
+
+    #[test]
+    fn test_add_page_numbers() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = create_blank_pdf(pdfium, &[LETTER_PT, LETTER_PT, A4_PT]).expect("Failed to create PDF");
+
+        let numbered = add_page_numbers(pdfium, &pdf_bytes, 7, PageNumberPosition::TopRight, 10.0, [255, 0, 0], None)
+            .expect("Failed to add page numbers");
+
+        let document = load_document(pdfium, &numbered).expect("Failed to load numbered PDF");
+        for (index, page) in document.pages().iter().enumerate() {
+            let text = page.text().expect("Failed to read page text").all();
+            assert_eq!(text.trim(), (7 + index).to_string());
+
+            let object = page.objects().iter().find_map(|object| object.as_text_object().map(|text| text.bounds().unwrap()))
+                .expect("Page number object missing");
+            assert!(object.right().value <= page.width().value - PAGE_NUMBER_MARGIN + 0.5);
+            assert!(object.top().value > page.height().value / 2.0);
+        }
+
+        assert!(add_page_numbers(pdfium, &pdf_bytes, 1, PageNumberPosition::BottomCenter, 0.0, [0, 0, 0], None).is_err());
+    }
+
+    #[test]
+    fn test_get_page_image_count() {
+        let pdfium = load_pdfium();
+
+        let image = "q 10 0 0 10 0 0 cm BI /W 1 /H 1 /CS /G /BPC 8 ID 0 EI Q";
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 5 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R \
+             /Resources << /XObject << /Fm1 7 0 R >> >> >>".to_string(),
+            stream_object("", &format!("{image} {image} /Fm1 Do")),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 6 0 R >>".to_string(),
+            stream_object("", "0 g 0 0 10 10 re f"),
+            stream_object("/Type /XObject /Subtype /Form /BBox [0 0 200 200]", image),
+        ]);
+
+        assert_eq!(get_page_image_count(pdfium, &pdf_bytes, None, None).expect("Failed to count images"), [3, 0]);
+    }
+
+    #[test]
+    fn test_append_pdf() {
+        let pdfium = load_pdfium();
+        let page_count = |bytes: &[u8]| load_document(pdfium, bytes).unwrap().pages().len();
+
+        let base = create_blank_pdf(pdfium, &[LETTER_PT, LETTER_PT]).expect("Failed to create PDF");
+        let appendix = create_blank_pdf(pdfium, &[A4_PT, A4_PT, A4_PT]).expect("Failed to create PDF");
+
+        let combined = append_pdf(pdfium, &base, &appendix, None).expect("Failed to append PDF");
+        assert_eq!(page_count(&combined), page_count(&base) + page_count(&appendix));
+        let document = load_document(pdfium, &combined).unwrap();
+        assert_eq!(document.pages().get(2).unwrap().height().value.round(), A4_PT.1.round());
+
+        assert_eq!(append_pdf(pdfium, &[], &appendix, None).expect("Failed to append PDF"), appendix);
+        assert!(append_pdf(pdfium, &base, b"not a pdf", None).is_err());
+    }
+
+    #[test]
+    fn test_rotate_to_landscape_and_portrait() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = create_blank_pdf(pdfium, &[LETTER_PT, (792.0, 612.0), (500.0, 500.0)]).expect("Failed to create PDF");
+        let rotations = |bytes: &[u8]| -> Vec<PdfPageRenderRotation> {
+            let document = load_document(pdfium, bytes).unwrap();
+            let rotations = document.pages().iter().map(|page| page.rotation().unwrap()).collect();
+            rotations
+        };
+
+        let landscape = rotate_to_landscape(pdfium, &pdf_bytes, None).expect("Failed to rotate");
+        assert_eq!(rotations(&landscape), [PdfPageRenderRotation::Degrees90, PdfPageRenderRotation::None, PdfPageRenderRotation::None]);
+        let document = load_document(pdfium, &landscape).unwrap();
+        assert!(document.pages().iter().all(|page| page.width() >= page.height()));
+
+        let portrait = rotate_to_portrait(pdfium, &pdf_bytes, None).expect("Failed to rotate");
+        assert_eq!(rotations(&portrait), [PdfPageRenderRotation::None, PdfPageRenderRotation::Degrees90, PdfPageRenderRotation::None]);
+
+        // Pages already turned by rotate_to_landscape now display as landscape
+        assert_eq!(rotate_to_landscape(pdfium, &landscape, None).map(|bytes| rotations(&bytes)).unwrap(), rotations(&landscape));
+    }
+
+    #[test]
+    fn test_max_pages() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = create_blank_pdf(pdfium, &[LETTER_PT; 5]).expect("Failed to create PDF");
+
+        let error = render_pdf(pdfium, &pdf_bytes, &RenderOptions::default().with_max_pages(3)).unwrap_err();
+        assert_eq!(error.to_string(), PdfError::InvalidArgument("PDF has 5 pages, max allowed is 3".to_string()).to_string());
+        assert!(document_stats(pdfium, &pdf_bytes, Some(3), None).is_err());
+        assert!(document_stats(pdfium, &pdf_bytes, None, Some(pdf_bytes.len() - 1)).is_err());
+        assert!(render_pdf_to_cbz_with_options(pdfium, &pdf_bytes, &RenderOptions::default().with_max_pages(3)).is_err());
+        assert!(render_pdf_to_png_with_options(pdfium, &pdf_bytes, &RenderOptions::default().with_max_pages(3)).is_err());
+        assert!(stitch_pages_vertically(pdfium, &pdf_bytes, 200, 75, ImageFormat::Webp, 0, Some(3)).is_err());
+        assert!(generate_page_previews_json(pdfium, &pdf_bytes, 100, 60, Some(3)).is_err());
+        assert!(pdf_to_text(pdfium, &pdf_bytes, false, Some(3)).is_err());
+        assert!(compress_pdf_with_downsample(pdfium, &pdf_bytes, 75, 150.0, Some(3)).is_err());
+        assert!(copy_pages(pdfium, &pdf_bytes, &pdf_bytes, &[0], Some(3)).is_err());
+        assert!(has_digital_signature(pdfium, &pdf_bytes, Some(3)).is_err());
+        assert!(count_digital_signatures(pdfium, &pdf_bytes, Some(3)).is_err());
+        assert!(list_signature_signers(pdfium, &pdf_bytes, Some(3)).is_err());
+        assert!(compare_metadata(pdfium, &pdf_bytes, &pdf_bytes, Some(3)).is_err());
+        assert!(rotate_to_landscape(pdfium, &pdf_bytes, Some(3)).is_err());
+        assert!(rotate_to_portrait(pdfium, &pdf_bytes, Some(3)).is_err());
+        assert!(open_document(pdfium, pdf_bytes.clone(), Some(3)).is_err());
+        assert!(render_thumbnails(pdfium, &pdf_bytes, 64, &RenderOptions::default().with_max_pages(3)).is_err());
+        assert!(render_page_to_raw(pdfium, &pdf_bytes, 0, 3, &RenderOptions::default().with_max_pages(3)).is_err());
+
+        assert_eq!(document_stats(pdfium, &pdf_bytes, Some(5), Some(pdf_bytes.len())).expect("Failed to get stats").page_count, 5);
+        assert_eq!(render_pdf(pdfium, &pdf_bytes, &RenderOptions::default().with_max_pages(5)).expect("Failed to render").len(), 5);
+    }
+
+    #[test]
+    fn test_max_pdf_bytes() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = create_blank_pdf(pdfium, &[LETTER_PT]).expect("Failed to create PDF");
+
+        let options = RenderOptions::default().with_max_pdf_bytes(pdf_bytes.len() - 1);
+        let error = render_pdf(pdfium, &pdf_bytes, &options).unwrap_err();
+        assert_eq!(error.to_string(), PdfError::InvalidArgument("PDF exceeds maximum allowed size".to_string()).to_string());
+        assert!(render_page_with_options(pdfium, &pdf_bytes, 0, &options).is_err());
+        assert!(render_thumbnails(pdfium, &pdf_bytes, 64, &options).is_err());
+
+        let options = RenderOptions::default().with_max_pdf_bytes(pdf_bytes.len());
+        assert_eq!(render_pdf(pdfium, &pdf_bytes, &options).expect("Failed to render").len(), 1);
+    }
+
+    #[test]
+    fn test_hash_pdf_content() {
+        let pdfium = load_pdfium();
+        let text_pdf = |text: &str| build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 100] /Contents 4 0 R \
+             /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >> >>".to_string(),
+            stream_object("", &format!("BT /F1 12 Tf 20 50 Td ({}) Tj ET", text)),
+        ]);
+        let original = text_pdf("Hello");
+
+        // Same content, but with an /Info dictionary and a rewritten file layout
+        let mut document = lopdf::Document::load_mem(&original).unwrap();
+        let info = document.add_object(lopdf::dictionary! { "Title" => lopdf::Object::string_literal("Draft") });
+        document.trailer.set("Info", info);
+        let mut retitled = Vec::new();
+        document.save_to(&mut retitled).unwrap();
+        assert_ne!(retitled, original);
+
+        let hash = hash_pdf_content(pdfium, &original, None).expect("Failed to hash PDF");
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash_pdf_content(pdfium, &retitled, None).unwrap(), hash);
+        assert_ne!(hash_pdf_content(pdfium, &text_pdf("Hullo"), None).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_median_cut() {
+        let red = [255, 0, 0];
+        let blue = [0, 0, 255];
+        let mut pixels = vec![red; 75];
+        pixels.extend([blue; 25]);
+
+        assert_eq!(median_cut(pixels.clone(), 2), [red, blue]);
+        // Two distinct colors can't make five clusters
+        assert_eq!(median_cut(pixels.clone(), 5), [red, blue]);
+        assert_eq!(median_cut(pixels, 1), [[191, 0, 63]]);
+    }
+
+    #[test]
+    fn test_extract_color_palette() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Contents 4 0 R >>".to_string(),
+            stream_object("", "1 0 0 rg 0 0 100 75 re f 0 0 1 rg 0 75 100 25 re f"),
+        ]);
+
+        let palette = extract_color_palette(pdfium, &pdf_bytes, 0, 5, None).expect("Failed to extract palette");
+        assert!((1..=5).contains(&palette.len()));
+        assert_eq!(palette[0], [255, 0, 0]);
+        assert!(palette.contains(&[0, 0, 255]));
+
+        assert!(extract_color_palette(pdfium, &pdf_bytes, 0, 0, None).is_err());
+        assert!(extract_color_palette(pdfium, &pdf_bytes, 1, 5, None).is_err());
+    }
+
+    #[test]
+    fn test_repair_pdf() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>".to_string(),
+        ]);
+
+        // Point startxref into the middle of an object
+        let text = String::from_utf8(pdf_bytes).unwrap();
+        let (head, tail) = text.rsplit_once("startxref\n").unwrap();
+        let (_, eof) = tail.split_once('\n').unwrap();
+        let broken = format!("{}startxref\n42\n{}", head, eof).into_bytes();
+
+        let repaired = repair_pdf(pdfium, &broken, None).expect("Failed to repair PDF");
+        assert_eq!(batch_get_page_counts(&[&repaired])[0].as_ref().unwrap(), &2);
+
+        let text = String::from_utf8_lossy(&repaired);
+        let offset: usize = text.rsplit_once("startxref").unwrap().1.split_whitespace().next().unwrap().parse().unwrap();
+        assert!(repaired[offset..].starts_with(b"xref"));
+    }
+
+    #[test]
+    fn test_output_color_space() {
+        assert_eq!("Display_P3".parse::<OutputColorSpace>().unwrap(), OutputColorSpace::DisplayP3);
+        assert_eq!("adobe_rgb".parse::<OutputColorSpace>().unwrap(), OutputColorSpace::AdobeRgb);
+        assert!("prophoto".parse::<OutputColorSpace>().is_err());
+        assert!(OutputColorSpace::Srgb.icc_profile().is_none());
+
+        // Pure sRGB red lies inside the wider gamuts, so it loses saturation
+        let icc_profile = OutputColorSpace::DisplayP3.icc_profile().unwrap();
+        let mut image = RgbImage::from_pixel(1, 1, image::Rgb([255, 0, 0]));
+        apply_icc_profile(&mut image, &icc_profile).unwrap();
+        let [r, g, b] = image.get_pixel(0, 0).0;
+        assert!(r < 255 && g > 0 && b > 0, "{:?}", [r, g, b]);
+
+        let options = RenderOptions::default().with_color_space(OutputColorSpace::AdobeRgb);
+        assert!(options.clone().validate().is_ok());
+        assert!(RenderOptions { icc_profile: Some(icc_profile), ..options }.validate().is_err());
+    }
+
+    #[test]
+    fn test_embed_icc_profile() {
+        use image::ImageDecoder;
+
+        let icc_profile = OutputColorSpace::AdobeRgb.icc_profile().unwrap();
+        let image = RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 128]));
+        let tagged = |format| {
+            let encoded = encode_image(&image, format, 80).unwrap();
+            embed_icc_profile(encoded, format, &icc_profile, image.dimensions()).unwrap()
+        };
+
+        let png = tagged(ImageFormat::Png);
+        let mut decoder = image::codecs::png::PngDecoder::new(Cursor::new(&png)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_ref(), Some(&icc_profile));
+        assert_eq!(image::load_from_memory(&png).unwrap().into_rgb8(), image);
+
+        let jpeg = tagged(ImageFormat::Jpeg);
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(&jpeg)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_ref(), Some(&icc_profile));
+
+        let webp = tagged(ImageFormat::Webp);
+        let mut decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(&webp)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_ref(), Some(&icc_profile));
+        assert_eq!(decoder.dimensions(), (16, 8));
+        assert!(webp::Decoder::new(&webp).decode().is_some());
+    }
+
+    #[test]
+    fn test_extract_signature_images() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R] /SigFlags 1 >> >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Annots [4 0 R] >>".to_string(),
+            "<< /Type /Annot /Subtype /Widget /FT /Sig /T (Signer1) /F 4 /P 3 0 R \
+             /Rect [50 50 150 100] /AP << /N 5 0 R >> >>".to_string(),
+            stream_object(
+                "/Type /XObject /Subtype /Form /BBox [0 0 100 50] /Resources << /XObject << /Im1 6 0 R >> >>",
+                "q 100 0 0 50 0 0 cm /Im1 Do Q",
+            ),
+            // A 4x2 checkerboard standing in for a scanned signature
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 4 /Height 2 /ColorSpace /DeviceGray \
+                 /BitsPerComponent 8 /Filter /ASCIIHexDecode",
+                "00FF00FFFF00FF00>",
+            ),
+        ]);
+
+        let images = extract_signature_images(pdfium, &pdf_bytes, None).expect("Failed to extract signature images");
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].page_index, 0);
+        assert_eq!(images[0].field_name, "Signer1");
+
+        let image = image::load_from_memory_with_format(&images[0].image_buffer, image::ImageFormat::Png).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 2));
+        assert_eq!(image.into_luma8().get_pixel(1, 0).0, [255]);
+    }
+
+    #[test]
+    fn test_downsample_pdf_images() {
+        let photo = |size: u32| -> Vec<u8> { (0..size * size).flat_map(|i| [(i % size) as u8, (i / size) as u8, 128]).collect() };
+        let base64_pdf = image_pdf(vec![
+            image_stream(600, 600, "DeviceRGB", 8, photo(600)),
+            image_stream(100, 100, "DeviceRGB", 8, photo(100)),
+            image_stream(200, 200, "DeviceRGB", 8, photo(200)),
+        ]);
+        // One inch squares: the first image at 600 DPI, the second at 100 DPI
+        // and the third, inside a form scaled down by half, at 400 DPI
+        let mut document = lopdf::Document::load_mem(&BASE64.decode(&base64_pdf).unwrap()).unwrap();
+        let mut form_xobjects = lopdf::Dictionary::new();
+        form_xobjects.set("Im2", (3, 0));
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("XObject", form_xobjects);
+        let mut form = lopdf::Dictionary::new();
+        form.set("Type", lopdf::Object::Name(b"XObject".to_vec()));
+        form.set("Subtype", lopdf::Object::Name(b"Form".to_vec()));
+        form.set("BBox", vec![0.into(), 0.into(), 144.into(), 144.into()]);
+        form.set("Matrix", vec![0.5.into(), 0.into(), 0.into(), 0.5.into(), 0.into(), 0.into()]);
+        form.set("Resources", resources);
+        let form_id = document.add_object(lopdf::Stream::new(form, b"q 72 0 0 72 0 0 cm /Im2 Do Q".to_vec()));
+        let page_id = *document.get_pages().get(&1).unwrap();
+        let content = b"q 72 0 0 72 0 0 cm /Im0 Do Q q 72 0 0 72 72 0 cm /Im1 Do Q /Fm0 Do".to_vec();
+        let content_id = document.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), content));
+        let page = document.get_object_mut(page_id).and_then(lopdf::Object::as_dict_mut).unwrap();
+        page.set("Contents", content_id);
+        let xobjects = page.get_mut(b"Resources").and_then(lopdf::Object::as_dict_mut).unwrap()
+            .get_mut(b"XObject").and_then(lopdf::Object::as_dict_mut).unwrap();
+        xobjects.set("Fm0", form_id);
+        let mut pdf_bytes = Vec::new();
+        document.save_to(&mut pdf_bytes).unwrap();
+
+        let dpi = image_display_dpi(&document);
+        assert_eq!(dpi.values().copied().collect::<Vec<f32>>(), vec![600.0, 100.0, 400.0]);
+
+        let compressed = downsample_pdf_images(&pdf_bytes, 75, 150.0).expect("Failed to compress");
+        let document = lopdf::Document::load_mem(&compressed).unwrap();
+        let sizes: Vec<(i64, i64)> = (1..=3)
+            .map(|number| {
+                let dict = &document.get_object((number, 0)).and_then(lopdf::Object::as_stream).unwrap().dict;
+                (dict.get(b"Width").unwrap().as_i64().unwrap(), dict.get(b"Height").unwrap().as_i64().unwrap())
+            })
+            .collect();
+        assert_eq!(sizes, vec![(150, 150), (100, 100), (75, 75)]);
+        assert_eq!(image_filters(&BASE64.encode(&compressed)), vec![Some("DCTDecode".to_string()); 3]);
+
+        assert!(downsample_pdf_images(&pdf_bytes, 75, 0.0).is_err());
+        assert!(downsample_pdf_images(&pdf_bytes, 0, 150.0).is_err());
+    }
+
+    #[test]
+    fn test_compress_pdf_with_downsample() {
+        let pdfium = load_pdfium();
+        let photo: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128]).collect();
+        let pdf_bytes = BASE64.decode(image_pdf(vec![image_stream(64, 64, "DeviceRGB", 8, photo)])).unwrap();
+
+        let compressed = compress_pdf_with_downsample(pdfium, &pdf_bytes, 75, 150.0, None).expect("Failed to compress");
+        assert!(compressed.len() < pdf_bytes.len());
+        assert!(compress_pdf_with_downsample(pdfium, b"not a pdf", 75, 150.0, None).is_err());
+    }
+
+    #[test]
+    fn test_smart_compress_images() {
+        let photo: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128]).collect();
+        let line_art: Vec<u8> = (0..64u32 * 64).flat_map(|i| if (i % 64 / 16 + i / 64 / 16) % 2 == 0 { [200, 30, 30] } else { [30, 30, 200] }).collect();
+        let screenshot: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i / 64 * 4) as u8, (i % 64 / 8 * 32) as u8, 90]).collect();
+        let bitonal: Vec<u8> = (0..64u32 * 64).map(|i| if (i % 64 / 4) % 2 == 0 { 0 } else { 255 }).collect();
+        let base64_pdf = image_pdf(vec![
+            image_stream(64, 64, "DeviceRGB", 8, photo),
+            image_stream(64, 64, "DeviceRGB", 8, line_art),
+            image_stream(64, 64, "DeviceRGB", 8, screenshot),
+            image_stream(64, 64, "DeviceGray", 8, bitonal),
+        ]);
+        let pdf_bytes = BASE64.decode(&base64_pdf).unwrap();
+
+        let (compressed, report) = smart_compress_images(&pdf_bytes).expect("Failed to compress");
+        assert_eq!(report.images_processed, 4);
+        let choices: Vec<&str> = report.per_image_choices.iter().map(|(_, choice)| choice.as_str()).collect();
+        assert_eq!(choices, vec!["JPEG", "Flate (indexed)", "Flate", "CCITT G4"]);
+        assert!(report.savings_bytes > 0);
+        assert!(compressed.len() < pdf_bytes.len());
+        assert_eq!(image_filters(&BASE64.encode(&compressed)), vec![
+            Some("DCTDecode".to_string()),
+            Some("FlateDecode".to_string()),
+            Some("FlateDecode".to_string()),
+            Some("CCITTFaxDecode".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_smart_compress_bitonal_rgb_image() {
+        let pdfium = load_pdfium();
+        // Left half black, right half white, stored as RGB with a /Decode
+        // and an opaque soft mask that only make sense for three components
+        let samples: Vec<u8> = (0..64u32 * 64).flat_map(|i| if i % 64 < 32 { [0; 3] } else { [255; 3] }).collect();
+        let mut image = image_stream(64, 64, "DeviceRGB", 8, samples);
+        image.dict.set("Decode", vec![0.into(), 1.into(), 0.into(), 1.into(), 0.into(), 1.into()]);
+        let pdf_bytes = BASE64.decode(image_pdf(vec![image])).unwrap();
+
+        let mut document = lopdf::Document::load_mem(&pdf_bytes).unwrap();
+        let image_id = *document.objects.keys().find(|&&id| document.get_object(id).unwrap().as_stream().is_ok()).unwrap();
+        let smask = document.add_object(image_stream(64, 64, "DeviceGray", 8, vec![255; 64 * 64]));
+        document.get_object_mut(image_id).and_then(lopdf::Object::as_stream_mut).unwrap().dict.set("SMask", smask);
+        let contents = document.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), b"q 200 0 0 200 0 0 cm /Im0 Do Q".to_vec()));
+        let page_id = document.get_pages()[&1];
+        document.get_dictionary_mut(page_id).unwrap().set("Contents", contents);
+        let mut pdf_bytes = Vec::new();
+        document.save_to(&mut pdf_bytes).unwrap();
+
+        let (compressed, report) = smart_compress_pdf(pdfium, &pdf_bytes, None).expect("Failed to compress");
+        assert!(report.per_image_choices.iter().any(|(id, choice)| *id == image_id.0 as usize && choice == "CCITT G4"));
+
+        let document = lopdf::Document::load_mem(&compressed).unwrap();
+        let dict = &document.get_object(image_id).unwrap().as_stream().unwrap().dict;
+        assert_eq!(dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceGray");
+        assert_eq!(dict.get(b"BitsPerComponent").unwrap().as_i64().unwrap(), 1);
+        assert!(dict.get(b"Decode").is_err() && dict.get(b"SMask").is_err());
+
+        let document = load_document(pdfium, &compressed).unwrap();
+        let rendered = render_page_rgb(&document.pages().get(0).unwrap(), &RenderOptions::default()).unwrap();
+        let (width, height) = rendered.dimensions();
+        assert_eq!(rendered.get_pixel(width / 4, height / 2).0, [0, 0, 0]);
+        assert_eq!(rendered.get_pixel(width * 3 / 4, height / 2).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_encode_flate_png_up() {
+        let samples: Vec<u8> = (0..12u8).collect();
+        let encoded = encode_flate_png_up(&samples, 4).unwrap();
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(&encoded[..]), &mut decoded).unwrap();
+        assert_eq!(decoded, vec![2, 0, 1, 2, 3, 2, 4, 4, 4, 4, 2, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn test_smart_compress_pdf() {
+        let pdfium = load_pdfium();
+        let photo: Vec<u8> = (0..64u32 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128]).collect();
+        let pdf_bytes = BASE64.decode(image_pdf(vec![image_stream(64, 64, "DeviceRGB", 8, photo)])).unwrap();
+
+        let (compressed, report) = smart_compress_pdf(pdfium, &pdf_bytes, None).expect("Failed to compress");
+        assert!(compressed.len() < pdf_bytes.len());
+        assert_eq!(report.per_image_choices.len(), 1);
+        assert!(smart_compress_pdf(pdfium, b"not a pdf", None).is_err());
+    }
+
+    #[test]
+    fn test_render_base64_pdf_with_hooks() {
+        struct Hooks {
+            seen: std::sync::Mutex<Vec<(usize, u32, u32)>>,
+        }
+
+        impl RenderHooks for Hooks {
+            fn before_render(&self, _page_index: usize, config: &mut PdfRenderConfig) {
+                *config = std::mem::take(config).set_target_width(36);
+            }
+
+            fn after_render(&self, page_index: usize, image: &mut DynamicImage) -> Result<(), String> {
+                self.seen.lock().unwrap().push((page_index, image.width(), image.height()));
+                if page_index == 1 {
+                    return Err("rejected".to_string());
                 }
-            };
-            
-            Ok(result)
-        })
-        .collect::<Result<Vec<_>, String>>()?;
+                *image = DynamicImage::ImageRgb8(RgbImage::from_pixel(image.width(), image.height(), image::Rgb([255, 0, 0])));
+                Ok(())
+            }
+        }
 
-    drop(document);
-    
-    Ok(images)
-}
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 144] >>".to_string(),
+        ]);
 
+        let hooks = Hooks { seen: Default::default() };
+        let pages = render_base64_pdf_with_hooks(pdfium, &pdf_bytes, 75, ImageFormat::Png, &hooks)
+            .expect("Failed to render PDF");
+        assert_eq!(*hooks.seen.lock().unwrap(), vec![(0, 36, 72)]);
+        let image = image::load_from_memory(&pages[0].image_buffer).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(10, 10).0, [255, 0, 0]);
 
-/// Opens a PDF from a base64 string and compresses its internal images to JPEG.
+        let two_pages = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 144] >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 144] >>".to_string(),
+        ]);
+        let error = render_base64_pdf_with_hooks(pdfium, &two_pages, 75, ImageFormat::Png, &hooks).unwrap_err();
+        assert!(matches!(error, PdfError::Hook(ref e) if e == "page 1: rejected"));
 
-/// # Arguments
-/// * `base64_pdf` - A base64 encoded string of the source PDF file.
-/// * `quality` - The JPEG quality setting, from 1 (lowest) to 100 (highest).
-///   A value around 75 is a good balance between size and quality.
-///
-/// # Returns
-/// A `Result` containing the base64-encoded compressed PDF, or an error.
-pub fn compress_pdf(
-    base64_pdf: &str,
-    quality: u8,
-) -> Result<String, Box<dyn Error>> {
-    if quality == 0 || quality > 100 {
-        return Err("Quality must be between 1 and 100".into());
+        let pages = render_base64_pdf_with_hooks(pdfium, &two_pages, 75, ImageFormat::Png, &NoOpHooks)
+            .expect("Failed to render PDF");
+        assert_eq!(pages.len(), 2);
     }
 
+    #[test]
+    fn test_stitch_pages_vertically() {
+        let pdfium = load_pdfium();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 5 0 R] /Count 2 >>".to_string(),
+        ];
+        for height in [200, 100] {
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 {}] /Contents {} 0 R >>", height, objects.len() + 2));
+            objects.push(stream_object("", &format!("0 g 0 0 100 {} re f", height)));
+        }
+        let pdf_bytes = build_pdf(&objects);
 
-    Ok(base64_pdf.to_string())
-}
+        let strip = stitch_pages_vertically(pdfium, &pdf_bytes, 50, 75, ImageFormat::Png, 10, None)
+            .expect("Failed to stitch pages");
+        let strip = image::load_from_memory(&strip.image_buffer).unwrap().into_rgb8();
+        assert_eq!(strip.dimensions(), (50, 100 + 10 + 50));
+        assert_eq!(strip.get_pixel(25, 50).0, [0, 0, 0]);
+        assert_eq!(strip.get_pixel(25, 105).0, [255, 255, 255]);
+        assert_eq!(strip.get_pixel(25, 130).0, [0, 0, 0]);
 
-#[cfg(test)]
-mod tests {
-    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-    use super::*;
+        assert!(stitch_pages_vertically(pdfium, &pdf_bytes, 0, 75, ImageFormat::Png, 10, None).is_err());
+    }
 
     #[test]
-    fn test_render_base64_pdf() {
-        let pdfium = Pdfium::new(
-        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
-            .or_else(|_| Pdfium::bind_to_system_library())
-            .expect("Failed to bind to Pdfium library")
+    fn test_smart_replacement() {
+        let choice = |image: DynamicImage| smart_replacement(&image).map(|(choice, _)| choice);
+
+        let mut seed = 12345u32;
+        let photo = GrayImage::from_fn(64, 64, |_, _| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            image::Luma([(seed >> 24) as u8])
+        });
+        assert_eq!(choice(DynamicImage::ImageLuma8(photo)), Some("JPEG"));
+
+        let diagram = GrayImage::from_fn(64, 64, |x, _| image::Luma([if x < 32 { 40 } else { 200 }]));
+        assert_eq!(choice(DynamicImage::ImageLuma8(diagram)), Some("Flate"));
+
+        let chart = RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x % 4) as u8 * 60, (y % 4) as u8 * 60, 90]));
+        assert_eq!(choice(DynamicImage::ImageRgb8(chart)), Some("Flate (indexed)"));
+    }
+
+    #[test]
+    fn test_flate_replacement_drops_decode() {
+        let mut stream = lopdf::Stream::new(
+            lopdf::dictionary! { "Decode" => vec![1.into(), 0.into()] },
+            Vec::new(),
         );
-        let test_pdf_path = "./samples/test.pdf";
-        let pdf_bytes = std::fs::read(test_pdf_path)
+        let replacement = ImageReplacement::Flate {
+            content: vec![1, 2, 3],
+            color_space: lopdf::Object::Name(b"DeviceGray".to_vec()),
+            colors: 1,
+            width: 3,
+        };
+        replacement.apply(&mut stream);
+
+        assert!(!stream.dict.has(b"Decode"));
+        assert_eq!(stream.content, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_encode_image_size_limits() {
+        let tall = RgbImage::new(1, WEBP_MAX_DIMENSION + 1);
+        assert!(matches!(encode_image(&tall, ImageFormat::Webp, 75), Err(PdfError::InvalidArgument(_))));
+        assert!(encode_image(&tall, ImageFormat::Png, 75).is_ok());
+
+        let wide = RgbImage::new(GIF_MAX_DIMENSION + 1, 1);
+        let gif = ImageFormat::Gif { frame_delay_ms: DEFAULT_GIF_FRAME_DELAY_MS };
+        assert!(matches!(encode_image(&wide, gif, 75), Err(PdfError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_clamp_image_colors() {
+        let original = RgbImage::from_raw(3, 1, vec![0, 64, 128, 192, 255, 100, 28, 228, 128]).unwrap();
+
+        let mut image = original.clone();
+        clamp_image_colors(&mut image, 0.0, 1.0);
+        assert_eq!(image, original);
+
+        let mut image = original.clone();
+        clamp_image_colors(&mut image, 40.0, 1.0);
+        assert_eq!(image.into_raw(), vec![40, 104, 168, 232, 255, 140, 68, 255, 168]);
+
+        let mut image = original.clone();
+        clamp_image_colors(&mut image, 0.0, 2.0);
+        assert_eq!(image.into_raw(), vec![0, 0, 128, 255, 255, 72, 0, 255, 128]);
+    }
+
+    #[test]
+    fn test_render_page_with_color_adjustment() {
+        let pdfium = load_pdfium();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 72 72] /Contents 4 0 R >>".to_string(),
+            stream_object("", "0.5 g 0 0 72 72 re f"),
+        ]);
+
+        let page = render_page_with_color_adjustment(pdfium, &pdf_bytes, 0, -50.0, 1.0, 72, 100, None)
+            .expect("Failed to render page");
+        let image = image::load_from_memory(&page.image_buffer).unwrap().into_rgb8();
+        assert_eq!(image.dimensions(), (72, 72));
+        assert!(image.get_pixel(36, 36).0.iter().all(|&value| (70..=85).contains(&value)));
+
+        assert!(render_page_with_color_adjustment(pdfium, &pdf_bytes, 0, 0.0, 0.0, 72, 75, None).is_err());
+        assert!(render_page_with_color_adjustment(pdfium, &pdf_bytes, 1, 0.0, 1.0, 72, 75, None).is_err());
+    }
+
+    #[test]
+    fn test_generate_page_previews_json() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
             .expect("Failed to read test PDF file");
-   
-        match render_base64_pdf(&pdfium, &pdf_bytes, 75) {
-            Ok(r) => {
-                assert_eq!(r.len(), 5);
-            },
-            Err(e) => {
-                println!("Error: {}", e);
+        let json = generate_page_previews_json(pdfium, &pdf_bytes, 100, 60, None).expect("Failed to generate previews");
+        let previews: serde_json::Value = serde_json::from_str(&json).expect("Previews are not valid JSON");
+        let previews = previews.as_array().expect("Previews are not an array");
+        assert_eq!(previews.len(), 5);
+
+        let counts = page_word_count(pdfium, &pdf_bytes, None).expect("Failed to count words");
+        for (preview, count) in previews.iter().zip(counts) {
+            assert_eq!(preview["word_count"], count);
+            assert_eq!(preview["has_text"], count > 0);
+            assert!(preview["width_pt"].as_f64().unwrap() > 0.0);
+
+            let data = preview["thumbnail_url"].as_str().unwrap().strip_prefix("data:image/webp;base64,").unwrap();
+            let thumbnail = image::load_from_memory(&base64::engine::general_purpose::STANDARD.decode(data).unwrap())
+                .expect("Failed to decode thumbnail");
+            assert_eq!(thumbnail.width().max(thumbnail.height()), 100);
+        }
+
+        assert!(generate_page_previews_json(pdfium, &pdf_bytes, 0, 60, None).is_err());
+    }
+
+    #[test]
+    fn test_images_to_pdf() {
+        let pdfium = load_pdfium();
+
+        let encode = |image: RgbImage| {
+            let mut buffer = Vec::new();
+            image.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png).unwrap();
+            buffer
+        };
+        let red = encode(RgbImage::from_pixel(300, 200, image::Rgb([255, 0, 0])));
+        let banner = encode(RgbImage::from_pixel(20000, 40, image::Rgb([0, 0, 255])));
+        let pdf_bytes = images_to_pdf(pdfium, &[(red.clone(), ImageFormat::Png), (banner, ImageFormat::Png)])
+            .expect("Failed to create PDF from images");
+
+        let document = load_document(pdfium, &pdf_bytes).unwrap();
+        let pages = document.pages();
+        assert_eq!(pages.len(), 2);
+
+        let first = pages.get(0).unwrap();
+        assert_eq!((first.width().value, first.height().value), (300.0, 200.0));
+        let rendered = render_page_rgb(&first, &RenderOptions::default()).unwrap();
+        assert_eq!(rendered.get_pixel(150, 100).0, [255, 0, 0]);
+
+        // Too wide to keep at 72 DPI, so fitted to landscape A4
+        let second = pages.get(1).unwrap();
+        assert!((second.width().value - A4_PT.1).abs() < 0.5);
+        assert!((second.height().value - A4_PT.1 * 40.0 / 20000.0).abs() < 0.5);
+
+        // JPEG scans are embedded without being re-encoded
+        let mut jpeg = Vec::new();
+        RgbImage::from_fn(400, 300, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]))
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let pdf_bytes = images_to_pdf(pdfium, &[(jpeg.clone(), ImageFormat::Jpeg)]).expect("Failed to create PDF from a JPEG");
+        assert!(pdf_bytes.windows(b"/DCTDecode".len()).any(|window| window == b"/DCTDecode"));
+        assert!(pdf_bytes.windows(jpeg.len()).any(|window| window == jpeg.as_slice()));
+        let document = load_document(pdfium, &pdf_bytes).unwrap();
+        let page = document.pages().get(0).unwrap();
+        assert_eq!((page.width().value, page.height().value), (400.0, 300.0));
+        let rendered = render_page_rgb(&page, &RenderOptions::default()).unwrap();
+        assert!(rendered.get_pixel(0, 0).0[2].abs_diff(128) < 16);
+
+        assert!(images_to_pdf(pdfium, &[]).is_err());
+        assert!(images_to_pdf(pdfium, &[(red, ImageFormat::Jpeg)]).is_err());
+    }
+
+    #[test]
+    fn test_render_with_print_marks() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /BleedBox [10 10 190 190] /TrimBox [19 19 181 181] >>".to_string(),
+        ]);
+        let render = |print_marks| {
+            let options = RenderOptions::default().with_format(ImageFormat::Png).with_print_marks(print_marks);
+            let pages = render_pdf(pdfium, &pdf_bytes, &options).expect("Failed to render PDF");
+            image::load_from_memory(&pages[0].image_buffer).expect("Failed to decode page").into_rgb8()
+        };
+
+        assert_eq!(render(false).dimensions(), (200, 200));
+
+        // The 180pt bleed box plus an 18pt margin on each side
+        let proof = render(true);
+        assert_eq!(proof.dimensions(), (216, 216));
+        // A crop mark in the left margin, level with the top trim edge (9pt below the bleed edge)
+        assert!((25..30).any(|y| proof.get_pixel(9, y).0 == [0, 0, 0]));
+        assert_eq!(proof.get_pixel(9, 100).0, [255, 255, 255]);
+
+        // The single-page and to-directory renders honour the option too
+        let options = RenderOptions::default().with_format(ImageFormat::Png).with_print_marks(true);
+        let page = render_page_with_options(pdfium, &pdf_bytes, 0, &options).expect("Failed to render page");
+        assert_eq!(page.image_buffer, render_pdf(pdfium, &pdf_bytes, &options).unwrap()[0].image_buffer);
+        let output_dir = std::env::temp_dir().join(format!("ztron_pdf-test-print-marks-{}", std::process::id()));
+        let paths = render_pdf_pages_to_dir_with_options(pdfium, &pdf_bytes, &output_dir, &options)
+            .expect("Failed to render pages");
+        let written = image::open(&paths[0]).expect("Failed to decode page").into_rgb8();
+        std::fs::remove_dir_all(&output_dir).unwrap();
+        assert_eq!(written.dimensions(), (216, 216));
+    }
+
+    /// Stalls before rendering `slow_page`, standing in for a page pdfium takes too long over
+    struct SlowPageHooks {
+        slow_page: usize,
+        delay: Duration,
+    }
+
+    impl RenderHooks for SlowPageHooks {
+        fn before_render(&self, page_index: usize, _config: &mut PdfRenderConfig) {
+            if page_index == self.slow_page {
+                std::thread::sleep(self.delay);
             }
         }
+
+        fn after_render(&self, _page_index: usize, _image: &mut DynamicImage) -> Result<(), String> {
+            Ok(())
+        }
     }
 
     #[test]
-    fn test_compress_pdf() {
-        let test_pdf_path = "./samples/test.pdf";
-        let pdf_bytes = std::fs::read(test_pdf_path)
+    fn test_render_pdf_iter() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
             .expect("Failed to read test PDF file");
-        let original_size = pdf_bytes.len();
-        let base64_pdf = BASE64.encode(&pdf_bytes);
+        let options = RenderOptions::default().with_format(ImageFormat::Png);
+        let expected = render_pdf(pdfium, &pdf_bytes, &options).expect("Failed to render PDF");
 
-        println!("Original PDF size: {} bytes", original_size);
-
-        match compress_pdf(&base64_pdf, 75) {
-            Ok(compressed_base64) => {
-                let compressed_bytes = BASE64.decode(&compressed_base64)
-                    .expect("Failed to decode compressed PDF");
-                let compressed_size = compressed_bytes.len();
-                
-                let compression_ratio = (original_size as f64 - compressed_size as f64) / original_size as f64 * 100.0;
-                
-                println!("Compressed PDF size: {} bytes", compressed_size);
-                println!("Compression ratio: {:.2}%", compression_ratio);
-                
-                // Save the compressed PDF to target folder
-                let output_path = "./target/compressed_test.pdf";
-                std::fs::write(output_path, &compressed_bytes)
-                    .expect("Failed to write compressed PDF");
-                println!("Compressed PDF saved to: {}", output_path);
-                
-                assert!(compressed_size > 0, "Compressed PDF should not be empty");
-                println!("✓ Compression test completed successfully");
-            },
-            Err(e) => {
-                println!("Error compressing PDF: {}", e);
-                panic!("PDF compression failed");
-            }
+        let mut frames = render_pdf_iter(pdfium, &pdf_bytes, options.clone()).expect("Failed to open PDF");
+        assert_eq!(frames.len(), 5);
+        let first = frames.next().unwrap().expect("Failed to render first page");
+        assert_eq!(first.image_buffer, expected[0].image_buffer);
+        assert_eq!(frames.len(), 4);
+
+        let rest: Vec<PageData> = frames.collect::<Result<_, _>>().expect("Failed to render pages");
+        assert_eq!(rest.len(), 4);
+        for (frame, expected) in rest.iter().zip(&expected[1..]) {
+            assert_eq!(frame.image_buffer, expected.image_buffer);
         }
+
+        assert!(render_pdf_iter(pdfium, &pdf_bytes, options.with_max_pages(3)).is_err());
+    }
+
+    #[test]
+    fn test_per_page_timeout() {
+        let pdfium = load_pdfium();
+
+        let pdf_bytes = std::fs::read("./samples/test.pdf")
+            .expect("Failed to read test PDF file");
+        let options = RenderOptions::default()
+            .with_format(ImageFormat::Png)
+            .with_per_page_timeout(Duration::from_secs(2));
+        let hooks = SlowPageHooks { slow_page: 1, delay: Duration::from_millis(2100) };
+        let pages = render_pdf_with_hooks(pdfium, &pdf_bytes, &options, &hooks).expect("Failed to render PDF");
+
+        assert_eq!(pages.len(), 5);
+        assert!(pages[1].image_buffer.is_empty());
+        assert_eq!(pages[1].error.as_deref(), Some(RENDER_TIMEOUT));
+
+        // The other pages render exactly as they would without a timeout
+        let expected = render_pdf(pdfium, &pdf_bytes, &RenderOptions::default().with_format(ImageFormat::Png))
+            .expect("Failed to render PDF");
+        for page_index in [0, 2, 3, 4] {
+            assert_eq!(pages[page_index].error, None);
+            let actual = image::load_from_memory(&pages[page_index].image_buffer).unwrap().into_rgb8();
+            let expected = image::load_from_memory(&expected[page_index].image_buffer).unwrap().into_rgb8();
+            assert!(actual == expected, "page {} differs from render_pdf", page_index);
+        }
+    }
+
+    #[test]
+    fn test_per_page_timeout_pauses_pdfium() {
+        let pdfium = load_pdfium();
+
+        // Enough small fills that pdfium is still drawing after a few ms, so
+        // the deadline is hit inside the progressive renderer itself
+        let content: String = (0..100_000)
+            .map(|i| format!("{} {} {} rg {} {} 3 3 re f\n", i % 2, i % 3 / 2, i % 5 / 4, i % 600, i / 600 % 780))
+            .collect();
+        let pdf_bytes = build_pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R >>".to_string(),
+            stream_object("", &content),
+        ]);
+        let document = load_document(pdfium, &pdf_bytes).expect("Failed to load PDF");
+        let mut page = document.pages().get(0).expect("Failed to get page");
+        let options = RenderOptions::default();
+        let config = render_config(&options);
+
+        let started = Instant::now();
+        let full = render_page_rgb_until(&mut page, &config, &options, started + Duration::from_secs(60))
+            .expect("Failed to render page");
+        let full_duration = started.elapsed();
+        assert!(full_duration > Duration::from_millis(50), "fixture renders too fast: {:?}", full_duration);
+        assert!(full == render_page_rgb(&page, &options).expect("Failed to render page"));
+
+        let started = Instant::now();
+        let result = render_page_rgb_until(&mut page, &config, &options, started + Duration::from_millis(5));
+        assert!(matches!(result, Err(PdfError::Timeout)));
+        assert!(started.elapsed() < full_duration / 2, "render wasn't abandoned at the deadline");
     }
-}
\ No newline at end of file
+}
@@ -1,75 +1,3794 @@
-use pdfium_render::prelude::Pdfium;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use once_cell::sync::OnceCell;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use numpy::{PyArray1, PyArray3, PyArrayMethods};
+use image::{DynamicImage, RgbImage};
+use pdfium_render::prelude::{PdfDocument, PdfRenderConfig, Pdfium};
 use pyo3::prelude::*;
-use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyBytes, PyDict, PyType};
+use pyo3::exceptions::{PyDeprecationWarning, PyIndexError, PyRuntimeError, PyValueError};
 
-mod core;
+pub mod core;
 use core::{
-    PageData
+    AccessibilityViolation,
+    CompressStrategy,
+    DocumentStats,
+    EmbeddedFile,
+    FontInfo,
+    HighlightRect,
+    HIGHLIGHT_YELLOW,
+    ImageFormat,
+    MetadataDiff,
+    NoOpHooks,
+    ImageQualityStat,
+    PageData,
+    PdfError,
+    PdfPermissions,
+    PortfolioEntry,
+    RawPageBuffer,
+    RenderHooks,
+    RenderOptions,
+    SignatureImage,
+    SignatureVerification,
+    SmartCompressReport,
+    StructureNode,
+    TocEntry
 };
 
 
 #[pyclass]
+#[derive(Clone)]
 pub struct PyPageData {
     #[pyo3(get)]
-    pub image_buffer: Vec<u8>
+    pub image_buffer: Vec<u8>,
+    /// Why the page has no image, or None when it was rendered
+    #[pyo3(get)]
+    pub error: Option<String>,
+}
+
+#[pymethods]
+impl PyPageData {
+    /// The image buffer encoded as a base64 string
+    #[getter]
+    fn image_buffer_as_base64(&self) -> String {
+        BASE64.encode(&self.image_buffer)
+    }
+
+    /// Converts the page to a JSON-serializable dict, e.g. for API responses
+    ///
+    /// Returns:
+    ///     dict: `image_buffer_b64` with the image as base64, and `error`
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("image_buffer_b64", self.image_buffer_as_base64())?;
+        dict.set_item("error", &self.error)?;
+        Ok(dict)
+    }
+
+    /// The JSON Schema of `to_dict` output, for frameworks that generate API
+    /// docs from it
+    ///
+    /// Returns:
+    ///     str: The schema as a JSON string
+    #[classmethod]
+    fn model_json_schema(_cls: &Bound<'_, PyType>) -> String {
+        serde_json::json!({
+            "title": "PyPageData",
+            "type": "object",
+            "properties": {
+                "image_buffer_b64": {
+                    "title": "Image Buffer B64",
+                    "description": "The encoded page image as base64",
+                    "type": "string",
+                    "contentEncoding": "base64",
+                },
+                "error": {
+                    "title": "Error",
+                    "description": "Why the page has no image, or null when it was rendered",
+                    "anyOf": [{ "type": "string" }, { "type": "null" }],
+                    "default": null,
+                },
+            },
+            "required": ["image_buffer_b64"],
+        })
+        .to_string()
+    }
 }
 
 // Implement conversion from PageData to PyPageData
 impl From<PageData> for PyPageData {
     fn from(page: PageData) -> Self {
         Self {
-            image_buffer: page.image_buffer
+            image_buffer: page.image_buffer,
+            error: page.error,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyDocumentStats {
+    #[pyo3(get)]
+    pub page_count: usize,
+    #[pyo3(get)]
+    pub total_words: usize,
+    #[pyo3(get)]
+    pub total_chars: usize,
+    #[pyo3(get)]
+    pub avg_words_per_page: f64,
+}
+
+#[pymethods]
+impl PyDocumentStats {
+    /// Converts the stats to a JSON-serializable dict, e.g. for API responses
+    ///
+    /// Returns:
+    ///     dict: `page_count`, `total_words`, `total_chars` and `avg_words_per_page`
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("page_count", self.page_count)?;
+        dict.set_item("total_words", self.total_words)?;
+        dict.set_item("total_chars", self.total_chars)?;
+        dict.set_item("avg_words_per_page", self.avg_words_per_page)?;
+        Ok(dict)
+    }
+
+    /// The JSON Schema of `to_dict` output, for frameworks that generate API
+    /// docs from it
+    ///
+    /// Returns:
+    ///     str: The schema as a JSON string
+    #[classmethod]
+    fn model_json_schema(_cls: &Bound<'_, PyType>) -> String {
+        serde_json::json!({
+            "title": "PyDocumentStats",
+            "type": "object",
+            "properties": {
+                "page_count": {
+                    "title": "Page Count",
+                    "description": "The number of pages",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "total_words": {
+                    "title": "Total Words",
+                    "description": "The number of words across all pages",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "total_chars": {
+                    "title": "Total Chars",
+                    "description": "The number of non-whitespace characters across all pages",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "avg_words_per_page": {
+                    "title": "Avg Words Per Page",
+                    "description": "The average number of words per page, 0 for an empty document",
+                    "type": "number",
+                    "minimum": 0,
+                },
+            },
+            "required": ["page_count", "total_words", "total_chars", "avg_words_per_page"],
+        })
+        .to_string()
+    }
+}
+
+impl From<DocumentStats> for PyDocumentStats {
+    fn from(stats: DocumentStats) -> Self {
+        Self {
+            page_count: stats.page_count,
+            total_words: stats.total_words,
+            total_chars: stats.total_chars,
+            avg_words_per_page: stats.avg_words_per_page,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyStructureNode {
+    #[pyo3(get)]
+    pub tag: String,
+    #[pyo3(get)]
+    pub alt_text: Option<String>,
+    #[pyo3(get)]
+    pub actual_text: Option<String>,
+    #[pyo3(get)]
+    pub children: Vec<PyStructureNode>,
+}
+
+impl From<StructureNode> for PyStructureNode {
+    fn from(node: StructureNode) -> Self {
+        Self {
+            tag: node.tag,
+            alt_text: node.alt_text,
+            actual_text: node.actual_text,
+            children: node.children.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyPortfolioEntry {
+    #[pyo3(get)]
+    pub filename: String,
+    #[pyo3(get)]
+    pub pages: Vec<PyPageData>,
+}
+
+impl From<PortfolioEntry> for PyPortfolioEntry {
+    fn from(entry: PortfolioEntry) -> Self {
+        Self {
+            filename: entry.filename,
+            pages: entry.pages.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyEmbeddedFile {
+    #[pyo3(get)]
+    pub filename: String,
+    #[pyo3(get)]
+    pub mime_type: Option<String>,
+    #[pyo3(get)]
+    pub data: Vec<u8>,
+    #[pyo3(get)]
+    pub creation_date: Option<String>,
+}
+
+impl From<EmbeddedFile> for PyEmbeddedFile {
+    fn from(file: EmbeddedFile) -> Self {
+        Self {
+            filename: file.filename,
+            mime_type: file.mime_type,
+            data: file.data,
+            creation_date: file.creation_date,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyFontInfo {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub is_embedded: bool,
+}
+
+impl From<FontInfo> for PyFontInfo {
+    fn from(font: FontInfo) -> Self {
+        Self {
+            name: font.name,
+            is_embedded: font.is_embedded,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyImageQualityStat {
+    #[pyo3(get)]
+    pub page_index: usize,
+    #[pyo3(get)]
+    pub object_index: usize,
+    #[pyo3(get)]
+    pub width: u32,
+    #[pyo3(get)]
+    pub height: u32,
+    #[pyo3(get)]
+    pub estimated_quality: Option<u8>,
+    #[pyo3(get)]
+    pub format: String,
+}
+
+impl From<ImageQualityStat> for PyImageQualityStat {
+    fn from(stat: ImageQualityStat) -> Self {
+        Self {
+            page_index: stat.page_index,
+            object_index: stat.object_index,
+            width: stat.width,
+            height: stat.height,
+            estimated_quality: stat.estimated_quality,
+            format: stat.format,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyTocEntry {
+    #[pyo3(get)]
+    pub level: usize,
+    #[pyo3(get)]
+    pub title: String,
+    #[pyo3(get)]
+    pub page_label: String,
+}
+
+impl From<TocEntry> for PyTocEntry {
+    fn from(entry: TocEntry) -> Self {
+        Self {
+            level: entry.level,
+            title: entry.title,
+            page_label: entry.page_label,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyAccessibilityViolation {
+    #[pyo3(get)]
+    pub rule: String,
+    #[pyo3(get)]
+    pub page_index: Option<usize>,
+    #[pyo3(get)]
+    pub object_index: Option<usize>,
+    /// "error" or "warning"
+    #[pyo3(get)]
+    pub severity: String,
+}
+
+impl From<AccessibilityViolation> for PyAccessibilityViolation {
+    fn from(violation: AccessibilityViolation) -> Self {
+        Self {
+            rule: violation.rule,
+            page_index: violation.page_index,
+            object_index: violation.object_index,
+            severity: violation.severity.as_str().to_string(),
+        }
+    }
+}
+
+#[pyclass]
+pub struct PySignatureImage {
+    #[pyo3(get)]
+    pub page_index: usize,
+    #[pyo3(get)]
+    pub field_name: String,
+    #[pyo3(get)]
+    pub image_buffer: Vec<u8>,
+}
+
+impl From<SignatureImage> for PySignatureImage {
+    fn from(image: SignatureImage) -> Self {
+        Self {
+            page_index: image.page_index,
+            field_name: image.field_name,
+            image_buffer: image.image_buffer,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PySmartCompressReport {
+    #[pyo3(get)]
+    pub images_processed: usize,
+    #[pyo3(get)]
+    pub savings_bytes: i64,
+    /// (object number, encoding) for each re-encoded image
+    #[pyo3(get)]
+    pub per_image_choices: Vec<(usize, String)>,
+}
+
+impl From<SmartCompressReport> for PySmartCompressReport {
+    fn from(report: SmartCompressReport) -> Self {
+        Self {
+            images_processed: report.images_processed,
+            savings_bytes: report.savings_bytes,
+            per_image_choices: report.per_image_choices,
         }
     }
 }
 
-/// Converts a base64-encoded PDF string into a Python list of base64-encoded images (one per page)
-/// 
+#[pyclass]
+pub struct PySignatureVerification {
+    #[pyo3(get)]
+    pub signer: String,
+    #[pyo3(get)]
+    pub valid: bool,
+    #[pyo3(get)]
+    pub covers_whole_document: bool,
+    #[pyo3(get)]
+    pub reason: Option<String>,
+    #[pyo3(get)]
+    pub signing_time: Option<String>,
+}
+
+impl From<SignatureVerification> for PySignatureVerification {
+    fn from(verification: SignatureVerification) -> Self {
+        Self {
+            signer: verification.signer,
+            valid: verification.valid,
+            covers_whole_document: verification.covers_whole_document,
+            reason: verification.reason,
+            signing_time: verification.signing_time,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyMetadataDiff {
+    #[pyo3(get)]
+    pub field: String,
+    #[pyo3(get)]
+    pub old_value: Option<String>,
+    #[pyo3(get)]
+    pub new_value: Option<String>,
+}
+
+impl From<MetadataDiff> for PyMetadataDiff {
+    fn from(diff: MetadataDiff) -> Self {
+        Self {
+            field: diff.field,
+            old_value: diff.old_value,
+            new_value: diff.new_value,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyRawPageBuffer {
+    #[pyo3(get)]
+    pub width: u32,
+    #[pyo3(get)]
+    pub height: u32,
+    #[pyo3(get)]
+    pub channels: u8,
+    /// Held as a Python `bytes` object so reading the attribute repeatedly
+    /// returns the same object rather than copying the pixels each time
+    #[pyo3(get)]
+    pub data: Py<PyBytes>,
+}
+
+impl PyRawPageBuffer {
+    fn new(py: Python<'_>, buffer: RawPageBuffer) -> Self {
+        Self {
+            width: buffer.width,
+            height: buffer.height,
+            channels: buffer.channels,
+            data: PyBytes::new(py, &buffer.data).unbind(),
+        }
+    }
+}
+
+/// Rendering options shared by the render functions. Passing an instance
+/// via `options=` takes precedence over the individual keyword arguments.
+#[pyclass]
+#[derive(Clone)]
+pub struct PyRenderOptions {
+    #[pyo3(get, set)]
+    pub quality: u8,
+    #[pyo3(get, set)]
+    pub format: String,
+    #[pyo3(get, set)]
+    pub dpi: Option<u32>,
+    #[pyo3(get, set)]
+    pub grayscale: bool,
+    #[pyo3(get, set)]
+    pub icc_profile: Option<Vec<u8>>,
+    #[pyo3(get, set)]
+    pub simulate_overprint: bool,
+    #[pyo3(get, set)]
+    pub max_pages: Option<usize>,
+    #[pyo3(get, set)]
+    pub max_pdf_bytes: Option<usize>,
+    #[pyo3(get, set)]
+    pub color_space: String,
+    #[pyo3(get, set)]
+    pub print_marks: bool,
+    /// Seconds after which a page's render is abandoned
+    #[pyo3(get, set)]
+    pub per_page_timeout: Option<f64>,
+}
+
+#[pymethods]
+impl PyRenderOptions {
+    #[new]
+    #[pyo3(signature = (quality=75, format="WEBP".to_string(), dpi=None, grayscale=false, icc_profile=None, simulate_overprint=false, max_pages=None, max_pdf_bytes=None, color_space="srgb".to_string(), print_marks=false, per_page_timeout=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        quality: u8,
+        format: String,
+        dpi: Option<u32>,
+        grayscale: bool,
+        icc_profile: Option<Vec<u8>>,
+        simulate_overprint: bool,
+        max_pages: Option<usize>,
+        max_pdf_bytes: Option<usize>,
+        color_space: String,
+        print_marks: bool,
+        per_page_timeout: Option<f64>,
+    ) -> Self {
+        Self { quality, format, dpi, grayscale, icc_profile, simulate_overprint, max_pages, max_pdf_bytes, color_space, print_marks, per_page_timeout }
+    }
+}
+
+impl TryFrom<PyRenderOptions> for RenderOptions {
+    type Error = PdfError;
+
+    fn try_from(options: PyRenderOptions) -> Result<Self, Self::Error> {
+        Ok(Self {
+            quality: options.quality,
+            format: options.format.parse()?,
+            dpi: options.dpi,
+            grayscale: options.grayscale,
+            icc_profile: options.icc_profile,
+            simulate_overprint: options.simulate_overprint,
+            max_pages: options.max_pages,
+            max_pdf_bytes: options.max_pdf_bytes,
+            color_space: options.color_space.parse()?,
+            render_with_print_marks: options.print_marks,
+            per_page_timeout: options
+                .per_page_timeout
+                .map(Duration::try_from_secs_f64)
+                .transpose()
+                .map_err(|e| PdfError::InvalidArgument(format!("Invalid per_page_timeout: {}", e)))?,
+        })
+    }
+}
+
+/// Uses `options` when the caller passed a `PyRenderOptions`, otherwise the
+/// options `keywords` assembles from keyword arguments. `keywords` only runs
+/// without `options`, so keyword defaults can't fail a call that passed them.
+fn resolve_options(
+    options: Option<PyRenderOptions>,
+    keywords: impl FnOnce() -> Result<RenderOptions, PdfError>,
+) -> PyResult<RenderOptions> {
+    match options {
+        Some(options) => Ok(options.try_into()?),
+        None => Ok(keywords()?),
+    }
+}
+
+/// What `render_pdf` accepts as its input: PDF bytes, a path (as an
+/// `os.PathLike`), a `data:` URL, or a base64 string
+#[derive(FromPyObject)]
+pub enum PdfInput {
+    Bytes(Vec<u8>),
+    Text(String),
+    Path(PathBuf),
+}
+
+/// Prefix of a PDF `data:` URL, as produced by browsers' `FileReader.readAsDataURL`
+const PDF_DATA_URL_PREFIX: &str = "data:application/pdf;base64,";
+
+impl PdfInput {
+    /// Resolves the input to PDF bytes. A string is a data URL when it has
+    /// the PDF data URL prefix, else base64; it is never taken as a path, so
+    /// untrusted text can't make us read local files. A file larger than
+    /// `max_pdf_bytes` is refused from its metadata, before reading it.
+    fn into_bytes(self, max_pdf_bytes: Option<usize>) -> PyResult<Vec<u8>> {
+        let text = match self {
+            PdfInput::Bytes(pdf_bytes) => return Ok(pdf_bytes),
+            PdfInput::Path(path) => {
+                if std::fs::metadata(&path)?.len() > max_pdf_bytes.unwrap_or(usize::MAX) as u64 {
+                    return Err(PyValueError::new_err("PDF exceeds maximum allowed size"));
+                }
+                return Ok(std::fs::read(path)?);
+            }
+            PdfInput::Text(text) => text,
+        };
+
+        if let Some(data) = text.strip_prefix(PDF_DATA_URL_PREFIX) {
+            return BASE64
+                .decode(data.trim())
+                .map_err(|e| PyValueError::new_err(format!("Invalid base64 in data URL: {}", e)));
+        }
+        BASE64.decode(text.trim()).map_err(|_| PyValueError::new_err(
+            "Input is not PDF bytes, a data URL, or base64; pass file paths as pathlib.Path"
+        ))
+    }
+}
+
+// Surface core errors to Python as ValueError
+impl From<PdfError> for PyErr {
+    fn from(e: PdfError) -> Self {
+        PyValueError::new_err(e.to_string())
+    }
+}
+
+// Binding the library and initializing pdfium is expensive, so a single
+// instance is shared by every call. The `sync` feature of pdfium-render makes
+// `Pdfium` safe to share across threads.
+//
+// This is deliberately not a binding per thread: pdfium keeps process-wide
+// state behind `FPDF_InitLibrary`, and every `Pdfium` destroys that state when
+// dropped, so a thread-local instance going away at thread exit would tear
+// the library down under every other thread. pdfium isn't reentrant either,
+// so separate bindings couldn't render in parallel anyway.
+static PDFIUM: OnceCell<Pdfium> = OnceCell::new();
+
+// Statics are never dropped, so `Pdfium`'s destructor never runs for the
+// shared instance; `shutdown_pdfium` releases the library instead. Leases are
+// only taken while this is locked, so the check that nothing is using pdfium
+// and the destroy can't race with a new call.
+static PDFIUM_DESTROYED: Mutex<bool> = Mutex::new(false);
+
+// Documents held open by `PyPdfDocument`. Closing a document calls into
+// pdfium, so `shutdown_pdfium` leaves the library up while any are still alive
+static OPEN_DOCUMENTS: AtomicUsize = AtomicUsize::new(0);
+
+// Calls currently using pdfium, including renders still running on the tokio
+// blocking pool after `render_pdf_pages_async` returned
+static ACTIVE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// The shared pdfium binding, counted in `ACTIVE_CALLS` for as long as it is
+/// alive
+struct PdfiumLease {
+    pdfium: &'static Pdfium,
+}
+
+impl PdfiumLease {
+    /// The binding itself, for documents that outlive this lease. Such
+    /// documents must take a `DocumentLease` before this one is dropped.
+    fn get(&self) -> &'static Pdfium {
+        self.pdfium
+    }
+}
+
+impl Deref for PdfiumLease {
+    type Target = Pdfium;
+
+    fn deref(&self) -> &Pdfium {
+        self.pdfium
+    }
+}
+
+impl Drop for PdfiumLease {
+    fn drop(&mut self) {
+        ACTIVE_CALLS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Binds pdfium on first use and leases it to the caller
+///
+/// Raises `RuntimeError` once `shutdown_pdfium` has destroyed the library,
+/// e.g. for a daemon thread still rendering at interpreter exit.
+fn load_pdfium() -> PyResult<PdfiumLease> {
+    let destroyed = PDFIUM_DESTROYED.lock().unwrap_or_else(PoisonError::into_inner);
+    if *destroyed {
+        return Err(PyRuntimeError::new_err("pdfium has been shut down"));
+    }
+
+    let pdfium = PDFIUM.get_or_init(|| {
+        core::init_pdfium(
+            Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+                .or_else(|_| Pdfium::bind_to_system_library())
+                .expect("Failed to bind to Pdfium library"),
+            &font_dirs()
+        )
+    });
+    ACTIVE_CALLS.fetch_add(1, Ordering::SeqCst);
+
+    Ok(PdfiumLease { pdfium })
+}
+
+/// Extra font directories for pdfium's font substitution
+fn font_dirs() -> Vec<PathBuf> {
+    #[cfg(feature = "bundled-fonts")]
+    if let Ok(dir) = core::bundled_font_dir() {
+        return vec![dir];
+    }
+
+    Vec::new()
+}
+
+/// Runs a Python callable as the `after_render` step of `render_pdf`
+struct PyRenderHook<'py> {
+    callback: Bound<'py, PyAny>,
+    /// What the callback raised, so it reaches the caller unchanged
+    error: RefCell<Option<PyErr>>,
+}
+
+impl RenderHooks for PyRenderHook<'_> {
+    fn before_render(&self, _page_index: usize, _config: &mut PdfRenderConfig) {}
+
+    fn after_render(&self, page_index: usize, image: &mut DynamicImage) -> Result<(), String> {
+        let rgb = image.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let buffer = RawPageBuffer { width, height, channels: 3, data: rgb.into_raw() };
+        let page = PyRawPageBuffer::new(self.callback.py(), buffer);
+
+        let replacement = self.callback
+            .call1((page_index, page))
+            .and_then(|result| result.extract::<Option<Vec<u8>>>());
+        match replacement {
+            Ok(None) => Ok(()),
+            Ok(Some(data)) => {
+                let replaced = RgbImage::from_raw(width, height, data).ok_or_else(|| format!(
+                    "render_hook must return {} bytes of RGB pixels", width as usize * height as usize * 3
+                ))?;
+                *image = DynamicImage::ImageRgb8(replaced);
+                Ok(())
+            }
+            Err(e) => {
+                let message = e.to_string();
+                *self.error.borrow_mut() = Some(e);
+                Err(message)
+            }
+        }
+    }
+}
+
+/// Renders every page of a PDF into a Python list of images (one per page)
+///
+/// Args:
+///     pdf_bytes (Union[bytes, str, os.PathLike]): The PDF data, or a path to a PDF file as an
+///         os.PathLike such as pathlib.Path. A str is taken as a "data:application/pdf;base64," URL
+///         when it has that prefix, else as base64, and never as a path
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     icc_profile (Optional[bytes]): Raw ICC profile to convert the rendered sRGB pages into
+///     simulate_overprint (bool): Whether to honour overprint flags when rendering print production files
+///     dpi (Optional[int]): The render resolution. Defaults to 72 DPI
+///     grayscale (bool): Whether to render the pages in grayscale
+///     options (Optional[PyRenderOptions]): Rendering options; when given, the other rendering keyword arguments are ignored
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///     color_space (str): srgb, adobe_rgb, or display_p3; PNG, JPEG and WebP output is tagged with
+///         the matching ICC profile
+///     render_hook (Optional[Callable[[int, PyRawPageBuffer], Optional[bytes]]]): Called with the page index
+///         and RGB pixels of each rendered page before it is encoded; may return replacement pixels of the
+///         same size, or None to keep the page as rendered
+///     print_marks (bool): Whether to render the bleed area with crop marks around it, for print proofs
+///
+/// Returns:
+///     List[PyPageData]: One encoded image per page
+///
+/// Raises:
+///     ValueError: If the PDF conversion fails, a str input is none of the above, or render_hook returns
+///         pixels of the wrong size
+///     OSError: If the PDF file can't be read
+///     Exception: Whatever render_hook raises, which stops rendering
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality=75, format="WEBP".to_string(), icc_profile=None, simulate_overprint=false, dpi=None, grayscale=false, options=None, max_pages=None, max_pdf_bytes=None, color_space="srgb".to_string(), render_hook=None, print_marks=false))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_pdf(
+    pdf_bytes: PdfInput,
+    quality: u8,
+    format: String,
+    icc_profile: Option<Vec<u8>>,
+    simulate_overprint: bool,
+    dpi: Option<u32>,
+    grayscale: bool,
+    options: Option<PyRenderOptions>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+    color_space: String,
+    render_hook: Option<Bound<'_, PyAny>>,
+    print_marks: bool,
+) -> PyResult<Vec<PyPageData>> {
+    let options = resolve_options(options, || PyRenderOptions {
+        quality,
+        format,
+        dpi,
+        grayscale,
+        icc_profile,
+        simulate_overprint,
+        max_pages,
+        max_pdf_bytes,
+        color_space,
+        print_marks,
+        per_page_timeout: None,
+    }.try_into())?;
+    let pdf_bytes = pdf_bytes.into_bytes(options.max_pdf_bytes)?;
+    let pdfium = load_pdfium()?;
+
+    let hook = render_hook.map(|callback| PyRenderHook { callback, error: RefCell::new(None) });
+    let hooks: &dyn RenderHooks = match &hook {
+        Some(hook) => hook,
+        None => &NoOpHooks,
+    };
+    let result = core::render_pdf_with_hooks(&pdfium, &pdf_bytes, &options, hooks)
+        .map_err(|e| hook.as_ref().and_then(|hook| hook.error.take()).unwrap_or_else(|| e.into()))?;
+
+    Ok(result.into_iter().map(Into::into).collect())
+}
+
+/// Deprecated alias of `render_pdf` that always renders WebP. Despite the
+/// name it takes raw PDF bytes, not base64.
+///
 /// Args:
-///     base64_pdf (str): A base64-encoded string containing the PDF data
-///     format (str): The format of the output images. Must be WEBP, PNG, or JPEG
+///     pdf_bytes (bytes): The PDF data
 ///     quality (int): The quality of the output images. Must be between 0 and 100
-///     max_edge_size (int): The maximum edge size of the output images. Must be between 1 and 10000
-///     extract_text (bool): Whether to extract text from the PDF (not using OCR)
-/// 
+///     icc_profile (Optional[bytes]): Raw ICC profile to convert the rendered sRGB pages into
+///     simulate_overprint (bool): Whether to honour overprint flags when rendering print production files
+///     dpi (Optional[int]): The render resolution. Defaults to 72 DPI
+///     grayscale (bool): Whether to render the pages in grayscale
+///     options (Optional[PyRenderOptions]): Rendering options, whose format is ignored; when given, the other
+///         rendering keyword arguments are ignored
+///     print_marks (bool): Whether to render the bleed area with crop marks around it, for print proofs
+///
 /// Returns:
-///     List[PageData]: A list of PageData objects, each containing a base64-encoded image and optional text
-/// 
+///     List[PyPageData]: One WebP image per page
+///
 /// Raises:
 ///     ValueError: If the PDF conversion fails
+///
+/// Warns:
+///     DeprecationWarning: Always; use render_pdf instead
 #[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality=75, icc_profile=None, simulate_overprint=false, dpi=None, grayscale=false, options=None, print_marks=false))]
+#[allow(clippy::too_many_arguments)]
 pub fn render_base64_pdf(
+    py: Python<'_>,
     pdf_bytes: Vec<u8>,
     quality: u8,
+    icc_profile: Option<Vec<u8>>,
+    simulate_overprint: bool,
+    dpi: Option<u32>,
+    grayscale: bool,
+    options: Option<PyRenderOptions>,
+    print_marks: bool,
 ) -> PyResult<Vec<PyPageData>> {
-    let pdfium = Pdfium::new(
-        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
-            .or_else(|_| Pdfium::bind_to_system_library())
-            .expect("Failed to bind to Pdfium library")
-    );
+    py.import("warnings")?.call_method1("warn", (
+        "render_base64_pdf is deprecated and will be removed in a future release; use render_pdf instead",
+        py.get_type::<PyDeprecationWarning>(),
+        2,
+    ))?;
 
-    let result = core::render_base64_pdf(&pdfium, &pdf_bytes, quality)
-        .map_err(|e| PyValueError::new_err(e))?;
+    // Like the Rust alias, the output is WebP even when options ask otherwise
+    let options = options.map(|options| PyRenderOptions { format: "WEBP".to_string(), ..options });
 
-    Ok(result.into_iter().map(Into::into).collect())
+    render_pdf(PdfInput::Bytes(pdf_bytes), quality, "WEBP".to_string(), icc_profile, simulate_overprint, dpi, grayscale, options, None, None, "srgb".to_string(), None, print_marks)
 }
 
+/// Renders every page of a PDF read from a binary file-like object, such as
+/// an `io.BytesIO` or a file opened with `open(path, "rb")`
+///
+/// Args:
+///     file_obj (BinaryIO): Any object whose read() returns the PDF as bytes; it is read to the end
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     List[PyPageData]: One encoded image per page
+///
+/// Raises:
+///     TypeError: If read() does not return bytes, e.g. for a file opened in text mode
+///     ValueError: If the PDF conversion fails
 #[pyfunction]
-pub fn compress_pdf(
-    base64_pdf: String,
-    quality: u8
-) -> PyResult<String> {
-    let compressed_pdf_base64 = core::compress_pdf(&base64_pdf, quality)
-        .map_err(|e| PyValueError::new_err(e.to_string()))?;
-    
-    Ok(compressed_pdf_base64)
+#[pyo3(signature = (file_obj, quality=75, format="WEBP".to_string(), max_pages=None, max_pdf_bytes=None))]
+pub fn render_pdf_from_file_like(
+    file_obj: Bound<'_, PyAny>,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<Vec<PyPageData>> {
+    let pdf_bytes: Vec<u8> = file_obj.call_method0("read")?.extract()?;
+
+    render_pdf(PdfInput::Bytes(pdf_bytes), quality, format, None, false, None, false, None, max_pages, max_pdf_bytes, "srgb".to_string(), None, false)
 }
 
+/// Renders a preview of the first page: a WebP image at quality 75, scaled so
+/// its longer edge is max_size pixels, returned as base64
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_size (int): The length of the preview's longer edge in pixels
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: The base64-encoded WebP image, e.g. for a data:image/webp;base64, URL
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded, has no pages, or max_size is 0
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_size = 512, max_pages = None))]
+pub fn render_cover(
+    pdf_bytes: Vec<u8>,
+    max_size: u32,
+    max_pages: Option<usize>,
+) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
 
-/// A Python module implemented in Rust.
-#[pymodule]
-fn ztron_pdf(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    Ok(BASE64.encode(core::render_cover(&pdfium, &pdf_bytes, max_size, max_pages)?))
+}
+
+/// Describes every page as JSON for web page pickers, so a viewer can build
+/// its page list in one call
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     thumb_size (int): The thumbnails' longer edge in pixels
+///     quality (int): The WebP quality of the thumbnails, between 0 and 100
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: A JSON array with one object per page holding page_index, thumbnail_url (a WebP data URL),
+///         width_pt, height_pt, label, word_count and has_text
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded, thumb_size is 0, or quality is over 100
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, thumb_size = 200, quality = 60, max_pages = None))]
+pub fn generate_page_previews_json(
+    pdf_bytes: Vec<u8>,
+    thumb_size: u32,
+    quality: u8,
+    max_pages: Option<usize>,
+) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::generate_page_previews_json(&pdfium, &pdf_bytes, thumb_size, quality, max_pages)?)
+}
+
+/// Recompresses the images embedded in a PDF
+///
+/// Args:
+///     base64_pdf (str): The base64-encoded PDF data
+///     quality (int): The JPEG quality. Must be between 1 and 100
+///     skip_if_no_savings (bool): Keep images whose re-encoded form would not be smaller
+///     preserve_bitonal (bool): Keep 1-bit images bi-level instead of JPEG-encoding them; JBIG2 images become CCITT G4
+///
+/// Returns:
+///     str: The base64-encoded compressed PDF
+///
+/// Raises:
+///     ValueError: If the quality is out of range or the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (base64_pdf, quality, skip_if_no_savings = true, preserve_bitonal = true))]
+pub fn compress_pdf(
+    base64_pdf: String,
+    quality: u8,
+    skip_if_no_savings: bool,
+    preserve_bitonal: bool,
+) -> PyResult<String> {
+    let strategy = CompressStrategy { skip_if_no_savings, preserve_bitonal };
+    let compressed_pdf_base64 = core::compress_pdf_with_strategy(&base64_pdf, quality, strategy)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    
+    Ok(compressed_pdf_base64)
+}
+
+/// Recompresses the images embedded in a PDF, first downsampling those placed
+/// at more than one and a half times target_dpi down to target_dpi
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     quality (int): The JPEG quality. Must be between 1 and 100
+///     target_dpi (float): The resolution to downsample oversized images to
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The compressed PDF
+///
+/// Raises:
+///     ValueError: If quality or target_dpi is out of range or the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality = 75, target_dpi = 150.0, max_pages = None))]
+pub fn compress_pdf_with_downsample(
+    pdf_bytes: Vec<u8>,
+    quality: u8,
+    target_dpi: f32,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::compress_pdf_with_downsample(&pdfium, &pdf_bytes, quality, target_dpi, max_pages)?)
+}
+
+/// Converts the text of a PDF into Markdown
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: Markdown with headings, bold/italic text and bullet lists inferred from font metadata
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn pdf_to_markdown(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::pdf_to_markdown(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Extracts the text of a PDF as plain text, with a form feed between pages
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     preserve_layout (bool): Place text by its position on the page so columns and tables stay
+///         aligned; when false, lines are trimmed and blank lines dropped
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: The text of every page
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, preserve_layout = true, max_pages = None))]
+pub fn pdf_to_text(
+    pdf_bytes: Vec<u8>,
+    preserve_layout: bool,
+    max_pages: Option<usize>,
+) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::pdf_to_text(&pdfium, &pdf_bytes, preserve_layout, max_pages)?)
+}
+
+/// Counts the whitespace-separated words on each page
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[int]: The word count of each page, in page order
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn page_word_count(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<usize>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::page_word_count(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Aggregates word and character counts across the whole document
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     PyDocumentStats: Page count, total words, total non-whitespace characters and average words per page
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn document_stats(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<PyDocumentStats> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::document_stats(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?.into())
+}
+
+/// Renders every page as JPEG and packs them into a CBZ (comic book ZIP) archive
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     quality (int): The JPEG quality. Must be between 1 and 100
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     options (Optional[PyRenderOptions]): Rendering options, whose format is ignored; when given, the
+///         other keyword arguments are ignored
+///
+/// Returns:
+///     bytes: The CBZ archive, with one `0001.jpg`, `0002.jpg`, ... entry per page
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality=85, max_pages = None, options = None))]
+pub fn render_pdf_to_cbz(
+    pdf_bytes: Vec<u8>,
+    quality: u8,
+    max_pages: Option<usize>,
+    options: Option<PyRenderOptions>,
+) -> PyResult<Vec<u8>> {
+    let options = resolve_options(options, || Ok(RenderOptions { quality, max_pages, ..RenderOptions::default() }))?;
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_pdf_to_cbz_with_options(&pdfium, &pdf_bytes, &options)?)
+}
+
+/// Renders every page to PNG using a low-copy path straight from pdfium's bitmap
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     options (Optional[PyRenderOptions]): Rendering options, whose format and quality are ignored;
+///         when given, max_pages is ignored
+///
+/// Returns:
+///     List[PyPageData]: One PNG-encoded image per page
+///
+/// Raises:
+///     ValueError: If options ask for an ICC profile, a color space other than srgb, print marks or
+///         a per-page timeout, which this path can't apply
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, options = None))]
+pub fn render_pdf_to_png(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    options: Option<PyRenderOptions>,
+) -> PyResult<Vec<PyPageData>> {
+    let options = resolve_options(options, || Ok(RenderOptions { max_pages, ..RenderOptions::default() }))?;
+    let pdfium = load_pdfium()?;
+
+    let result = core::render_pdf_to_png_with_options(&pdfium, &pdf_bytes, &options)?;
+
+    Ok(result.into_iter().map(Into::into).collect())
+}
+
+/// Extracts the logical structure tree of a tagged PDF for accessibility analysis
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyStructureNode: A root node whose children are the top-level structure elements.
+///     Untagged PDFs return a root with no children
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn extract_structure_tree(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<PyStructureNode> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::extract_structure_tree(&pdfium, &pdf_bytes, max_pages)?.into())
+}
+
+/// Checks whether a PDF is an image-only scan without a text layer
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     text_threshold (int): The minimum number of characters across the first five pages for the PDF to count as text
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bool: True if fewer than `text_threshold` characters could be extracted
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, text_threshold=50, max_pages = None))]
+pub fn is_scanned_pdf(
+    pdf_bytes: Vec<u8>,
+    text_threshold: usize,
+    max_pages: Option<usize>,
+) -> PyResult<bool> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::is_scanned_pdf(&pdfium, &pdf_bytes, text_threshold, max_pages)?)
+}
+
+/// Renders every page and writes the images into a directory
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     output_dir (str): The directory to write into. Created if missing
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     dpi (Optional[int]): The render resolution. Defaults to 72 DPI
+///     grayscale (bool): Whether to render in grayscale
+///     options (Optional[PyRenderOptions]): Rendering options; when given, the other rendering keyword arguments are ignored
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///     color_space (str): srgb, adobe_rgb, or display_p3; PNG, JPEG and WebP output is tagged with
+///         the matching ICC profile
+///
+/// Returns:
+///     List[str]: The paths of the written files, named page_0001.<ext>, page_0002.<ext>, ...
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, output_dir, quality=75, format="WEBP".to_string(), dpi=None, grayscale=false, options=None, max_pages=None, max_pdf_bytes=None, color_space="srgb".to_string()))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_pdf_pages_to_dir(
+    pdf_bytes: Vec<u8>,
+    output_dir: String,
+    quality: u8,
+    format: String,
+    dpi: Option<u32>,
+    grayscale: bool,
+    options: Option<PyRenderOptions>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+    color_space: String,
+) -> PyResult<Vec<String>> {
+    let pdfium = load_pdfium()?;
+    let options = resolve_options(options, || PyRenderOptions {
+        quality,
+        format,
+        dpi,
+        grayscale,
+        icc_profile: None,
+        simulate_overprint: false,
+        max_pages,
+        max_pdf_bytes,
+        color_space,
+        print_marks: false,
+        per_page_timeout: None,
+    }.try_into())?;
+
+    let paths = core::render_pdf_pages_to_dir_with_options(&pdfium, &pdf_bytes, output_dir.as_ref(), &options)?;
+
+    Ok(paths.into_iter().map(|path| path.to_string_lossy().into_owned()).collect())
+}
+
+/// Checks whether a PDF has at least one signed signature field
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bool: True if any signature field carries a signature value
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn has_digital_signature(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<bool> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::has_digital_signature(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Counts the signature fields of a PDF that have been signed
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     int: The number of signed signature fields
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn count_digital_signatures(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<usize> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::count_digital_signatures(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Lists the signer names recorded in a PDF's signature dictionaries
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[str]: The `/Name` of each signature that has one
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn list_signature_signers(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<String>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::list_signature_signers(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Checks that the content covered by each signature hasn't been modified
+///
+/// A signature is valid when the bytes its `/ByteRange` covers still hash to
+/// the digest in its CMS data and the RSA or P-256 signature over that digest
+/// verifies against the signer's embedded certificate. Whether the certificate
+/// is trusted, expired or revoked is not checked.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PySignatureVerification]: One entry per signed signature field. covers_whole_document
+///         is False when content was appended after signing
+///
+/// Raises:
+///     ValueError: If the PDF can't be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn verify_signatures(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PySignatureVerification>> {
+    let pdfium = load_pdfium()?;
+
+    let verifications = core::verify_signatures(&pdfium, &pdf_bytes, max_pages)?;
+    Ok(verifications.into_iter().map(PySignatureVerification::from).collect())
+}
+
+/// Straightens scanned pages that were captured at a slight angle
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with skewed pages rotated back to horizontal
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded or saved
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn deskew_pdf(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::deskew_pdf(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Renders a single page, addressed by its zero-based index
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     format (str): The format of the output image. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     dpi (Optional[int]): The render resolution. Defaults to 72 DPI
+///     grayscale (bool): Whether to render in grayscale
+///     options (Optional[PyRenderOptions]): Rendering options; when given, the other rendering keyword arguments are ignored
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///     color_space (str): srgb, adobe_rgb, or display_p3; PNG, JPEG and WebP output is tagged with
+///         the matching ICC profile
+///
+/// Returns:
+///     PyPageData: The rendered page
+///
+/// Raises:
+///     ValueError: If the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, quality = 75, format = "WEBP".to_string(), dpi = None, grayscale = false, options = None, max_pages = None, max_pdf_bytes = None, color_space = "srgb".to_string()))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_page(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    quality: u8,
+    format: String,
+    dpi: Option<u32>,
+    grayscale: bool,
+    options: Option<PyRenderOptions>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+    color_space: String,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+    let options = resolve_options(options, || PyRenderOptions {
+        quality,
+        format,
+        dpi,
+        grayscale,
+        icc_profile: None,
+        simulate_overprint: false,
+        max_pages,
+        max_pdf_bytes,
+        color_space,
+        print_marks: false,
+        per_page_timeout: None,
+    }.try_into())?;
+
+    Ok(core::render_page_with_options(&pdfium, &pdf_bytes, page_index, &options)?.into())
+}
+
+/// Returns the display label of every page
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     List[Optional[str]]: One label per page (e.g. "iii", "1"), or None where the document defines none
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn get_page_labels(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<Vec<Option<String>>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::get_page_labels(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?)
+}
+
+/// Renders the page with the given display label
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     label (str): The page label as shown by PDF viewers, e.g. "iv" or "12"
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     format (str): The format of the output image. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     dpi (Optional[int]): The render resolution. Defaults to 72 DPI
+///     grayscale (bool): Whether to render in grayscale
+///     options (Optional[PyRenderOptions]): Rendering options; when given, the other rendering keyword arguments are ignored
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///     color_space (str): srgb, adobe_rgb, or display_p3; PNG, JPEG and WebP output is tagged with
+///         the matching ICC profile
+///
+/// Returns:
+///     PyPageData: The rendered page
+///
+/// Raises:
+///     ValueError: If no page carries the label
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, label, quality = 75, format = "WEBP".to_string(), dpi = None, grayscale = false, options = None, max_pages = None, max_pdf_bytes = None, color_space = "srgb".to_string()))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_page_by_label(
+    pdf_bytes: Vec<u8>,
+    label: &str,
+    quality: u8,
+    format: String,
+    dpi: Option<u32>,
+    grayscale: bool,
+    options: Option<PyRenderOptions>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+    color_space: String,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+    let options = resolve_options(options, || PyRenderOptions {
+        quality,
+        format,
+        dpi,
+        grayscale,
+        icc_profile: None,
+        simulate_overprint: false,
+        max_pages,
+        max_pdf_bytes,
+        color_space,
+        print_marks: false,
+        per_page_timeout: None,
+    }.try_into())?;
+
+    Ok(core::render_page_by_label_with_options(&pdfium, &pdf_bytes, label, &options)?.into())
+}
+
+/// Renders every page as a square thumbnail
+///
+/// Pages are scaled to fit and letterboxed on a white background.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     thumb_size (int): The width and height of each thumbnail in pixels
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyPageData]: One thumb_size × thumb_size image per page
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, thumb_size = 256, quality = 60, format = "WEBP".to_string(), max_pages = None))]
+pub fn render_thumbnails(
+    pdf_bytes: Vec<u8>,
+    thumb_size: u32,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyPageData>> {
+    let pdfium = load_pdfium()?;
+    let options = RenderOptions {
+        max_pages,
+        ..RenderOptions::default().with_quality(quality).with_format(format.parse()?)
+    };
+
+    let thumbnails = core::render_thumbnails(&pdfium, &pdf_bytes, thumb_size, &options)?;
+
+    Ok(thumbnails.into_iter().map(Into::into).collect())
+}
+
+/// Password-protects a PDF
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     user_password (str): The password required to open the document
+///     owner_password (str): The password that grants full access
+///     allow_print (bool): Whether user-password readers may print
+///     allow_copy (bool): Whether user-password readers may copy text and images
+///     allow_modify (bool): Whether user-password readers may edit, annotate, or fill forms
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The encrypted PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, user_password, owner_password, allow_print=true, allow_copy=false, allow_modify=false, max_pages = None))]
+pub fn set_pdf_password(
+    pdf_bytes: Vec<u8>,
+    user_password: &str,
+    owner_password: &str,
+    allow_print: bool,
+    allow_copy: bool,
+    allow_modify: bool,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+    let permissions = PdfPermissions { allow_print, allow_copy, allow_modify };
+
+    Ok(core::set_pdf_password(&pdfium, &pdf_bytes, user_password, owner_password, permissions, max_pages)?)
+}
+
+/// Rewrites a PDF without superseded revisions and orphaned objects
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The rewritten PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn garbage_collect_pdf(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::garbage_collect_pdf(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Renders every PDF embedded in a portfolio (PDF collection)
+///
+/// Args:
+///     pdf_bytes (bytes): The portfolio PDF data
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyPortfolioEntry]: One entry per embedded PDF, with its filename and rendered pages
+///
+/// Raises:
+///     ValueError: If the PDF is not a portfolio
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality = 75, format = "WEBP".to_string(), max_pages = None))]
+pub fn render_portfolio(
+    pdf_bytes: Vec<u8>,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyPortfolioEntry>> {
+    let pdfium = load_pdfium()?;
+    let options = RenderOptions {
+        max_pages,
+        ..RenderOptions::default().with_quality(quality).with_format(format.parse()?)
+    };
+
+    let entries = core::render_portfolio(&pdfium, &pdf_bytes, &options)?;
+
+    Ok(entries.into_iter().map(Into::into).collect())
+}
+
+/// Extracts the files attached to a PDF
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyEmbeddedFile]: Each attachment's filename, MIME type, data, and creation date
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn extract_embedded_files(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyEmbeddedFile>> {
+    let pdfium = load_pdfium()?;
+
+    let files = core::extract_embedded_files(&pdfium, &pdf_bytes, max_pages)?;
+
+    Ok(files.into_iter().map(Into::into).collect())
+}
+
+/// Attaches a file to a PDF through its embedded files name tree
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     filename (str): The name to attach the file under; must not already be attached
+///     file_data (bytes): The file contents
+///     mime_type (Optional[str]): The file's MIME type, e.g. "application/xml"
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the file attached
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded, the filename is empty, or a file with that name is
+///         already attached
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, filename, file_data, mime_type = None, max_pages = None))]
+pub fn attach_file(
+    pdf_bytes: Vec<u8>,
+    filename: String,
+    file_data: Vec<u8>,
+    mime_type: Option<String>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::attach_file(&pdfium, &pdf_bytes, &filename, &file_data, mime_type.as_deref(), max_pages)?)
+}
+
+/// Removes every attached file from a PDF, including files associated with
+/// pages through `/AF`, to shrink it before distribution
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF without attachments
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn strip_embedded_files(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::strip_embedded_files(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Flattens a PDF into static content for printing or archiving: form fields
+/// and annotations become part of the page contents, and the interactive
+/// form, JavaScript and other actions are removed
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The flattened PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn flatten_pdf(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::flatten_pdf(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Builds a PDF with one page per image, each image filling its page at
+/// 72 DPI; images too small or too large for that are fitted to A4. JPEGs are
+/// embedded without re-encoding.
+///
+/// Args:
+///     images (List[bytes]): The encoded images, in page order
+///     formats (Optional[List[str]]): The format of each image: WEBP, PNG, JPEG, or GIF. Detected from
+///         the image data when omitted
+///
+/// Returns:
+///     bytes: The new PDF
+///
+/// Raises:
+///     ValueError: If no images are given, formats doesn't have one entry per image, or an image can't
+///         be decoded
+#[pyfunction]
+#[pyo3(signature = (images, formats = None))]
+pub fn images_to_pdf(images: Vec<Vec<u8>>, formats: Option<Vec<String>>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+    let formats = match formats {
+        Some(formats) if formats.len() != images.len() => {
+            return Err(PyValueError::new_err(format!("Got {} formats for {} images", formats.len(), images.len())));
+        }
+        Some(formats) => formats.iter().map(|format| format.parse()).collect::<Result<Vec<ImageFormat>, _>>()?,
+        None => images.iter().map(|image| ImageFormat::guess(image)).collect::<Result<Vec<_>, _>>()?,
+    };
+
+    Ok(core::images_to_pdf(&pdfium, &images.into_iter().zip(formats).collect::<Vec<_>>())?)
+}
+
+/// Releases pdfium's global state; registered with `atexit` so it runs before
+/// the interpreter unloads the extension. Does nothing if pdfium was never
+/// loaded, documents opened with `open_pdf` are still alive or a call is still
+/// using pdfium, and only destroys the library once. Later calls raise.
+#[pyfunction]
+fn shutdown_pdfium() {
+    let mut destroyed = PDFIUM_DESTROYED.lock().unwrap_or_else(PoisonError::into_inner);
+    if *destroyed || OPEN_DOCUMENTS.load(Ordering::SeqCst) > 0 || ACTIVE_CALLS.load(Ordering::SeqCst) > 0 {
+        return;
+    }
+    if let Some(pdfium) = PDFIUM.get() {
+        pdfium.bindings().FPDF_DestroyLibrary();
+        *destroyed = true;
+    }
+}
+
+/// Detects the language of a PDF's text layer
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: An ISO 639-1 code such as "en", or "unknown" when the PDF has under 100 characters of text
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn detect_language(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::detect_language(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Stamps a QR code onto every page, e.g. to embed a tracking ID
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     data (str): The text to encode in the QR code
+///     position (str): Where to place the code. Must be top_left, top_right, bottom_left, bottom_right, or center
+///     opacity (float): The opacity of the code, between 0.0 and 1.0
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the QR code added to each page
+///
+/// Raises:
+///     ValueError: If the position or opacity is invalid, or the data is too long to encode
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, data, position = "bottom_right".to_string(), opacity = 0.3, max_pages = None))]
+pub fn add_qr_watermark(
+    pdf_bytes: Vec<u8>,
+    data: String,
+    position: String,
+    opacity: f32,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::add_qr_watermark(&pdfium, &pdf_bytes, &data, position.parse()?, opacity, max_pages)?)
+}
+
+/// Renders a single page with semi-transparent yellow highlights drawn over it
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     highlights (List[Tuple[float, float, float, float]]): Rectangles to highlight as (x, y, width, height) in PDF points, measured from the bottom-left of the page
+///     dpi (int): The render resolution
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     format (str): The format of the output image. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     PyPageData: The rendered page with highlights
+///
+/// Raises:
+///     ValueError: If the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, highlights, dpi = 150, quality = 75, format = "WEBP".to_string(), max_pages = None, max_pdf_bytes = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_page_with_highlights(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    highlights: Vec<(f32, f32, f32, f32)>,
+    dpi: u32,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+    let options = RenderOptions {
+        max_pages,
+        max_pdf_bytes,
+        ..RenderOptions::default().with_quality(quality).with_format(format.parse()?).with_dpi(dpi)
+    };
+    let highlights: Vec<HighlightRect> = highlights
+        .into_iter()
+        .map(|(x_pt, y_pt, w_pt, h_pt)| HighlightRect { x_pt, y_pt, w_pt, h_pt, color: HIGHLIGHT_YELLOW })
+        .collect();
+
+    Ok(core::render_page_with_highlights(&pdfium, &pdf_bytes, page_index, &highlights, &options)?.into())
+}
+
+/// Searches a page for some text and renders it with every match highlighted in yellow
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     query (str): The text to search for. Matching is case-insensitive
+///     dpi (int): The render resolution
+///     quality (int): The quality of the output WebP image. Must be between 0 and 100
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The rendered page with matches highlighted
+///
+/// Raises:
+///     ValueError: If the PDF can't be loaded or the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, query, dpi = 150, quality = 75, max_pages = None))]
+pub fn render_page_with_search_results(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    query: &str,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+    Ok(core::render_page_with_search_results(&pdfium, &pdf_bytes, page_index, query, dpi, quality, max_pages)?.into())
+}
+
+/// Estimates how noisy a page is, to help decide whether it needs denoising before OCR
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     float: The variance of the page's Laplacian of Gaussian response; higher values mean more noise
+///
+/// Raises:
+///     ValueError: If the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, max_pages = None))]
+pub fn estimate_page_noise(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    max_pages: Option<usize>,
+) -> PyResult<f32> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::estimate_page_noise(&pdfium, &pdf_bytes, page_index, max_pages)?)
+}
+
+/// Counts the pages of each PDF in a batch, processing them in parallel
+/// without holding the GIL. Only each file's page tree is read, not the
+/// whole document.
+///
+/// Args:
+///     pdfs (List[bytes]): The PDF documents
+///
+/// Returns:
+///     List[int]: The page count of each document, in input order
+///
+/// Raises:
+///     ValueError: If any document cannot be loaded; the message lists every failing entry by index
+#[pyfunction]
+pub fn batch_get_page_counts(
+    py: Python<'_>,
+    pdfs: Vec<Vec<u8>>,
+) -> PyResult<Vec<usize>> {
+    let slices: Vec<&[u8]> = pdfs.iter().map(Vec::as_slice).collect();
+    let results = py.detach(|| core::batch_get_page_counts(&slices));
+
+    let mut counts = Vec::with_capacity(slices.len());
+    let mut failures = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(count) => counts.push(count),
+            Err(e) => failures.push(format!("entry {}: {}", index, e)),
+        }
+    }
+    if !failures.is_empty() {
+        return Err(PyValueError::new_err(failures.join("; ")));
+    }
+
+    Ok(counts)
+}
+
+/// Tiles several pages onto each sheet for n-up printing
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     n (int): The number of pages per sheet. Must be 2, 4, 6, or 8
+///     page_width_pt (float): The width of each output sheet in points
+///     page_height_pt (float): The height of each output sheet in points
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The imposed PDF; empty cells on the last sheet are left blank
+///
+/// Raises:
+///     ValueError: If n or the sheet size is invalid, or the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, n, page_width_pt, page_height_pt, max_pages = None))]
+pub fn impose_nup(
+    pdf_bytes: Vec<u8>,
+    n: usize,
+    page_width_pt: f32,
+    page_height_pt: f32,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::impose_nup(&pdfium, &pdf_bytes, n, [page_width_pt, page_height_pt], max_pages)?)
+}
+
+/// Appends selected pages of one PDF to the end of another
+///
+/// Args:
+///     source (bytes): The PDF to copy pages from
+///     destination (bytes): The PDF to append the pages to
+///     page_indices (List[int]): The zero-based indices of the source pages, in the order they should be appended
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The destination PDF with the copied pages appended
+///
+/// Raises:
+///     ValueError: If a page index is out of range or either PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (source, destination, page_indices, max_pages = None))]
+pub fn copy_pages(
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    page_indices: Vec<usize>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::copy_pages(&pdfium, &source, &destination, &page_indices, max_pages)?)
+}
+
+/// Renders a single page to uncompressed pixels, the lowest-latency render path
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     dpi (int): The render resolution
+///     channels (int): 3 for RGB or 4 for RGBA
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before rendering anything
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     PyRawPageBuffer: The page's dimensions and row-major pixel bytes
+///
+/// Raises:
+///     ValueError: If the page index or channel count is invalid
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, dpi = 150, channels = 3, max_pages = None, max_pdf_bytes = None))]
+pub fn render_page_to_raw(
+    py: Python<'_>,
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    dpi: u32,
+    channels: u8,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<PyRawPageBuffer> {
+    let pdfium = load_pdfium()?;
+    let options = RenderOptions { max_pages, max_pdf_bytes, ..RenderOptions::default().with_dpi(dpi) };
+
+    let buffer = core::render_page_to_raw(&pdfium, &pdf_bytes, page_index, channels, &options)?;
+
+    Ok(PyRawPageBuffer::new(py, buffer))
+}
+
+/// Resizes a page, optionally scaling its content to fit
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     width_pt (float): The new page width in points
+///     height_pt (float): The new page height in points
+///     scale_content (bool): Whether to scale and centre the content to fit the new size, keeping its aspect ratio
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the page resized
+///
+/// Raises:
+///     ValueError: If the page index or size is invalid
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, width_pt, height_pt, scale_content = true, max_pages = None))]
+pub fn set_page_size(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    width_pt: f32,
+    height_pt: f32,
+    scale_content: bool,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::set_page_size(&pdfium, &pdf_bytes, page_index, width_pt, height_pt, scale_content, max_pages)?)
+}
+
+/// Returns the document's XMP metadata
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     Optional[str]: The XMP packet as XML, or None if the document has no XMP metadata
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn get_xmp_metadata(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Option<String>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::get_xmp_metadata(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Writes the document's XMP metadata, replacing any existing packet
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     xmp_xml (str): The complete XMP packet as XML
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the new XMP metadata
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded or saved
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, xmp_xml, max_pages = None))]
+pub fn set_xmp_metadata(
+    pdf_bytes: Vec<u8>,
+    xmp_xml: &str,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::set_xmp_metadata(&pdfium, &pdf_bytes, xmp_xml, max_pages)?)
+}
+
+/// Lists the document information fields that differ between two PDFs
+///
+/// Args:
+///     pdf_a (bytes): The original PDF data
+///     pdf_b (bytes): The changed PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyMetadataDiff]: One entry per changed field, e.g. "Title" or "ModDate", with its
+///         value in pdf_a as old_value and in pdf_b as new_value. Empty when every field matches
+///
+/// Raises:
+///     ValueError: If either PDF can't be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_a, pdf_b, max_pages = None))]
+pub fn compare_metadata(
+    pdf_a: Vec<u8>,
+    pdf_b: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyMetadataDiff>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::compare_metadata(&pdfium, &pdf_a, &pdf_b, max_pages)?.into_iter().map(Into::into).collect())
+}
+
+/// Lists the distinct fonts used by the text on a document's pages
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     List[PyFontInfo]: Each font's name and whether its program is embedded, in order of first use
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn get_fonts(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<Vec<PyFontInfo>> {
+    let pdfium = load_pdfium()?;
+    let fonts = core::get_fonts(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?;
+
+    Ok(fonts.into_iter().map(Into::into).collect())
+}
+
+/// Embeds fonts that the document references by name only
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     font_dir (str): A directory of .ttf and .otf files, matched to fonts by name case-insensitively
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with every matched font embedded
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded or the font directory cannot be read
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, font_dir, max_pages = None))]
+pub fn embed_missing_fonts(
+    pdf_bytes: Vec<u8>,
+    font_dir: String,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::embed_missing_fonts(&pdfium, &pdf_bytes, font_dir.as_ref(), max_pages)?)
+}
+
+/// Reports the size, codec and estimated JPEG quality of every image in a PDF
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyImageQualityStat]: One entry per image object, in page order
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn image_quality_stats(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyImageQualityStat>> {
+    let pdfium = load_pdfium()?;
+    let stats = core::image_quality_stats(&pdfium, &pdf_bytes, max_pages)?;
+
+    Ok(stats.into_iter().map(Into::into).collect())
+}
+
+/// Inserts a table of contents at the front of a PDF
+///
+/// The TOC pages match the size of the first page, and entries that don't fit
+/// continue onto further TOC pages.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     entries (List[Tuple[str, int]]): (title, page number) pairs, printed as given; titles too long to fit end in an ellipsis
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the table of contents prepended
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, entries, max_pages = None))]
+pub fn prepend_toc_page(
+    pdf_bytes: Vec<u8>,
+    entries: Vec<(String, usize)>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::prepend_toc_page(&pdfium, &pdf_bytes, &entries, max_pages)?)
+}
+
+/// Renders a visual diff of the same page in two versions of a PDF
+///
+/// Unchanged pixels are green, content only in the first version (removed)
+/// is red and content only in the second version (added) is blue.
+///
+/// Args:
+///     pdf_a (bytes): The original PDF data
+///     pdf_b (bytes): The modified PDF data
+///     page_index (int): The zero-based index of the page to compare
+///     dpi (int): Resolution to render both pages at
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The difference image as PNG
+///
+/// Raises:
+///     ValueError: If the page index is out of range in either PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_a, pdf_b, page_index, dpi = 150, max_pages = None))]
+pub fn render_page_difference(
+    pdf_a: Vec<u8>,
+    pdf_b: Vec<u8>,
+    page_index: usize,
+    dpi: u32,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_page_difference(&pdfium, &pdf_a, &pdf_b, page_index, dpi, max_pages)?.into())
+}
+
+/// Subsets embedded TrueType fonts to the glyphs the document uses
+///
+/// Fonts that are already subset, CFF-based fonts and form field fonts are
+/// left as they are.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with its fonts subset
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn subset_embedded_fonts(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::subset_embedded_fonts(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Splits a batch scan into separate documents at blank separator pages
+///
+/// Blank pages are dropped from the output, including leading and trailing
+/// ones.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     blank_threshold (float): Pages whose luminance standard deviation at 50 DPI is below this count as blank
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[bytes]: One PDF per document found between the blank pages
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, blank_threshold = 5.0, max_pages = None))]
+pub fn split_by_blank_pages(
+    pdf_bytes: Vec<u8>,
+    blank_threshold: f32,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<Vec<u8>>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::split_by_blank_pages(&pdfium, &pdf_bytes, blank_threshold, max_pages)?)
+}
+
+/// Renders every page into a single grid image
+///
+/// Pages are scaled to thumb_size pixels wide and laid out left to right,
+/// top to bottom, on a white background.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     columns (int): The number of thumbnails per row
+///     thumb_size (int): The width of each thumbnail in pixels
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     format (str): The format of the output image. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The composite image
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, columns = 4, thumb_size = 200, quality = 75, format = "WEBP".to_string(), max_pages = None))]
+pub fn render_pdf_grid(
+    pdf_bytes: Vec<u8>,
+    columns: usize,
+    thumb_size: u32,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_pdf_grid(&pdfium, &pdf_bytes, columns, thumb_size, quality, format.parse()?, max_pages)?.into())
+}
+
+/// Measures how much of a page is covered by text
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     float: The fraction of the page area covered by word bounding boxes, from 0.0 to 1.0
+///
+/// Raises:
+///     ValueError: If the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, max_pages = None))]
+pub fn page_text_density(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    max_pages: Option<usize>,
+) -> PyResult<f32> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::page_text_density(&pdfium, &pdf_bytes, page_index, max_pages)?)
+}
+
+/// Re-saves a PDF with a compressed cross-reference stream (PDF 1.5+)
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF using a cross-reference stream and object streams
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn enable_xref_stream(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::enable_xref_stream(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Renders a single page with inverted colours for dark-mode viewing
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     dpi (int): The render resolution
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The inverted page as WebP
+///
+/// Raises:
+///     ValueError: If the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, dpi = 150, quality = 75, max_pages = None))]
+pub fn render_page_inverted(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_page_inverted(&pdfium, &pdf_bytes, page_index, dpi, quality, max_pages)?.into())
+}
+
+/// Keeps `OPEN_DOCUMENTS` counting a document for as long as it is alive
+struct DocumentLease;
+
+impl DocumentLease {
+    fn new() -> Self {
+        OPEN_DOCUMENTS.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for DocumentLease {
+    fn drop(&mut self) {
+        OPEN_DOCUMENTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A document loaded through the shared pdfium binding
+struct OpenDocument {
+    // Fields drop in order, so the document is closed before its lease ends
+    document: PdfDocument<'static>,
+    _lease: DocumentLease,
+}
+
+/// Maps a Python-style index, where negative values count from the end, onto
+/// `0..len`
+fn resolve_index(index: isize, len: usize) -> Option<usize> {
+    let index = if index < 0 { len.checked_sub(index.unsigned_abs())? } else { index as usize };
+
+    (index < len).then_some(index)
+}
+
+/// An open document shared between a `PyPdfDocument` and its iterators;
+/// `None` once the document has been closed
+type SharedDocument = Arc<RwLock<Option<OpenDocument>>>;
+
+/// Runs `f` on the shared document, or raises ValueError if it was closed
+fn with_open_document<T>(shared: &SharedDocument, f: impl FnOnce(&PdfDocument) -> PyResult<T>) -> PyResult<T> {
+    let guard = shared.read().unwrap_or_else(PoisonError::into_inner);
+    let open = guard
+        .as_ref()
+        .ok_or_else(|| PyValueError::new_err("Document is closed"))?;
+
+    f(&open.document)
+}
+
+/// A PDF held open between calls, rendering pages on demand
+///
+/// Indexing (including negative indices) and iteration render pages with the
+/// default options: 72 DPI WebP at quality 75. Used as a context manager, the
+/// document is closed as soon as the `with` block exits rather than when it
+/// is garbage collected.
+#[pyclass]
+pub struct PyPdfDocument {
+    inner: SharedDocument,
+}
+
+#[pymethods]
+impl PyPdfDocument {
+    fn __len__(&self) -> PyResult<usize> {
+        with_open_document(&self.inner, |document| Ok(document.pages().len() as usize))
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<PyPageData> {
+        with_open_document(&self.inner, |document| {
+            let page_index = resolve_index(index, document.pages().len() as usize)
+                .ok_or_else(|| PyIndexError::new_err("page index out of range"))?;
+
+            Ok(core::render_document_page(document, page_index, &RenderOptions::default())?.into())
+        })
+    }
+
+    fn __iter__(&self) -> PyPdfDocumentIter {
+        PyPdfDocumentIter {
+            inner: Arc::clone(&self.inner),
+            next_index: 0,
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Closes the document, releasing pdfium's copy immediately; exceptions
+    /// raised in the `with` block propagate
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        _exc_type: Option<Bound<'_, PyAny>>,
+        _exc_value: Option<Bound<'_, PyAny>>,
+        _traceback: Option<Bound<'_, PyAny>>,
+    ) -> bool {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner).take();
+
+        false
+    }
+}
+
+/// Iterator over the pages of a `PyPdfDocument`, rendering each as it is reached
+#[pyclass]
+pub struct PyPdfDocumentIter {
+    inner: SharedDocument,
+    next_index: usize,
+}
+
+#[pymethods]
+impl PyPdfDocumentIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyPageData>> {
+        let page_index = self.next_index;
+        let page = with_open_document(&self.inner, |document| {
+            if page_index >= document.pages().len() as usize {
+                return Ok(None);
+            }
+
+            Ok(Some(core::render_document_page(document, page_index, &RenderOptions::default())?))
+        })?;
+        self.next_index += 1;
+
+        Ok(page.map(Into::into))
+    }
+}
+
+/// Opens a PDF for repeated access
+///
+/// Use the result in a `with` block to close the document as soon as the block
+/// exits.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPdfDocument: A handle supporting len(), indexing, and iteration over rendered pages
+///
+/// Raises:
+///     ValueError: If the PDF can't be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn open_pdf(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<PyPdfDocument> {
+    let pdfium = load_pdfium()?;
+    let document = core::open_document(pdfium.get(), pdf_bytes, max_pages)?;
+
+    Ok(PyPdfDocument {
+        inner: Arc::new(RwLock::new(Some(OpenDocument { document, _lease: DocumentLease::new() }))),
+    })
+}
+
+/// Iterator over the rendered pages of a PDF, rendering one page per step
+#[pyclass]
+pub struct PyPageFrameIter {
+    // Fields drop in order, so the document is closed before its lease ends
+    frames: core::PdfPageFrameIter<'static>,
+    _lease: DocumentLease,
+}
+
+#[pymethods]
+impl PyPageFrameIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyPageData>> {
+        match self.frames.next() {
+            Some(frame) => Ok(Some(frame?.into())),
+            None => Ok(None),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+/// Renders the pages of a PDF lazily, so only one rendered page is held at a
+/// time
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     options (Optional[PyRenderOptions]): Rendering options. Defaults to 72 DPI WebP at quality 75
+///
+/// Returns:
+///     PyPageFrameIter: An iterator yielding one PyPageData per page; len() gives the pages left
+///
+/// Raises:
+///     ValueError: If the PDF can't be loaded or exceeds the options' limits, or a page fails to render
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, options = None))]
+pub fn render_pdf_iter(pdf_bytes: Vec<u8>, options: Option<PyRenderOptions>) -> PyResult<PyPageFrameIter> {
+    let pdfium = load_pdfium()?;
+    let options = resolve_options(options, || Ok(RenderOptions::default()))?;
+
+    Ok(PyPageFrameIter {
+        frames: core::render_pdf_iter(pdfium.get(), &pdf_bytes, options)?,
+        _lease: DocumentLease::new(),
+    })
+}
+
+/// Renders the first n pages of a PDF, for previews
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     n (int): The maximum number of pages to render
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyPageData]: One image per rendered page; fewer than n if the document is shorter
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, n, quality = 75, format = "WEBP".to_string(), max_pages = None))]
+pub fn render_first_n_pages(
+    pdf_bytes: Vec<u8>,
+    n: usize,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyPageData>> {
+    let pdfium = load_pdfium()?;
+
+    let pages = core::render_first_n_pages(&pdfium, &pdf_bytes, n, quality, format.parse()?, max_pages)?;
+
+    Ok(pages.into_iter().map(Into::into).collect())
+}
+
+/// Creates a new PDF of blank pages
+///
+/// Args:
+///     page_sizes (List[Tuple[float, float]]): (width, height) in points for each page, in order; see A4_PT and LETTER_PT
+///
+/// Returns:
+///     bytes: The new PDF
+///
+/// Raises:
+///     ValueError: If no pages are given or a size isn't positive
+#[pyfunction]
+pub fn create_blank_pdf(page_sizes: Vec<(f32, f32)>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::create_blank_pdf(&pdfium, &page_sizes)?)
+}
+
+/// Draws text on a page
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     text (str): The text to draw
+///     x (float): The start of the baseline, in points from the left edge of the page
+///     y (float): The baseline, in points from the bottom edge of the page
+///     font_size (float): The font size in points
+///     color (Tuple[int, int, int]): The RGB text colour
+///     font (Optional[str]): A standard PDF font such as "Times-Roman", or the name of a system or bundled font file; Helvetica when omitted
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF with the text added
+///
+/// Raises:
+///     ValueError: If the page index is out of range or the font can't be found
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, text, x, y, font_size, color = (0, 0, 0), font = None, max_pages = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn add_text_to_page(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    text: &str,
+    x: f32,
+    y: f32,
+    font_size: f32,
+    color: (u8, u8, u8),
+    font: Option<String>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+    let color = [color.0, color.1, color.2];
+
+    Ok(core::add_text_to_page(&pdfium, &pdf_bytes, page_index, text, x, y, font_size, color, font.as_deref(), max_pages)?)
+}
+
+/// Measures how wide text is when drawn with add_text_to_page, e.g. to center
+/// or right-align it
+///
+/// Args:
+///     text (str): The text to measure
+///     font (Optional[str]): A standard PDF font such as "Times-Roman", or the name of a system or bundled font file; Helvetica when omitted
+///     font_size (float): The font size in points
+///
+/// Returns:
+///     float: The width of the text in points
+///
+/// Raises:
+///     ValueError: If the font can't be found or font_size isn't positive
+#[pyfunction]
+#[pyo3(signature = (text, font = None, font_size = 12.0))]
+pub fn measure_text(text: &str, font: Option<String>, font_size: f32) -> PyResult<f32> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::measure_text(&pdfium, text, font.as_deref(), font_size)?)
+}
+
+/// Flattens a PDF's bookmarks into a table of contents
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     List[PyTocEntry]: Each bookmark's nesting level (1 for top level), title, and target page label, depth first
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn extract_toc_text(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<Vec<PyTocEntry>> {
+    let pdfium = load_pdfium()?;
+
+    let entries = core::extract_toc_text(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?;
+
+    Ok(entries.into_iter().map(Into::into).collect())
+}
+
+/// Renders every page of several PDF files, processing the files in parallel
+///
+/// Args:
+///     paths (List[str]): Paths of the PDF files
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///
+/// Returns:
+///     List[Tuple[str, List[PyPageData]]]: Each path with its rendered pages, in input order
+///
+/// Raises:
+///     ValueError: If any file cannot be read or rendered; the message lists every failing path
+#[pyfunction]
+#[pyo3(signature = (paths, quality = 75, format = "WEBP".to_string()))]
+pub fn render_pdf_files_parallel(
+    paths: Vec<PathBuf>,
+    quality: u8,
+    format: String,
+) -> PyResult<Vec<(String, Vec<PyPageData>)>> {
+    let pdfium = load_pdfium()?;
+    let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+
+    let mut rendered = Vec::with_capacity(paths.len());
+    let mut failures = Vec::new();
+    for (path, result) in core::render_pdf_files_parallel(&pdfium, &paths, quality, format.parse()?) {
+        match result {
+            Ok(pages) => rendered.push((path.display().to_string(), pages.into_iter().map(Into::into).collect())),
+            Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    if !failures.is_empty() {
+        return Err(PyValueError::new_err(failures.join("; ")));
+    }
+
+    Ok(rendered)
+}
+
+/// Checks a PDF against a practical subset of PDF/UA (ISO 14289-1)
+///
+/// Reports a missing document language, untagged pages, images without
+/// alternate text, skipped heading levels and tables without header cells.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PyAccessibilityViolation]: The failed checks; empty if none failed
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn validate_pdfua(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PyAccessibilityViolation>> {
+    let pdfium = load_pdfium()?;
+
+    let violations = core::validate_pdfua(&pdfium, &pdf_bytes, max_pages)?;
+
+    Ok(violations.into_iter().map(Into::into).collect())
+}
+
+/// Renders every page of a PDF into a looping animated WebP, one frame per page
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     quality (int): The quality of the frames. Must be between 0 and 100
+///     frame_duration_ms (int): How long each page is shown, in milliseconds
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The animated WebP; a still WebP for single-page PDFs
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality = 75, frame_duration_ms = 2000, max_pages = None))]
+pub fn render_pdf_as_animated_webp(
+    pdf_bytes: Vec<u8>,
+    quality: u8,
+    frame_duration_ms: u32,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_pdf_as_animated_webp(&pdfium, &pdf_bytes, quality, frame_duration_ms, max_pages)?)
+}
+
+/// Renders every page of a PDF into one GIF, animated when the PDF has more than one page
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     frame_delay_ms (int): How long each page is shown, in milliseconds
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The GIF, quantized to 256 colors
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, frame_delay_ms = 2000, max_pages = None))]
+pub fn render_pdf_as_gif(
+    pdf_bytes: Vec<u8>,
+    frame_delay_ms: u16,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_pdf_as_gif(&pdfium, &pdf_bytes, frame_delay_ms, max_pages)?)
+}
+
+/// Stamps a visible page number onto every page of a PDF
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     start (int): The number shown on the first page
+///     position (str): Where to place the number. Must be bottom_center, bottom_right, top_center, or top_right
+///     font_size (float): The font size in points
+///     color (Tuple[int, int, int]): The text color as (red, green, blue)
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The numbered PDF
+///
+/// Raises:
+///     ValueError: If the position is unknown or the font size is not positive
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, start = 1, position = "bottom_center".to_string(), font_size = 10.0, color = (0, 0, 0), max_pages = None))]
+pub fn add_page_numbers(
+    pdf_bytes: Vec<u8>,
+    start: usize,
+    position: String,
+    font_size: f32,
+    color: (u8, u8, u8),
+    max_pages: Option<usize>,
+) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::add_page_numbers(&pdfium, &pdf_bytes, start, position.parse()?, font_size, [color.0, color.1, color.2], max_pages)?)
+}
+
+/// Counts the images on each page of a PDF without decoding them
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     List[int]: The number of images on each page, in page order
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn get_page_image_count(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<Vec<usize>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::get_page_image_count(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?)
+}
+
+/// Counts the images across all pages of a PDF without decoding them
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///     max_pdf_bytes (Optional[int]): Refuse PDFs larger than this many bytes; see DEFAULT_MAX_PDF_BYTES
+///
+/// Returns:
+///     int: The total number of images
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None, max_pdf_bytes = None))]
+pub fn total_image_count(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+    max_pdf_bytes: Option<usize>,
+) -> PyResult<usize> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::get_page_image_count(&pdfium, &pdf_bytes, max_pages, max_pdf_bytes)?.into_iter().sum())
+}
+
+/// Wraps RGB pixels as an OpenCV-style `(height, width, 3)` uint8 array in BGR order
+fn bgr_array(py: Python<'_>, buffer: RawPageBuffer) -> PyResult<Bound<'_, PyArray3<u8>>> {
+    // rust-numpy panics when the NumPy C API is unavailable, so surface a
+    // missing package as an ImportError first
+    py.import("numpy")?;
+
+    let mut data = buffer.data;
+    for pixel in data.chunks_exact_mut(3) {
+        pixel.swap(0, 2);
+    }
+
+    PyArray1::from_vec(py, data).reshape([buffer.height as usize, buffer.width as usize, 3])
+}
+
+/// Renders a single page as a BGR numpy array, ready for OpenCV
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     dpi (int): The render resolution
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     numpy.ndarray: A (height, width, 3) uint8 array in BGR channel order
+///
+/// Raises:
+///     ValueError: If the page index is invalid
+///     ImportError: If numpy is not installed
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, dpi = 150, max_pages = None))]
+pub fn render_page_to_opencv_mat(
+    py: Python<'_>,
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    dpi: u32,
+    max_pages: Option<usize>,
+) -> PyResult<Bound<'_, PyArray3<u8>>> {
+    let pdfium = load_pdfium()?;
+    let options = RenderOptions { max_pages, ..RenderOptions::default().with_dpi(dpi) };
+
+    let buffer = core::render_page_to_raw(&pdfium, &pdf_bytes, page_index, 3, &options)?;
+
+    bgr_array(py, buffer)
+}
+
+/// Appends every page of one PDF to the end of another
+///
+/// Args:
+///     base (bytes): The PDF to append to; empty bytes act as a document with no pages
+///     appendix (bytes): The PDF whose pages are appended
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The combined PDF
+#[pyfunction]
+#[pyo3(signature = (base, appendix, max_pages = None))]
+pub fn append_pdf(base: Vec<u8>, appendix: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::append_pdf(&pdfium, &base, &appendix, max_pages)?)
+}
+
+/// Rotates every portrait page of a PDF 90 degrees clockwise so all pages are landscape
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The rotated PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn rotate_to_landscape(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::rotate_to_landscape(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Rotates every landscape page of a PDF 90 degrees clockwise so all pages are portrait
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The rotated PDF
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn rotate_to_portrait(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::rotate_to_portrait(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Renders every page of a PDF on a background thread without blocking the
+/// asyncio event loop, for use from FastAPI, aiohttp and similar frameworks.
+/// Must be called while an event loop is running. Built on pyo3-async-runtimes,
+/// the maintained successor of pyo3-asyncio, which doesn't support our PyO3.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     quality (int): The quality of the output images. Must be between 0 and 100
+///     format (str): The format of the output images. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     Awaitable[List[PyPageData]]: Resolves to the rendered pages
+///
+/// Raises:
+///     ValueError: If the options are invalid, or when awaited, if the PDF cannot be rendered
+///     RuntimeError: If no event loop is running
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, quality = 75, format = "WEBP".to_string(), max_pages = None))]
+pub fn render_pdf_pages_async(
+    py: Python<'_>,
+    pdf_bytes: Vec<u8>,
+    quality: u8,
+    format: String,
+    max_pages: Option<usize>,
+) -> PyResult<Bound<'_, PyAny>> {
+    let options = RenderOptions {
+        max_pages,
+        ..RenderOptions::default().with_quality(quality).with_format(format.parse()?)
+    };
+    // Leased up front, so `shutdown_pdfium` waits for the render however long
+    // it sits on the blocking pool
+    let pdfium = load_pdfium()?;
+
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        let pages = pyo3_async_runtimes::tokio::get_runtime()
+            .spawn_blocking(move || core::render_pdf(&pdfium, &pdf_bytes, &options))
+            .await
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))??;
+
+        Ok(pages.into_iter().map(PyPageData::from).collect::<Vec<_>>())
+    })
+}
+
+/// Hashes what a PDF shows rather than its bytes, for deduplication and
+/// change detection. Unaffected by metadata edits and re-saves.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     str: A SHA-256 hex digest of the text, font names and image pixels
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn hash_pdf_content(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<String> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::hash_pdf_content(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Extracts the dominant colors of a page, e.g. to group covers by color
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     n_colors (int): The maximum number of colors to return
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[Tuple[int, int, int]]: RGB colors, most dominant first; fewer than n_colors when
+///         the page has fewer distinct colors
+///
+/// Raises:
+///     ValueError: If the page index is out of range or n_colors is 0
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index = 0, n_colors = 5, max_pages = None))]
+pub fn extract_color_palette(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    n_colors: usize,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<(u8, u8, u8)>> {
+    let pdfium = load_pdfium()?;
+    let palette = core::extract_color_palette(&pdfium, &pdf_bytes, page_index, n_colors, max_pages)?;
+
+    Ok(palette.into_iter().map(|[r, g, b]| (r, g, b)).collect())
+}
+
+/// Compresses the images in a batch of PDFs, processing them in parallel
+/// without holding the GIL
+///
+/// Args:
+///     pdfs (List[Tuple[bytes, int]]): Each PDF with the JPEG quality to use for it, between 1 and 100
+///
+/// Returns:
+///     Tuple[List[Optional[bytes]], List[Optional[str]]]: The compressed PDFs and, at the same
+///         positions, the error for each entry; a failed entry has None in the first list
+#[pyfunction]
+pub fn batch_compress_pdfs(
+    py: Python<'_>,
+    pdfs: Vec<(Vec<u8>, u8)>,
+) -> (Vec<Option<Vec<u8>>>, Vec<Option<String>>) {
+    py.detach(|| core::batch_compress_pdfs(pdfs))
+        .into_iter()
+        .map(|result| match result {
+            Ok(compressed) => (Some(compressed), None),
+            Err(e) => (None, Some(e.to_string())),
+        })
+        .unzip()
+}
+
+/// Rebuilds a mildly corrupt PDF, e.g. one with a truncated cross-reference
+/// table or wrong offsets, so stricter readers can open it
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     bytes: The PDF rewritten with a fresh cross-reference table
+///
+/// Raises:
+///     ValueError: If the PDF is too damaged for pdfium to recover
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn repair_pdf(pdf_bytes: Vec<u8>, max_pages: Option<usize>) -> PyResult<Vec<u8>> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::repair_pdf(&pdfium, &pdf_bytes, max_pages)?)
+}
+
+/// Builds the pure-Python `ztron_pdf.utils` submodule from `src/utils.py`
+fn utils_module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    PyModule::from_code(
+        py,
+        pyo3::ffi::c_str!(include_str!("utils.py")),
+        c"ztron_pdf/utils.py",
+        c"ztron_pdf.utils",
+    )
+}
+
+/// Builds the pure-Python `ztron_pdf.schemas` submodule from `src/schemas.py`
+fn schemas_module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    PyModule::from_code(
+        py,
+        pyo3::ffi::c_str!(include_str!("schemas.py")),
+        c"ztron_pdf/schemas.py",
+        c"ztron_pdf.schemas",
+    )
+}
+
+/// Extracts the visible images of signature fields, e.g. scanned
+/// handwritten signatures on contracts
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     List[PySignatureImage]: Each image as PNG with its page and field name, in page order
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded or an image cannot be decoded
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn extract_signature_images(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<Vec<PySignatureImage>> {
+    let pdfium = load_pdfium()?;
+    let images = core::extract_signature_images(&pdfium, &pdf_bytes, max_pages)?;
+
+    Ok(images.into_iter().map(Into::into).collect())
+}
+
+/// Recompresses each embedded image with the encoding that suits its content:
+/// CCITT G4 for black-and-white scans, lossless Flate (indexed when in color) for
+/// line art and screenshots, and JPEG for photographs. An image is only replaced
+/// when the new encoding is smaller.
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF file content as bytes
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     Tuple[bytes, PySmartCompressReport]: The compressed PDF and what was done to its images
+///
+/// Raises:
+///     ValueError: If the PDF can't be loaded or the result fails to load
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, max_pages = None))]
+pub fn smart_compress_pdf(
+    pdf_bytes: Vec<u8>,
+    max_pages: Option<usize>,
+) -> PyResult<(Vec<u8>, PySmartCompressReport)> {
+    let pdfium = load_pdfium()?;
+    let (compressed, report) = core::smart_compress_pdf(&pdfium, &pdf_bytes, max_pages)?;
+
+    Ok((compressed, report.into()))
+}
+
+/// Renders every page into a single tall image, stacked top to bottom like a
+/// continuous scroll view
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     target_width (int): The width every page is scaled to, in pixels
+///     quality (int): The quality of the output image. Must be between 0 and 100
+///     format (str): The format of the output image. Must be WEBP, PNG, JPEG, or GIF; also JP2 with the
+///         jpeg2000 feature, HEIC with the heic feature
+///     gap (int): The white space between consecutive pages, in pixels
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The stitched image
+///
+/// Raises:
+///     ValueError: If the PDF cannot be loaded, has no pages, or target_width is 0, or the strip is
+///         taller than the format allows (16383px for WEBP, 65535px for GIF and JPEG)
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, target_width = 800, quality = 75, format = "WEBP".to_string(), gap = 10, max_pages = None))]
+pub fn stitch_pages_vertically(
+    pdf_bytes: Vec<u8>,
+    target_width: u32,
+    quality: u8,
+    format: String,
+    gap: u32,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::stitch_pages_vertically(&pdfium, &pdf_bytes, target_width, quality, format.parse()?, gap, max_pages)?.into())
+}
+
+/// Renders a page as WebP with its brightness and contrast adjusted, for
+/// scans that come out too dark or too light
+///
+/// Args:
+///     pdf_bytes (bytes): The PDF data
+///     page_index (int): The zero-based index of the page
+///     brightness (float): Added to every channel, e.g. 20.0 to lighten or -20.0 to darken
+///     contrast (float): Scales every channel's distance from mid-grey; above 1.0 increases contrast
+///     dpi (int): The render resolution
+///     quality (int): The WebP quality. Must be between 0 and 100
+///     max_pages (Optional[int]): Refuse PDFs with more pages than this before reading them
+///
+/// Returns:
+///     PyPageData: The adjusted page
+///
+/// Raises:
+///     ValueError: If contrast is not greater than 0 or the page index is out of range
+#[pyfunction]
+#[pyo3(signature = (pdf_bytes, page_index, brightness = 0.0, contrast = 1.0, dpi = 150, quality = 75, max_pages = None))]
+pub fn render_page_with_color_adjustment(
+    pdf_bytes: Vec<u8>,
+    page_index: usize,
+    brightness: f32,
+    contrast: f32,
+    dpi: u32,
+    quality: u8,
+    max_pages: Option<usize>,
+) -> PyResult<PyPageData> {
+    let pdfium = load_pdfium()?;
+
+    Ok(core::render_page_with_color_adjustment(&pdfium, &pdf_bytes, page_index, brightness, contrast, dpi, quality, max_pages)?.into())
+}
+
+/// The pdfium-render crate version this module was built against
+const PDFIUM_RENDER_VERSION: &str = env!("PDFIUM_RENDER_VERSION");
+
+/// The pdfium build this package is built and tested against, as recorded in
+/// its VERSION file. pdfium has no runtime API for querying the loaded
+/// library's version, so a library picked up from elsewhere may differ.
+fn pdfium_build_version() -> String {
+    let fields: Vec<&str> = include_str!("../VERSION")
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(_, value)| value.trim())
+        .collect();
+
+    fields.join(".")
+}
+
+
+/// Fast PDF rendering and inspection backed by pdfium.
+///
+/// `render_cover(pdf_bytes)` returns a base64 WebP preview of the first page,
+/// ready for an `<img>` tag; it covers the most common use of this module.
+///
+/// `render_pdf` is the canonical rendering entry point. `render_base64_pdf`,
+/// whose name wrongly suggested base64 input, is kept as a deprecated alias
+/// that emits a DeprecationWarning.
+#[pymodule]
+fn ztron_pdf(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.py().import("atexit")?.call_method1("register", (wrap_pyfunction!(shutdown_pdfium, m)?,))?;
+    m.add("__pdfium_render_version__", PDFIUM_RENDER_VERSION)?;
+    m.add("__pdfium_build_version__", pdfium_build_version())?;
+    // Written out as f64 so Python sees 595.28 rather than the nearest f32
+    // to `core::A4_PT`
+    m.add("A4_PT", (595.28, 841.89))?;
+    m.add("LETTER_PT", (612.0, 792.0))?;
+    m.add("DEFAULT_MAX_PDF_BYTES", core::DEFAULT_MAX_PDF_BYTES)?;
+    let utils = utils_module(m.py())?;
+    m.add_submodule(&utils)?;
+    // `from ztron_pdf.utils import ...` resolves submodules through sys.modules
+    m.py().import("sys")?.getattr("modules")?.set_item("ztron_pdf.utils", &utils)?;
+    let schemas = schemas_module(m.py())?;
+    m.add_submodule(&schemas)?;
+    m.py().import("sys")?.getattr("modules")?.set_item("ztron_pdf.schemas", &schemas)?;
+    m.add_class::<PyRenderOptions>()?;
+    m.add_class::<PyPdfDocument>()?;
+    m.add_function(wrap_pyfunction!(render_cover, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_page_previews_json, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf, m)?)?;
     m.add_function(wrap_pyfunction!(render_base64_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_from_file_like, m)?)?;
     m.add_function(wrap_pyfunction!(compress_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(compress_pdf_with_downsample, m)?)?;
+    m.add_function(wrap_pyfunction!(pdf_to_markdown, m)?)?;
+    m.add_function(wrap_pyfunction!(pdf_to_text, m)?)?;
+    m.add_function(wrap_pyfunction!(page_word_count, m)?)?;
+    m.add_function(wrap_pyfunction!(document_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_to_cbz, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_to_png, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_structure_tree, m)?)?;
+    m.add_function(wrap_pyfunction!(is_scanned_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_pages_to_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(has_digital_signature, m)?)?;
+    m.add_function(wrap_pyfunction!(count_digital_signatures, m)?)?;
+    m.add_function(wrap_pyfunction!(list_signature_signers, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_signatures, m)?)?;
+    m.add_function(wrap_pyfunction!(deskew_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page, m)?)?;
+    m.add_function(wrap_pyfunction!(get_page_labels, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_by_label, m)?)?;
+    m.add_function(wrap_pyfunction!(render_thumbnails, m)?)?;
+    m.add_function(wrap_pyfunction!(set_pdf_password, m)?)?;
+    m.add_function(wrap_pyfunction!(garbage_collect_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_portfolio, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_embedded_files, m)?)?;
+    m.add_function(wrap_pyfunction!(attach_file, m)?)?;
+    m.add_function(wrap_pyfunction!(strip_embedded_files, m)?)?;
+    m.add_function(wrap_pyfunction!(flatten_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(images_to_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_language, m)?)?;
+    m.add_function(wrap_pyfunction!(add_qr_watermark, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_with_highlights, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_with_search_results, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_page_noise, m)?)?;
+    m.add_function(wrap_pyfunction!(batch_get_page_counts, m)?)?;
+    m.add_function(wrap_pyfunction!(impose_nup, m)?)?;
+    m.add_function(wrap_pyfunction!(copy_pages, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_to_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(set_page_size, m)?)?;
+    m.add_function(wrap_pyfunction!(get_xmp_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(set_xmp_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(get_fonts, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_missing_fonts, m)?)?;
+    m.add_function(wrap_pyfunction!(image_quality_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(prepend_toc_page, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_difference, m)?)?;
+    m.add_function(wrap_pyfunction!(subset_embedded_fonts, m)?)?;
+    m.add_function(wrap_pyfunction!(split_by_blank_pages, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_grid, m)?)?;
+    m.add_function(wrap_pyfunction!(page_text_density, m)?)?;
+    m.add_function(wrap_pyfunction!(enable_xref_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_inverted, m)?)?;
+    m.add_function(wrap_pyfunction!(open_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(render_first_n_pages, m)?)?;
+    m.add_function(wrap_pyfunction!(create_blank_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(add_text_to_page, m)?)?;
+    m.add_function(wrap_pyfunction!(measure_text, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_toc_text, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_files_parallel, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_pdfua, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_as_animated_webp, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_as_gif, m)?)?;
+    m.add_function(wrap_pyfunction!(add_page_numbers, m)?)?;
+    m.add_function(wrap_pyfunction!(get_page_image_count, m)?)?;
+    m.add_function(wrap_pyfunction!(total_image_count, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_to_opencv_mat, m)?)?;
+    m.add_function(wrap_pyfunction!(append_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(rotate_to_landscape, m)?)?;
+    m.add_function(wrap_pyfunction!(rotate_to_portrait, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_pages_async, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_pdf_content, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_color_palette, m)?)?;
+    m.add_function(wrap_pyfunction!(batch_compress_pdfs, m)?)?;
+    m.add_function(wrap_pyfunction!(repair_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_signature_images, m)?)?;
+    m.add_function(wrap_pyfunction!(smart_compress_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(stitch_pages_vertically, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page_with_color_adjustment, m)?)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_buffer_as_base64() {
+        let page = PyPageData {
+            image_buffer: vec![0x52, 0x49, 0x46, 0x46, 0x00, 0xff, 0x10],
+            error: None,
+        };
+
+        let decoded = BASE64.decode(page.image_buffer_as_base64())
+            .expect("Failed to decode base64 image buffer");
+        assert_eq!(decoded, page.image_buffer);
+    }
+
+    #[test]
+    fn test_version_attributes() {
+        let field = |key: &str| {
+            include_str!("../VERSION")
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .expect("Missing VERSION field")
+        };
+        let expected = format!("{}.{}.{}.{}", field("MAJOR"), field("MINOR"), field("BUILD"), field("PATCH"));
+        assert_eq!(pdfium_build_version(), expected);
+        assert!(PDFIUM_RENDER_VERSION.starts_with("0.8."));
+    }
+
+    #[test]
+    fn test_render_base64_pdf_warns() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Escalate the warning so the call fails before touching pdfium
+            let warnings = py.import("warnings").unwrap();
+            let catcher = warnings.call_method0("catch_warnings").unwrap();
+            catcher.call_method0("__enter__").unwrap();
+            warnings.call_method1("simplefilter", ("error", py.get_type::<PyDeprecationWarning>())).unwrap();
+
+            let result = render_base64_pdf(py, Vec::new(), 75, None, false, None, false, None, false);
+            catcher.call_method1("__exit__", (py.None(), py.None(), py.None())).unwrap();
+
+            let error = result.err().expect("render_base64_pdf did not warn");
+            assert!(error.is_instance_of::<PyDeprecationWarning>(py));
+        });
+    }
+
+    #[test]
+    fn test_render_hook() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = pyo3::types::PyDict::new(py);
+            py.run(pyo3::ffi::c_str!(r#"
+def invert(index, page):
+    if index == 1:
+        raise KeyError("page 1")
+    if index == 2:
+        return b"short"
+    return bytes(255 - value for value in page.data)
+"#), Some(&globals), None).unwrap();
+            let hook = PyRenderHook { callback: globals.get_item("invert").unwrap().unwrap(), error: RefCell::new(None) };
+
+            let mut image = DynamicImage::ImageRgb8(RgbImage::from_raw(2, 1, vec![0, 10, 20, 30, 40, 50]).unwrap());
+            hook.after_render(0, &mut image).expect("Hook failed");
+            assert_eq!(image.to_rgb8().into_raw(), [255, 245, 235, 225, 215, 205]);
+
+            assert!(hook.after_render(1, &mut image).is_err());
+            assert!(hook.error.take().expect("Exception was not kept").is_instance_of::<pyo3::exceptions::PyKeyError>(py));
+
+            let error = hook.after_render(2, &mut image).unwrap_err();
+            assert_eq!(error, "render_hook must return 6 bytes of RGB pixels");
+            assert!(hook.error.take().is_none());
+        });
+    }
+
+    #[test]
+    fn test_pdf_input() {
+        let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+        let encoded = BASE64.encode(&pdf_bytes);
+
+        assert_eq!(PdfInput::Bytes(pdf_bytes.clone()).into_bytes(None).unwrap(), pdf_bytes);
+        assert_eq!(PdfInput::Path("./samples/test.pdf".into()).into_bytes(None).unwrap(), pdf_bytes);
+        assert_eq!(PdfInput::Text(format!("{}{}", PDF_DATA_URL_PREFIX, encoded)).into_bytes(None).unwrap(), pdf_bytes);
+        assert_eq!(PdfInput::Text(encoded).into_bytes(None).unwrap(), pdf_bytes);
+
+        Python::initialize();
+        Python::attach(|py| {
+            assert!(PdfInput::Text("./samples/test.pdf".to_string()).into_bytes(None).unwrap_err().is_instance_of::<PyValueError>(py));
+            assert!(PdfInput::Path("./samples/missing.pdf".into()).into_bytes(None).unwrap_err().is_instance_of::<pyo3::exceptions::PyOSError>(py));
+            let error = PdfInput::Path("./samples/test.pdf".into()).into_bytes(Some(pdf_bytes.len() - 1)).unwrap_err();
+            assert_eq!(error.value(py).to_string(), "PDF exceeds maximum allowed size");
+
+            let input: PdfInput = pyo3::types::PyBytes::new(py, b"%PDF").extract().unwrap();
+            assert!(matches!(input, PdfInput::Bytes(_)));
+            let input: PdfInput = "abc".into_pyobject(py).unwrap().extract().unwrap();
+            assert!(matches!(input, PdfInput::Text(_)));
+            let path = py.import("pathlib").unwrap().call_method1("Path", ("./samples/test.pdf",)).unwrap();
+            assert!(matches!(path.extract::<PdfInput>().unwrap(), PdfInput::Path(_)));
+        });
+    }
+
+    #[test]
+    fn test_bgr_array() {
+        use numpy::PyUntypedArrayMethods;
+
+        Python::initialize();
+        Python::attach(|py| {
+            let buffer = RawPageBuffer { width: 2, height: 1, channels: 3, data: vec![1, 2, 3, 4, 5, 6] };
+            let array = bgr_array(py, buffer).expect("Failed to build array");
+
+            let np = py.import("numpy").expect("numpy is not installed");
+            assert!(array.getattr("dtype").unwrap().eq(np.getattr("uint8").unwrap()).unwrap());
+            assert_eq!(array.shape(), [1, 2, 3]);
+            assert_eq!(array.to_vec().unwrap(), [3, 2, 1, 6, 5, 4]);
+        });
+    }
+
+    #[test]
+    fn test_render_pdf_pages_async() {
+        use pyo3::types::PyDict;
+
+        Python::initialize();
+        Python::attach(|py| {
+            let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+            let globals = PyDict::new(py);
+            globals.set_item("render", wrap_pyfunction!(render_pdf_pages_async, py).unwrap()).unwrap();
+            globals.set_item("pdf_bytes", pdf_bytes).unwrap();
+
+            py.run(
+                c"import asyncio\n\
+                  async def main():\n    return await render(pdf_bytes)\n\
+                  pages = asyncio.run(main())",
+                Some(&globals),
+                None,
+            ).expect("Failed to render asynchronously");
+
+            let pages = globals.get_item("pages").unwrap().unwrap();
+            assert_eq!(pages.len().unwrap(), 5);
+        });
+    }
+
+    #[test]
+    fn test_render_pdf_from_file_like() {
+        Python::initialize();
+        Python::attach(|py| {
+            let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+            let io = py.import("io").unwrap();
+
+            let file_obj = io.call_method1("BytesIO", (pdf_bytes.clone(),)).unwrap();
+            let pages = render_pdf_from_file_like(file_obj, 75, "PNG".to_string(), None, None).expect("Failed to render");
+            assert_eq!(pages.len(), 5);
+            assert!(pages[0].image_buffer.starts_with(b"\x89PNG"));
+
+            let text_file = io.call_method1("StringIO", ("not bytes",)).unwrap();
+            let error = render_pdf_from_file_like(text_file, 75, "PNG".to_string(), None, None).err().unwrap();
+            assert!(error.is_instance_of::<pyo3::exceptions::PyTypeError>(py));
+
+            let file_obj = io.call_method1("BytesIO", (pdf_bytes.clone(),)).unwrap();
+            let error = render_pdf_from_file_like(file_obj, 75, "PNG".to_string(), Some(3), None).err().unwrap();
+            assert!(error.is_instance_of::<pyo3::exceptions::PyValueError>(py));
+
+            let file_obj = io.call_method1("BytesIO", (pdf_bytes.clone(),)).unwrap();
+            let error = render_pdf_from_file_like(file_obj, 75, "PNG".to_string(), None, Some(pdf_bytes.len() - 1)).err().unwrap();
+            assert!(error.is_instance_of::<pyo3::exceptions::PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_utils_module() {
+        Python::initialize();
+        Python::attach(|py| {
+            let utils = utils_module(py).expect("Failed to build ztron_pdf.utils");
+            assert!(utils.hasattr("page_to_pil").unwrap());
+
+            let buffer = RawPageBuffer { width: 2, height: 1, channels: 3, data: vec![1, 2, 3, 4, 5, 6] };
+            let raw = Bound::new(py, PyRawPageBuffer::new(py, buffer)).unwrap();
+            // The pixels are converted once, not on every access
+            assert!(raw.getattr("data").unwrap().is(raw.getattr("data").unwrap()));
+            let image = utils.call_method1("raw_to_pil", (raw,)).expect("Pillow is not installed");
+            assert_eq!(image.getattr("size").unwrap().extract::<(u32, u32)>().unwrap(), (2, 1));
+            assert_eq!(image.call_method1("getpixel", ((1, 0),)).unwrap().extract::<(u8, u8, u8)>().unwrap(), (4, 5, 6));
+        });
+    }
+
+    #[test]
+    fn test_page_data_to_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let page = PyPageData { image_buffer: b"{\"page\": 1}".to_vec(), error: None };
+            let globals = PyDict::new(py);
+            globals.set_item("page", Bound::new(py, page).unwrap()).unwrap();
+
+            py.run(
+                cr#"
+import base64, json
+data = page.to_dict()
+assert json.loads(base64.b64decode(data["image_buffer_b64"])) == {"page": 1}
+assert data["error"] is None
+json.dumps(data)
+schema = json.loads(type(page).model_json_schema())
+assert set(schema["properties"]) == set(data)
+assert schema["required"] == ["image_buffer_b64"]
+"#,
+                Some(&globals),
+                None,
+            ).expect("to_dict output doesn't match its schema");
+
+            let schemas = schemas_module(py).expect("Failed to build ztron_pdf.schemas");
+            assert!(schemas.hasattr("PageData").unwrap());
+            let dict = schemas.call_method1("page_to_dict", (globals.get_item("page").unwrap().unwrap(),)).unwrap();
+            assert!(dict.cast::<PyDict>().unwrap().contains("image_buffer_b64").unwrap());
+        });
+    }
+
+    #[test]
+    fn test_document_stats_to_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let stats = PyDocumentStats { page_count: 2, total_words: 7, total_chars: 30, avg_words_per_page: 3.5 };
+            let globals = PyDict::new(py);
+            globals.set_item("stats", Bound::new(py, stats).unwrap()).unwrap();
+
+            py.run(
+                cr#"
+import json
+data = stats.to_dict()
+assert data == {"page_count": 2, "total_words": 7, "total_chars": 30, "avg_words_per_page": 3.5}
+json.dumps(data)
+schema = json.loads(type(stats).model_json_schema())
+assert set(schema["properties"]) == set(data)
+assert schema["required"] == list(data)
+"#,
+                Some(&globals),
+                None,
+            ).expect("to_dict output doesn't match its schema");
+
+            let schemas = schemas_module(py).expect("Failed to build ztron_pdf.schemas");
+            assert!(schemas.hasattr("DocumentStats").unwrap());
+            let dict = schemas.call_method1("stats_to_dict", (globals.get_item("stats").unwrap().unwrap(),)).unwrap();
+            assert!(dict.cast::<PyDict>().unwrap().contains("avg_words_per_page").unwrap());
+        });
+    }
+
+    #[test]
+    fn test_resolve_options_skips_keywords() {
+        let options = PyRenderOptions {
+            quality: 40,
+            format: "PNG".to_string(),
+            dpi: None,
+            grayscale: false,
+            icc_profile: None,
+            simulate_overprint: false,
+            max_pages: None,
+            max_pdf_bytes: None,
+            color_space: "srgb".to_string(),
+            print_marks: false,
+            per_page_timeout: None,
+        };
+        let keywords = || "BOGUS".parse::<ImageFormat>().map(|format| RenderOptions::default().with_format(format));
+
+        let resolved = resolve_options(Some(options), keywords).expect("Keywords were parsed despite options");
+        assert_eq!((resolved.quality, resolved.format), (40, ImageFormat::Png));
+        assert!(resolve_options(None, keywords).is_err());
+    }
+
+    #[test]
+    fn test_resolve_index() {
+        assert_eq!(resolve_index(0, 3), Some(0));
+        assert_eq!(resolve_index(2, 3), Some(2));
+        assert_eq!(resolve_index(3, 3), None);
+        assert_eq!(resolve_index(-1, 3), Some(2));
+        assert_eq!(resolve_index(-3, 3), Some(0));
+        assert_eq!(resolve_index(-4, 3), None);
+        assert_eq!(resolve_index(0, 0), None);
+    }
+
+    #[test]
+    fn test_pdf_document_sequence() {
+        Python::initialize();
+        Python::attach(|py| {
+            let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+            let globals = PyDict::new(py);
+            globals.set_item("document", Bound::new(py, open_pdf(pdf_bytes, None).expect("Failed to open PDF")).unwrap()).unwrap();
+
+            py.run(
+                cr#"
+assert len(document) == 5
+first, last = document[0], document[4]
+assert first.error is None and first.image_buffer.startswith(b"RIFF")
+assert document[-1].image_buffer == last.image_buffer
+assert document[-5].image_buffer == first.image_buffer
+for index in (5, -6):
+    try:
+        document[index]
+    except IndexError:
+        pass
+    else:
+        raise AssertionError("indexed page {} of 5".format(index))
+
+pages = list(document)
+assert [page.image_buffer for page in pages] == [document[index].image_buffer for index in range(5)]
+assert len(list(iter(document))) == 5
+"#,
+                Some(&globals),
+                None,
+            ).expect("PyPdfDocument doesn't behave as a sequence of pages");
+        });
+    }
+
+    /// Set in the child process of `run_isolated`
+    const ISOLATED_TEST_ENV: &str = "ZTRON_PDF_ISOLATED_TEST";
+
+    /// Runs one test alone in a fresh copy of this test binary, for tests that
+    /// need pdfium's process-wide state to themselves. The test itself returns
+    /// straight away unless `ISOLATED_TEST_ENV` is set.
+    fn run_isolated(test: &str) {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([test, "--exact", "--nocapture", "--test-threads=1"])
+            .env(ISOLATED_TEST_ENV, "1")
+            .output()
+            .expect("Failed to run the test binary");
+        assert!(
+            output.status.success(),
+            "{} failed in its own process:\n{}{}",
+            test,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    #[test]
+    fn test_shutdown_pdfium_without_load() {
+        run_isolated("tests::shutdown_pdfium_without_load");
+    }
+
+    #[test]
+    fn shutdown_pdfium_without_load() {
+        if std::env::var_os(ISOLATED_TEST_ENV).is_none() {
+            return;
+        }
+
+        // Must not bind pdfium just to tear it down
+        shutdown_pdfium();
+
+        assert!(PDFIUM.get().is_none());
+        assert!(!*PDFIUM_DESTROYED.lock().unwrap());
+    }
+
+    #[test]
+    fn test_pdf_document_exit_closes_document() {
+        run_isolated("tests::pdf_document_exit_closes_document");
+    }
+
+    #[test]
+    fn pdf_document_exit_closes_document() {
+        if std::env::var_os(ISOLATED_TEST_ENV).is_none() {
+            return;
+        }
+
+        Python::initialize();
+        Python::attach(|py| {
+            let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+            let document = Bound::new(py, open_pdf(pdf_bytes, None).expect("Failed to open PDF")).unwrap();
+            assert_eq!(OPEN_DOCUMENTS.load(Ordering::SeqCst), 1);
+
+            let globals = PyDict::new(py);
+            globals.set_item("document", document).unwrap();
+            py.run(
+                cr#"
+with document as opened:
+    assert opened is document
+    assert len(document) == 5
+    pages = iter(document)
+"#,
+                Some(&globals),
+                None,
+            ).expect("Failed to use the document in a with block");
+
+            // Closed on exit, even though the iterator still shares it
+            assert_eq!(OPEN_DOCUMENTS.load(Ordering::SeqCst), 0);
+            py.run(
+                cr#"
+for use in (lambda: document[0], lambda: len(document), lambda: next(pages)):
+    try:
+        use()
+    except ValueError as e:
+        assert "closed" in str(e)
+    else:
+        raise AssertionError("used a closed document")
+"#,
+                Some(&globals),
+                None,
+            ).expect("A closed document was still usable");
+        });
+    }
+
+    #[test]
+    fn test_shutdown_pdfium_exits_cleanly() {
+        run_isolated("tests::shutdown_pdfium_exits_cleanly");
+    }
+
+    #[test]
+    fn shutdown_pdfium_exits_cleanly() {
+        if std::env::var_os(ISOLATED_TEST_ENV).is_none() {
+            return;
+        }
+
+        // A call still using pdfium keeps the library up
+        let lease = load_pdfium().unwrap();
+        shutdown_pdfium();
+        assert!(!*PDFIUM_DESTROYED.lock().unwrap());
+        drop(lease);
+
+        Python::initialize();
+        Python::attach(|py| {
+            let module = pyo3::wrap_pymodule!(ztron_pdf)(py);
+            let globals = PyDict::new(py);
+            globals.set_item("ztron_pdf", module).unwrap();
+            globals.set_item("pdf_bytes", std::fs::read("./samples/test.pdf").unwrap()).unwrap();
+
+            // What the interpreter does at exit, after the module registered
+            // `shutdown_pdfium`
+            py.run(
+                cr#"
+import atexit
+assert len(ztron_pdf.render_pdf(pdf_bytes)) == 5
+atexit._run_exitfuncs()
+try:
+    ztron_pdf.render_pdf(pdf_bytes)
+except RuntimeError as e:
+    assert "shut down" in str(e)
+else:
+    raise AssertionError("render_pdf used pdfium after shutdown")
+"#,
+                Some(&globals),
+                None,
+            ).expect("pdfium didn't shut down cleanly");
+        });
+
+        assert!(*PDFIUM_DESTROYED.lock().unwrap());
+        assert!(load_pdfium().is_err());
+    }
+}
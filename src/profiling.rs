@@ -1,23 +1,110 @@
-mod core;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
+use ztron_pdf::core;
 use pdfium_render::prelude::*;
 
-fn main() {
-    let pdfium = Pdfium::new(
-        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
-            .or_else(|_| Pdfium::bind_to_system_library())
-            .expect("Failed to bind to Pdfium library")
-    );
+/// Renders timed by `time_single_page_renders`
+const RENDERS: u32 = 10;
+
+/// The system allocator, tracking the bytes currently allocated and the
+/// high-water mark since the last `reset_peak`
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Runs `render` and returns how far it pushed the Rust heap above where it
+/// started. pdfium's own (C) allocations are not counted.
+fn peak_heap_growth(render: impl FnOnce()) -> usize {
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    render();
 
+    PEAK.load(Ordering::Relaxed) - baseline
+}
+
+/// The mean time `render` takes over `RENDERS` runs
+fn time_single_page_renders(mut render: impl FnMut()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..RENDERS {
+        render();
+    }
+
+    started.elapsed() / RENDERS
+}
+
+fn bind() -> Box<dyn PdfiumLibraryBindings> {
+    Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+        .or_else(|_| Pdfium::bind_to_system_library())
+        .expect("Failed to bind to Pdfium library")
+}
+
+fn main() {
     let test_pdf_path = "./samples/test2.pdf";
     let pdf_bytes = std::fs::read(test_pdf_path)
         .expect("Failed to read test PDF file");
 
+    let render_first_page = |pdfium: &Pdfium| {
+        core::render_page_with_options(pdfium, &pdf_bytes, 0, &core::RenderOptions::default())
+            .expect("Failed to render page");
+    };
+
+    // What the Python module did before sharing one instance: bind and
+    // initialize pdfium for every call. These instances must all be gone
+    // before the shared one exists, since dropping one shuts the library down.
+    let per_call = time_single_page_renders(|| render_first_page(&Pdfium::new(bind())));
+    let pdfium = Pdfium::new(bind());
+    let shared = time_single_page_renders(|| render_first_page(&pdfium));
+    println!(
+        "Single-page render: {:?} with a new Pdfium per call, {:?} shared ({:.0}% faster)",
+        per_call,
+        shared,
+        100.0 * (1.0 - shared.as_secs_f64() / per_call.as_secs_f64())
+    );
+
+    // A full page at 300 DPI through DynamicImage and through the direct
+    // bitmap-to-PNG path
+    let options = core::RenderOptions::default().with_format(core::ImageFormat::Png).with_dpi(300);
+    let via_image = peak_heap_growth(|| {
+        core::render_pdf(&pdfium, &pdf_bytes, &options).expect("Failed to render PNG");
+    });
+    let direct = peak_heap_growth(|| {
+        core::render_pdf_to_png_with_options(&pdfium, &pdf_bytes, &options).expect("Failed to render PNG");
+    });
+    println!(
+        "300 DPI PNG peak heap: {} MiB via DynamicImage, {} MiB direct ({:.0}% less)",
+        via_image >> 20,
+        direct >> 20,
+        100.0 * (1.0 - direct as f64 / via_image as f64)
+    );
+
     for _ in 0..3 {
-        match core::render_base64_pdf(&pdfium, &pdf_bytes, 75) {
+        let started = Instant::now();
+        match core::render_pdf(&pdfium, &pdf_bytes, &core::RenderOptions::default()) {
             Ok(r) => {
-                assert!(r.len() > 0);
-                println!("Rendered {} images", r.len());
+                assert!(!r.is_empty());
+                println!("Rendered {} images in {:?}", r.len(), started.elapsed());
             },
             Err(e) => {
                 println!("Error: {}", e);
@@ -26,9 +113,9 @@ fn main() {
         thread::sleep(std::time::Duration::from_secs(1));
     }
 
-    pdfium.bindings().FPDF_DestroyLibrary();
+    // Dropping `Pdfium` calls FPDF_DestroyLibrary
     drop(pdfium);
 
     thread::sleep(std::time::Duration::from_secs(2));
     std::process::exit(0);
-}
\ No newline at end of file
+}
@@ -0,0 +1,91 @@
+"""Pydantic and JSON helpers for ztron_pdf results.
+
+Pydantic is imported on first use, so ``import ztron_pdf`` works without it.
+``PageData`` and ``DocumentStats`` let a ``PyPageData`` or ``PyDocumentStats``
+be used as a Pydantic field, e.g. in a FastAPI response model::
+
+    from typing import Annotated
+
+    from pydantic import BaseModel
+    from ztron_pdf.schemas import DocumentStats, PageData
+
+    class RenderResponse(BaseModel):
+        pages: list[Annotated[ztron_pdf.PyPageData, PageData]]
+        stats: Annotated[ztron_pdf.PyDocumentStats, DocumentStats]
+"""
+
+
+def page_to_dict(page):
+    """Converts a ``PyPageData`` to a JSON-serializable ``dict``, with the
+    image as base64 under ``image_buffer_b64``."""
+    return page.to_dict()
+
+
+def page_json_schema():
+    """The JSON Schema of ``page_to_dict`` output, as a ``dict``."""
+    import json
+
+    import ztron_pdf
+
+    return json.loads(ztron_pdf.PyPageData.model_json_schema())
+
+
+def stats_to_dict(stats):
+    """Converts a ``PyDocumentStats`` to a JSON-serializable ``dict``."""
+    return stats.to_dict()
+
+
+def stats_json_schema():
+    """The JSON Schema of ``stats_to_dict`` output, as a ``dict``."""
+    import json
+
+    import ztron_pdf
+
+    return json.loads(ztron_pdf.PyDocumentStats.model_json_schema())
+
+
+def _validator(class_name):
+    def validate(value):
+        import ztron_pdf
+
+        if not isinstance(value, getattr(ztron_pdf, class_name)):
+            raise ValueError("Expected a {}, got {}".format(class_name, type(value).__name__))
+        return value
+
+    return validate
+
+
+class PageData:
+    """Pydantic annotation for ``PyPageData`` fields: validates instances and
+    serializes them with ``page_to_dict``."""
+
+    @classmethod
+    def __get_pydantic_core_schema__(cls, source_type, handler):
+        from pydantic_core import core_schema
+
+        return core_schema.no_info_plain_validator_function(
+            _validator("PyPageData"),
+            serialization=core_schema.plain_serializer_function_ser_schema(page_to_dict),
+        )
+
+    @classmethod
+    def __get_pydantic_json_schema__(cls, core_schema, handler):
+        return page_json_schema()
+
+
+class DocumentStats:
+    """Pydantic annotation for ``PyDocumentStats`` fields: validates instances
+    and serializes them with ``stats_to_dict``."""
+
+    @classmethod
+    def __get_pydantic_core_schema__(cls, source_type, handler):
+        from pydantic_core import core_schema
+
+        return core_schema.no_info_plain_validator_function(
+            _validator("PyDocumentStats"),
+            serialization=core_schema.plain_serializer_function_ser_schema(stats_to_dict),
+        )
+
+    @classmethod
+    def __get_pydantic_json_schema__(cls, core_schema, handler):
+        return stats_json_schema()
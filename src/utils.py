@@ -0,0 +1,26 @@
+"""Pillow helpers for ztron_pdf results.
+
+Pillow is imported on first use, so ``import ztron_pdf`` works without it::
+
+    from ztron_pdf.utils import page_to_pil
+
+    image = page_to_pil(ztron_pdf.render_page(pdf_bytes, 0, format="PNG"))
+"""
+
+
+def page_to_pil(page):
+    """Opens the encoded image of a ``PyPageData`` as a ``PIL.Image.Image``."""
+    import io
+
+    from PIL import Image
+
+    return Image.open(io.BytesIO(page.image_buffer))
+
+
+def raw_to_pil(raw):
+    """Wraps the pixels of a ``PyRawPageBuffer`` from ``render_page_to_raw``
+    in a ``PIL.Image.Image``, as RGB or RGBA to match its channel count."""
+    from PIL import Image
+
+    mode = "RGBA" if raw.channels == 4 else "RGB"
+    return Image.frombytes(mode, (raw.width, raw.height), raw.data)
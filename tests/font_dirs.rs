@@ -0,0 +1,21 @@
+//! `init_pdfium` sets up pdfium's process-wide font configuration, so it gets
+//! a test binary of its own rather than racing the renders in the unit tests.
+
+use std::path::PathBuf;
+
+use pdfium_render::prelude::*;
+use ztron_pdf::core::{self, RenderOptions};
+
+#[test]
+fn test_init_pdfium_with_font_dirs() {
+    let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+        .or_else(|_| Pdfium::bind_to_system_library())
+        .expect("Failed to bind to Pdfium library");
+    let pdfium = core::init_pdfium(bindings, &[PathBuf::from("./samples")]);
+
+    let pdf_bytes = std::fs::read("./samples/test.pdf")
+        .expect("Failed to read test PDF file");
+    let pages = core::render_pdf(&pdfium, &pdf_bytes, &RenderOptions::default())
+        .expect("Failed to render with extra font paths");
+    assert_eq!(pages.len(), 5);
+}
@@ -0,0 +1,36 @@
+//! Runs the `render_ndjson` binary the way shell pipelines do.
+
+use std::process::{Command, Output};
+
+/// Runs `script` under `sh` with `$BIN` set to the render_ndjson binary
+fn sh(script: &str) -> Output {
+    Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .env("BIN", env!("CARGO_BIN_EXE_render_ndjson"))
+        .output()
+        .expect("Failed to run sh")
+}
+
+#[test]
+fn test_render_ndjson_from_stdin() {
+    let output = sh("cat ./samples/test.pdf | \"$BIN\" --pdf - --quality 75");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).expect("stdout is not UTF-8");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5);
+    for (page_index, line) in lines.iter().enumerate() {
+        assert!(line.starts_with(&format!(r#"{{"page":{},"width":"#, page_index)), "{}", line);
+        assert!(line.contains(r#","data":""#) && line.ends_with(r#""}"#), "{}", line);
+    }
+}
+
+#[test]
+fn test_render_ndjson_truncated_stdin() {
+    let output = sh("head -c 2000 ./samples/test.pdf | \"$BIN\" --pdf -");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stdin closed before the end of the PDF"));
+}
@@ -0,0 +1,61 @@
+//! Renders a PDF, rebuilds it from the rendered pages and renders it again,
+//! to catch encoding, color space and dimension rounding bugs anywhere along
+//! the pipeline.
+
+use pdfium_render::prelude::*;
+use ztron_pdf::core::{self, ImageFormat, RenderOptions};
+
+/// Highest mean squared error per channel allowed between the two renders
+const MAX_MSE: f64 = 5.0;
+
+fn decode(image_buffer: &[u8]) -> image::RgbImage {
+    image::load_from_memory_with_format(image_buffer, image::ImageFormat::Png)
+        .expect("Failed to decode rendered page")
+        .into_rgb8()
+}
+
+/// Mean squared error over every channel of two images of the same size
+fn mse(a: &image::RgbImage, b: &image::RgbImage) -> f64 {
+    let total: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+        .sum();
+
+    total / a.as_raw().len() as f64
+}
+
+#[test]
+fn test_render_images_to_pdf_roundtrip() {
+    let pdfium = Pdfium::new(
+        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+            .or_else(|_| Pdfium::bind_to_system_library())
+            .expect("Failed to bind to Pdfium library")
+    );
+    let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+
+    let options = RenderOptions::default().with_format(ImageFormat::Png).with_dpi(300);
+    let pages = core::render_pdf(&pdfium, &pdf_bytes, &options).expect("Failed to render PDF");
+    let images: Vec<_> = pages.iter().map(|page| (page.image_buffer.clone(), ImageFormat::Png)).collect();
+    let rebuilt = core::images_to_pdf(&pdfium, &images).expect("Failed to build PDF from images");
+
+    // images_to_pdf lays pixels out at 72 DPI, so rendering at 72 DPI maps
+    // them one to one back onto the 300 DPI renders
+    let options = RenderOptions::default().with_format(ImageFormat::Png).with_dpi(72);
+    let rerendered = core::render_pdf(&pdfium, &rebuilt, &options).expect("Failed to render rebuilt PDF");
+    assert_eq!(rerendered.len(), pages.len(), "The rebuilt PDF has a different page count");
+
+    for (page_index, (original, rerendered)) in pages.iter().zip(&rerendered).enumerate() {
+        let (original, rerendered) = (decode(&original.image_buffer), decode(&rerendered.image_buffer));
+        assert_eq!(
+            original.dimensions(),
+            rerendered.dimensions(),
+            "Page {} changed size in the round trip",
+            page_index
+        );
+
+        let error = mse(&original, &rerendered);
+        assert!(error < MAX_MSE, "Page {} differs after the round trip: MSE {:.2} exceeds {}", page_index, error, MAX_MSE);
+    }
+}
@@ -0,0 +1,43 @@
+//! Long-running checks for leaks that short runs don't show. Run with
+//! `cargo test --test stress -- --ignored`.
+
+use pdfium_render::prelude::*;
+use ztron_pdf::core::{self, RenderOptions};
+
+/// The resident set size of this process in kB, from `/proc/self/status`
+#[cfg(target_os = "linux")]
+fn rss_kb() -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").expect("Failed to read /proc/self/status");
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .expect("No VmRSS in /proc/self/status")
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore]
+fn test_render_does_not_leak() {
+    let pdfium = Pdfium::new(
+        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"))
+            .or_else(|_| Pdfium::bind_to_system_library())
+            .expect("Failed to bind to Pdfium library")
+    );
+    let pdf_bytes = std::fs::read("./samples/test.pdf").expect("Failed to read test PDF file");
+
+    let mut samples = Vec::new();
+    for iteration in 1..=100 {
+        let pages = core::render_pdf(&pdfium, &pdf_bytes, &RenderOptions::default()).expect("Failed to render");
+        assert_eq!(pages.len(), 5);
+        drop(pages);
+
+        if [1, 10, 50, 100].contains(&iteration) {
+            samples.push((iteration, rss_kb()));
+        }
+    }
+
+    let first = samples[0].1;
+    let last = samples[samples.len() - 1].1;
+    assert!(last < first * 3, "RSS grew from {} kB to {} kB over 100 renders", first, last);
+}
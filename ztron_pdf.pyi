@@ -1,8 +1,311 @@
-from typing import List
+import os
+
+from typing import TYPE_CHECKING, Any, Awaitable, BinaryIO, Callable, Dict, Iterator, List, Optional, Tuple, Union
+
+if TYPE_CHECKING:
+    import numpy
+
+__pdfium_render_version__: str
+__pdfium_build_version__: str
+A4_PT: Tuple[float, float]
+LETTER_PT: Tuple[float, float]
+DEFAULT_MAX_PDF_BYTES: int
 
 class PyPageData:
     image_buffer: bytes
+    error: Optional[str]
+    image_buffer_as_base64: str
+    def to_dict(self) -> Dict[str, Optional[str]]: ...
+    @classmethod
+    def model_json_schema(cls) -> str: ...
+
+class PyStructureNode:
+    tag: str
+    alt_text: Optional[str]
+    actual_text: Optional[str]
+    children: List[PyStructureNode]
+
+class PyPortfolioEntry:
+    filename: str
+    pages: List[PyPageData]
+
+class PyEmbeddedFile:
+    filename: str
+    mime_type: Optional[str]
+    data: bytes
+    creation_date: Optional[str]
+
+class PyFontInfo:
+    name: str
+    is_embedded: bool
+
+class PyImageQualityStat:
+    page_index: int
+    object_index: int
+    width: int
+    height: int
+    estimated_quality: Optional[int]
+    format: str
+
+class PyTocEntry:
+    level: int
+    title: str
+    page_label: str
+
+class PyAccessibilityViolation:
+    rule: str
+    page_index: Optional[int]
+    object_index: Optional[int]
+    severity: str
+
+class PySignatureImage:
+    page_index: int
+    field_name: str
+    image_buffer: bytes
+
+class PySmartCompressReport:
+    images_processed: int
+    savings_bytes: int
+    per_image_choices: List[Tuple[int, str]]
+
+class PySignatureVerification:
+    signer: str
+    valid: bool
+    covers_whole_document: bool
+    reason: Optional[str]
+    signing_time: Optional[str]
+
+class PyMetadataDiff:
+    field: str
+    old_value: Optional[str]
+    new_value: Optional[str]
+
+class PyRawPageBuffer:
+    width: int
+    height: int
+    channels: int
+    data: bytes
+
+class PyPdfDocument:
+    def __len__(self) -> int: ...
+    def __getitem__(self, index: int) -> PyPageData: ...
+    def __iter__(self) -> Iterator[PyPageData]: ...
+    def __enter__(self) -> "PyPdfDocument": ...
+    def __exit__(self, exc_type: Optional[type] = None, exc_value: Optional[BaseException] = None, traceback: Optional[object] = None) -> bool: ...
+
+class PyPageFrameIter:
+    def __iter__(self) -> "PyPageFrameIter": ...
+    def __next__(self) -> PyPageData: ...
+    def __len__(self) -> int: ...
+
+class PyRenderOptions:
+    quality: int
+    format: str
+    dpi: Optional[int]
+    grayscale: bool
+    icc_profile: Optional[bytes]
+    simulate_overprint: bool
+    max_pages: Optional[int]
+    max_pdf_bytes: Optional[int]
+    color_space: str
+    print_marks: bool
+    per_page_timeout: Optional[float]
+
+    def __init__(self, quality: int = 75, format: str = "WEBP", dpi: Optional[int] = None, grayscale: bool = False, icc_profile: Optional[bytes] = None, simulate_overprint: bool = False, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None, color_space: str = "srgb", print_marks: bool = False, per_page_timeout: Optional[float] = None) -> None: ...
+
+class PyDocumentStats:
+    page_count: int
+    total_words: int
+    total_chars: int
+    avg_words_per_page: float
+    def to_dict(self) -> Dict[str, Union[int, float]]: ...
+    @classmethod
+    def model_json_schema(cls) -> str: ...
+
+def render_cover(pdf_bytes: bytes, max_size: int = 512, max_pages: Optional[int] = None) -> str:
+    """Base64-encoded WebP preview of the first page, max_size pixels on its longer edge."""
+    ...
+
+def generate_page_previews_json(pdf_bytes: bytes, thumb_size: int = 200, quality: int = 60, max_pages: Optional[int] = None) -> str: ...
+
+def render_pdf(pdf_bytes: Union[bytes, str, os.PathLike], quality: int = 75, format: str = "WEBP", icc_profile: Optional[bytes] = None, simulate_overprint: bool = False, dpi: Optional[int] = None, grayscale: bool = False, options: Optional[PyRenderOptions] = None, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None, color_space: str = "srgb", render_hook: Optional[Callable[[int, PyRawPageBuffer], Optional[bytes]]] = None, print_marks: bool = False) -> List[PyPageData]: ...
+
+def render_base64_pdf(pdf_bytes: bytes, quality: int = 75, icc_profile: Optional[bytes] = None, simulate_overprint: bool = False, dpi: Optional[int] = None, grayscale: bool = False, options: Optional[PyRenderOptions] = None, print_marks: bool = False) -> List[PyPageData]: ...
+
+def render_pdf_from_file_like(file_obj: BinaryIO, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> List[PyPageData]: ...
+
+def compress_pdf(base64_pdf: str, quality: int, skip_if_no_savings: bool = True, preserve_bitonal: bool = True) -> str: ...
+
+def compress_pdf_with_downsample(pdf_bytes: bytes, quality: int = 75, target_dpi: float = 150.0, max_pages: Optional[int] = None) -> bytes: ...
+
+def pdf_to_markdown(pdf_bytes: bytes, max_pages: Optional[int] = None) -> str: ...
+
+def pdf_to_text(pdf_bytes: bytes, preserve_layout: bool = True, max_pages: Optional[int] = None) -> str: ...
+
+def page_word_count(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[int]: ...
+
+def document_stats(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> PyDocumentStats: ...
+
+def render_pdf_to_cbz(pdf_bytes: bytes, quality: int = 85, max_pages: Optional[int] = None, options: Optional[PyRenderOptions] = None) -> bytes: ...
+
+def render_pdf_to_png(pdf_bytes: bytes, max_pages: Optional[int] = None, options: Optional[PyRenderOptions] = None) -> List[PyPageData]: ...
+
+def extract_structure_tree(pdf_bytes: bytes, max_pages: Optional[int] = None) -> PyStructureNode: ...
+
+def is_scanned_pdf(pdf_bytes: bytes, text_threshold: int = 50, max_pages: Optional[int] = None) -> bool: ...
+
+def render_pdf_pages_to_dir(pdf_bytes: bytes, output_dir: str, quality: int = 75, format: str = "WEBP", dpi: Optional[int] = None, grayscale: bool = False, options: Optional[PyRenderOptions] = None, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None, color_space: str = "srgb") -> List[str]: ...
+
+def has_digital_signature(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bool: ...
+
+def count_digital_signatures(pdf_bytes: bytes, max_pages: Optional[int] = None) -> int: ...
+
+def list_signature_signers(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[str]: ...
+
+def verify_signatures(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[PySignatureVerification]: ...
+
+def deskew_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_page(pdf_bytes: bytes, page_index: int, quality: int = 75, format: str = "WEBP", dpi: Optional[int] = None, grayscale: bool = False, options: Optional[PyRenderOptions] = None, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None, color_space: str = "srgb") -> PyPageData: ...
+
+def get_page_labels(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> List[Optional[str]]: ...
+
+def render_page_by_label(pdf_bytes: bytes, label: str, quality: int = 75, format: str = "WEBP", dpi: Optional[int] = None, grayscale: bool = False, options: Optional[PyRenderOptions] = None, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None, color_space: str = "srgb") -> PyPageData: ...
+
+def render_thumbnails(pdf_bytes: bytes, thumb_size: int = 256, quality: int = 60, format: str = "WEBP", max_pages: Optional[int] = None) -> List[PyPageData]: ...
+
+def set_pdf_password(pdf_bytes: bytes, user_password: str, owner_password: str, allow_print: bool = True, allow_copy: bool = False, allow_modify: bool = False, max_pages: Optional[int] = None) -> bytes: ...
+
+def garbage_collect_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_portfolio(pdf_bytes: bytes, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None) -> List[PyPortfolioEntry]: ...
+
+def extract_embedded_files(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[PyEmbeddedFile]: ...
+
+def attach_file(pdf_bytes: bytes, filename: str, file_data: bytes, mime_type: Optional[str] = None, max_pages: Optional[int] = None) -> bytes: ...
+
+def strip_embedded_files(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def flatten_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def images_to_pdf(images: List[bytes], formats: Optional[List[str]] = None) -> bytes: ...
+
+def detect_language(pdf_bytes: bytes, max_pages: Optional[int] = None) -> str: ...
+
+def add_qr_watermark(pdf_bytes: bytes, data: str, position: str = "bottom_right", opacity: float = 0.3, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_page_with_highlights(pdf_bytes: bytes, page_index: int, highlights: List[Tuple[float, float, float, float]], dpi: int = 150, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> PyPageData: ...
+
+def render_page_with_search_results(pdf_bytes: bytes, page_index: int, query: str, dpi: int = 150, quality: int = 75, max_pages: Optional[int] = None) -> PyPageData: ...
+
+def estimate_page_noise(pdf_bytes: bytes, page_index: int, max_pages: Optional[int] = None) -> float: ...
+
+def batch_get_page_counts(pdfs: List[bytes]) -> List[int]: ...
+
+def impose_nup(pdf_bytes: bytes, n: int, page_width_pt: float, page_height_pt: float, max_pages: Optional[int] = None) -> bytes: ...
+
+def copy_pages(source: bytes, destination: bytes, page_indices: List[int], max_pages: Optional[int] = None) -> bytes: ...
+
+def render_page_to_raw(pdf_bytes: bytes, page_index: int, dpi: int = 150, channels: int = 3, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> PyRawPageBuffer: ...
+
+def set_page_size(pdf_bytes: bytes, page_index: int, width_pt: float, height_pt: float, scale_content: bool = True, max_pages: Optional[int] = None) -> bytes: ...
+
+def get_xmp_metadata(pdf_bytes: bytes, max_pages: Optional[int] = None) -> Optional[str]: ...
+
+def set_xmp_metadata(pdf_bytes: bytes, xmp_xml: str, max_pages: Optional[int] = None) -> bytes: ...
+
+def compare_metadata(pdf_a: bytes, pdf_b: bytes, max_pages: Optional[int] = None) -> List[PyMetadataDiff]: ...
+
+def get_fonts(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> List[PyFontInfo]: ...
+
+def embed_missing_fonts(pdf_bytes: bytes, font_dir: str, max_pages: Optional[int] = None) -> bytes: ...
+
+def image_quality_stats(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[PyImageQualityStat]: ...
+
+def prepend_toc_page(pdf_bytes: bytes, entries: List[Tuple[str, int]], max_pages: Optional[int] = None) -> bytes: ...
+
+def render_page_difference(pdf_a: bytes, pdf_b: bytes, page_index: int, dpi: int = 150, max_pages: Optional[int] = None) -> PyPageData: ...
+
+def subset_embedded_fonts(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def split_by_blank_pages(pdf_bytes: bytes, blank_threshold: float = 5.0, max_pages: Optional[int] = None) -> List[bytes]: ...
+
+def render_pdf_grid(pdf_bytes: bytes, columns: int = 4, thumb_size: int = 200, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None) -> PyPageData: ...
+
+def page_text_density(pdf_bytes: bytes, page_index: int, max_pages: Optional[int] = None) -> float: ...
+
+def enable_xref_stream(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_page_inverted(pdf_bytes: bytes, page_index: int, dpi: int = 150, quality: int = 75, max_pages: Optional[int] = None) -> PyPageData: ...
+
+def open_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> PyPdfDocument: ...
+
+def render_pdf_iter(pdf_bytes: bytes, options: Optional[PyRenderOptions] = None) -> PyPageFrameIter: ...
+
+def render_first_n_pages(pdf_bytes: bytes, n: int, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None) -> List[PyPageData]: ...
+
+def create_blank_pdf(page_sizes: List[Tuple[float, float]]) -> bytes: ...
+
+def add_text_to_page(pdf_bytes: bytes, page_index: int, text: str, x: float, y: float, font_size: float, color: Tuple[int, int, int] = (0, 0, 0), font: Optional[str] = None, max_pages: Optional[int] = None) -> bytes: ...
+
+def measure_text(text: str, font: Optional[str] = None, font_size: float = 12.0) -> float: ...
+
+def extract_toc_text(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> List[PyTocEntry]: ...
+
+def render_pdf_files_parallel(paths: List[str], quality: int = 75, format: str = "WEBP") -> List[Tuple[str, List[PyPageData]]]: ...
+
+def validate_pdfua(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[PyAccessibilityViolation]: ...
+
+def render_pdf_as_animated_webp(pdf_bytes: bytes, quality: int = 75, frame_duration_ms: int = 2000, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_pdf_as_gif(pdf_bytes: bytes, frame_delay_ms: int = 2000, max_pages: Optional[int] = None) -> bytes: ...
+
+def add_page_numbers(pdf_bytes: bytes, start: int = 1, position: str = "bottom_center", font_size: float = 10.0, color: Tuple[int, int, int] = (0, 0, 0), max_pages: Optional[int] = None) -> bytes: ...
+
+def get_page_image_count(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> List[int]: ...
+def total_image_count(pdf_bytes: bytes, max_pages: Optional[int] = None, max_pdf_bytes: Optional[int] = None) -> int: ...
+
+def render_page_to_opencv_mat(pdf_bytes: bytes, page_index: int, dpi: int = 150, max_pages: Optional[int] = None) -> "numpy.ndarray": ...
+
+def append_pdf(base: bytes, appendix: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def rotate_to_landscape(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+def rotate_to_portrait(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def render_pdf_pages_async(pdf_bytes: bytes, quality: int = 75, format: str = "WEBP", max_pages: Optional[int] = None) -> Awaitable[List[PyPageData]]: ...
+
+def hash_pdf_content(pdf_bytes: bytes, max_pages: Optional[int] = None) -> str: ...
+
+def extract_color_palette(pdf_bytes: bytes, page_index: int = 0, n_colors: int = 5, max_pages: Optional[int] = None) -> List[Tuple[int, int, int]]: ...
+
+def batch_compress_pdfs(pdfs: List[Tuple[bytes, int]]) -> Tuple[List[Optional[bytes]], List[Optional[str]]]: ...
+
+def repair_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> bytes: ...
+
+def extract_signature_images(pdf_bytes: bytes, max_pages: Optional[int] = None) -> List[PySignatureImage]: ...
+
+def smart_compress_pdf(pdf_bytes: bytes, max_pages: Optional[int] = None) -> Tuple[bytes, PySmartCompressReport]: ...
+
+def stitch_pages_vertically(pdf_bytes: bytes, target_width: int = 800, quality: int = 75, format: str = "WEBP", gap: int = 10, max_pages: Optional[int] = None) -> PyPageData: ...
+
+def render_page_with_color_adjustment(pdf_bytes: bytes, page_index: int, brightness: float = 0.0, contrast: float = 1.0, dpi: int = 150, quality: int = 75, max_pages: Optional[int] = None) -> PyPageData: ...
 
-def render_base64_pdf(pdf_bytes: bytes, quality: int) -> List[PyPageData]: ...
+# The pure-Python submodules built from src/schemas.py and src/utils.py. A
+# single-file stub can't declare submodules, so they are typed as namespaces.
+class schemas:
+    @staticmethod
+    def page_to_dict(page: PyPageData) -> Dict[str, Optional[str]]: ...
+    @staticmethod
+    def page_json_schema() -> Dict[str, Any]: ...
+    @staticmethod
+    def stats_to_dict(stats: PyDocumentStats) -> Dict[str, Union[int, float]]: ...
+    @staticmethod
+    def stats_json_schema() -> Dict[str, Any]: ...
+    class PageData: ...
+    class DocumentStats: ...
 
-def compress_pdf(base64_pdf: str, quality: int) -> str: ...
\ No newline at end of file
+class utils:
+    @staticmethod
+    def page_to_pil(page: PyPageData) -> Any: ...
+    @staticmethod
+    def raw_to_pil(raw: PyRawPageBuffer) -> Any: ...